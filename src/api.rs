@@ -1,45 +1,128 @@
 use crate::error::{Result, VmCliError};
-use reqwest::Client;
-use serde::{Deserialize, Serialize};
-use std::time::Duration;
-use tracing::debug;
+use crate::config::{AuthConfig, HttpConfig, TlsConfig};
+use crate::trace;
+use percent_encoding::{utf8_percent_encode, AsciiSet, NON_ALPHANUMERIC};
+use bytes::Bytes;
+use reqwest::{Client, NoProxy, Proxy, RequestBuilder, Response};
+use serde::de::{DeserializeOwned, DeserializeSeed, Deserializer, IgnoredAny, MapAccess, SeqAccess, Visitor};
+use serde::{Deserialize, Serialize, Serializer};
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::io::{Read, Write};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio_util::sync::CancellationToken;
+use tracing::{debug, warn};
+
+const POST_QUERY_THRESHOLD: usize = 2000;
+const QUERY_CACHE_CAPACITY: usize = 64;
+const DEFAULT_RETRIES: u32 = 2;
+const DEFAULT_RETRY_BASE_MS: u64 = 250;
+const MAX_RETRY_DELAY: Duration = Duration::from_secs(30);
+const IDEMPOTENCY_KEY_HEADER: &str = "X-Idempotency-Key";
 
 #[derive(Debug, Clone)]
 pub struct VmClient {
     client: Client,
     base_url: String,
     cluster_config: Option<crate::config::ClusterConfig>,
+    http_config: HttpConfig,
+    tls_config: TlsConfig,
+    auth: Option<AuthConfig>,
+    select_hosts: Vec<String>,
+    base_path: String,
+    cancel: CancellationToken,
+    query_cache: Option<Arc<QueryCache>>,
+    dry_run: bool,
+    round_digits: Option<u32>,
+    extra_filters: Vec<String>,
+    extra_labels: Vec<String>,
 }
 
+#[derive(Debug)]
+struct QueryCache {
+    ttl: Duration,
+    entries: Mutex<VecDeque<CacheEntry>>,
+}
+
+#[derive(Debug)]
+struct CacheEntry {
+    key: String,
+    stored_at: Instant,
+    response: QueryResponse,
+}
+
+impl QueryCache {
+    fn get(&self, key: &str) -> Option<QueryResponse> {
+        let mut entries = self.entries.lock().unwrap();
+        entries.retain(|entry| entry.stored_at.elapsed() < self.ttl);
+        let position = entries.iter().position(|entry| entry.key == key)?;
+        let entry = entries.remove(position)?;
+        let response = entry.response.clone();
+        entries.push_back(entry);
+        Some(response)
+    }
+
+    fn insert(&self, key: String, response: &QueryResponse) {
+        let mut entries = self.entries.lock().unwrap();
+        entries.retain(|entry| entry.key != key);
+        if entries.len() >= QUERY_CACHE_CAPACITY {
+            entries.pop_front();
+        }
+        entries.push_back(CacheEntry {
+            key,
+            stored_at: Instant::now(),
+            response: response.clone(),
+        });
+    }
+}
+
+#[allow(dead_code)]
 pub struct VmInsertClient {
     client: Client,
     base_url: String,
 }
 
+#[allow(dead_code)]
 pub struct VmStorageClient {
     client: Client,
     base_url: String,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct QueryResponse {
     pub status: String,
     pub data: QueryData,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct QueryData {
     pub result_type: Option<String>,
     pub result: Vec<QueryResult>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct QueryResult {
+    #[serde(serialize_with = "serialize_sorted_labels")]
     pub metric: std::collections::HashMap<String, String>,
     pub value: Option<(f64, String)>,
     pub values: Option<Vec<(f64, String)>>,
 }
 
+fn serialize_sorted_labels<S: Serializer>(
+    labels: &HashMap<String, String>,
+    serializer: S,
+) -> std::result::Result<S::Ok, S::Error> {
+    labels.iter().collect::<BTreeMap<_, _>>().serialize(serializer)
+}
+
+fn serialize_sorted_label_sets<S: Serializer>(
+    label_sets: &[HashMap<String, String>],
+    serializer: S,
+) -> std::result::Result<S::Ok, S::Error> {
+    let sorted: Vec<BTreeMap<&String, &String>> = label_sets.iter().map(|labels| labels.iter().collect()).collect();
+    sorted.serialize(serializer)
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct HealthResponse {
     pub status: String,
@@ -53,6 +136,29 @@ pub struct MetricsList {
     pub data: Vec<String>,
 }
 
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SeriesResponse {
+    pub status: String,
+    #[serde(serialize_with = "serialize_sorted_label_sets")]
+    pub data: Vec<std::collections::HashMap<String, String>>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct MetadataResponse {
+    pub status: String,
+    pub data: std::collections::HashMap<String, Vec<MetricMetadata>>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MetricMetadata {
+    #[serde(rename = "type")]
+    pub metric_type: String,
+    #[serde(default)]
+    pub help: String,
+    #[serde(default)]
+    pub unit: String,
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct SnapshotInfo {
     pub name: String,
@@ -71,27 +177,379 @@ pub struct RetentionInfo {
     pub metrics_older_than_90d: Option<String>,
 }
 
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RelabelDebugResponse {
+    #[serde(default)]
+    pub original_labels: String,
+    #[serde(default)]
+    pub resulting_labels: String,
+    #[serde(default)]
+    pub steps: Vec<RelabelStep>,
+    pub error_msg: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RelabelStep {
+    #[serde(default)]
+    pub rule: String,
+    #[serde(default)]
+    pub in_labels: String,
+    #[serde(default)]
+    pub out_labels: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct TsdbStatusResponse {
+    pub status: String,
+    pub data: TsdbStatus,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TsdbStatus {
+    #[serde(default)]
+    pub total_series: Option<u64>,
+    #[serde(default)]
+    pub total_label_value_pairs: u64,
+    #[serde(default)]
+    pub series_count_by_metric_name: Vec<TsdbStatEntry>,
+    #[serde(default)]
+    pub series_count_by_label_name: Vec<TsdbStatEntry>,
+    #[serde(default)]
+    pub series_count_by_focus_label_value: Vec<TsdbStatEntry>,
+    #[serde(default)]
+    pub series_count_by_label_value_pair: Vec<TsdbStatEntry>,
+    #[serde(default)]
+    pub label_value_count_by_label_name: Vec<TsdbStatEntry>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TsdbStatEntry {
+    pub name: String,
+    pub value: u64,
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct SlowQueryInfo {
     pub query: String,
-    pub duration: f64,
-    pub reason: String,
+    pub duration: Option<f64>,
+    pub count: u64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum SlowQueryRanking {
+    AvgDuration,
+    SumDuration,
+    Count,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct TopQueriesResponse {
+    #[serde(default)]
+    top_by_count: Vec<TopQueryEntry>,
+    #[serde(default)]
+    top_by_avg_duration: Vec<TopQueryEntry>,
+    #[serde(default)]
+    top_by_sum_duration: Vec<TopQueryEntry>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct TopQueryEntry {
+    query: String,
+    #[serde(default)]
+    count: u64,
+    avg_duration_seconds: Option<f64>,
+    sum_duration_seconds: Option<f64>,
 }
 
 impl VmClient {
-    pub fn new(host: &str, timeout: u64, cluster_config: Option<crate::config::ClusterConfig>) -> Result<Self> {
-        let client = Client::builder()
-            .timeout(Duration::from_secs(timeout))
-            .build()
-            .map_err(|e| VmCliError::HttpError(reqwest::Error::from(e)))?;
+    pub fn new(
+        host: &str,
+        timeout: u64,
+        cluster_config: Option<crate::config::ClusterConfig>,
+        http_config: Option<HttpConfig>,
+        tls_config: Option<TlsConfig>,
+    ) -> Result<Self> {
+        let http_config = http_config.unwrap_or_default();
+        let tls_config = tls_config.unwrap_or_default();
+        if tls_config.insecure {
+            warn!("Проверка TLS-сертификатов отключена (--insecure); не используйте этот режим в production");
+        }
+        let client = build_http_client(timeout, &http_config, &tls_config)?;
+
+        let select_hosts = cluster_config
+            .as_ref()
+            .map(|c| c.vmselect_hosts.clone())
+            .unwrap_or_default();
 
         Ok(Self {
             client,
             base_url: host.to_string(),
             cluster_config,
+            http_config,
+            tls_config,
+            auth: None,
+            select_hosts,
+            base_path: String::new(),
+            cancel: CancellationToken::new(),
+            query_cache: None,
+            dry_run: false,
+            round_digits: None,
+            extra_filters: Vec::new(),
+            extra_labels: Vec::new(),
         })
     }
 
+    pub fn with_auth(mut self, auth: Option<AuthConfig>) -> Self {
+        self.auth = auth;
+        self
+    }
+
+    pub fn with_base_path(mut self, base_path: Option<String>) -> Self {
+        self.base_path = base_path.unwrap_or_default();
+        self
+    }
+
+    fn url_for(&self, host: &str, endpoint: &str) -> String {
+        join_url(host, &self.base_path, endpoint)
+    }
+
+    pub fn base_url(&self) -> &str {
+        &self.base_url
+    }
+
+    pub fn tls_insecure(&self) -> bool {
+        self.tls_config.insecure
+    }
+
+    /// TLS-коннектор для ручного рукопожатия (debug connect, doctor) с теми же
+    /// CA и --insecure, что и у HTTP-клиента.
+    pub fn tls_connector(&self) -> Result<native_tls::TlsConnector> {
+        let mut builder = native_tls::TlsConnector::builder();
+        if let Some(path) = self.tls_config.ca_cert.as_deref() {
+            for cert in load_native_ca_certs(path)? {
+                builder.add_root_certificate(cert);
+            }
+        }
+        builder.danger_accept_invalid_certs(self.tls_config.insecure);
+        builder.build().map_err(|e| VmCliError::Unknown(format!("TLS: {}", e)))
+    }
+
+    pub fn with_select_hosts(mut self, hosts: Vec<String>) -> Self {
+        if !hosts.is_empty() {
+            self.select_hosts = hosts;
+        }
+        self
+    }
+
+    pub fn with_cancellation(mut self, cancel: CancellationToken) -> Self {
+        self.cancel = cancel;
+        self
+    }
+
+    pub fn with_query_cache(mut self, ttl: Option<Duration>) -> Self {
+        self.query_cache = ttl.filter(|ttl| !ttl.is_zero()).map(|ttl| {
+            Arc::new(QueryCache {
+                ttl,
+                entries: Mutex::new(VecDeque::new()),
+            })
+        });
+        self
+    }
+
+    fn cached_query(&self, key: &str) -> Option<QueryResponse> {
+        let response = self.query_cache.as_ref()?.get(key)?;
+        debug!("Query served from cache: {}", key);
+        Some(response)
+    }
+
+    fn cache_query(&self, key: String, response: &QueryResponse) {
+        if let Some(cache) = &self.query_cache {
+            cache.insert(key, response);
+        }
+    }
+
+    pub fn with_enforced_labels(mut self, extra_filters: Vec<String>, extra_labels: Vec<String>) -> Self {
+        self.extra_filters = extra_filters;
+        self.extra_labels = extra_labels;
+        self
+    }
+
+    fn enforcement_params(&self) -> impl Iterator<Item = (&str, &str)> {
+        let filters = self.extra_filters.iter().map(|filter| ("extra_filters[]", filter.as_str()));
+        let labels = self.extra_labels.iter().map(|label| ("extra_label", label.as_str()));
+        filters.chain(labels)
+    }
+
+    pub fn with_round_digits(mut self, round_digits: Option<u32>) -> Self {
+        self.round_digits = round_digits;
+        self
+    }
+
+    pub fn with_dry_run(mut self, dry_run: bool) -> Self {
+        self.dry_run = dry_run;
+        self
+    }
+
+    pub fn is_dry_run(&self) -> bool {
+        self.dry_run
+    }
+
+    pub async fn cancelled(&self) {
+        self.cancel.cancelled().await
+    }
+
+    async fn send(&self, request: RequestBuilder) -> Result<Response> {
+        let retries = self.http_config.retries.unwrap_or(DEFAULT_RETRIES);
+        self.send_with_retry(request, retries).await
+    }
+
+    async fn send_with_retry(&self, mut request: RequestBuilder, retries: u32) -> Result<Response> {
+        let base = Duration::from_millis(self.http_config.retry_base_ms.unwrap_or(DEFAULT_RETRY_BASE_MS));
+        let mut attempt = 0;
+
+        loop {
+            let next = if attempt < retries { request.try_clone() } else { None };
+            let result = self.send_once(request).await;
+            let Some(next) = next else {
+                return result;
+            };
+
+            let reason = match &result {
+                Ok(response) if response.status().is_server_error() => response.status().to_string(),
+                Err(VmCliError::HttpError(e)) if e.is_connect() || e.is_timeout() || e.is_request() => e.to_string(),
+                _ => return result,
+            };
+
+            attempt += 1;
+            let delay = retry_delay(base, attempt);
+            warn!("Попытка {} из {} не удалась ({}), повтор через {:?}", attempt, retries + 1, reason, delay);
+            tokio::select! {
+                _ = tokio::time::sleep(delay) => {}
+                _ = self.cancel.cancelled() => return Err(VmCliError::Cancelled),
+            }
+            request = next;
+        }
+    }
+
+    async fn send_once(&self, request: RequestBuilder) -> Result<Response> {
+        if self.cancel.is_cancelled() {
+            return Err(VmCliError::Cancelled);
+        }
+
+        tokio::select! {
+            response = self.execute(request) => response,
+            _ = self.cancel.cancelled() => Err(VmCliError::Cancelled),
+        }
+    }
+
+    async fn send_write(&self, request: RequestBuilder) -> Result<Response> {
+        if !self.dry_run {
+            let retries = if self.http_config.retry_writes {
+                self.http_config.retries.unwrap_or(DEFAULT_RETRIES)
+            } else {
+                0
+            };
+            return self.send_with_retry(request, retries).await;
+        }
+
+        let request = request.build()?;
+        let body_size = request.body().and_then(|body| body.as_bytes()).map_or(0, <[u8]>::len);
+        eprintln!(
+            "[dry-run] {} {} (тело: {} байт) — запрос не отправлен",
+            request.method(),
+            request.url(),
+            body_size
+        );
+        Ok(Response::from(http::Response::new(Bytes::new())))
+    }
+
+    fn authorize(&self, request: RequestBuilder) -> RequestBuilder {
+        match &self.auth {
+            Some(AuthConfig { token: Some(token), .. }) => request.bearer_auth(token),
+            Some(AuthConfig { username: Some(username), password, .. }) => request.basic_auth(username, password.as_ref()),
+            _ => request,
+        }
+    }
+
+    async fn execute(&self, request: RequestBuilder) -> Result<Response> {
+        let request = self.authorize(request).build()?;
+        let Some(trace_file) = self.http_config.trace_file.as_deref() else {
+            return Ok(self.client.execute(request).await?);
+        };
+
+        let request_entry = trace::format_request(&request);
+        let response = match self.client.execute(request).await {
+            Ok(response) => response,
+            Err(e) => {
+                trace::append(trace_file, &format!("{}< ошибка: {}\n", request_entry, e))?;
+                return Err(e.into());
+            }
+        };
+
+        let status = response.status();
+        let version = response.version();
+        let headers = response.headers().clone();
+
+        let entry = format!("{}{}", request_entry, trace::format_response(status, &headers));
+        let body = trace::traced_body(trace_file, entry, response);
+
+        let mut rebuilt = http::Response::new(body);
+        *rebuilt.status_mut() = status;
+        *rebuilt.version_mut() = version;
+        *rebuilt.headers_mut() = headers;
+        Ok(Response::from(rebuilt))
+    }
+
+    async fn send_select<F>(&self, endpoint: &str, build: F) -> Result<Response>
+    where
+        F: Fn(&str) -> RequestBuilder,
+    {
+        if self.select_hosts.is_empty() {
+            return self.send(build(&self.url_for(&self.base_url, endpoint))).await;
+        }
+
+        let mut last_error = None;
+        for host in &self.select_hosts {
+            let url = self.url_for(host, endpoint);
+            match self.send(build(&url)).await {
+                Ok(response) => return Ok(response),
+                Err(VmCliError::HttpError(e)) if e.is_connect() || e.is_timeout() => {
+                    warn!("vmselect {} недоступен, переход к следующему: {}", host, e);
+                    last_error = Some(VmCliError::HttpError(e));
+                }
+                Err(e) => return Err(e),
+            }
+        }
+
+        Err(last_error.unwrap_or_else(|| VmCliError::Unknown("Нет доступных vmselect".to_string())))
+    }
+
+    fn query_request(&self, url: &str, params: &[(&str, &str)]) -> RequestBuilder {
+        let round_digits = self.round_digits.map(|digits| digits.to_string());
+        let mut params: Vec<(&str, &str)> = params.to_vec();
+        if let Some(digits) = &round_digits {
+            params.push(("round_digits", digits));
+        }
+        params.extend(self.enforcement_params());
+
+        let encoded_len = reqwest::Url::parse_with_params(url, &params)
+            .ok()
+            .and_then(|u| u.query().map(str::len))
+            .unwrap_or(0);
+
+        if self.http_config.post_queries || encoded_len > POST_QUERY_THRESHOLD {
+            debug!("Sending query via POST ({} encoded chars)", encoded_len);
+            self.client.post(url).form(&params)
+        } else {
+            self.client.get(url).query(&params)
+        }
+    }
+
     fn get_endpoint(&self, default_endpoint: &str) -> String {
         if let Some(cluster) = &self.cluster_config {
             if cluster.use_select_endpoint {
@@ -108,6 +566,7 @@ impl VmClient {
         }
     }
 
+    #[allow(dead_code)]
     pub fn create_insert_client(&self, timeout: u64) -> Result<VmInsertClient> {
         let insert_host = if let Some(cluster) = &self.cluster_config {
             cluster.vminsert_host.as_ref().unwrap_or(&self.base_url)
@@ -115,10 +574,7 @@ impl VmClient {
             &self.base_url
         };
 
-        let client = Client::builder()
-            .timeout(Duration::from_secs(timeout))
-            .build()
-            .map_err(|e| VmCliError::HttpError(reqwest::Error::from(e)))?;
+        let client = build_http_client(timeout, &self.http_config, &self.tls_config)?;
 
         Ok(VmInsertClient {
             client,
@@ -126,6 +582,7 @@ impl VmClient {
         })
     }
 
+    #[allow(dead_code)]
     pub fn create_storage_client(&self, timeout: u64) -> Result<VmStorageClient> {
         let storage_host = if let Some(cluster) = &self.cluster_config {
             cluster.vmstorage_host.as_ref().unwrap_or(&self.base_url)
@@ -133,10 +590,7 @@ impl VmClient {
             &self.base_url
         };
 
-        let client = Client::builder()
-            .timeout(Duration::from_secs(timeout))
-            .build()
-            .map_err(|e| VmCliError::HttpError(reqwest::Error::from(e)))?;
+        let client = build_http_client(timeout, &self.http_config, &self.tls_config)?;
 
         Ok(VmStorageClient {
             client,
@@ -146,18 +600,19 @@ impl VmClient {
 
     pub async fn query(&self, query: &str, time: Option<&str>) -> Result<QueryResponse> {
         let endpoint = self.get_endpoint("/api/v1/query");
-        let url = format!("{}{}", self.base_url, endpoint);
         let mut params = vec![("query", query)];
         
         if let Some(t) = time {
             params.push(("time", t));
         }
 
+        let cache_key = query_cache_key(&endpoint, &params, self.round_digits);
+        if let Some(cached) = self.cached_query(&cache_key) {
+            return Ok(cached);
+        }
+
         let response = self
-            .client
-            .get(&url)
-            .query(&params)
-            .send()
+            .send_select(&endpoint, |url| self.query_request(url, &params))
             .await?;
 
         debug!("Query response status: {}", response.status());
@@ -171,7 +626,9 @@ impl VmClient {
             });
         }
 
-        let query_response: QueryResponse = response.json().await?;
+        let query_response: QueryResponse =
+            read_json_limited(response, self.http_config.max_response_bytes).await?;
+        self.cache_query(cache_key, &query_response);
         Ok(query_response)
     }
 
@@ -182,8 +639,9 @@ impl VmClient {
         end: &str,
         step: &str,
     ) -> Result<QueryResponse> {
+        crate::utils::validate_range_params(start, end, step).map_err(VmCliError::InvalidQuery)?;
+
         let endpoint = self.get_endpoint("/api/v1/query_range");
-        let url = format!("{}{}", self.base_url, endpoint);
         let params = vec![
             ("query", query),
             ("start", start),
@@ -191,11 +649,13 @@ impl VmClient {
             ("step", step),
         ];
 
+        let cache_key = query_cache_key(&endpoint, &params, self.round_digits);
+        if let Some(cached) = self.cached_query(&cache_key) {
+            return Ok(cached);
+        }
+
         let response = self
-            .client
-            .get(&url)
-            .query(&params)
-            .send()
+            .send_select(&endpoint, |url| self.query_request(url, &params))
             .await?;
 
         debug!("Query range response status: {}", response.status());
@@ -209,15 +669,64 @@ impl VmClient {
             });
         }
 
-        let query_response: QueryResponse = response.json().await?;
+        let query_response: QueryResponse =
+            read_json_limited(response, self.http_config.max_response_bytes).await?;
+        self.cache_query(cache_key, &query_response);
         Ok(query_response)
     }
 
+    pub async fn stream_query<W>(&self, query: &str, time: Option<&str>, out: W) -> Result<u64>
+    where
+        W: Write + Send + 'static,
+    {
+        let endpoint = self.get_endpoint("/api/v1/query");
+        let mut params = vec![("query", query)];
+        if let Some(t) = time {
+            params.push(("time", t));
+        }
+
+        self.stream_results(&endpoint, &params, out).await
+    }
+
+    pub async fn stream_query_range<W>(&self, query: &str, start: &str, end: &str, step: &str, out: W) -> Result<u64>
+    where
+        W: Write + Send + 'static,
+    {
+        crate::utils::validate_range_params(start, end, step).map_err(VmCliError::InvalidQuery)?;
+
+        let endpoint = self.get_endpoint("/api/v1/query_range");
+        let params = vec![("query", query), ("start", start), ("end", end), ("step", step)];
+
+        self.stream_results(&endpoint, &params, out).await
+    }
+
+    async fn stream_results<W>(&self, endpoint: &str, params: &[(&str, &str)], out: W) -> Result<u64>
+    where
+        W: Write + Send + 'static,
+    {
+        let response = self
+            .send_select(endpoint, |url| self.query_request(url, params))
+            .await?;
+
+        debug!("Streaming query response status: {}", response.status());
+
+        if !response.status().is_success() {
+            let status = response.status().as_u16();
+            let error_text = response.text().await.unwrap_or_default();
+            return Err(VmCliError::ApiError {
+                message: error_text,
+                status: Some(status),
+            });
+        }
+
+        write_results_ndjson(response, out).await
+    }
+
     pub async fn health(&self) -> Result<HealthResponse> {
         let endpoint = self.get_endpoint("/health");
-        let url = format!("{}{}", self.base_url, endpoint);
+        let url = self.url_for(&self.base_url, &endpoint);
         
-        let response = self.client.get(&url).send().await?;
+        let response = self.send(self.client.get(&url)).await?;
         
         debug!("Health response status: {}", response.status());
 
@@ -239,13 +748,12 @@ impl VmClient {
         Ok(health_response)
     }
 
-    pub async fn metrics(&self) -> Result<MetricsList> {
-        let endpoint = self.get_endpoint("/api/v1/label/__name__/values");
-        let url = format!("{}{}", self.base_url, endpoint);
-        
-        let response = self.client.get(&url).send().await?;
-        
-        debug!("Metrics response status: {}", response.status());
+    pub async fn probe_health(&self, host: &str) -> Result<String> {
+        let url = self.url_for(host, "/health");
+
+        let response = self.send(self.client.get(&url)).await?;
+
+        debug!("Probe {} response status: {}", url, response.status());
 
         if !response.status().is_success() {
             let status = response.status().as_u16();
@@ -256,29 +764,13 @@ impl VmClient {
             });
         }
 
-        let metrics_list: MetricsList = response.json().await?;
-        Ok(metrics_list)
+        Ok(response.text().await?.trim().to_string())
     }
 
-    pub async fn delete_series(&self, match_: &str, start: Option<&str>, end: Option<&str>) -> Result<()> {
-        let url = format!("{}/api/v1/admin/tsdb/delete_series", self.base_url);
-        let mut params = vec![("match[]", match_)];
-        
-        if let Some(s) = start {
-            params.push(("start", s));
-        }
-        if let Some(e) = end {
-            params.push(("end", e));
-        }
-
-        let response = self
-            .client
-            .post(&url)
-            .query(&params)
-            .send()
-            .await?;
+    pub async fn app_version(&self) -> Result<Option<String>> {
+        let url = self.url_for(&self.base_url, "/metrics");
 
-        debug!("Delete series response status: {}", response.status());
+        let response = self.send(self.client.get(&url)).await?;
 
         if !response.status().is_success() {
             let status = response.status().as_u16();
@@ -289,28 +781,46 @@ impl VmClient {
             });
         }
 
-        Ok(())
+        let text = response.text().await?;
+        let version = text
+            .lines()
+            .find(|line| line.starts_with("vm_app_version{"))
+            .and_then(|line| line.split_once("short_version=\""))
+            .and_then(|(_, rest)| rest.split_once('"'))
+            .map(|(version, _)| version.to_string());
+        Ok(version)
     }
 
-    pub async fn export(&self, match_: &str, start: Option<&str>, end: Option<&str>) -> Result<String> {
-        let url = format!("{}/api/v1/export", self.base_url);
-        let mut params = vec![("match[]", match_)];
-        
+    pub async fn metrics(&self) -> Result<MetricsList> {
+        self.label_values("__name__", &[], None, None, None).await
+    }
+
+    pub async fn label_names(
+        &self,
+        match_: Option<&str>,
+        start: Option<&str>,
+        end: Option<&str>,
+        limit: Option<usize>,
+    ) -> Result<MetricsList> {
+        let endpoint = self.get_endpoint("/api/v1/labels");
+        let mut params: Vec<(&str, String)> = match_.map(|m| ("match[]", m.to_string())).into_iter().collect();
+
         if let Some(s) = start {
-            params.push(("start", s));
+            params.push(("start", s.to_string()));
         }
         if let Some(e) = end {
-            params.push(("end", e));
+            params.push(("end", e.to_string()));
         }
+        if let Some(l) = limit {
+            params.push(("limit", l.to_string()));
+        }
+        params.extend(self.enforcement_params().map(|(name, value)| (name, value.to_string())));
 
         let response = self
-            .client
-            .get(&url)
-            .query(&params)
-            .send()
+            .send_select(&endpoint, |url| self.client.get(url).query(&params))
             .await?;
 
-        debug!("Export response status: {}", response.status());
+        debug!("Label names response status: {}", response.status());
 
         if !response.status().is_success() {
             let status = response.status().as_u16();
@@ -321,21 +831,37 @@ impl VmClient {
             });
         }
 
-        let export_data = response.text().await?;
-        Ok(export_data)
+        let metrics_list: MetricsList = response.json().await?;
+        Ok(metrics_list)
     }
 
-    pub async fn import(&self, data: &str) -> Result<()> {
-        let url = format!("{}/api/v1/import", self.base_url);
-        
+    pub async fn label_values(
+        &self,
+        label: &str,
+        matches: &[&str],
+        start: Option<&str>,
+        end: Option<&str>,
+        limit: Option<usize>,
+    ) -> Result<MetricsList> {
+        let endpoint = self.get_endpoint(&format!("/api/v1/label/{}/values", encode_path_segment(label)));
+        let mut params: Vec<(&str, String)> = matches.iter().map(|m| ("match[]", m.to_string())).collect();
+
+        if let Some(s) = start {
+            params.push(("start", s.to_string()));
+        }
+        if let Some(e) = end {
+            params.push(("end", e.to_string()));
+        }
+        if let Some(l) = limit {
+            params.push(("limit", l.to_string()));
+        }
+        params.extend(self.enforcement_params().map(|(name, value)| (name, value.to_string())));
+
         let response = self
-            .client
-            .post(&url)
-            .body(data.to_string())
-            .send()
+            .send_select(&endpoint, |url| self.client.get(url).query(&params))
             .await?;
 
-        debug!("Import response status: {}", response.status());
+        debug!("Label values response status: {}", response.status());
 
         if !response.status().is_success() {
             let status = response.status().as_u16();
@@ -346,31 +872,36 @@ impl VmClient {
             });
         }
 
-        Ok(())
+        let metrics_list: MetricsList = response.json().await?;
+        Ok(metrics_list)
     }
 
-    pub async fn create_snapshot(&self, name: &str) -> Result<String> {
-        let url = if let Some(cluster_config) = &self.cluster_config {
-            if let Some(vmstorage_host) = &cluster_config.vmstorage_host {
-                format!("{}/snapshot/create", vmstorage_host)
-            } else {
-                return Err(VmCliError::ApiError {
-                    message: "vmstorage_host не настроен в конфигурации кластера".to_string(),
-                    status: None,
-                });
-            }
-        } else {
-            format!("{}/snapshot/create", self.base_url)
-        };
-        
+    pub async fn series(
+        &self,
+        matches: &[&str],
+        start: Option<&str>,
+        end: Option<&str>,
+        limit: Option<usize>,
+    ) -> Result<SeriesResponse> {
+        let endpoint = self.get_endpoint("/api/v1/series");
+        let mut params: Vec<(&str, String)> = matches.iter().map(|m| ("match[]", m.to_string())).collect();
+
+        if let Some(s) = start {
+            params.push(("start", s.to_string()));
+        }
+        if let Some(e) = end {
+            params.push(("end", e.to_string()));
+        }
+        if let Some(l) = limit {
+            params.push(("limit", l.to_string()));
+        }
+        params.extend(self.enforcement_params().map(|(name, value)| (name, value.to_string())));
+
         let response = self
-            .client
-            .post(&url)
-            .query(&[("snapshot", name)])
-            .send()
+            .send_select(&endpoint, |url| self.client.post(url).form(&params))
             .await?;
 
-        debug!("Create snapshot response status: {}", response.status());
+        debug!("Series response status: {}", response.status());
 
         if !response.status().is_success() {
             let status = response.status().as_u16();
@@ -381,35 +912,310 @@ impl VmClient {
             });
         }
 
-        let snapshot_id = response.text().await?;
-        Ok(snapshot_id)
+        let series: SeriesResponse = response.json().await?;
+        Ok(series)
     }
 
-    pub async fn list_snapshots(&self) -> Result<Vec<SnapshotInfo>> {
-        let url = if let Some(cluster_config) = &self.cluster_config {
-            if let Some(vmstorage_host) = &cluster_config.vmstorage_host {
-                format!("{}/snapshot/list", vmstorage_host)
-            } else {
-                return Err(VmCliError::ApiError {
-                    message: "vmstorage_host не настроен в конфигурации кластера".to_string(),
-                    status: None,
-                });
-            }
-        } else {
-            format!("{}/snapshot/list", self.base_url)
+    pub async fn series_paged(
+        &self,
+        matches: &[&str],
+        start: i64,
+        end: i64,
+        window: Duration,
+        limit: Option<usize>,
+        mut on_page: impl FnMut(usize, usize),
+    ) -> Result<SeriesResponse> {
+        let windows = time_windows(start, end, window);
+        let pages = windows.len();
+
+        let mut seen: std::collections::HashSet<BTreeMap<String, String>> = std::collections::HashSet::new();
+        let mut merged = SeriesResponse {
+            status: "success".to_string(),
+            data: Vec::new(),
         };
-        
-        let response = self.client.get(&url).send().await?;
 
-        debug!("List snapshots response status: {}", response.status());
+        for (page, (page_start, page_end)) in windows.into_iter().enumerate() {
+            let response = self
+                .series(matches, Some(&page_start.to_string()), Some(&page_end.to_string()), limit)
+                .await?;
 
-        if !response.status().is_success() {
-            let status = response.status().as_u16();
-            let error_text = response.text().await.unwrap_or_default();
-            return Err(VmCliError::ApiError {
-                message: error_text,
-                status: Some(status),
-            });
+            for labels in response.data {
+                if seen.insert(labels.iter().map(|(k, v)| (k.clone(), v.clone())).collect()) {
+                    merged.data.push(labels);
+                }
+            }
+            on_page(page + 1, pages);
+
+            if limit.is_some_and(|limit| merged.data.len() >= limit) {
+                break;
+            }
+        }
+
+        if let Some(limit) = limit {
+            merged.data.truncate(limit);
+        }
+        Ok(merged)
+    }
+
+    /// Значения метки за диапазон `start..end`, запрашиваемые окнами по `window`;
+    /// повторы из разных окон отбрасываются с сохранением порядка.
+    #[allow(clippy::too_many_arguments)]
+    pub async fn label_values_paged(
+        &self,
+        label: &str,
+        matches: &[&str],
+        start: i64,
+        end: i64,
+        window: Duration,
+        limit: Option<usize>,
+        mut on_page: impl FnMut(usize, usize),
+    ) -> Result<MetricsList> {
+        let windows = time_windows(start, end, window);
+        let pages = windows.len();
+
+        let mut seen = std::collections::HashSet::new();
+        let mut merged = MetricsList {
+            status: "success".to_string(),
+            data: Vec::new(),
+        };
+
+        for (page, (page_start, page_end)) in windows.into_iter().enumerate() {
+            let response = self
+                .label_values(label, matches, Some(&page_start.to_string()), Some(&page_end.to_string()), limit)
+                .await?;
+
+            for value in response.data {
+                if seen.insert(value.clone()) {
+                    merged.data.push(value);
+                }
+            }
+            on_page(page + 1, pages);
+
+            if limit.is_some_and(|limit| merged.data.len() >= limit) {
+                break;
+            }
+        }
+
+        if let Some(limit) = limit {
+            merged.data.truncate(limit);
+        }
+        Ok(merged)
+    }
+
+    pub async fn metadata(&self, metric: Option<&str>, limit: Option<usize>) -> Result<MetadataResponse> {
+        let endpoint = self.get_endpoint("/api/v1/metadata");
+        let mut params = Vec::new();
+
+        if let Some(m) = metric {
+            params.push(("metric", m.to_string()));
+        }
+        if let Some(l) = limit {
+            params.push(("limit", l.to_string()));
+        }
+
+        let response = self
+            .send_select(&endpoint, |url| self.client.get(url).query(&params))
+            .await?;
+
+        debug!("Metadata response status: {}", response.status());
+
+        if !response.status().is_success() {
+            let status = response.status().as_u16();
+            let error_text = response.text().await.unwrap_or_default();
+            return Err(VmCliError::ApiError {
+                message: error_text,
+                status: Some(status),
+            });
+        }
+
+        let metadata: MetadataResponse = response.json().await?;
+        Ok(metadata)
+    }
+
+    pub async fn delete_series(&self, matches: &[&str], start: Option<&str>, end: Option<&str>) -> Result<()> {
+        let url = self.url_for(&self.base_url, "/api/v1/admin/tsdb/delete_series");
+        let mut params: Vec<(&str, &str)> = matches.iter().map(|m| ("match[]", *m)).collect();
+        
+        if let Some(s) = start {
+            params.push(("start", s));
+        }
+        if let Some(e) = end {
+            params.push(("end", e));
+        }
+
+        let response = self.send_write(self.client.post(&url).query(&params)).await?;
+
+        debug!("Delete series response status: {}", response.status());
+
+        if !response.status().is_success() {
+            let status = response.status().as_u16();
+            let error_text = response.text().await.unwrap_or_default();
+            return Err(VmCliError::ApiError {
+                message: error_text,
+                status: Some(status),
+            });
+        }
+
+        Ok(())
+    }
+
+    pub async fn export_stream(
+        &self,
+        matches: &[&str],
+        start: Option<&str>,
+        end: Option<&str>,
+    ) -> Result<Response> {
+        let url = self.url_for(&self.base_url, "/api/v1/export/prometheus");
+        let mut params: Vec<(&str, &str)> = matches.iter().map(|m| ("match[]", *m)).collect();
+        
+        if let Some(s) = start {
+            params.push(("start", s));
+        }
+        if let Some(e) = end {
+            params.push(("end", e));
+        }
+        params.extend(self.enforcement_params());
+
+        let response = self.send(self.client.get(&url).query(&params)).await?;
+
+        debug!("Export response status: {}", response.status());
+
+        if !response.status().is_success() {
+            let status = response.status().as_u16();
+            let error_text = response.text().await.unwrap_or_default();
+            return Err(VmCliError::ApiError {
+                message: error_text,
+                status: Some(status),
+            });
+        }
+
+        Ok(response)
+    }
+
+    /// Адрес, принимающий запись: vminsert_host в кластере, иначе base_url.
+    pub fn insert_host(&self) -> &str {
+        self.cluster_config
+            .as_ref()
+            .and_then(|cluster| cluster.vminsert_host.as_deref())
+            .unwrap_or(&self.base_url)
+    }
+
+    fn insert_url(&self, tenant: Option<&str>, endpoint: &str) -> Result<String> {
+        let host = self.insert_host();
+        let Some(cluster) = &self.cluster_config else {
+            return match tenant {
+                Some(tenant) => Err(VmCliError::InvalidQuery(format!(
+                    "Тенант {} можно указать только для кластера: добавьте секцию [cluster] в конфигурацию",
+                    tenant
+                ))),
+                None => Ok(self.url_for(host, endpoint)),
+            };
+        };
+
+        let tenant = tenant
+            .map(str::to_string)
+            .unwrap_or_else(|| format!("{}:{}", cluster.select_account_id, cluster.select_project_id));
+        Ok(self.url_for(host, &format!("/insert/{}/prometheus{}", tenant, endpoint)))
+    }
+
+    pub fn import_url(&self, tenant: Option<&str>) -> Result<String> {
+        self.insert_url(tenant, "/api/v1/import/prometheus")
+    }
+
+    /// Текст в формате Prometheus; JSON и CSV приводятся к нему до отправки.
+    pub async fn import(&self, data: &str, tenant: Option<&str>, idempotency_key: Option<&str>) -> Result<()> {
+        self.import_prometheus_exposition(data, &[], tenant, idempotency_key).await
+    }
+
+    pub async fn import_prometheus_exposition(
+        &self,
+        data: &str,
+        extra_labels: &[(String, String)],
+        tenant: Option<&str>,
+        idempotency_key: Option<&str>,
+    ) -> Result<()> {
+        let url = self.import_url(tenant)?;
+        let params: Vec<(&str, String)> = extra_labels
+            .iter()
+            .map(|(name, value)| ("extra_label", format!("{}={}", name, value)))
+            .collect();
+
+        let mut request = self.client.post(&url).query(&params).body(data.to_string());
+        if let Some(key) = idempotency_key {
+            request = request.header(IDEMPOTENCY_KEY_HEADER, key);
+        }
+        let response = self.send_write(request).await?;
+
+        debug!("Import response status: {}", response.status());
+
+        if !response.status().is_success() {
+            let status = response.status().as_u16();
+            let error_text = response.text().await.unwrap_or_default();
+            return Err(VmCliError::ApiError {
+                message: error_text,
+                status: Some(status),
+            });
+        }
+
+        Ok(())
+    }
+
+    pub async fn create_snapshot(&self, name: &str) -> Result<String> {
+        let url = if let Some(cluster_config) = &self.cluster_config {
+            if let Some(vmstorage_host) = &cluster_config.vmstorage_host {
+                self.url_for(vmstorage_host, "/snapshot/create")
+            } else {
+                return Err(VmCliError::ApiError {
+                    message: "vmstorage_host не настроен в конфигурации кластера".to_string(),
+                    status: None,
+                });
+            }
+        } else {
+            self.url_for(&self.base_url, "/snapshot/create")
+        };
+        
+        let response = self.send_write(self.client.post(&url).query(&[("snapshot", name)])).await?;
+
+        debug!("Create snapshot response status: {}", response.status());
+
+        if !response.status().is_success() {
+            let status = response.status().as_u16();
+            let error_text = response.text().await.unwrap_or_default();
+            return Err(VmCliError::ApiError {
+                message: error_text,
+                status: Some(status),
+            });
+        }
+
+        let snapshot_id = response.text().await?;
+        Ok(snapshot_id)
+    }
+
+    pub async fn list_snapshots(&self) -> Result<Vec<SnapshotInfo>> {
+        let url = if let Some(cluster_config) = &self.cluster_config {
+            if let Some(vmstorage_host) = &cluster_config.vmstorage_host {
+                self.url_for(vmstorage_host, "/snapshot/list")
+            } else {
+                return Err(VmCliError::ApiError {
+                    message: "vmstorage_host не настроен в конфигурации кластера".to_string(),
+                    status: None,
+                });
+            }
+        } else {
+            self.url_for(&self.base_url, "/snapshot/list")
+        };
+        
+        let response = self.send(self.client.get(&url)).await?;
+
+        debug!("List snapshots response status: {}", response.status());
+
+        if !response.status().is_success() {
+            let status = response.status().as_u16();
+            let error_text = response.text().await.unwrap_or_default();
+            return Err(VmCliError::ApiError {
+                message: error_text,
+                status: Some(status),
+            });
         }
 
         let response_text = response.text().await?;
@@ -437,7 +1243,7 @@ impl VmClient {
     pub async fn delete_snapshot(&self, name: &str) -> Result<()> {
         let url = if let Some(cluster_config) = &self.cluster_config {
             if let Some(vmstorage_host) = &cluster_config.vmstorage_host {
-                format!("{}/snapshot/delete", vmstorage_host)
+                self.url_for(vmstorage_host, "/snapshot/delete")
             } else {
                 return Err(VmCliError::ApiError {
                     message: "vmstorage_host не настроен в конфигурации кластера".to_string(),
@@ -445,15 +1251,10 @@ impl VmClient {
                 });
             }
         } else {
-            format!("{}/snapshot/delete", self.base_url)
+            self.url_for(&self.base_url, "/snapshot/delete")
         };
         
-        let response = self
-            .client
-            .post(&url)
-            .query(&[("snapshot", name)])
-            .send()
-            .await?;
+        let response = self.send_write(self.client.post(&url).query(&[("snapshot", name)])).await?;
 
         debug!("Delete snapshot response status: {}", response.status());
 
@@ -472,7 +1273,7 @@ impl VmClient {
     pub async fn restore_snapshot(&self, name: &str) -> Result<()> {
         let url = if let Some(cluster_config) = &self.cluster_config {
             if let Some(vmstorage_host) = &cluster_config.vmstorage_host {
-                format!("{}/snapshot/restore", vmstorage_host)
+                self.url_for(vmstorage_host, "/snapshot/restore")
             } else {
                 return Err(VmCliError::ApiError {
                     message: "vmstorage_host не настроен в конфигурации кластера".to_string(),
@@ -480,15 +1281,10 @@ impl VmClient {
                 });
             }
         } else {
-            format!("{}/snapshot/restore", self.base_url)
+            self.url_for(&self.base_url, "/snapshot/restore")
         };
         
-        let response = self
-            .client
-            .post(&url)
-            .query(&[("snapshot", name)])
-            .send()
-            .await?;
+        let response = self.send_write(self.client.post(&url).query(&[("snapshot", name)])).await?;
 
         debug!("Restore snapshot response status: {}", response.status());
 
@@ -505,9 +1301,9 @@ impl VmClient {
     }
 
     pub async fn get_retention_info(&self) -> Result<RetentionInfo> {
-        let url = format!("{}/admin/tsdb/retention", self.base_url);
+        let url = self.url_for(&self.base_url, "/admin/tsdb/retention");
         
-        let response = self.client.get(&url).send().await?;
+        let response = self.send(self.client.get(&url)).await?;
 
         debug!("Retention info response status: {}", response.status());
 
@@ -525,14 +1321,9 @@ impl VmClient {
     }
 
     pub async fn set_retention(&self, retention: &str) -> Result<()> {
-        let url = format!("{}/admin/tsdb/retention", self.base_url);
+        let url = self.url_for(&self.base_url, "/admin/tsdb/retention");
         
-        let response = self
-            .client
-            .post(&url)
-            .query(&[("retention", retention)])
-            .send()
-            .await?;
+        let response = self.send_write(self.client.post(&url).query(&[("retention", retention)])).await?;
 
         debug!("Set retention response status: {}", response.status());
 
@@ -550,10 +1341,11 @@ impl VmClient {
 
 
 
-    pub async fn get_flags(&self) -> Result<serde_json::Value> {
-        let url = format!("{}/flags", self.base_url);
-        
-        let response = self.client.get(&url).send().await?;
+    /// Флаги запуска компонента по адресу host: /flags отдает текст вида `-name="value"`.
+    pub async fn get_flags(&self, host: &str) -> Result<BTreeMap<String, String>> {
+        let url = self.url_for(host, "/flags");
+
+        let response = self.send(self.client.get(&url)).await?;
 
         debug!("Get flags response status: {}", response.status());
 
@@ -566,14 +1358,13 @@ impl VmClient {
             });
         }
 
-        let flags: serde_json::Value = response.json().await?;
-        Ok(flags)
+        Ok(parse_flags(&response.text().await?))
     }
 
     pub async fn get_build_info(&self) -> Result<serde_json::Value> {
-        let url = format!("{}/api/v1/status/buildinfo", self.base_url);
+        let url = self.url_for(&self.base_url, "/api/v1/status/buildinfo");
         
-        let response = self.client.get(&url).send().await?;
+        let response = self.send(self.client.get(&url)).await?;
 
         debug!("Get build info response status: {}", response.status());
 
@@ -590,10 +1381,45 @@ impl VmClient {
         Ok(build_info)
     }
 
+    pub async fn tsdb_status(
+        &self,
+        top_n: Option<usize>,
+        focus_label: Option<&str>,
+        date: Option<&str>,
+    ) -> Result<TsdbStatusResponse> {
+        let endpoint = self.get_endpoint("/api/v1/status/tsdb");
+        let mut params: Vec<(&str, String)> = top_n.map(|n| ("topN", n.to_string())).into_iter().collect();
+
+        if let Some(label) = focus_label {
+            params.push(("focusLabel", label.to_string()));
+        }
+        if let Some(d) = date {
+            params.push(("date", d.to_string()));
+        }
+
+        let response = self
+            .send_select(&endpoint, |url| self.client.get(url).query(&params))
+            .await?;
+
+        debug!("TSDB status response status: {}", response.status());
+
+        if !response.status().is_success() {
+            let status = response.status().as_u16();
+            let error_text = response.text().await.unwrap_or_default();
+            return Err(VmCliError::ApiError {
+                message: error_text,
+                status: Some(status),
+            });
+        }
+
+        let tsdb_status: TsdbStatusResponse = response.json().await?;
+        Ok(tsdb_status)
+    }
+
     pub async fn get_metrics_info(&self) -> Result<serde_json::Value> {
-        let url = format!("{}/metrics", self.base_url);
+        let url = self.url_for(&self.base_url, "/metrics");
         
-        let response = self.client.get(&url).send().await?;
+        let response = self.send(self.client.get(&url)).await?;
 
         debug!("Get metrics info response status: {}", response.status());
 
@@ -616,9 +1442,11 @@ impl VmClient {
             
             if let Some((metric_name, value)) = line.split_once(' ') {
                 if let Ok(num_value) = value.parse::<f64>() {
-                    if let Some(number) = serde_json::Number::from_f64(num_value) {
-                        metrics_data.insert(metric_name.to_string(), serde_json::Value::Number(number));
-                    }
+                    let json_value = match serde_json::Number::from_f64(num_value) {
+                        Some(number) => serde_json::Value::Number(number),
+                        None => serde_json::Value::String(crate::utils::format_float(num_value)),
+                    };
+                    metrics_data.insert(metric_name.to_string(), json_value);
                 }
             }
         }
@@ -626,49 +1454,1330 @@ impl VmClient {
         Ok(serde_json::Value::Object(metrics_data))
     }
 
-    pub async fn get_slow_queries(&self) -> Result<Vec<SlowQueryInfo>> {
-        let metrics = self.get_metrics_info().await?;
-        
-        let mut slow_queries = Vec::new();
-        
-        if let Some(metrics_obj) = metrics.as_object() {
-            if let Some(duration_metric) = metrics_obj.get("vm_request_duration_seconds") {
-                if let Some(duration) = duration_metric.as_f64() {
-                    if duration > 1.0 {
-                        slow_queries.push(SlowQueryInfo {
-                            query: "Медленный запрос".to_string(),
-                            duration: duration,
-                            reason: "Время выполнения > 1 секунды".to_string(),
-                        });
-                    }
-                }
-            }
-            
-            if let Some(requests_total) = metrics_obj.get("vm_http_requests_total") {
-                if let Some(total) = requests_total.as_f64() {
-                    if total > 1000.0 {
-                        slow_queries.push(SlowQueryInfo {
-                            query: "Высокая нагрузка".to_string(),
-                            duration: 0.0,
-                            reason: format!("Общее количество запросов: {}", total),
-                        });
-                    }
-                }
+    pub async fn relabel_debug(&self, metric: &str, relabel_configs: &str) -> Result<RelabelDebugResponse> {
+        let url = self.url_for(&self.base_url, "/metric-relabel-debug");
+        let params = [
+            ("metric", metric),
+            ("relabel_configs", relabel_configs),
+            ("format", "json"),
+        ];
+
+        let response = self.send(self.client.post(&url).form(&params)).await?;
+
+        debug!("Relabel debug response status: {}", response.status());
+
+        if !response.status().is_success() {
+            let status = response.status().as_u16();
+            let error_text = response.text().await.unwrap_or_default();
+            return Err(VmCliError::ApiError {
+                message: error_text,
+                status: Some(status),
+            });
+        }
+
+        let relabel_response: RelabelDebugResponse = response.json().await?;
+        Ok(relabel_response)
+    }
+
+    pub async fn get_slow_queries(
+        &self,
+        top: usize,
+        max_lifetime: &str,
+        by: SlowQueryRanking,
+    ) -> Result<Vec<SlowQueryInfo>> {
+        let endpoint = self.get_endpoint("/api/v1/status/top_queries");
+        let params = [("topN", top.to_string()), ("maxLifetime", max_lifetime.to_string())];
+
+        let response = self
+            .send_select(&endpoint, |url| self.client.get(url).query(&params))
+            .await?;
+
+        debug!("Top queries response status: {}", response.status());
+
+        if !response.status().is_success() {
+            let status = response.status().as_u16();
+            let error_text = response.text().await.unwrap_or_default();
+            return Err(VmCliError::ApiError {
+                message: error_text,
+                status: Some(status),
+            });
+        }
+
+        let top_queries: TopQueriesResponse = response.json().await?;
+        let (entries, duration): (_, fn(&TopQueryEntry) -> Option<f64>) = match by {
+            SlowQueryRanking::AvgDuration => (top_queries.top_by_avg_duration, |e| e.avg_duration_seconds),
+            SlowQueryRanking::SumDuration => (top_queries.top_by_sum_duration, |e| e.sum_duration_seconds),
+            SlowQueryRanking::Count => (top_queries.top_by_count, |e| e.avg_duration_seconds),
+        };
+
+        Ok(entries
+            .iter()
+            .map(|entry| SlowQueryInfo {
+                query: entry.query.clone(),
+                duration: duration(entry),
+                count: entry.count,
+            })
+            .collect())
+    }
+}
+
+struct ChunkReader {
+    receiver: tokio::sync::mpsc::Receiver<Bytes>,
+    current: Bytes,
+}
+
+impl Read for ChunkReader {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        while self.current.is_empty() {
+            match self.receiver.blocking_recv() {
+                Some(chunk) => self.current = chunk,
+                None => return Ok(0),
             }
-            
-            if let Some(cache_size) = metrics_obj.get("vm_cache_size_bytes") {
-                if let Some(size) = cache_size.as_f64() {
-                    if size > 1_000_000_000.0 {
-                        slow_queries.push(SlowQueryInfo {
-                            query: "Большой кэш".to_string(),
-                            duration: 0.0,
-                            reason: format!("Размер кэша: {:.2} GB", size / 1_000_000_000.0),
-                        });
-                    }
-                }
+        }
+
+        let len = buf.len().min(self.current.len());
+        buf[..len].copy_from_slice(&self.current.split_to(len));
+        Ok(len)
+    }
+}
+
+async fn read_json_limited<T>(mut response: Response, limit: Option<u64>) -> Result<T>
+where
+    T: DeserializeOwned + Send + 'static,
+{
+    if let (Some(limit), Some(length)) = (limit, response.content_length()) {
+        if length > limit {
+            return Err(VmCliError::ResultTooLarge { limit });
+        }
+    }
+
+    let (sender, receiver) = tokio::sync::mpsc::channel::<Bytes>(16);
+    let parser = tokio::task::spawn_blocking(move || {
+        serde_json::from_reader::<_, T>(ChunkReader { receiver, current: Bytes::new() })
+    });
+
+    let mut received = 0u64;
+    while let Some(chunk) = response.chunk().await? {
+        received += chunk.len() as u64;
+        if let Some(limit) = limit {
+            if received > limit {
+                drop(sender);
+                let _ = parser.await;
+                return Err(VmCliError::ResultTooLarge { limit });
             }
         }
-        
-        Ok(slow_queries)
+
+        if sender.send(chunk).await.is_err() {
+            break;
+        }
+    }
+    drop(sender);
+
+    parser
+        .await
+        .map_err(|e| VmCliError::Unknown(e.to_string()))?
+        .map_err(VmCliError::JsonError)
+}
+
+async fn write_results_ndjson<W>(mut response: Response, out: W) -> Result<u64>
+where
+    W: Write + Send + 'static,
+{
+    let (sender, receiver) = tokio::sync::mpsc::channel::<Bytes>(16);
+    let writer = tokio::task::spawn_blocking(move || {
+        let mut sink = NdjsonSink { out, written: 0, io_error: None };
+        let mut deserializer =
+            serde_json::Deserializer::from_reader(ChunkReader { receiver, current: Bytes::new() });
+        let parsed = ResponseSeed(&mut sink).deserialize(&mut deserializer);
+        if let Some(e) = sink.io_error.take() {
+            return Err(VmCliError::IoError(e));
+        }
+        parsed.map_err(VmCliError::JsonError)?;
+        sink.out.flush()?;
+        Ok(sink.written)
+    });
+
+    while let Some(chunk) = response.chunk().await? {
+        if sender.send(chunk).await.is_err() {
+            break;
+        }
+    }
+    drop(sender);
+
+    writer.await.map_err(|e| VmCliError::Unknown(e.to_string()))?
+}
+
+struct NdjsonSink<W> {
+    out: W,
+    written: u64,
+    io_error: Option<std::io::Error>,
+}
+
+impl<W: Write> NdjsonSink<W> {
+    fn write(&mut self, result: &QueryResult) -> std::io::Result<()> {
+        serde_json::to_writer(&mut self.out, result)?;
+        self.out.write_all(b"\n")?;
+        self.written += 1;
+        Ok(())
+    }
+}
+
+struct ResponseSeed<'a, W>(&'a mut NdjsonSink<W>);
+
+impl<'de, W: Write> DeserializeSeed<'de> for ResponseSeed<'_, W> {
+    type Value = ();
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> std::result::Result<(), D::Error> {
+        deserializer.deserialize_map(self)
+    }
+}
+
+impl<'de, W: Write> Visitor<'de> for ResponseSeed<'_, W> {
+    type Value = ();
+
+    fn expecting(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.write_str("query response object")
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> std::result::Result<(), A::Error> {
+        while let Some(key) = map.next_key::<String>()? {
+            if key == "data" {
+                map.next_value_seed(DataSeed(&mut *self.0))?;
+            } else {
+                map.next_value::<IgnoredAny>()?;
+            }
+        }
+        Ok(())
+    }
+}
+
+struct DataSeed<'a, W>(&'a mut NdjsonSink<W>);
+
+impl<'de, W: Write> DeserializeSeed<'de> for DataSeed<'_, W> {
+    type Value = ();
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> std::result::Result<(), D::Error> {
+        deserializer.deserialize_map(self)
+    }
+}
+
+impl<'de, W: Write> Visitor<'de> for DataSeed<'_, W> {
+    type Value = ();
+
+    fn expecting(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.write_str("query data object")
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> std::result::Result<(), A::Error> {
+        while let Some(key) = map.next_key::<String>()? {
+            if key == "result" {
+                map.next_value_seed(ResultSeed(&mut *self.0))?;
+            } else {
+                map.next_value::<IgnoredAny>()?;
+            }
+        }
+        Ok(())
+    }
+}
+
+struct ResultSeed<'a, W>(&'a mut NdjsonSink<W>);
+
+impl<'de, W: Write> DeserializeSeed<'de> for ResultSeed<'_, W> {
+    type Value = ();
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> std::result::Result<(), D::Error> {
+        deserializer.deserialize_seq(self)
+    }
+}
+
+impl<'de, W: Write> Visitor<'de> for ResultSeed<'_, W> {
+    type Value = ();
+
+    fn expecting(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.write_str("array of series")
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> std::result::Result<(), A::Error> {
+        while let Some(result) = seq.next_element::<QueryResult>()? {
+            if let Err(e) = self.0.write(&result) {
+                self.0.io_error = Some(e);
+                return Err(serde::de::Error::custom("запись результата прервана"));
+            }
+        }
+        Ok(())
+    }
+}
+
+fn query_cache_key(endpoint: &str, params: &[(&str, &str)], round_digits: Option<u32>) -> String {
+    let mut params: Vec<String> = params.iter().map(|(name, value)| format!("{}={}", name, value)).collect();
+    if let Some(digits) = round_digits {
+        params.push(format!("round_digits={}", digits));
+    }
+    format!("{}?{}", endpoint, params.join("&"))
+}
+
+fn join_url(host: &str, base_path: &str, endpoint: &str) -> String {
+    let mut url = host.trim_end_matches('/').to_string();
+
+    for part in [base_path, endpoint] {
+        let part = part.trim_matches('/');
+        if !part.is_empty() {
+            url.push('/');
+            url.push_str(part);
+        }
+    }
+
+    url
+}
+
+const PATH_SEGMENT: &AsciiSet = &NON_ALPHANUMERIC.remove(b'-').remove(b'.').remove(b'_').remove(b'~');
+
+fn encode_path_segment(segment: &str) -> String {
+    utf8_percent_encode(segment, PATH_SEGMENT).to_string()
+}
+
+/// Разбивает `start..end` на последовательные окна длиной `window` (не меньше секунды).
+fn time_windows(start: i64, end: i64, window: Duration) -> Vec<(i64, i64)> {
+    let step = (window.as_secs() as i64).max(1);
+    let pages = ((end - start).max(0) as u64).div_ceil(step as u64).max(1) as usize;
+    (0..pages)
+        .map(|page| {
+            let page_start = start + page as i64 * step;
+            (page_start, (page_start + step).min(end))
+        })
+        .collect()
+}
+
+fn build_http_client(timeout: u64, http_config: &HttpConfig, tls_config: &TlsConfig) -> Result<Client> {
+    let mut builder = Client::builder().timeout(Duration::from_secs(timeout));
+    if let Some(path) = tls_config.ca_cert.as_deref() {
+        for cert in load_ca_certs(path)? {
+            builder = builder.add_root_certificate(cert);
+        }
+    }
+    if tls_config.insecure {
+        builder = builder.danger_accept_invalid_certs(true);
+    }
+    builder = apply_pool_options(builder, http_config);
+    for proxy in build_proxies(http_config)? {
+        builder = builder.proxy(proxy);
+    }
+    builder.build().map_err(VmCliError::HttpError)
+}
+
+/// Настройки пула соединений из [http]; трейт позволяет проверить в тестах,
+/// какие значения получает построитель клиента.
+trait PoolOptions: Sized {
+    fn pool_max_idle_per_host(self, max_idle: usize) -> Self;
+    fn pool_idle_timeout(self, timeout: Duration) -> Self;
+    fn http2_prior_knowledge(self) -> Self;
+}
+
+impl PoolOptions for reqwest::ClientBuilder {
+    fn pool_max_idle_per_host(self, max_idle: usize) -> Self {
+        reqwest::ClientBuilder::pool_max_idle_per_host(self, max_idle)
+    }
+
+    fn pool_idle_timeout(self, timeout: Duration) -> Self {
+        reqwest::ClientBuilder::pool_idle_timeout(self, timeout)
+    }
+
+    fn http2_prior_knowledge(self) -> Self {
+        reqwest::ClientBuilder::http2_prior_knowledge(self)
+    }
+}
+
+fn apply_pool_options<B: PoolOptions>(mut builder: B, http_config: &HttpConfig) -> B {
+    if let Some(max_idle) = http_config.pool_max_idle_per_host {
+        builder = builder.pool_max_idle_per_host(max_idle);
+    }
+    if let Some(idle_timeout) = http_config.pool_idle_timeout {
+        builder = builder.pool_idle_timeout(Duration::from_secs(idle_timeout));
+    }
+    if http_config.http2_prior_knowledge {
+        builder = builder.http2_prior_knowledge();
+    }
+    builder
+}
+
+fn retry_delay(base: Duration, attempt: u32) -> Duration {
+    let backoff = base.saturating_mul(1 << attempt.saturating_sub(1).min(16)).min(MAX_RETRY_DELAY);
+    let nanos = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map_or(0, |d| d.subsec_nanos());
+    let jitter = backoff.mul_f64(f64::from(nanos % 1000) / 2000.0);
+    backoff + jitter
+}
+
+fn build_proxies(http_config: &HttpConfig) -> Result<Vec<Proxy>> {
+    let no_proxy = if http_config.no_proxy.is_empty() {
+        NoProxy::from_env()
+    } else {
+        NoProxy::from_string(&http_config.no_proxy.join(","))
+    };
+
+    if let Some(url) = &http_config.proxy {
+        return Ok(vec![Proxy::all(url)?.no_proxy(no_proxy)]);
+    }
+
+    let mut proxies = Vec::new();
+    if let Some(url) = env_proxy(&["HTTP_PROXY", "http_proxy"]) {
+        proxies.push(Proxy::http(url)?.no_proxy(no_proxy.clone()));
+    }
+    if let Some(url) = env_proxy(&["HTTPS_PROXY", "https_proxy"]) {
+        proxies.push(Proxy::https(url)?.no_proxy(no_proxy));
+    }
+    Ok(proxies)
+}
+
+fn env_proxy(names: &[&str]) -> Option<String> {
+    names
+        .iter()
+        .filter_map(|name| std::env::var(name).ok())
+        .find(|value| !value.trim().is_empty())
+}
+
+const PEM_CERT_BEGIN: &str = "-----BEGIN CERTIFICATE-----";
+const PEM_CERT_END: &str = "-----END CERTIFICATE-----";
+
+fn tls_error(path: &str, reason: impl ToString) -> VmCliError {
+    VmCliError::TlsError { path: path.to_string(), reason: reason.to_string() }
+}
+
+fn load_ca_certs(path: &str) -> Result<Vec<reqwest::Certificate>> {
+    let pem = std::fs::read(path).map_err(|e| tls_error(path, e))?;
+    let certs = reqwest::Certificate::from_pem_bundle(&pem).map_err(|e| tls_error(path, e))?;
+    if certs.is_empty() {
+        return Err(tls_error(path, "файл не содержит сертификатов в формате PEM"));
+    }
+    Ok(certs)
+}
+
+fn load_native_ca_certs(path: &str) -> Result<Vec<native_tls::Certificate>> {
+    let pem = std::fs::read_to_string(path).map_err(|e| tls_error(path, e))?;
+    let certs = pem_certificates(&pem)
+        .map(|block| native_tls::Certificate::from_pem(block.as_bytes()).map_err(|e| tls_error(path, e)))
+        .collect::<Result<Vec<_>>>()?;
+    if certs.is_empty() {
+        return Err(tls_error(path, "файл не содержит сертификатов в формате PEM"));
+    }
+    Ok(certs)
+}
+
+fn pem_certificates(pem: &str) -> impl Iterator<Item = &str> {
+    pem.match_indices(PEM_CERT_BEGIN).filter_map(|(start, _)| {
+        let end = start + pem[start..].find(PEM_CERT_END)? + PEM_CERT_END.len();
+        Some(&pem[start..end])
+    })
+}
+
+fn parse_flags(text: &str) -> BTreeMap<String, String> {
+    text.lines()
+        .filter_map(|line| line.trim().strip_prefix('-')?.split_once('='))
+        .map(|(name, value)| (name.to_string(), value.trim_matches('"').to_string()))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{MockServer, StreamingServer};
+    use std::sync::atomic::{AtomicU64, Ordering};
+
+    fn client(server: &MockServer) -> VmClient {
+        VmClient::new(&server.url, 5, None, None, None).unwrap()
+    }
+
+    #[tokio::test]
+    async fn export_requests_prometheus_text_format() {
+        let server = MockServer::start(vec![(200, "up{job=\"a\"} 1 1700000000000\n")]);
+
+        let response = client(&server).export_stream(&["up"], Some("1"), Some("2")).await.unwrap();
+        let data = response.text().await.unwrap();
+
+        assert_eq!(data, "up{job=\"a\"} 1 1700000000000\n");
+        let requests = server.requests();
+        assert_eq!(requests[0].method, "GET");
+        assert_eq!(requests[0].path(), "/api/v1/export/prometheus");
+        assert!(requests[0].target.contains("match%5B%5D=up"));
+    }
+
+    fn ca_bundle(dir: &tempfile::TempDir) -> String {
+        let path = dir.path().join("bundle.pem");
+        let bundle = [include_str!("../testdata/other-ca.pem"), include_str!("../testdata/ca.pem")].concat();
+        std::fs::write(&path, bundle).unwrap();
+        path.display().to_string()
+    }
+
+    fn tls_client(url: &str, ca_cert: Option<String>, insecure: bool) -> VmClient {
+        VmClient::new(url, 5, None, None, Some(TlsConfig { ca_cert, insecure })).unwrap()
+    }
+
+    #[test]
+    fn ca_bundle_loads_every_certificate() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = ca_bundle(&dir);
+
+        assert_eq!(load_ca_certs(&path).unwrap().len(), 2);
+        assert_eq!(load_native_ca_certs(&path).unwrap().len(), 2);
+    }
+
+    #[test]
+    fn ca_bundle_errors_are_tls_errors() {
+        let dir = tempfile::tempdir().unwrap();
+        let empty = dir.path().join("empty.pem");
+        std::fs::write(&empty, "not a certificate").unwrap();
+        let missing = dir.path().join("missing.pem");
+
+        for path in [&empty, &missing] {
+            let path = path.display().to_string();
+            assert!(matches!(load_ca_certs(&path), Err(VmCliError::TlsError { .. })));
+            assert!(matches!(load_native_ca_certs(&path), Err(VmCliError::TlsError { .. })));
+        }
+    }
+
+    #[tokio::test]
+    async fn https_requests_trust_any_certificate_from_bundle() {
+        let server = MockServer::start_tls(vec![(200, "OK")]);
+        let dir = tempfile::tempdir().unwrap();
+
+        let trusted = tls_client(&server.url, Some(ca_bundle(&dir)), false);
+        assert_eq!(trusted.health().await.unwrap().status, "OK");
+
+        let untrusted = tls_client(&server.url, None, false);
+        assert!(matches!(untrusted.health().await, Err(VmCliError::HttpError(_))));
+    }
+
+    #[test]
+    fn flags_are_parsed_from_text() {
+        let flags = parse_flags("-httpListenAddr=\":8428\"\n-readonly=\"true\"\n\n# comment\n-retentionPeriod=\"1\"\n");
+        assert_eq!(flags.get("httpListenAddr").map(String::as_str), Some(":8428"));
+        assert_eq!(flags.get("readonly").map(String::as_str), Some("true"));
+        assert_eq!(flags.len(), 3);
+    }
+
+    #[test]
+    fn import_url_uses_tenant_only_in_cluster() {
+        let single = VmClient::new("http://vm:8428", 5, None, None, None).unwrap();
+        assert_eq!(single.import_url(None).unwrap(), "http://vm:8428/api/v1/import/prometheus");
+        assert!(matches!(single.import_url(Some("42:7")), Err(VmCliError::InvalidQuery(_))));
+
+        let cluster = toml::from_str("vminsert_host = \"http://vminsert:8480\"\nselect_account_id = \"3\"").unwrap();
+        let cluster = VmClient::new("http://vmselect:8481", 5, Some(cluster), None, None).unwrap();
+        assert_eq!(
+            cluster.import_url(None).unwrap(),
+            "http://vminsert:8480/insert/3:0/prometheus/api/v1/import/prometheus"
+        );
+        assert_eq!(
+            cluster.import_url(Some("42:7")).unwrap(),
+            "http://vminsert:8480/insert/42:7/prometheus/api/v1/import/prometheus"
+        );
+    }
+
+    fn traced_client(server: &MockServer, trace_file: &std::path::Path, max_response_bytes: Option<u64>) -> VmClient {
+        let http = HttpConfig {
+            trace_file: Some(trace_file.display().to_string()),
+            max_response_bytes,
+            ..Default::default()
+        };
+        VmClient::new(&server.url, 5, None, Some(http), None).unwrap()
+    }
+
+    #[tokio::test]
+    async fn trace_file_records_url_status_and_body() {
+        let body = r#"{"status":"success","data":{"resultType":"vector","result":[]}}"#;
+        let server = MockServer::start(vec![(200, body)]);
+        let dir = tempfile::tempdir().unwrap();
+        let trace = dir.path().join("trace.log");
+
+        traced_client(&server, &trace, None).query("up", None).await.unwrap();
+
+        let entry = std::fs::read_to_string(&trace).unwrap();
+        assert!(entry.contains(&format!("> GET {}/api/v1/query?query=up", server.url)), "{}", entry);
+        assert!(entry.contains("< 200 OK"), "{}", entry);
+        assert!(entry.contains(body), "{}", entry);
+    }
+
+    #[tokio::test]
+    async fn trace_file_does_not_bypass_response_limit() {
+        let body = format!(r#"{{"status":"success","data":{{"resultType":"vector","result":[{}]}}}}"#, "1,".repeat(5000) + "1");
+        let server = MockServer::start(vec![(200, &body)]);
+        let dir = tempfile::tempdir().unwrap();
+        let trace = dir.path().join("trace.log");
+
+        let result = traced_client(&server, &trace, Some(100)).query("up", None).await;
+
+        assert!(matches!(result, Err(VmCliError::ResultTooLarge { limit: 100 })));
+        let entry = std::fs::read_to_string(&trace).unwrap();
+        assert!(entry.contains("тело прочитано не полностью"), "{}", entry);
+    }
+
+    #[tokio::test]
+    async fn metadata_is_parsed_and_filtered_by_metric() {
+        let server = MockServer::start(vec![(
+            200,
+            r#"{"status":"success","data":{"http_requests_total":[{"type":"counter","help":"Total requests","unit":""}]}}"#,
+        )]);
+
+        let metadata = client(&server).metadata(Some("http_requests_total"), Some(5)).await.unwrap();
+
+        let entries = &metadata.data["http_requests_total"];
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].metric_type, "counter");
+        assert_eq!(entries[0].help, "Total requests");
+        let request = &server.requests()[0];
+        assert_eq!(request.path(), "/api/v1/metadata");
+        assert!(request.target.contains("metric=http_requests_total"));
+        assert!(request.target.contains("limit=5"));
+    }
+
+    #[tokio::test]
+    async fn relabel_debug_posts_configs_and_parses_steps() {
+        let server = MockServer::start(vec![(
+            200,
+            r#"{"originalLabels":"{__name__=\"up\", job=\"node\"}","resultingLabels":"{__name__=\"up\", job=\"node-exporter\"}",
+                "steps":[{"rule":"- target_label: job\n  replacement: node-exporter\n","inLabels":"{job=\"node\"}","outLabels":"{job=\"node-exporter\"}"}],
+                "errorMsg":""}"#,
+        )]);
+
+        let result = client(&server)
+            .relabel_debug("up{job=\"node\"}", "- target_label: job\n  replacement: node-exporter\n")
+            .await
+            .unwrap();
+
+        assert_eq!(result.original_labels, "{__name__=\"up\", job=\"node\"}");
+        assert_eq!(result.resulting_labels, "{__name__=\"up\", job=\"node-exporter\"}");
+        assert_eq!(result.steps.len(), 1);
+        assert_eq!(result.steps[0].out_labels, "{job=\"node-exporter\"}");
+        assert_eq!(result.error_msg.as_deref(), Some(""));
+
+        let request = &server.requests()[0];
+        assert_eq!((request.method.as_str(), request.path()), ("POST", "/metric-relabel-debug"));
+        assert_eq!(request.param("metric"), ["up{job=\"node\"}"]);
+        assert_eq!(request.param("format"), ["json"]);
+    }
+
+    #[test]
+    fn relabel_debug_response_tolerates_missing_fields() {
+        let result: RelabelDebugResponse = serde_json::from_str(r#"{"errorMsg":"cannot parse relabel configs"}"#).unwrap();
+
+        assert!(result.steps.is_empty());
+        assert!(result.resulting_labels.is_empty());
+        assert_eq!(result.error_msg.as_deref(), Some("cannot parse relabel configs"));
+    }
+
+    #[derive(Default)]
+    struct RecordingBuilder(Vec<String>);
+
+    impl PoolOptions for RecordingBuilder {
+        fn pool_max_idle_per_host(mut self, max_idle: usize) -> Self {
+            self.0.push(format!("pool_max_idle_per_host={}", max_idle));
+            self
+        }
+
+        fn pool_idle_timeout(mut self, timeout: Duration) -> Self {
+            self.0.push(format!("pool_idle_timeout={:?}", timeout));
+            self
+        }
+
+        fn http2_prior_knowledge(mut self) -> Self {
+            self.0.push("http2_prior_knowledge".to_string());
+            self
+        }
+    }
+
+    #[test]
+    fn pool_options_are_passed_to_builder() {
+        let config = HttpConfig {
+            pool_max_idle_per_host: Some(64),
+            pool_idle_timeout: Some(90),
+            http2_prior_knowledge: true,
+            ..Default::default()
+        };
+
+        assert_eq!(
+            apply_pool_options(RecordingBuilder::default(), &config).0,
+            ["pool_max_idle_per_host=64", "pool_idle_timeout=90s", "http2_prior_knowledge"]
+        );
+    }
+
+    #[test]
+    fn default_http_config_keeps_builder_defaults() {
+        assert!(apply_pool_options(RecordingBuilder::default(), &HttpConfig::default()).0.is_empty());
+    }
+
+    const VECTOR_PREFIX: &str = r#"{"status":"success","data":{"resultType":"vector","result":["#;
+    const VECTOR_SAMPLE: &str = r#"{"metric":{"__name__":"up","job":"node"},"value":[1700000000,"1"]},"#;
+    const VECTOR_SUFFIX: &str = r#"{"metric":{},"value":[1700000000,"0"]}]}}"#;
+
+    fn limited_client(url: &str, max_response_bytes: u64) -> VmClient {
+        let http = HttpConfig { max_response_bytes: Some(max_response_bytes), ..Default::default() };
+        VmClient::new(url, 30, None, Some(http), None).unwrap()
+    }
+
+    #[tokio::test]
+    async fn response_limit_aborts_streamed_body_before_it_is_fully_sent() {
+        let count = 200_000;
+        let server = StreamingServer::start(VECTOR_PREFIX, VECTOR_SAMPLE, count, VECTOR_SUFFIX);
+        let total = (VECTOR_SAMPLE.len() * count) as u64;
+
+        let result = limited_client(&server.url, 256 * 1024).query("up", None).await;
+
+        assert!(matches!(result, Err(VmCliError::ResultTooLarge { limit: 262144 })));
+        tokio::time::sleep(Duration::from_millis(200)).await;
+        assert!(server.sent() < total / 2, "отправлено {} из {}", server.sent(), total);
+    }
+
+    #[tokio::test]
+    async fn streamed_body_within_limit_is_parsed() {
+        let server = StreamingServer::start(VECTOR_PREFIX, VECTOR_SAMPLE, 1000, VECTOR_SUFFIX);
+
+        let response = limited_client(&server.url, 1024 * 1024).query("up", None).await.unwrap();
+
+        assert_eq!(response.data.result.len(), 1001);
+    }
+
+    const EMPTY_VECTOR: &str = r#"{"status":"success","data":{"resultType":"vector","result":[]}}"#;
+
+    fn long_query() -> String {
+        format!("sum(up{{job=~\"{}\"}})", vec!["node"; 500].join("|"))
+    }
+
+    #[tokio::test]
+    async fn long_query_is_sent_as_form_post_and_short_one_as_get() {
+        let server = MockServer::start(vec![(200, EMPTY_VECTOR)]);
+        let client = client(&server);
+
+        client.query("up", None).await.unwrap();
+        client.query(&long_query(), None).await.unwrap();
+        client.query_range(&long_query(), "1700000000", "1700003600", "60s").await.unwrap();
+
+        let requests = server.requests();
+        assert_eq!(requests[0].method, "GET");
+        assert_eq!(requests[0].param("query"), ["up"]);
+        for request in &requests[1..] {
+            assert_eq!(request.method, "POST");
+            assert!(!request.target.contains("query="), "{}", request.target);
+            assert_eq!(request.param("query"), [long_query()]);
+        }
+        assert_eq!(requests[2].param("step"), ["60s"]);
+    }
+
+    #[tokio::test]
+    async fn post_queries_option_forces_post_for_short_queries() {
+        let server = MockServer::start(vec![(200, EMPTY_VECTOR)]);
+        let http = HttpConfig { post_queries: true, ..Default::default() };
+        let client = VmClient::new(&server.url, 5, None, Some(http), None).unwrap();
+
+        client.query("up", None).await.unwrap();
+
+        assert_eq!(server.requests()[0].method, "POST");
+        assert_eq!(server.requests()[0].body, "query=up");
+    }
+
+    #[tokio::test]
+    async fn every_selector_is_sent_as_separate_match_param() {
+        let server = MockServer::start(vec![(204, "")]);
+        let client = client(&server);
+        let matches = ["up", "node_load1{instance=\"a\"}"];
+
+        client.export_stream(&matches, None, None).await.unwrap();
+        client.delete_series(&matches, Some("1700000000"), None).await.unwrap();
+
+        let requests = server.requests();
+        assert_eq!(requests[0].path(), "/api/v1/export/prometheus");
+        assert_eq!(requests[1].path(), "/api/v1/admin/tsdb/delete_series");
+        for request in &requests {
+            assert_eq!(request.param("match[]"), matches);
+        }
+        assert_eq!(requests[1].param("start"), ["1700000000"]);
+    }
+
+    #[tokio::test]
+    async fn unreachable_vmselect_falls_through_to_next_host() {
+        let server = MockServer::start(vec![
+            (200, EMPTY_VECTOR),
+            (200, r#"{"status":"success","data":["job"]}"#),
+            (200, r#"{"status":"success","data":[]}"#),
+        ]);
+        let dead = std::net::TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap();
+        let http = HttpConfig { retry_base_ms: Some(1), ..Default::default() };
+        let client = VmClient::new("http://127.0.0.1:1", 5, None, Some(http), None)
+            .unwrap()
+            .with_select_hosts(vec![format!("http://{}", dead), server.url.clone()]);
+
+        client.query("up", None).await.unwrap();
+        assert_eq!(client.label_names(None, None, None, None).await.unwrap().data, ["job"]);
+        client.series(&["up"], None, None, None).await.unwrap();
+
+        let paths: Vec<String> = server.requests().iter().map(|r| r.path().to_string()).collect();
+        assert_eq!(paths, ["/api/v1/query", "/api/v1/labels", "/api/v1/series"]);
+    }
+
+    #[tokio::test]
+    async fn api_errors_from_vmselect_are_not_retried_on_next_host() {
+        let first = MockServer::start(vec![(400, r#"{"status":"error","error":"bad query"}"#)]);
+        let second = MockServer::start(vec![(200, EMPTY_VECTOR)]);
+        let client = VmClient::new("http://127.0.0.1:1", 5, None, None, None)
+            .unwrap()
+            .with_select_hosts(vec![first.url.clone(), second.url.clone()]);
+
+        assert!(client.query("up", None).await.is_err());
+        assert_eq!(first.requests().len(), 1);
+        assert!(second.requests().is_empty());
+    }
+
+    #[test]
+    fn base_path_is_joined_without_duplicate_slashes() {
+        let cases = [
+            ("http://vm:8428", "", "/api/v1/query", "http://vm:8428/api/v1/query"),
+            ("http://vm:8428/", "", "/api/v1/query", "http://vm:8428/api/v1/query"),
+            ("https://host", "victoria", "/api/v1/query", "https://host/victoria/api/v1/query"),
+            ("https://host/", "/victoria/", "/api/v1/query", "https://host/victoria/api/v1/query"),
+            ("https://host", "/a/b", "health", "https://host/a/b/health"),
+            ("https://host", "/", "/", "https://host"),
+        ];
+        for (host, base_path, endpoint, expected) in cases {
+            assert_eq!(join_url(host, base_path, endpoint), expected, "{} + {} + {}", host, base_path, endpoint);
+        }
+    }
+
+    #[tokio::test]
+    async fn base_path_is_prepended_to_query_and_admin_endpoints() {
+        let server = MockServer::start(vec![(200, EMPTY_VECTOR)]);
+        let client = client(&server).with_base_path(Some("/victoria/".to_string()));
+
+        client.query("up", None).await.unwrap();
+        let _ = client.list_snapshots().await;
+
+        let paths: Vec<String> = server.requests().iter().map(|r| r.path().to_string()).collect();
+        assert_eq!(paths, ["/victoria/api/v1/query", "/victoria/snapshot/list"]);
+    }
+
+    #[tokio::test]
+    async fn cancelled_client_issues_no_new_requests() {
+        let server = MockServer::start(vec![(200, EMPTY_VECTOR)]);
+        let cancel = CancellationToken::new();
+        let client = client(&server).with_cancellation(cancel.clone());
+
+        client.query("up", None).await.unwrap();
+        cancel.cancel();
+
+        assert!(matches!(client.query("up", None).await, Err(VmCliError::Cancelled)));
+        assert!(matches!(client.health().await, Err(VmCliError::Cancelled)));
+        assert_eq!(server.requests().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn cancellation_interrupts_retry_backoff() {
+        let server = MockServer::start(vec![(503, "unavailable")]);
+        let cancel = CancellationToken::new();
+        let http = HttpConfig { retries: Some(5), retry_base_ms: Some(60_000), ..Default::default() };
+        let client = VmClient::new(&server.url, 5, None, Some(http), None).unwrap().with_cancellation(cancel.clone());
+
+        let canceller = async {
+            tokio::time::sleep(Duration::from_millis(200)).await;
+            cancel.cancel();
+        };
+        let (result, ()) = tokio::join!(client.query("up", None), canceller);
+
+        assert!(matches!(result, Err(VmCliError::Cancelled)));
+        assert_eq!(server.requests().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn exposition_import_posts_to_prometheus_endpoint_with_extra_labels() {
+        let server = MockServer::start(vec![(204, "")]);
+        let data = "# TYPE up gauge\nup{job=\"node\"} 1\n";
+        let labels = [("env".to_string(), "prod".to_string()), ("dc".to_string(), "eu west".to_string())];
+
+        client(&server).import_prometheus_exposition(data, &labels, None, None).await.unwrap();
+
+        let request = &server.requests()[0];
+        assert_eq!(request.method, "POST");
+        assert_eq!(request.path(), "/api/v1/import/prometheus");
+        assert_eq!(request.param("extra_label"), ["env=prod", "dc=eu west"]);
+        assert_eq!(request.body, data);
+    }
+
+    #[tokio::test]
+    async fn select_insert_and_storage_hosts_share_configured_client() {
+        let select = MockServer::start_tls(vec![(200, EMPTY_VECTOR)]);
+        let insert = MockServer::start_tls(vec![(204, "")]);
+        let storage = MockServer::start_tls(vec![(200, "snap-1")]);
+        let dir = tempfile::tempdir().unwrap();
+        let cluster = toml::from_str(&format!(
+            "vminsert_host = \"{}\"\nvmstorage_host = \"{}\"",
+            insert.url, storage.url
+        ))
+        .unwrap();
+        let tls = TlsConfig { ca_cert: Some(ca_bundle(&dir)), insecure: false };
+        let client = VmClient::new(&select.url, 5, Some(cluster), None, Some(tls)).unwrap();
+
+        client.query("up", None).await.unwrap();
+        client.import("up 1\n", None, None).await.unwrap();
+        assert_eq!(client.create_snapshot("nightly").await.unwrap(), "snap-1");
+
+        assert_eq!(select.requests().len(), 1);
+        assert_eq!(insert.requests()[0].path(), "/insert/0:0/prometheus/api/v1/import/prometheus");
+        assert_eq!(storage.requests()[0].path(), "/snapshot/create");
+    }
+
+    #[tokio::test]
+    async fn label_name_is_percent_encoded_as_path_segment() {
+        let server = MockServer::start(vec![(200, r#"{"status":"success","data":["v"]}"#)]);
+        let client = client(&server);
+
+        client.label_values("team/owner name", &[], None, None, None).await.unwrap();
+        client.label_values("зона", &[], None, None, None).await.unwrap();
+
+        let requests = server.requests();
+        assert_eq!(requests[0].path(), "/api/v1/label/team%2Fowner%20name/values");
+        assert_eq!(requests[1].path(), "/api/v1/label/%D0%B7%D0%BE%D0%BD%D0%B0/values");
+    }
+
+    #[test]
+    fn path_segments_keep_unreserved_characters() {
+        assert_eq!(encode_path_segment("__name__"), "__name__");
+        assert_eq!(encode_path_segment("a.b-c~d"), "a.b-c~d");
+        assert_eq!(encode_path_segment("a/b c?d#e"), "a%2Fb%20c%3Fd%23e");
+    }
+
+    #[tokio::test]
+    async fn metadata_metric_name_is_encoded_in_query() {
+        let server = MockServer::start(vec![(200, r#"{"status":"success","data":{}}"#)]);
+
+        client(&server).metadata(Some("odd name/total"), None).await.unwrap();
+
+        let request = &server.requests()[0];
+        assert_eq!(request.path(), "/api/v1/metadata");
+        assert_eq!(request.param("metric"), ["odd name/total"]);
+        assert!(!request.target.contains(' '), "{}", request.target);
+    }
+
+    #[tokio::test]
+    async fn cached_query_is_not_resent_within_ttl() {
+        let server = MockServer::start(vec![(200, EMPTY_VECTOR)]);
+        let client = client(&server).with_query_cache(Some(Duration::from_millis(300)));
+
+        client.query("up", Some("1700000000")).await.unwrap();
+        client.query("up", Some("1700000000")).await.unwrap();
+        assert_eq!(server.requests().len(), 1);
+
+        client.query("up", Some("1700000060")).await.unwrap();
+        client.query_range("up", "1700000000", "1700003600", "60s").await.unwrap();
+        client.query_range("up", "1700000000", "1700003600", "30s").await.unwrap();
+        assert_eq!(server.requests().len(), 4);
+
+        tokio::time::sleep(Duration::from_millis(350)).await;
+        client.query("up", Some("1700000000")).await.unwrap();
+        assert_eq!(server.requests().len(), 5);
+    }
+
+    #[tokio::test]
+    async fn zero_cache_ttl_disables_query_cache() {
+        let server = MockServer::start(vec![(200, EMPTY_VECTOR)]);
+        let client = client(&server).with_query_cache(Some(Duration::ZERO));
+
+        client.query("up", Some("1700000000")).await.unwrap();
+        client.query("up", Some("1700000000")).await.unwrap();
+
+        assert_eq!(server.requests().len(), 2);
+    }
+
+    #[test]
+    fn query_cache_evicts_least_recently_used_entry() {
+        let cache = QueryCache { ttl: Duration::from_secs(60), entries: Mutex::new(VecDeque::new()) };
+        let response: QueryResponse = serde_json::from_str(EMPTY_VECTOR).unwrap();
+
+        for index in 0..QUERY_CACHE_CAPACITY {
+            cache.insert(index.to_string(), &response);
+        }
+        assert!(cache.get("0").is_some());
+        cache.insert("new".to_string(), &response);
+
+        assert!(cache.get("0").is_some());
+        assert!(cache.get("1").is_none());
+        assert!(cache.get("new").is_some());
+    }
+
+    #[tokio::test]
+    async fn limit_is_forwarded_to_series_and_label_values() {
+        let server = MockServer::start(vec![(200, r#"{"status":"success","data":[]}"#)]);
+        let client = client(&server);
+
+        client.series(&["up"], None, None, Some(50)).await.unwrap();
+        client.label_values("job", &[], None, None, Some(10)).await.unwrap();
+        client.series(&["up"], None, None, None).await.unwrap();
+        client.label_values("job", &[], None, None, None).await.unwrap();
+
+        let requests = server.requests();
+        assert_eq!(requests[0].path(), "/api/v1/series");
+        assert_eq!(requests[0].param("limit"), ["50"]);
+        assert_eq!(requests[1].path(), "/api/v1/label/job/values");
+        assert_eq!(requests[1].param("limit"), ["10"]);
+        assert!(requests[2].param("limit").is_empty());
+        assert!(requests[3].param("limit").is_empty());
+    }
+
+    #[tokio::test]
+    async fn zero_step_is_rejected_before_sending() {
+        let server = MockServer::start(vec![(200, EMPTY_VECTOR)]);
+
+        let result = client(&server).query_range("up", "1700000000", "1700003600", "0").await;
+
+        assert!(matches!(result, Err(VmCliError::InvalidQuery(_))));
+        assert!(server.requests().is_empty());
+    }
+
+    #[test]
+    fn labels_are_serialized_in_sorted_order() {
+        let json = r#"{"zone":"b","__name__":"up","job":"api","a":"1"}"#;
+        let expected = r#"{"__name__":"up","a":"1","job":"api","zone":"b"}"#;
+
+        for _ in 0..20 {
+            let result: QueryResult = serde_json::from_str(&format!(r#"{{"metric":{},"value":[1,"1"]}}"#, json)).unwrap();
+            assert_eq!(serde_json::to_string(&result).unwrap(), format!(r#"{{"metric":{},"value":[1.0,"1"],"values":null}}"#, expected));
+
+            let series: SeriesResponse = serde_json::from_str(&format!(r#"{{"status":"success","data":[{}]}}"#, json)).unwrap();
+            assert_eq!(serde_json::to_string(&series).unwrap(), format!(r#"{{"status":"success","data":[{}]}}"#, expected));
+        }
+    }
+
+    #[tokio::test]
+    async fn series_paged_deduplicates_series_from_overlapping_windows() {
+        let server = MockServer::start(vec![
+            (200, r#"{"status":"success","data":[{"__name__":"up","job":"a"},{"__name__":"up","job":"b"}]}"#),
+            (200, r#"{"status":"success","data":[{"job":"b","__name__":"up"},{"__name__":"up","job":"c"}]}"#),
+            (200, r#"{"status":"success","data":[{"__name__":"up","job":"a"}]}"#),
+        ]);
+        let mut progress = Vec::new();
+
+        let series = client(&server)
+            .series_paged(&["up"], 1000, 1250, Duration::from_secs(100), None, |page, pages| progress.push((page, pages)))
+            .await
+            .unwrap();
+
+        let jobs: Vec<&str> = series.data.iter().map(|labels| labels["job"].as_str()).collect();
+        assert_eq!(jobs, ["a", "b", "c"]);
+        assert_eq!(progress, [(1, 3), (2, 3), (3, 3)]);
+
+        let windows: Vec<(Vec<String>, Vec<String>)> =
+            server.requests().iter().map(|r| (r.param("start"), r.param("end"))).collect();
+        let window = |start: &str, end: &str| (vec![start.to_string()], vec![end.to_string()]);
+        assert_eq!(windows, [window("1000", "1100"), window("1100", "1200"), window("1200", "1250")]);
+    }
+
+    #[tokio::test]
+    async fn series_paged_stops_once_limit_is_reached() {
+        let server = MockServer::start(vec![
+            (200, r#"{"status":"success","data":[{"job":"a"},{"job":"b"}]}"#),
+            (200, r#"{"status":"success","data":[{"job":"c"},{"job":"d"}]}"#),
+        ]);
+
+        let series = client(&server)
+            .series_paged(&["up"], 0, 300, Duration::from_secs(100), Some(3), |_, _| {})
+            .await
+            .unwrap();
+
+        assert_eq!(series.data.len(), 3);
+        assert_eq!(server.requests().len(), 2);
+    }
+
+    /// Считает строки NDJSON и запоминает, сколько байт сервер успел отправить
+    /// к моменту первой записи.
+    struct LineCounter {
+        server: Arc<StreamingServer>,
+        lines: Arc<AtomicU64>,
+        sent_at_first_line: Arc<AtomicU64>,
+    }
+
+    impl Write for LineCounter {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            if self.lines.load(Ordering::Relaxed) == 0 {
+                self.sent_at_first_line.store(self.server.sent(), Ordering::Relaxed);
+            }
+            let lines = buf.iter().filter(|b| **b == b'\n').count() as u64;
+            self.lines.fetch_add(lines, Ordering::Relaxed);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn stream_query_emits_series_before_the_body_is_fully_received() {
+        let count = 100_000;
+        let server = Arc::new(StreamingServer::start(VECTOR_PREFIX, VECTOR_SAMPLE, count, VECTOR_SUFFIX));
+        let total = (VECTOR_SAMPLE.len() * count) as u64;
+        let lines = Arc::new(AtomicU64::new(0));
+        let sent_at_first_line = Arc::new(AtomicU64::new(0));
+        let counter = LineCounter { server: server.clone(), lines: lines.clone(), sent_at_first_line: sent_at_first_line.clone() };
+
+        let written = VmClient::new(&server.url, 30, None, None, None)
+            .unwrap()
+            .stream_query("up", None, counter)
+            .await
+            .unwrap();
+
+        assert_eq!(written, count as u64 + 1);
+        assert_eq!(lines.load(Ordering::Relaxed), count as u64 + 1);
+        let first = sent_at_first_line.load(Ordering::Relaxed);
+        assert!(first < total / 2, "к первой строке отправлено {} из {}", first, total);
+    }
+
+    #[tokio::test]
+    async fn stream_query_writes_one_json_object_per_series() {
+        let server = StreamingServer::start(VECTOR_PREFIX, VECTOR_SAMPLE, 2, VECTOR_SUFFIX);
+        let path = tempfile::NamedTempFile::new().unwrap().into_temp_path();
+
+        let file = std::fs::File::create(&path).unwrap();
+        VmClient::new(&server.url, 5, None, None, None).unwrap().stream_query("up", None, file).await.unwrap();
+
+        let content = std::fs::read_to_string(&path).unwrap();
+        let series: Vec<QueryResult> = content.lines().map(|line| serde_json::from_str(line).unwrap()).collect();
+        assert_eq!(series.len(), 3);
+        assert_eq!(series[0].metric["job"], "node");
+        assert_eq!(series[2].value.as_ref().unwrap().1, "0");
+    }
+
+    #[tokio::test]
+    async fn round_digits_is_forwarded_to_query_endpoints() {
+        let server = MockServer::start(vec![(200, EMPTY_VECTOR)]);
+        let rounded = client(&server).with_round_digits(Some(2));
+
+        rounded.query("up", None).await.unwrap();
+        rounded.query_range("up", "1700000000", "1700003600", "60s").await.unwrap();
+        client(&server).query("up", None).await.unwrap();
+
+        let requests = server.requests();
+        assert_eq!(requests[0].param("round_digits"), ["2"]);
+        assert_eq!(requests[1].path(), "/api/v1/query_range");
+        assert_eq!(requests[1].param("round_digits"), ["2"]);
+        assert!(requests[2].param("round_digits").is_empty());
+    }
+
+    #[tokio::test]
+    async fn enforced_labels_are_appended_to_query_and_export_requests() {
+        let server = MockServer::start(vec![(200, EMPTY_VECTOR)]);
+        let tenant = client(&server).with_enforced_labels(
+            vec!["{team=\"infra\"}".to_string()],
+            vec!["env=prod".to_string(), "region=eu".to_string()],
+        );
+
+        tenant.query("up", None).await.unwrap();
+        tenant.query_range("up", "1700000000", "1700003600", "60s").await.unwrap();
+        tenant.export_stream(&["up"], None, None).await.unwrap();
+        let _ = tenant.series(&["up"], None, None, None).await;
+        client(&server).query("up", None).await.unwrap();
+
+        let requests = server.requests();
+        let paths: Vec<&str> = requests.iter().map(|r| r.path()).collect();
+        assert_eq!(paths, ["/api/v1/query", "/api/v1/query_range", "/api/v1/export/prometheus", "/api/v1/series", "/api/v1/query"]);
+        for request in &requests[..4] {
+            assert_eq!(request.param("extra_filters[]"), ["{team=\"infra\"}"], "{}", request.target);
+            assert_eq!(request.param("extra_label"), ["env=prod", "region=eu"], "{}", request.target);
+        }
+        assert!(requests[4].param("extra_filters[]").is_empty());
+        assert!(requests[4].param("extra_label").is_empty());
+    }
+
+    #[tokio::test]
+    async fn enforced_labels_are_sent_in_post_query_body() {
+        let server = MockServer::start(vec![(200, EMPTY_VECTOR)]);
+        let http = HttpConfig { post_queries: true, ..Default::default() };
+        let tenant = VmClient::new(&server.url, 5, None, Some(http), None)
+            .unwrap()
+            .with_enforced_labels(Vec::new(), vec!["env=prod".to_string()]);
+
+        tenant.query("up", None).await.unwrap();
+
+        let request = &server.requests()[0];
+        assert_eq!(request.method, "POST");
+        assert!(request.body.contains("extra_label=env%3Dprod"), "{}", request.body);
+    }
+
+    fn auth(username: Option<&str>, password: Option<&str>, token: Option<&str>) -> Option<AuthConfig> {
+        Some(AuthConfig {
+            username: username.map(String::from),
+            password: password.map(String::from),
+            token: token.map(String::from),
+        })
+    }
+
+    #[tokio::test]
+    async fn basic_auth_header_is_sent_with_every_request() {
+        let server = MockServer::start(vec![(200, EMPTY_VECTOR)]);
+        let client = client(&server).with_auth(auth(Some("vm"), Some("secret"), None));
+
+        client.query("up", None).await.unwrap();
+        client.export_stream(&["up"], None, None).await.unwrap();
+        client.import("up 1 1\n", None, None).await.unwrap();
+        client.create_snapshot("nightly").await.unwrap();
+        client.health().await.unwrap();
+
+        let requests = server.requests();
+        assert_eq!(requests.len(), 5);
+        for request in &requests {
+            assert_eq!(request.header("authorization"), Some("Basic dm06c2VjcmV0"), "{}", request.target);
+        }
+    }
+
+    #[tokio::test]
+    async fn bearer_token_takes_precedence_over_basic_auth() {
+        let server = MockServer::start(vec![(200, EMPTY_VECTOR)]);
+
+        client(&server).with_auth(auth(Some("vm"), Some("secret"), Some("t0ken"))).query("up", None).await.unwrap();
+        client(&server).with_auth(auth(Some("vm"), None, None)).query("up", None).await.unwrap();
+        client(&server).query("up", None).await.unwrap();
+
+        let requests = server.requests();
+        assert_eq!(requests[0].header("Authorization"), Some("Bearer t0ken"));
+        assert_eq!(requests[1].header("Authorization"), Some("Basic dm06"));
+        assert_eq!(requests[2].header("Authorization"), None);
+    }
+
+    const UNAVAILABLE_TWICE: [(u16, &str); 3] = [(503, "overloaded"), (503, "overloaded"), (200, EMPTY_VECTOR)];
+
+    fn retrying_client(server: &MockServer, retries: Option<u32>, retry_writes: bool) -> VmClient {
+        let http = HttpConfig { retries, retry_base_ms: Some(1), retry_writes, ..Default::default() };
+        VmClient::new(&server.url, 5, None, Some(http), None).unwrap()
+    }
+
+    #[tokio::test]
+    async fn query_retries_after_two_unavailable_responses() {
+        let server = MockServer::start(UNAVAILABLE_TWICE.to_vec());
+
+        let response = retrying_client(&server, None, false).query("up", None).await.unwrap();
+
+        assert_eq!(response.status, "success");
+        assert_eq!(server.requests().len(), 3);
+    }
+
+    #[tokio::test]
+    async fn retries_stop_at_configured_limit() {
+        let server = MockServer::start(UNAVAILABLE_TWICE.to_vec());
+
+        let result = retrying_client(&server, Some(1), false).query("up", None).await;
+
+        assert!(matches!(result, Err(VmCliError::ApiError { status: Some(503), .. })));
+        assert_eq!(server.requests().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn writes_are_not_retried_unless_opted_in() {
+        let server = MockServer::start(UNAVAILABLE_TWICE.to_vec());
+        let result = retrying_client(&server, Some(3), false).import("up 1 1\n", None, None).await;
+        assert!(matches!(result, Err(VmCliError::ApiError { status: Some(503), .. })));
+        assert_eq!(server.requests().len(), 1);
+
+        let server = MockServer::start(UNAVAILABLE_TWICE.to_vec());
+        let result = retrying_client(&server, Some(3), false).delete_series(&["old"], None, None).await;
+        assert!(result.is_err());
+        assert_eq!(server.requests().len(), 1);
+
+        let server = MockServer::start(UNAVAILABLE_TWICE.to_vec());
+        retrying_client(&server, Some(3), true).import("up 1 1\n", None, None).await.unwrap();
+        assert_eq!(server.requests().len(), 3);
+    }
+
+    #[tokio::test]
+    async fn client_errors_are_not_retried() {
+        let server = MockServer::start(vec![(400, "bad query"), (200, EMPTY_VECTOR)]);
+
+        let result = retrying_client(&server, Some(3), false).query("up", None).await;
+
+        assert!(matches!(result, Err(VmCliError::ApiError { status: Some(400), .. })));
+        assert_eq!(server.requests().len(), 1);
+    }
+
+    #[test]
+    fn retry_delay_grows_exponentially_with_bounded_jitter() {
+        let base = Duration::from_millis(100);
+        for (attempt, backoff) in [(1, 100), (2, 200), (3, 400), (4, 800)] {
+            let delay = retry_delay(base, attempt);
+            let backoff = Duration::from_millis(backoff);
+            assert!(delay >= backoff && delay <= backoff.mul_f64(1.5), "attempt {}: {:?}", attempt, delay);
+        }
+        assert!(retry_delay(base, 30) <= MAX_RETRY_DELAY.mul_f64(1.5));
+    }
+
+    fn cluster_client(vminsert: &str, vmstorage: &str, http: Option<HttpConfig>, tls: Option<TlsConfig>) -> VmClient {
+        let cluster = toml::from_str(&format!("vminsert_host = \"{}\"\nvmstorage_host = \"{}\"", vminsert, vmstorage)).unwrap();
+        VmClient::new("http://vmselect.invalid:8481", 5, Some(cluster), http, tls).unwrap()
+    }
+
+    /// Клиенты select, insert и storage вместе с их базовыми URL.
+    fn sub_clients(client: &VmClient) -> [(Client, String); 3] {
+        let insert = client.create_insert_client(5).unwrap();
+        let storage = client.create_storage_client(5).unwrap();
+        [
+            (client.client.clone(), client.base_url.clone()),
+            (insert.client, insert.base_url),
+            (storage.client, storage.base_url),
+        ]
+    }
+
+    #[tokio::test]
+    async fn select_insert_and_storage_clients_share_configured_options() {
+        let proxy = MockServer::start(vec![(200, "OK")]);
+        let http = HttpConfig { proxy: Some(proxy.url.clone()), ..Default::default() };
+        let client = cluster_client("http://vminsert.invalid:8480", "http://vmstorage.invalid:8482", Some(http), None);
+
+        for (http_client, base_url) in sub_clients(&client) {
+            let response = http_client.get(format!("{}/health", base_url)).send().await.unwrap();
+            assert_eq!(response.status(), 200, "{}", base_url);
+        }
+
+        let targets: Vec<String> = proxy.requests().into_iter().map(|r| r.target).collect();
+        assert_eq!(
+            targets,
+            [
+                "http://vmselect.invalid:8481/health",
+                "http://vminsert.invalid:8480/health",
+                "http://vmstorage.invalid:8482/health",
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn insert_and_storage_clients_trust_configured_ca() {
+        let insert = MockServer::start_tls(vec![(200, "OK")]);
+        let storage = MockServer::start_tls(vec![(200, "OK")]);
+        let dir = tempfile::tempdir().unwrap();
+
+        let tls = TlsConfig { ca_cert: Some(ca_bundle(&dir)), insecure: false };
+        let trusted = cluster_client(&insert.url, &storage.url, None, Some(tls));
+        for (http_client, base_url) in sub_clients(&trusted).into_iter().skip(1) {
+            assert!(http_client.get(format!("{}/health", base_url)).send().await.is_ok(), "{}", base_url);
+        }
+
+        let untrusted = cluster_client(&insert.url, &storage.url, None, None);
+        for (http_client, base_url) in sub_clients(&untrusted).into_iter().skip(1) {
+            assert!(http_client.get(format!("{}/health", base_url)).send().await.is_err(), "{}", base_url);
+        }
+
+        let broken = TlsConfig { ca_cert: Some(dir.path().join("missing.pem").display().to_string()), insecure: false };
+        let client = cluster_client(&insert.url, &storage.url, None, None);
+        let client = VmClient { tls_config: broken, ..client };
+        assert!(matches!(client.create_insert_client(5), Err(VmCliError::TlsError { .. })));
+        assert!(matches!(client.create_storage_client(5), Err(VmCliError::TlsError { .. })));
+    }
+
+    #[tokio::test]
+    async fn insecure_applies_to_insert_and_storage_clients() {
+        let insert = MockServer::start_tls(vec![(200, "OK")]);
+        let storage = MockServer::start_tls(vec![(200, "OK")]);
+
+        let tls = TlsConfig { ca_cert: None, insecure: true };
+        let client = cluster_client(&insert.url, &storage.url, None, Some(tls));
+
+        for (http_client, base_url) in sub_clients(&client).into_iter().skip(1) {
+            assert!(http_client.get(format!("{}/health", base_url)).send().await.is_ok(), "{}", base_url);
+        }
+        assert_eq!(insert.requests().len(), 1);
+        assert_eq!(storage.requests().len(), 1);
     }
 }
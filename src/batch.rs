@@ -0,0 +1,214 @@
+use crate::api::VmClient;
+use crate::commands::health::is_healthy;
+use crate::error::{Result, VmCliError};
+use crate::messages::{self, Msg};
+use crate::output;
+use colored::*;
+use std::time::{Duration, Instant};
+use tracing::{debug, info, warn};
+
+const BREAKER_POLL_INTERVAL: Duration = Duration::from_secs(2);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorPolicy {
+    FailFast,
+    Continue,
+}
+
+impl ErrorPolicy {
+    pub fn from_flags(skip_errors: bool) -> Self {
+        if skip_errors {
+            ErrorPolicy::Continue
+        } else {
+            ErrorPolicy::FailFast
+        }
+    }
+}
+
+pub struct BatchErrors {
+    policy: ErrorPolicy,
+    total: usize,
+    failures: Vec<(String, String)>,
+}
+
+impl BatchErrors {
+    pub fn new(policy: ErrorPolicy) -> Self {
+        Self {
+            policy,
+            total: 0,
+            failures: Vec::new(),
+        }
+    }
+
+    pub fn record<T>(&mut self, item: &str, result: Result<T>) -> Result<Option<T>> {
+        self.total += 1;
+
+        match result {
+            Ok(value) => Ok(Some(value)),
+            Err(e) if self.policy == ErrorPolicy::FailFast => Err(e),
+            Err(e) => {
+                if output::is_json_mode() {
+                    output::print_error(&e);
+                } else {
+                    eprintln!(
+                        "{} {}: {}",
+                        messages::t(Msg::Warning).yellow(),
+                        item,
+                        messages::error_message(&e)
+                    );
+                }
+                self.failures.push((item.to_string(), messages::error_message(&e)));
+                Ok(None)
+            }
+        }
+    }
+
+    /// Печатает сводку и в режиме Continue возвращает ошибку, если хотя бы один
+    /// элемент не удался, чтобы код выхода не был нулевым.
+    pub fn finish(self) -> Result<()> {
+        self.print_summary();
+        if self.failures.is_empty() {
+            return Ok(());
+        }
+
+        Err(VmCliError::BatchFailed {
+            failed: self.failures.into_iter().map(|(item, _)| item).collect(),
+            total: self.total,
+        })
+    }
+
+    fn print_summary(&self) {
+        if self.failures.is_empty() || output::is_json_mode() {
+            return;
+        }
+
+        eprintln!(
+            "{} {} из {}",
+            messages::t(Msg::Errors).red().bold(),
+            self.failures.len(),
+            self.total
+        );
+        for (item, message) in &self.failures {
+            eprintln!("  {}: {}", item, message);
+        }
+    }
+}
+
+pub struct CircuitBreaker {
+    threshold: Option<usize>,
+    max_wait: Duration,
+    consecutive_failures: usize,
+}
+
+impl CircuitBreaker {
+    pub fn new(threshold: Option<usize>, max_wait: Duration) -> Self {
+        Self {
+            threshold,
+            max_wait,
+            consecutive_failures: 0,
+        }
+    }
+
+    pub async fn observe(&mut self, client: &VmClient, succeeded: bool) -> Result<()> {
+        if succeeded {
+            self.consecutive_failures = 0;
+            return Ok(());
+        }
+
+        self.consecutive_failures += 1;
+        match self.threshold {
+            Some(threshold) if self.consecutive_failures >= threshold => {}
+            _ => return Ok(()),
+        }
+
+        if !output::is_json_mode() {
+            eprintln!(
+                "{} неудачных пакетов подряд: {}, импорт приостановлен до готовности VictoriaMetrics (не более {})",
+                messages::t(Msg::Warning).yellow(),
+                self.consecutive_failures,
+                humantime::format_duration(self.max_wait)
+            );
+        }
+        self.wait_for_health(client).await?;
+        self.consecutive_failures = 0;
+        Ok(())
+    }
+
+    async fn wait_for_health(&self, client: &VmClient) -> Result<()> {
+        let deadline = Instant::now() + self.max_wait;
+
+        loop {
+            match client.health().await {
+                Ok(health) if is_healthy(&health.status) => {
+                    info!("VictoriaMetrics снова доступна, импорт продолжается");
+                    return Ok(());
+                }
+                Ok(health) => debug!("Статус VictoriaMetrics: {}", health.status),
+                Err(e) => debug!("Проверка /health не удалась: {}", e),
+            }
+
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            if remaining.is_zero() {
+                warn!(
+                    "VictoriaMetrics не восстановилась за {}, импорт прерван",
+                    humantime::format_duration(self.max_wait)
+                );
+                return Err(VmCliError::Timeout);
+            }
+
+            tokio::select! {
+                _ = tokio::time::sleep(BREAKER_POLL_INTERVAL.min(remaining)) => {}
+                _ = client.cancelled() => return Err(VmCliError::Cancelled),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn run(policy: ErrorPolicy) -> (Vec<&'static str>, Result<()>) {
+        let mut errors = BatchErrors::new(policy);
+        let mut done = Vec::new();
+
+        let outcome = (|| {
+            for (item, fails) in [("first", false), ("middle", true), ("last", false)] {
+                let result = if fails { Err(VmCliError::Timeout) } else { Ok(item) };
+                if let Some(item) = errors.record(item, result)? {
+                    done.push(item);
+                }
+            }
+            Ok(())
+        })();
+
+        (done, outcome.and_then(|_| errors.finish()))
+    }
+
+    #[test]
+    fn fail_fast_stops_at_middle_failure() {
+        let (done, result) = run(ErrorPolicy::FailFast);
+        assert_eq!(done, ["first"]);
+        assert!(matches!(result, Err(VmCliError::Timeout)));
+    }
+
+    #[test]
+    fn continue_processes_remaining_items_and_reports_failure() {
+        let (done, result) = run(ErrorPolicy::Continue);
+        assert_eq!(done, ["first", "last"]);
+        match result {
+            Err(VmCliError::BatchFailed { failed, total }) => {
+                assert_eq!(failed, ["middle"]);
+                assert_eq!(total, 3);
+            }
+            other => panic!("unexpected result: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn finish_succeeds_without_failures() {
+        let mut errors = BatchErrors::new(ErrorPolicy::Continue);
+        errors.record("only", Ok(())).unwrap();
+        assert!(errors.finish().is_ok());
+    }
+}
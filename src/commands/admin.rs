@@ -1,8 +1,20 @@
-use crate::api::VmClient;
+use crate::api::{RetentionInfo, SnapshotInfo, VmClient};
+use crate::config::OutputFormat;
 use crate::error::Result;
+use crate::output;
+use crate::utils::{
+    broad_matcher_reason, format_bytes, format_retention, format_usage_bar, parse_bytes, parse_retention,
+    print_formatted, Formatter,
+};
 use clap::{Parser, Subcommand};
 use colored::*;
-use tracing::info;
+use serde::Serialize;
+use std::time::{Duration, Instant};
+use tracing::{info, warn};
+
+const BROAD_DELETE_SERIES_THRESHOLD: usize = 10_000;
+const SNAPSHOT_POLL_INTERVAL: Duration = Duration::from_secs(1);
+const DRY_RUN: &str = "[dry-run]";
 
 #[derive(Parser)]
 pub struct AdminCommand {
@@ -13,8 +25,14 @@ pub struct AdminCommand {
 #[derive(Subcommand)]
 pub enum AdminSubcommand {
     Delete {
-        #[arg(value_name = "MATCH")]
-        match_: String,
+        #[arg(value_name = "MATCH", required_unless_present = "match_file")]
+        match_: Option<String>,
+
+        #[arg(long, value_name = "PATH")]
+        match_file: Option<String>,
+
+        #[arg(long = "match", value_name = "MATCH")]
+        extra_matches: Vec<String>,
 
         #[arg(short, long)]
         start: Option<String>,
@@ -24,6 +42,9 @@ pub enum AdminSubcommand {
 
         #[arg(long)]
         confirm: bool,
+
+        #[arg(long)]
+        force_broad: bool,
     },
 
     Retention {
@@ -49,6 +70,24 @@ pub enum AdminSubcommand {
 
         #[arg(long)]
         delete: Option<String>,
+
+        #[arg(long, value_name = "TIMEOUT", requires = "name")]
+        wait: Option<String>,
+
+        #[arg(short, long, value_enum, default_value = "table")]
+        format: OutputFormat,
+
+        #[arg(long)]
+        prune: bool,
+
+        #[arg(long, requires = "prune")]
+        keep: Option<usize>,
+
+        #[arg(long, value_name = "DURATION", requires = "prune")]
+        older_than: Option<String>,
+
+        #[arg(long, requires = "prune")]
+        confirm: bool,
     },
 
     Mode {
@@ -66,14 +105,40 @@ pub enum AdminSubcommand {
 impl AdminCommand {
     pub async fn execute(&self, client: &VmClient) -> Result<()> {
         match &self.command {
-            AdminSubcommand::Delete { match_, start, end, confirm } => {
-                self.delete_metrics(client, match_, start.as_deref(), end.as_deref(), *confirm).await
+            AdminSubcommand::Delete { match_, match_file, extra_matches, start, end, confirm, force_broad } => {
+                let file_matches = match match_file {
+                    Some(path) => read_match_file(path)?,
+                    None => Vec::new(),
+                };
+                let matches: Vec<&str> = match_
+                    .iter()
+                    .chain(extra_matches)
+                    .chain(&file_matches)
+                    .map(String::as_str)
+                    .collect();
+                if !force_broad {
+                    self.check_broad_delete(client, &matches, start.as_deref(), end.as_deref()).await?;
+                }
+                self.delete_metrics(client, &matches, start.as_deref(), end.as_deref(), *confirm).await
             }
             AdminSubcommand::Retention { set, show, check } => {
                 self.manage_retention(client, set.as_deref(), *show, *check).await
             }
-            AdminSubcommand::Snapshot { name, list, restore, delete } => {
-                self.manage_snapshots(client, name.as_deref(), *list, restore.as_deref(), delete.as_deref()).await
+            AdminSubcommand::Snapshot { prune: true, keep, older_than, confirm, .. } => {
+                self.prune_snapshots(client, *keep, older_than.as_deref(), *confirm).await
+            }
+            AdminSubcommand::Snapshot { list: true, format, .. } => self.list_snapshots(client, format).await,
+            AdminSubcommand::Snapshot { name, restore, delete, wait, format, .. } => {
+                let wait = wait
+                    .as_deref()
+                    .map(|value| {
+                        humantime::parse_duration(value).map_err(|_| {
+                            crate::error::VmCliError::TimeParseError(format!("Неверный таймаут --wait: {}", value))
+                        })
+                    })
+                    .transpose()?;
+                self.manage_snapshots(client, name.as_deref(), restore.as_deref(), delete.as_deref(), wait, format)
+                    .await
             }
             AdminSubcommand::Mode { readonly, maintenance, show } => {
                 self.manage_mode(client, *readonly, *maintenance, *show).await
@@ -81,19 +146,67 @@ impl AdminCommand {
         }
     }
 
+    async fn check_broad_delete(
+        &self,
+        client: &VmClient,
+        matches: &[&str],
+        start: Option<&str>,
+        end: Option<&str>,
+    ) -> Result<()> {
+        if let Some(reason) = matches.iter().find_map(|m| broad_matcher_reason(m)) {
+            return Err(crate::error::VmCliError::InvalidQuery(format!(
+                "Удаление отклонено: {}. Для подтверждения используйте --force-broad",
+                reason
+            )));
+        }
+
+        let series = client.series(matches, start, end, None).await?;
+        if series.data.len() > BROAD_DELETE_SERIES_THRESHOLD {
+            return Err(crate::error::VmCliError::InvalidQuery(format!(
+                "Удаление отклонено: под фильтр попадает {} серий (порог {}). Для подтверждения используйте --force-broad",
+                series.data.len(),
+                BROAD_DELETE_SERIES_THRESHOLD
+            )));
+        }
+
+        Ok(())
+    }
+
     async fn delete_metrics(
         &self,
         client: &VmClient,
-        match_: &str,
+        matches: &[&str],
         start: Option<&str>,
         end: Option<&str>,
         confirm: bool,
     ) -> Result<()> {
-        info!("Удаление метрик: {}", match_);
+        info!("Удаление метрик: {}", matches.join(", "));
 
         if !confirm {
+            let series_count = match client.series(matches, start, end, None).await {
+                Ok(series) => Some(series.data.len()),
+                Err(e) => {
+                    warn!("Не удалось подсчитать серии для удаления: {}", e);
+                    None
+                }
+            };
+
+            if output::is_json_mode() {
+                return output::print_data(&serde_json::json!({
+                    "matches": matches,
+                    "start": start,
+                    "end": end,
+                    "series": series_count,
+                    "confirmed": false,
+                }));
+            }
             println!("{}", "ВНИМАНИЕ: Это действие необратимо!".red().bold());
-            println!("Метрики, соответствующие фильтру '{}', будут удалены.", match_);
+            for match_ in matches {
+                println!("Метрики, соответствующие фильтру '{}', будут удалены.", match_);
+            }
+            if let Some(count) = series_count {
+                println!("Всего серий под фильтрами: {}", count);
+            }
             
             if let Some(start_time) = start {
                 println!("Начальное время: {}", start_time);
@@ -106,9 +219,21 @@ impl AdminCommand {
             return Ok(());
         }
 
+        if output::is_json_mode() {
+            client.delete_series(matches, start, end).await?;
+            if client.is_dry_run() {
+                return output::print_data(&serde_json::json!({ "dry_run": true, "would_delete": matches }));
+            }
+            return output::print_data(&serde_json::json!({ "deleted": matches }));
+        }
+
         println!("{}", "Удаление метрик...".yellow());
-        client.delete_series(match_, start, end).await?;
-        
+        client.delete_series(matches, start, end).await?;
+
+        if client.is_dry_run() {
+            println!("{} метрики под фильтрами {} были бы удалены", DRY_RUN.yellow().bold(), matches.join(", "));
+            return Ok(());
+        }
         println!("{}", "Метрики успешно удалены".green().bold());
         Ok(())
     }
@@ -120,13 +245,51 @@ impl AdminCommand {
         show: bool,
         check: bool,
     ) -> Result<()> {
+        if output::is_json_mode() {
+            return if show {
+                let info = client.get_retention_info().await?;
+                output::print_data(&RetentionView::new(&info))
+            } else if check {
+                output::print_data(&client.get_retention_info().await?)
+            } else if let Some(duration) = set {
+                client.set_retention(duration).await?;
+                if client.is_dry_run() {
+                    output::print_data(&serde_json::json!({ "dry_run": true, "would_set_retention": duration }))
+                } else {
+                    output::print_data(&serde_json::json!({ "retention": duration }))
+                }
+            } else {
+                Err(crate::error::VmCliError::InvalidQuery(
+                    "Используйте --show, --set или --check".to_string(),
+                ))
+            };
+        }
+
         if show {
             println!("{}", "Информация о retention:".bold());
             match client.get_retention_info().await {
                 Ok(info) => {
-                    println!("Текущий retention: {}", info.current_retention);
-                    println!("Используемое место: {}", info.used_space);
-                    println!("Общее место: {}", info.total_space);
+                    let view = RetentionView::new(&info);
+
+                    match view.retention_seconds {
+                        Some(seconds) => println!(
+                            "Текущий retention: {}",
+                            format_retention(std::time::Duration::from_secs(seconds))
+                        ),
+                        None => println!("Текущий retention: {}", info.current_retention),
+                    }
+
+                    match (view.used_bytes, view.total_bytes) {
+                        (Some(used), Some(total)) => {
+                            println!("Используемое место: {}", format_bytes(used));
+                            println!("Общее место: {}", format_bytes(total));
+                            println!("Заполнено: {}", format_usage_bar(used as f64, total as f64, 30));
+                        }
+                        _ => {
+                            println!("Используемое место: {}", info.used_space);
+                            println!("Общее место: {}", info.total_space);
+                        }
+                    }
                 }
                 Err(e) => {
                     println!("{}", "Ошибка получения информации о retention:".red().bold());
@@ -136,6 +299,7 @@ impl AdminCommand {
         } else if let Some(duration) = set {
             println!("{} retention на {}", "Установка:".yellow().bold(), duration);
             match client.set_retention(duration).await {
+                Ok(_) if client.is_dry_run() => println!("{} retention был бы изменен на {}", DRY_RUN.yellow().bold(), duration),
                 Ok(_) => println!("{}", "Retention успешно обновлен".green()),
                 Err(e) => {
                     println!("{}", "Ошибка установки retention:".red().bold());
@@ -168,47 +332,93 @@ impl AdminCommand {
         Ok(())
     }
 
+    async fn list_snapshots(&self, client: &VmClient, format: &OutputFormat) -> Result<()> {
+        if output::is_json_mode() {
+            return output::print_data(&client.list_snapshots().await?);
+        }
+
+        let table = *format == OutputFormat::Table;
+        if table {
+            println!("{}", "Доступные снепшоты:".bold());
+        }
+        match client.list_snapshots().await {
+            Ok(snapshots) => {
+                if snapshots.is_empty() && table {
+                    println!("Снепшоты не найдены");
+                } else {
+                    print_formatted(snapshots.as_slice(), format)?;
+                }
+            }
+            Err(e) => {
+                println!("{}", "Ошибка получения списка снепшотов:".red().bold());
+                println!("{}", e);
+            }
+        }
+
+        Ok(())
+    }
+
     async fn manage_snapshots(
         &self,
         client: &VmClient,
         name: Option<&str>,
-        list: bool,
         restore: Option<&str>,
         delete: Option<&str>,
+        wait: Option<Duration>,
+        format: &OutputFormat,
     ) -> Result<()> {
-        if list {
-            println!("{}", "Доступные снепшоты:".bold());
-            match client.list_snapshots().await {
-                Ok(snapshots) => {
-                    if snapshots.is_empty() {
-                        println!("Снепшоты не найдены");
-                    } else {
-                        println!("{:<30} {:<20} {:<10} {}", 
-                            "Имя".bold(), 
-                            "Создан".bold(), 
-                            "Размер".bold(), 
-                            "Статус".bold());
-                        println!("{}", "-".repeat(80));
-                        for snapshot in snapshots {
-                            println!("{:<30} {:<20} {:<10} {}", 
-                                snapshot.name, 
-                                snapshot.created_at, 
-                                snapshot.size, 
-                                snapshot.status);
-                        }
+        if output::is_json_mode() {
+            return if let Some(snapshot_name) = name {
+                let snapshot_id = client.create_snapshot(snapshot_name).await?;
+                if client.is_dry_run() {
+                    return output::print_data(&serde_json::json!({ "dry_run": true, "would_create": snapshot_name }));
+                }
+                match wait {
+                    Some(timeout) => {
+                        let snapshot = wait_for_snapshot(client, snapshot_name, &snapshot_id, timeout).await?;
+                        output::print_data(&serde_json::json!({
+                            "created": snapshot_name,
+                            "id": snapshot_id.trim(),
+                            "snapshot": snapshot,
+                        }))
                     }
+                    None => output::print_data(&serde_json::json!({ "created": snapshot_name, "id": snapshot_id.trim() })),
                 }
-                Err(e) => {
-                    println!("{}", "Ошибка получения списка снепшотов:".red().bold());
-                    println!("{}", e);
+            } else if let Some(snapshot_name) = restore {
+                client.restore_snapshot(snapshot_name).await?;
+                if client.is_dry_run() {
+                    output::print_data(&serde_json::json!({ "dry_run": true, "would_restore": snapshot_name }))
+                } else {
+                    output::print_data(&serde_json::json!({ "restored": snapshot_name }))
                 }
-            }
-        } else if let Some(snapshot_name) = name {
+            } else if let Some(snapshot_name) = delete {
+                client.delete_snapshot(snapshot_name).await?;
+                if client.is_dry_run() {
+                    output::print_data(&serde_json::json!({ "dry_run": true, "would_delete": snapshot_name }))
+                } else {
+                    output::print_data(&serde_json::json!({ "deleted": snapshot_name }))
+                }
+            } else {
+                Err(crate::error::VmCliError::InvalidQuery(
+                    "Используйте --list, --name, --restore или --delete".to_string(),
+                ))
+            };
+        }
+
+        if let Some(snapshot_name) = name {
             println!("{} снепшота: {}", "Создание:".yellow().bold(), snapshot_name);
             match client.create_snapshot(snapshot_name).await {
+                Ok(_) if client.is_dry_run() => {
+                    println!("{} снепшот {} был бы создан", DRY_RUN.yellow().bold(), snapshot_name)
+                }
                 Ok(snapshot_id) => {
                     println!("{}", "Снепшот успешно создан".green());
                     println!("ID снепшота: {}", snapshot_id);
+                    if let Some(timeout) = wait {
+                        println!("Ожидание появления снепшота в списке (таймаут {})...", humantime::format_duration(timeout));
+                        let snapshot = wait_for_snapshot(client, snapshot_name, &snapshot_id, timeout).await?;
+                        print_formatted(std::slice::from_ref(&snapshot), format)?;
+                    }
                 }
                 Err(e) => {
                     println!("{}", "Ошибка создания снепшота:".red().bold());
@@ -218,6 +428,9 @@ impl AdminCommand {
         } else if let Some(snapshot_name) = restore {
             println!("{} снепшота: {}", "Восстановление:".yellow().bold(), snapshot_name);
             match client.restore_snapshot(snapshot_name).await {
+                Ok(_) if client.is_dry_run() => {
+                    println!("{} снепшот {} был бы восстановлен", DRY_RUN.yellow().bold(), snapshot_name)
+                }
                 Ok(_) => println!("{}", "Снепшот успешно восстановлен".green()),
                 Err(e) => {
                     println!("{}", "Ошибка восстановления снепшота:".red().bold());
@@ -227,6 +440,9 @@ impl AdminCommand {
         } else if let Some(snapshot_name) = delete {
             println!("{} снепшота: {}", "Удаление:".yellow().bold(), snapshot_name);
             match client.delete_snapshot(snapshot_name).await {
+                Ok(_) if client.is_dry_run() => {
+                    println!("{} снепшот {} был бы удален", DRY_RUN.yellow().bold(), snapshot_name)
+                }
                 Ok(_) => println!("{}", "Снепшот успешно удален".green()),
                 Err(e) => {
                     println!("{}", "Ошибка удаления снепшота:".red().bold());
@@ -240,6 +456,80 @@ impl AdminCommand {
         Ok(())
     }
 
+    async fn prune_snapshots(
+        &self,
+        client: &VmClient,
+        keep: Option<usize>,
+        older_than: Option<&str>,
+        confirm: bool,
+    ) -> Result<()> {
+        if keep.is_none() && older_than.is_none() {
+            return Err(crate::error::VmCliError::InvalidQuery(
+                "Для --prune укажите --keep и/или --older-than".to_string(),
+            ));
+        }
+
+        let max_age = older_than
+            .map(|value| {
+                humantime::parse_duration(value).map_err(|e| {
+                    crate::error::VmCliError::TimeParseError(format!("{}: {}", value, e))
+                })
+            })
+            .transpose()?;
+
+        let snapshots = client.list_snapshots().await?;
+        let selected = select_snapshots_to_prune(&snapshots, keep, max_age, chrono::Utc::now());
+        let names: Vec<&str> = selected.iter().map(|s| s.name.as_str()).collect();
+
+        if !confirm {
+            if output::is_json_mode() {
+                return output::print_data(&serde_json::json!({
+                    "candidates": names,
+                    "confirmed": false,
+                }));
+            }
+            if names.is_empty() {
+                println!("Нет снепшотов для удаления");
+                return Ok(());
+            }
+            println!("{}", "Будут удалены снепшоты:".yellow().bold());
+            for snapshot in &selected {
+                println!("  {} ({})", snapshot.name, snapshot.created_at);
+            }
+            println!("Для подтверждения используйте флаг --confirm");
+            return Ok(());
+        }
+
+        for name in &names {
+            client.delete_snapshot(name).await?;
+            if !output::is_json_mode() {
+                if client.is_dry_run() {
+                    println!("{} был бы удален: {}", DRY_RUN.yellow().bold(), name);
+                } else {
+                    println!("{} {}", "Удален:".green(), name);
+                }
+            }
+        }
+
+        if output::is_json_mode() {
+            if client.is_dry_run() {
+                return output::print_data(&serde_json::json!({ "dry_run": true, "would_delete": names }));
+            }
+            return output::print_data(&serde_json::json!({ "deleted": names }));
+        }
+        if client.is_dry_run() {
+            return Ok(());
+        }
+
+        println!(
+            "{} {} из {}",
+            "Удалено снепшотов:".green().bold(),
+            names.len(),
+            snapshots.len()
+        );
+        Ok(())
+    }
+
     async fn manage_mode(
         &self,
         client: &VmClient,
@@ -247,19 +537,31 @@ impl AdminCommand {
         maintenance: bool,
         show: bool,
     ) -> Result<()> {
+        if output::is_json_mode() {
+            return if show {
+                let flags = client.get_flags(client.base_url()).await?;
+                let build_info = client.get_build_info().await?;
+                output::print_data(&serde_json::json!({ "flags": flags, "build_info": build_info }))
+            } else if readonly {
+                output::print_data(&serde_json::json!({ "mode": "readonly", "hint": "./victoria-metrics -readonly" }))
+            } else if maintenance {
+                output::print_data(&serde_json::json!({ "mode": "maintenance", "hint": "./victoria-metrics -maintenance" }))
+            } else {
+                Err(crate::error::VmCliError::InvalidQuery(
+                    "Используйте --show, --readonly или --maintenance".to_string(),
+                ))
+            };
+        }
+
         if show {
             println!("{}", "Информация о VictoriaMetrics:".bold());
             println!();
             
             println!("{}", "Флаги запуска:".bold());
-            match client.get_flags().await {
-                Ok(flags_data) => {
-                    if let Some(flags_obj) = flags_data.as_object() {
-                        for (key, value) in flags_obj {
-                            println!("  {}: {}", key, value);
-                        }
-                    } else {
-                        println!("  {}", flags_data);
+            match client.get_flags(client.base_url()).await {
+                Ok(flags) => {
+                    for (key, value) in &flags {
+                        println!("  {}: {}", key, value);
                     }
                 }
                 Err(e) => {
@@ -302,3 +604,371 @@ impl AdminCommand {
         Ok(())
     }
 }
+
+#[derive(Serialize)]
+struct RetentionView<'a> {
+    #[serde(flatten)]
+    info: &'a RetentionInfo,
+    retention_seconds: Option<u64>,
+    used_bytes: Option<u64>,
+    total_bytes: Option<u64>,
+}
+
+impl<'a> RetentionView<'a> {
+    fn new(info: &'a RetentionInfo) -> Self {
+        Self {
+            info,
+            retention_seconds: parse_retention(&info.current_retention).map(|d| d.as_secs()),
+            used_bytes: parse_bytes(&info.used_space),
+            total_bytes: parse_bytes(&info.total_space),
+        }
+    }
+}
+
+impl Formatter for [SnapshotInfo] {
+    fn headers(&self) -> Vec<String> {
+        ["name", "created_at", "size", "status"].map(String::from).into()
+    }
+
+    fn rows(&self) -> Vec<Vec<String>> {
+        self.iter()
+            .map(|snapshot| {
+                vec![
+                    snapshot.name.clone(),
+                    snapshot.created_at.clone(),
+                    snapshot.size.clone(),
+                    snapshot.status.clone(),
+                ]
+            })
+            .collect()
+    }
+}
+
+async fn wait_for_snapshot(client: &VmClient, requested: &str, response: &str, timeout: Duration) -> Result<SnapshotInfo> {
+    let created = created_snapshot_name(response);
+    let deadline = Instant::now() + timeout;
+    let mut attempt = 0u64;
+
+    loop {
+        attempt += 1;
+        let found = client
+            .list_snapshots()
+            .await?
+            .into_iter()
+            .find(|snapshot| snapshot.name == requested || created.as_deref() == Some(snapshot.name.as_str()));
+        if let Some(snapshot) = found {
+            return Ok(snapshot);
+        }
+
+        let remaining = deadline.saturating_duration_since(Instant::now());
+        if remaining.is_zero() {
+            warn!("Снепшот {} не появился в списке после {} попыток", requested, attempt);
+            return Err(crate::error::VmCliError::Timeout);
+        }
+        info!("Снепшот {} еще не в списке, попытка {}", requested, attempt);
+        tokio::select! {
+            _ = tokio::time::sleep(SNAPSHOT_POLL_INTERVAL.min(remaining)) => {}
+            _ = client.cancelled() => return Err(crate::error::VmCliError::Cancelled),
+        }
+    }
+}
+
+fn created_snapshot_name(response: &str) -> Option<String> {
+    let response = response.trim();
+    match serde_json::from_str::<serde_json::Value>(response) {
+        Ok(value) => value.get("snapshot").and_then(|s| s.as_str()).map(str::to_string),
+        Err(_) => Some(response.to_string()).filter(|name| !name.is_empty()),
+    }
+}
+
+fn read_match_file(path: &str) -> Result<Vec<String>> {
+    if !std::path::Path::new(path).exists() {
+        return Err(crate::error::VmCliError::FileNotFound(path.to_string()));
+    }
+
+    let matches: Vec<String> = std::fs::read_to_string(path)?
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(str::to_string)
+        .collect();
+
+    if matches.is_empty() {
+        return Err(crate::error::VmCliError::InvalidQuery(format!(
+            "Файл {} не содержит селекторов",
+            path
+        )));
+    }
+    Ok(matches)
+}
+
+fn select_snapshots_to_prune(
+    snapshots: &[SnapshotInfo],
+    keep: Option<usize>,
+    max_age: Option<std::time::Duration>,
+    now: chrono::DateTime<chrono::Utc>,
+) -> Vec<&SnapshotInfo> {
+    let mut sorted: Vec<(&SnapshotInfo, Option<chrono::DateTime<chrono::Utc>>)> = snapshots
+        .iter()
+        .map(|s| {
+            let created = parse_snapshot_time(&s.created_at)
+                .or_else(|| s.name.get(..14).and_then(parse_snapshot_time));
+            (s, created)
+        })
+        .collect();
+    sorted.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| b.0.created_at.cmp(&a.0.created_at)));
+
+    let cutoff = max_age
+        .and_then(|age| chrono::Duration::from_std(age).ok())
+        .map(|age| now - age);
+
+    sorted
+        .into_iter()
+        .enumerate()
+        .filter(|(index, _)| keep.is_none_or(|keep| *index >= keep))
+        .filter(|(_, (_, created))| match cutoff {
+            Some(cutoff) => created.is_some_and(|created| created < cutoff),
+            None => true,
+        })
+        .map(|(_, (snapshot, _))| snapshot)
+        .collect()
+}
+
+fn parse_snapshot_time(value: &str) -> Option<chrono::DateTime<chrono::Utc>> {
+    if let Ok(dt) = chrono::DateTime::parse_from_rfc3339(value) {
+        return Some(dt.with_timezone(&chrono::Utc));
+    }
+    if let Ok(dt) = chrono::NaiveDateTime::parse_from_str(value, "%Y-%m-%d %H:%M:%S") {
+        return Some(dt.and_utc());
+    }
+    if let Ok(dt) = chrono::NaiveDateTime::parse_from_str(value, "%Y%m%d%H%M%S") {
+        return Some(dt.and_utc());
+    }
+    value
+        .parse::<i64>()
+        .ok()
+        .and_then(|ts| chrono::DateTime::from_timestamp(ts, 0))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{assert_consistent_formats, MockServer};
+
+    fn admin_command(args: &[&str]) -> AdminCommand {
+        AdminCommand::try_parse_from(std::iter::once("admin").chain(args.iter().copied())).unwrap()
+    }
+
+    async fn run_dry(args: &[&str]) -> MockServer {
+        let server = MockServer::start(vec![(200, "{}")]);
+        let client = VmClient::new(&server.url, 5, None, None, None).unwrap().with_dry_run(true);
+        admin_command(args).execute(&client).await.unwrap();
+        server
+    }
+
+    #[tokio::test]
+    async fn dry_run_writes_are_not_sent() {
+        for args in [
+            &["delete", "old_metric", "--confirm", "--force-broad"][..],
+            &["retention", "--set", "30d"],
+            &["snapshot", "--restore", "snap"],
+            &["snapshot", "--delete", "snap"],
+        ] {
+            let server = run_dry(args).await;
+            assert!(server.requests().is_empty(), "{:?} sent {:?}", args, server.requests());
+        }
+    }
+
+    #[tokio::test]
+    async fn dry_run_snapshot_does_not_wait_for_missing_snapshot() {
+        let server = run_dry(&["snapshot", "--name", "nightly", "--wait", "1m"]).await;
+        assert!(server.requests().is_empty());
+    }
+
+    #[test]
+    fn snapshot_list_renders_consistently_across_formats() {
+        let snapshots = vec![SnapshotInfo {
+            name: "20240115, nightly".to_string(),
+            created_at: "2024-01-15 10:00:00".to_string(),
+            size: "1.2 GB".to_string(),
+            status: "ready".to_string(),
+        }];
+        assert_consistent_formats(snapshots.as_slice());
+    }
+
+    #[tokio::test]
+    async fn broad_delete_is_refused_before_any_request() {
+        let server = MockServer::start(vec![(204, "")]);
+        let client = VmClient::new(&server.url, 5, None, None, None).unwrap();
+
+        let result = admin_command(&["delete", "{__name__=~\".+\"}", "--confirm"]).execute(&client).await;
+
+        match result {
+            Err(crate::error::VmCliError::InvalidQuery(message)) => assert!(message.contains("--force-broad"), "{}", message),
+            other => panic!("unexpected result: {:?}", other),
+        }
+        assert!(server.requests().is_empty());
+    }
+
+    #[tokio::test]
+    async fn narrow_delete_checks_series_count_and_proceeds() {
+        let server = MockServer::start(vec![(200, r#"{"status":"success","data":[{"__name__":"old_metric"}]}"#), (204, "")]);
+        let client = VmClient::new(&server.url, 5, None, None, None).unwrap();
+
+        admin_command(&["delete", "old_metric", "--confirm"]).execute(&client).await.unwrap();
+
+        let requests = server.requests();
+        assert_eq!(requests[0].path(), "/api/v1/series");
+        assert_eq!(requests[1].path(), "/api/v1/admin/tsdb/delete_series");
+        assert_eq!(requests[1].param("match[]"), ["old_metric"]);
+    }
+
+    #[tokio::test]
+    async fn delete_matching_too_many_series_is_refused() {
+        let series = vec![r#"{"__name__":"up"}"#; BROAD_DELETE_SERIES_THRESHOLD + 1].join(",");
+        let body = format!(r#"{{"status":"success","data":[{}]}}"#, series);
+        let server = MockServer::start(vec![(200, &body)]);
+        let client = VmClient::new(&server.url, 5, None, None, None).unwrap();
+
+        let result = admin_command(&["delete", "up", "--confirm"]).execute(&client).await;
+
+        assert!(matches!(result, Err(crate::error::VmCliError::InvalidQuery(_))));
+        assert_eq!(server.requests().len(), 1);
+    }
+
+    fn snapshot(name: &str, created_at: &str) -> SnapshotInfo {
+        SnapshotInfo { name: name.to_string(), created_at: created_at.to_string(), size: String::new(), status: String::new() }
+    }
+
+    fn snapshot_set() -> Vec<SnapshotInfo> {
+        vec![
+            snapshot("20240103000000-a", "2024-01-03T00:00:00Z"),
+            snapshot("20240101000000-b", "2024-01-01 00:00:00"),
+            snapshot("20240105000000-c", "1704412800"),
+            snapshot("20240102000000-d", ""),
+        ]
+    }
+
+    fn pruned(keep: Option<usize>, max_age: Option<std::time::Duration>) -> Vec<String> {
+        let now = chrono::DateTime::parse_from_rfc3339("2024-01-06T00:00:00Z").unwrap().with_timezone(&chrono::Utc);
+        select_snapshots_to_prune(&snapshot_set(), keep, max_age, now).into_iter().map(|s| s.name.clone()).collect()
+    }
+
+    #[test]
+    fn prune_keeps_newest_snapshots() {
+        assert_eq!(pruned(Some(2), None), ["20240102000000-d", "20240101000000-b"]);
+        assert!(pruned(Some(10), None).is_empty());
+        assert_eq!(pruned(Some(0), None).len(), 4);
+    }
+
+    #[test]
+    fn prune_selects_snapshots_older_than_age() {
+        let three_days = std::time::Duration::from_secs(3 * 86400);
+
+        assert_eq!(pruned(None, Some(three_days)), ["20240102000000-d", "20240101000000-b"]);
+        assert_eq!(pruned(Some(3), Some(three_days)), ["20240101000000-b"]);
+    }
+
+    fn match_file(content: &str) -> (tempfile::TempDir, String) {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("matches.txt");
+        std::fs::write(&path, content).unwrap();
+        (dir, path.to_string_lossy().into_owned())
+    }
+
+    #[test]
+    fn match_file_skips_blank_and_comment_lines() {
+        let (_dir, path) = match_file("# decommissioned\nold_metric\n\n  legacy_total{job=\"x\"}  \n# end\n");
+
+        assert_eq!(read_match_file(&path).unwrap(), ["old_metric", "legacy_total{job=\"x\"}"]);
+
+        let (_dir, empty) = match_file("# nothing\n\n");
+        assert!(matches!(read_match_file(&empty), Err(crate::error::VmCliError::InvalidQuery(_))));
+        assert!(matches!(read_match_file("/nonexistent/matches.txt"), Err(crate::error::VmCliError::FileNotFound(_))));
+    }
+
+    #[tokio::test]
+    async fn match_file_selectors_are_sent_in_one_delete_request() {
+        let (_dir, path) = match_file("old_metric\n# skip\nlegacy_total\n");
+        let server = MockServer::start(vec![(200, r#"{"status":"success","data":[]}"#), (204, "")]);
+        let client = VmClient::new(&server.url, 5, None, None, None).unwrap();
+
+        admin_command(&["delete", "first_metric", "--match-file", &path, "--confirm"]).execute(&client).await.unwrap();
+
+        let requests = server.requests();
+        assert_eq!(requests.len(), 2);
+        assert_eq!(requests[0].param("match[]"), ["first_metric", "old_metric", "legacy_total"]);
+        assert_eq!(requests[1].path(), "/api/v1/admin/tsdb/delete_series");
+        assert_eq!(requests[1].param("match[]"), ["first_metric", "old_metric", "legacy_total"]);
+    }
+
+    #[tokio::test]
+    async fn match_file_preview_counts_series_across_all_selectors() {
+        let (_dir, path) = match_file("old_metric\nlegacy_total\n");
+        let server = MockServer::start(vec![(200, r#"{"status":"success","data":[{"__name__":"old_metric"},{"__name__":"legacy_total"}]}"#)]);
+        let client = VmClient::new(&server.url, 5, None, None, None).unwrap();
+
+        admin_command(&["delete", "--match-file", &path]).execute(&client).await.unwrap();
+
+        let requests = server.requests();
+        assert!(requests.iter().all(|r| r.path() == "/api/v1/series"));
+        assert_eq!(requests.last().unwrap().param("match[]"), ["old_metric", "legacy_total"]);
+    }
+
+    #[tokio::test]
+    async fn wait_for_snapshot_polls_until_it_is_listed() {
+        let server = MockServer::start(vec![
+            (200, r#"{"status":"ok","snapshots":[]}"#),
+            (200, r#"{"status":"ok","snapshots":[{"name":"20240101000000-17A1B2","created_at":"2024-01-01T00:00:00Z"}]}"#),
+        ]);
+        let client = VmClient::new(&server.url, 5, None, None, None).unwrap();
+
+        let snapshot = wait_for_snapshot(&client, "nightly", r#"{"status":"ok","snapshot":"20240101000000-17A1B2"}"#, Duration::from_secs(10))
+            .await
+            .unwrap();
+
+        assert_eq!(snapshot.name, "20240101000000-17A1B2");
+        let requests = server.requests();
+        assert_eq!(requests.len(), 2);
+        assert!(requests.iter().all(|r| r.path() == "/snapshot/list"));
+    }
+
+    #[tokio::test]
+    async fn wait_for_snapshot_times_out_when_never_listed() {
+        let server = MockServer::start(vec![(200, r#"{"status":"ok","snapshots":[{"name":"other"}]}"#)]);
+        let client = VmClient::new(&server.url, 5, None, None, None).unwrap();
+
+        let result = wait_for_snapshot(&client, "nightly", "nightly", Duration::ZERO).await;
+
+        assert!(matches!(result, Err(crate::error::VmCliError::Timeout)));
+        assert_eq!(server.requests().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn wait_for_snapshot_stops_polling_when_cancelled() {
+        let server = MockServer::start(vec![(200, r#"{"status":"ok","snapshots":[]}"#)]);
+        let cancel = tokio_util::sync::CancellationToken::new();
+        let client = VmClient::new(&server.url, 5, None, None, None).unwrap().with_cancellation(cancel.clone());
+
+        let canceller = async {
+            tokio::time::sleep(Duration::from_millis(100)).await;
+            cancel.cancel();
+        };
+        let wait = tokio::time::timeout(
+            SNAPSHOT_POLL_INTERVAL / 2,
+            wait_for_snapshot(&client, "nightly", "nightly", Duration::from_secs(60)),
+        );
+        let (result, ()) = tokio::join!(wait, canceller);
+
+        assert!(matches!(result.expect("ожидание должно прерываться сразу"), Err(crate::error::VmCliError::Cancelled)));
+        assert_eq!(server.requests().len(), 1);
+    }
+
+    #[test]
+    fn created_snapshot_name_reads_json_or_plain_response() {
+        assert_eq!(created_snapshot_name(r#"{"status":"ok","snapshot":"snap-1"}"#), Some("snap-1".to_string()));
+        assert_eq!(created_snapshot_name(" snap-2\n"), Some("snap-2".to_string()));
+        assert_eq!(created_snapshot_name(r#"{"status":"ok"}"#), None);
+        assert_eq!(created_snapshot_name(""), None);
+    }
+}
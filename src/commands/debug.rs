@@ -1,8 +1,21 @@
-use crate::api::VmClient;
+use crate::api::{QueryResponse, SeriesResponse, SlowQueryRanking, TsdbStatEntry, TsdbStatus, VmClient};
+use crate::config::OutputFormat;
 use crate::error::Result;
+use crate::messages::{self, Msg};
+use crate::output;
+use crate::utils::{colorize_value, format_bytes, parse_time_range, print_formatted, Formatter};
 use clap::{Parser, Subcommand};
 use colored::*;
+use futures::stream::{self, StreamExt};
+use regex::Regex;
+use serde::Serialize;
+use tabled::{Table, Tabled};
+use std::collections::{BTreeMap, BTreeSet};
+use std::future::Future;
+use std::net::SocketAddr;
+use std::time::{Duration, Instant};
 
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
 
 #[derive(Parser)]
 pub struct DebugCommand {
@@ -17,6 +30,15 @@ pub enum DebugSubcommand {
         top: usize,
         #[arg(short, long, default_value = "1h")]
         range: String,
+
+        #[arg(long, default_value = "1.0")]
+        warn: f64,
+
+        #[arg(long, default_value = "2.0")]
+        crit: f64,
+
+        #[arg(long, value_enum, default_value = "avg-duration")]
+        by: SlowQueryRanking,
     },
 
     Gaps {
@@ -43,18 +65,132 @@ pub enum DebugSubcommand {
 
         #[arg(short, long, default_value = "up")]
         query: String,
+
+        #[arg(long, default_value = "0")]
+        warmup: usize,
+
+        #[arg(long, default_value = "1", value_parser = clap::value_parser!(u16).range(1..))]
+        concurrency: u16,
+    },
+
+    Bench {
+        #[arg(short, long, default_value = "up")]
+        query: String,
+
+        #[arg(
+            long,
+            value_name = "N,...",
+            value_delimiter = ',',
+            default_value = "1,2,4,8",
+            value_parser = clap::value_parser!(u16).range(1..)
+        )]
+        concurrency_levels: Vec<u16>,
+
+        #[arg(long, default_value = "10s", value_parser = humantime::parse_duration)]
+        duration: Duration,
     },
 
     Metrics {
         #[arg(value_name = "PATTERN")]
         pattern: Option<String>,
 
+        #[arg(long, requires = "pattern")]
+        regex: bool,
+
+        #[arg(long = "match", value_name = "SELECTOR", conflicts_with_all = ["pattern", "stats"])]
+        selector: Option<String>,
+
         #[arg(long)]
         stats: bool,
 
+        #[arg(long, value_name = "N", requires = "selector")]
+        limit: Option<usize>,
+
+        #[arg(short, long, requires = "selector")]
+        range: Option<String>,
+
+        #[arg(long, value_name = "DURATION", requires = "range", value_parser = humantime::parse_duration)]
+        page_window: Option<Duration>,
+
         #[arg(short, long)]
         export: Option<String>,
+
+        #[arg(long, value_enum, default_value = "txt")]
+        export_format: MetricsExportFormat,
     },
+
+    Relabel {
+        #[arg(short, long)]
+        metric: String,
+
+        #[arg(short, long)]
+        config_file: String,
+    },
+
+    Connect,
+
+    Tsdb {
+        #[arg(short, long, default_value = "10", value_parser = clap::value_parser!(u64).range(1..))]
+        top: u64,
+
+        #[arg(long, value_name = "LABEL")]
+        focus_label: Option<String>,
+
+        #[arg(long, value_name = "YYYY-MM-DD")]
+        date: Option<String>,
+
+        #[arg(short, long, value_enum)]
+        format: Option<OutputFormat>,
+    },
+
+    Stats {
+        #[arg(long, default_value = "1s")]
+        interval: String,
+
+        #[arg(short, long, value_enum, default_value = "text")]
+        format: StatsFormat,
+    },
+}
+
+#[derive(clap::ValueEnum, Clone, Copy, PartialEq, Eq)]
+pub enum StatsFormat {
+    Text,
+    Json,
+}
+
+#[derive(Serialize, Default)]
+struct InstanceStats {
+    series: Option<u64>,
+    ingestion_rate: Option<f64>,
+    query_rate: Option<f64>,
+    memory_bytes: Option<u64>,
+    disk_bytes: Option<u64>,
+}
+
+#[derive(Serialize, Tabled)]
+struct BenchLevel {
+    concurrency: usize,
+    requests: usize,
+    errors: usize,
+    #[tabled(display_with = "display_float")]
+    qps: f64,
+    #[tabled(display_with = "display_ms")]
+    avg_ms: Option<f64>,
+    #[tabled(display_with = "display_ms")]
+    p50_ms: Option<f64>,
+    #[tabled(display_with = "display_ms")]
+    p90_ms: Option<f64>,
+    #[tabled(display_with = "display_ms")]
+    p99_ms: Option<f64>,
+    #[tabled(display_with = "display_ms")]
+    max_ms: Option<f64>,
+}
+
+#[derive(clap::ValueEnum, Clone, Copy)]
+pub enum MetricsExportFormat {
+    Txt,
+    Json,
+    Csv,
 }
 
 #[derive(clap::ValueEnum, Clone)]
@@ -67,8 +203,8 @@ pub enum MemorySort {
 impl DebugCommand {
     pub async fn execute(&self, client: &VmClient) -> Result<()> {
         match &self.command {
-            DebugSubcommand::SlowQueries { top, range } => {
-                self.analyze_slow_queries(client, *top, range).await
+            DebugSubcommand::SlowQueries { top, range, warn, crit, by } => {
+                self.analyze_slow_queries(client, *top, range, *warn, *crit, *by).await
             }
             DebugSubcommand::Gaps { metric, range, min_gap } => {
                 self.find_data_gaps(client, metric, range, *min_gap).await
@@ -76,12 +212,39 @@ impl DebugCommand {
             DebugSubcommand::Memory { verbose, sort } => {
                 self.analyze_memory_usage(client, *verbose, sort).await
             }
-            DebugSubcommand::Performance { count, query } => {
-                self.test_performance(client, *count, query).await
+            DebugSubcommand::Performance { count, query, warmup, concurrency } => {
+                self.test_performance(client, *count, query, *warmup, *concurrency as usize).await
             }
-            DebugSubcommand::Metrics { pattern, stats, export } => {
-                self.analyze_metrics(client, pattern.as_deref(), *stats, export.as_deref()).await
+            DebugSubcommand::Bench { query, concurrency_levels, duration } => {
+                self.bench(client, query, concurrency_levels, *duration).await
             }
+            DebugSubcommand::Metrics {
+                selector: Some(selector),
+                limit,
+                range,
+                page_window,
+                export,
+                export_format,
+                ..
+            } => {
+                let window = SeriesWindow { range: range.as_deref(), page_window: *page_window };
+                self.analyze_series(client, selector, *limit, window, export.as_deref(), *export_format)
+                    .await
+            }
+            DebugSubcommand::Metrics { pattern, regex, stats, export, export_format, .. } => {
+                let filter = MetricFilter::new(pattern.as_deref(), *regex)?;
+                self.analyze_metrics(client, pattern.as_deref(), &filter, *stats, export.as_deref(), *export_format)
+                    .await
+            }
+            DebugSubcommand::Relabel { metric, config_file } => {
+                self.debug_relabel(client, metric, config_file).await
+            }
+            DebugSubcommand::Connect => self.debug_connect(client).await,
+            DebugSubcommand::Tsdb { top, focus_label, date, format } => {
+                self.tsdb_status(client, *top as usize, focus_label.as_deref(), date.as_deref(), format.as_ref())
+                    .await
+            }
+            DebugSubcommand::Stats { interval, format } => self.instance_stats(client, interval, *format).await,
         }
     }
 
@@ -90,34 +253,44 @@ impl DebugCommand {
         client: &VmClient,
         top: usize,
         range: &str,
+        warn: f64,
+        crit: f64,
+        by: SlowQueryRanking,
     ) -> Result<()> {
+        if output::is_json_mode() {
+            let slow_queries = client.get_slow_queries(top, range, by).await?;
+            let top_queries: Vec<_> = slow_queries.iter().take(top).collect();
+            return output::print_data(&top_queries);
+        }
+
         println!("{}", "Анализ медленных запросов:".bold());
         println!("Диапазон: {}", range);
         println!();
 
-        match client.get_slow_queries().await {
+        match client.get_slow_queries(top, range, by).await {
             Ok(slow_queries) => {
                 if slow_queries.is_empty() {
                     println!("{}", "Медленные запросы не обнаружены".green());
                     return Ok(());
                 }
 
-                println!("{:<30} {:<10} {}", "Тип проблемы", "Время (с)", "Причина");
+                let duration_header = match by {
+                    SlowQueryRanking::SumDuration => "Всего (с)",
+                    SlowQueryRanking::AvgDuration | SlowQueryRanking::Count => "Среднее (с)",
+                };
+                println!("{:<12} {:<10} Запрос", duration_header, "Запросов");
                 println!("{:-<60}", "");
 
                 for query_info in slow_queries.iter().take(top) {
-                    let time_color = if query_info.duration > 2.0 {
-                        format!("{:.2}", query_info.duration).red()
-                    } else if query_info.duration > 1.0 {
-                        format!("{:.2}", query_info.duration).yellow()
-                    } else {
-                        format!("{:.2}", query_info.duration).green()
+                    let time_color = match query_info.duration {
+                        Some(duration) => colorize_value(&format!("{:.3}", duration), Some(warn), Some(crit)),
+                        None => "-".dimmed().to_string(),
                     };
 
-                    println!("{:<30} {:<10} {}", 
-                        query_info.query, 
+                    println!("{:<12} {:<10} {}", 
                         time_color, 
-                        query_info.reason);
+                        query_info.count, 
+                        query_info.query);
                 }
             }
             Err(e) => {
@@ -136,76 +309,48 @@ impl DebugCommand {
         range: &str,
         min_gap: u64,
     ) -> Result<()> {
-        println!("{}", "Поиск пропусков в данных:".bold());
-        println!("Метрика: {}", metric);
-        println!("Диапазон: {}", range);
-        println!("Минимальный пропуск: {} секунд", min_gap);
-        println!();
+        let json_mode = output::is_json_mode();
+
+        if !json_mode {
+            println!("{}", "Поиск пропусков в данных:".bold());
+            println!("Метрика: {}", metric);
+            println!("Диапазон: {}", range);
+            println!("Минимальный пропуск: {} секунд", min_gap);
+            println!();
+        }
 
         let query = format!("count({})", metric);
         let response = client.query(&query, None).await?;
 
         if response.data.result.is_empty() {
+            if json_mode {
+                return output::print_data(&Vec::<DataGap>::new());
+            }
             println!("{}", "Метрика не найдена".yellow());
             return Ok(());
         }
 
-        println!("{:<20} {:<20} {:<15} {}", "Начало", "Конец", "Длительность", "Статус");
-        println!("{:-<70}", "");
-
         let step = "60s";
         let end_time = chrono::Utc::now();
         let start_time = end_time - chrono::Duration::hours(24);
         
         let start_str = start_time.timestamp().to_string();
         let end_str = end_time.timestamp().to_string();
-        
-        let range_query = format!("{}", metric);
-        match client.query_range(&range_query, &start_str, &end_str, step).await {
+
+        if json_mode {
+            let range_response = client.query_range(metric, &start_str, &end_str, step).await?;
+            return output::print_data(&collect_gaps(&range_response, min_gap));
+        }
+
+        println!("{:<20} {:<20} {:<15} Статус", "Начало", "Конец", "Длительность");
+        println!("{:-<70}", "");
+
+        match client.query_range(metric, &start_str, &end_str, step).await {
             Ok(range_response) => {
-                let mut gaps = Vec::new();
-                
-                for result in &range_response.data.result {
-                    if let Some(values) = &result.values {
-                        if values.len() > 1 {
-                            for i in 1..values.len() {
-                                let prev_time = values[i-1].0;
-                                let curr_time = values[i].0;
-                                let gap_duration = curr_time - prev_time;
-                                
-                                if gap_duration > min_gap as f64 {
-                                    let start_dt = chrono::DateTime::from_timestamp(prev_time as i64, 0)
-                                        .unwrap_or_default()
-                                        .format("%Y-%m-%d %H:%M:%S")
-                                        .to_string();
-                                    let end_dt = chrono::DateTime::from_timestamp(curr_time as i64, 0)
-                                        .unwrap_or_default()
-                                        .format("%Y-%m-%d %H:%M:%S")
-                                        .to_string();
-                                    
-                                    let duration_str = if gap_duration > 3600.0 {
-                                        format!("{:.0}h", gap_duration / 3600.0)
-                                    } else if gap_duration > 60.0 {
-                                        format!("{:.0}m", gap_duration / 60.0)
-                                    } else {
-                                        format!("{:.0}s", gap_duration)
-                                    };
-                                    
-                                    gaps.push((start_dt, end_dt, duration_str, "Найден".to_string()));
-                                }
-                            }
-                        }
-                    }
-                }
-                
-                for (start, end, duration, status) in &gaps {
-                    let status_color = if *status == "Найден" {
-                        status.red()
-                    } else {
-                        status.green()
-                    };
+                let gaps = collect_gaps(&range_response, min_gap);
 
-                    println!("{:<20} {:<20} {:<15} {}", start, end, duration, status_color);
+                for gap in &gaps {
+                    println!("{:<20} {:<20} {:<15} {}", gap.start, gap.end, gap.duration, "Найден".red());
                 }
 
                 if gaps.is_empty() {
@@ -227,6 +372,19 @@ impl DebugCommand {
         verbose: bool,
         _sort: &MemorySort,
     ) -> Result<()> {
+        if output::is_json_mode() {
+            let metrics = client.get_metrics_info().await?;
+            let memory: serde_json::Map<String, serde_json::Value> = [
+                "process_resident_memory_bytes",
+                "vm_cache_size_bytes",
+                "go_memstats_heap_alloc_bytes",
+            ]
+            .iter()
+            .filter_map(|name| metrics.get(*name).map(|v| (name.to_string(), v.clone())))
+            .collect();
+            return output::print_data(&memory);
+        }
+
         println!("{}", "Анализ использования памяти:".bold());
         println!();
 
@@ -299,41 +457,240 @@ impl DebugCommand {
         client: &VmClient,
         count: usize,
         query: &str,
+        warmup: usize,
+        concurrency: usize,
     ) -> Result<()> {
-        println!("{}", "Тестирование производительности:".bold());
-        println!("Запрос: {}", query);
-        println!("Количество тестов: {}", count);
-        println!();
+        let json_mode = output::is_json_mode();
+
+        if !json_mode {
+            println!("{}", "Тестирование производительности:".bold());
+            println!("Запрос: {}", query);
+            println!("Количество тестов: {}", count);
+            if warmup > 0 {
+                println!("Прогрев: {} запросов (не учитываются)", warmup);
+            }
+            if concurrency > 1 {
+                println!("Параллельность: {}", concurrency);
+            }
+            println!();
+        }
+
+        for _ in 0..warmup {
+            let _ = client.query(query, None).await;
+        }
 
         let mut times = Vec::new();
+        let mut errors = 0;
 
-        for i in 1..=count {
-            let start = std::time::Instant::now();
-            
-            match client.query(query, None).await {
-                Ok(_) => {
-                    let duration = start.elapsed();
+        let started = Instant::now();
+        let mut results = stream::iter(1..=count)
+            .map(|i| async move {
+                let start = Instant::now();
+                (i, client.query(query, None).await.map(|_| start.elapsed()))
+            })
+            .buffer_unordered(concurrency);
+
+        while let Some((i, result)) = results.next().await {
+            match result {
+                Ok(duration) => {
                     times.push(duration);
-                    println!("Итерация {}: {:?}", i, duration);
+                    if !json_mode {
+                        println!("Итерация {}: {:?}", i, duration);
+                    }
                 }
                 Err(e) => {
-                    println!("Итерация {}: Ошибка - {}", i, e);
+                    errors += 1;
+                    if !json_mode {
+                        println!("Итерация {}: Ошибка - {}", i, e);
+                    }
                 }
             }
         }
+        let elapsed = started.elapsed();
+
+        let mut sorted = times.clone();
+        sorted.sort();
+        let throughput = times.len() as f64 / elapsed.as_secs_f64().max(f64::EPSILON);
+        let as_ms = |d: Duration| d.as_secs_f64() * 1000.0;
+
+        if json_mode {
+            let times_ms: Vec<f64> = times.iter().map(|t| as_ms(*t)).collect();
+            return output::print_data(&serde_json::json!({
+                "query": query,
+                "count": count,
+                "warmup": warmup,
+                "concurrency": concurrency,
+                "errors": errors,
+                "avg_ms": times_ms.iter().sum::<f64>() / times_ms.len().max(1) as f64,
+                "min_ms": sorted.first().map(|t| as_ms(*t)),
+                "max_ms": sorted.last().map(|t| as_ms(*t)),
+                "p50_ms": percentile(&sorted, 50.0).map(as_ms),
+                "p90_ms": percentile(&sorted, 90.0).map(as_ms),
+                "p99_ms": percentile(&sorted, 99.0).map(as_ms),
+                "jitter_ms": jitter(&times).map(as_ms),
+                "throughput_rps": throughput,
+                "times_ms": times_ms,
+            }));
+        }
 
         if !times.is_empty() {
-            let avg_time = times.iter().sum::<std::time::Duration>() / times.len() as u32;
-            let min_time = times.iter().min().unwrap();
-            let max_time = times.iter().max().unwrap();
+            let avg_time = times.iter().sum::<Duration>() / times.len() as u32;
 
             println!();
             println!("{}", "Результаты:".bold());
             println!("Среднее время: {:?}", avg_time);
-            println!("Минимальное время: {:?}", min_time);
-            println!("Максимальное время: {:?}", max_time);
+            println!("Минимальное время: {:?}", sorted[0]);
+            println!("Максимальное время: {:?}", sorted[sorted.len() - 1]);
+            for p in [50.0, 90.0, 99.0] {
+                if let Some(value) = percentile(&sorted, p) {
+                    println!("p{}: {:?}", p, value);
+                }
+            }
+            if let Some(value) = jitter(&times) {
+                println!("Разброс (стандартное отклонение): {:?}", value);
+            }
+            println!("Пропускная способность: {:.2} запросов/с", throughput);
+        }
+
+        Ok(())
+    }
+
+    async fn bench(&self, client: &VmClient, query: &str, levels: &[u16], duration: Duration) -> Result<()> {
+        let json_mode = output::is_json_mode();
+        if !json_mode {
+            println!("{}", "Нагрузочный тест:".bold());
+            println!("Запрос: {}", query);
+            println!("Длительность уровня: {}", humantime::format_duration(duration));
+            println!();
         }
 
+        let mut results = Vec::with_capacity(levels.len());
+        for &level in levels {
+            let level = level as usize;
+            if !json_mode {
+                println!("Параллельность {}...", level);
+            }
+
+            let started = Instant::now();
+            let deadline = started + duration;
+            let workers = (0..level).map(|_| async move {
+                let mut times = Vec::new();
+                let mut errors = 0;
+                while Instant::now() < deadline {
+                    let start = Instant::now();
+                    match client.query(query, None).await {
+                        Ok(_) => times.push(start.elapsed()),
+                        Err(crate::error::VmCliError::Cancelled) => break,
+                        Err(_) => errors += 1,
+                    }
+                }
+                (times, errors)
+            });
+            let (times, errors): (Vec<Vec<Duration>>, Vec<usize>) =
+                futures::future::join_all(workers).await.into_iter().unzip();
+
+            results.push(bench_level_stats(
+                level,
+                times.into_iter().flatten().collect(),
+                errors.into_iter().sum(),
+                started.elapsed(),
+            ));
+        }
+
+        if json_mode {
+            return output::print_data(&serde_json::json!({
+                "query": query,
+                "duration_ms": duration.as_secs_f64() * 1000.0,
+                "levels": results,
+            }));
+        }
+
+        println!();
+        println!("{}", Table::new(&results));
+        if let Some(best) = results.iter().max_by(|a, b| a.qps.total_cmp(&b.qps)) {
+            println!(
+                "Максимальная пропускная способность: {:.2} запросов/с при параллельности {}",
+                best.qps, best.concurrency
+            );
+        }
+        Ok(())
+    }
+
+    async fn tsdb_status(
+        &self,
+        client: &VmClient,
+        top: usize,
+        focus_label: Option<&str>,
+        date: Option<&str>,
+        format: Option<&OutputFormat>,
+    ) -> Result<()> {
+        let status = client.tsdb_status(Some(top), focus_label, date).await?.data;
+
+        if output::is_json_mode() {
+            return output::print_data(&status);
+        }
+        if let Some(format) = format {
+            return print_formatted(&TsdbReport::new(&status, focus_label), format);
+        }
+
+        println!("{}", "Статистика TSDB:".bold());
+        println!(
+            "  {:<24} {}",
+            "Всего серий:",
+            status.total_series.map(|v| v.to_string()).unwrap_or_else(|| "н/д".dimmed().to_string())
+        );
+        println!("  {:<24} {}", "Пар метка=значение:", status.total_label_value_pairs);
+
+        for section in tsdb_sections(&status, focus_label) {
+            println!("\n{}", section.title.bold());
+            if section.table.entries.is_empty() {
+                println!("{}", messages::t(Msg::NoData).yellow());
+                continue;
+            }
+            println!("{}", section.table.render(&OutputFormat::Table, output::is_pretty())?);
+        }
+
+        Ok(())
+    }
+
+    async fn instance_stats(&self, client: &VmClient, interval: &str, format: StatsFormat) -> Result<()> {
+        let interval = humantime::parse_duration(interval)
+            .map_err(|e| crate::error::VmCliError::TimeParseError(format!("{}: {}", interval, e)))?;
+
+        let started = Instant::now();
+        let (first, tsdb) = futures::join!(client.get_metrics_info(), client.tsdb_status(None, None, None));
+        let first = first?;
+        tokio::time::sleep(interval).await;
+        let second = client.get_metrics_info().await?;
+        let elapsed = started.elapsed().as_secs_f64();
+
+        let tsdb = tsdb.ok().map(|response| response.data);
+        let stats = build_instance_stats(&first, &second, elapsed, tsdb.as_ref());
+
+        if output::is_json_mode() {
+            return output::print_data(&stats);
+        }
+        if format == StatsFormat::Json {
+            println!("{}", serde_json::to_string_pretty(&stats)?);
+            return Ok(());
+        }
+
+        let missing = || "н/д".dimmed().to_string();
+        println!("{}", "Сводка VictoriaMetrics:".bold());
+        println!("  {:<16} {}", "Серий:", stats.series.map(|v| v.to_string()).unwrap_or_else(missing));
+        println!(
+            "  {:<16} {}",
+            "Прием данных:",
+            stats.ingestion_rate.map(|v| format!("{:.1} точек/с", v)).unwrap_or_else(missing)
+        );
+        println!(
+            "  {:<16} {}",
+            "Запросы:",
+            stats.query_rate.map(|v| format!("{:.2} запросов/с", v)).unwrap_or_else(missing)
+        );
+        println!("  {:<16} {}", "Память (RSS):", stats.memory_bytes.map(format_bytes).unwrap_or_else(missing));
+        println!("  {:<16} {}", "Диск:", stats.disk_bytes.map(format_bytes).unwrap_or_else(missing));
+
         Ok(())
     }
 
@@ -341,19 +698,51 @@ impl DebugCommand {
         &self,
         client: &VmClient,
         pattern: Option<&str>,
+        filter: &MetricFilter,
         stats: bool,
         export: Option<&str>,
+        export_format: MetricsExportFormat,
     ) -> Result<()> {
+        let metrics = client.metrics().await?;
+
+        if output::is_json_mode() {
+            if let Some(export_path) = export {
+                std::fs::write(export_path, render_metrics_export(&metrics.data, export_format)?)?;
+            }
+
+            let filtered: Vec<&String> = metrics
+                .data
+                .iter()
+                .filter(|m| filter.matches(m))
+                .collect();
+
+            if stats {
+                let mut prefixes: std::collections::HashMap<&str, usize> = std::collections::HashMap::new();
+                for metric in &filtered {
+                    if let Some(prefix) = metric.split('_').next() {
+                        *prefixes.entry(prefix).or_insert(0) += 1;
+                    }
+                }
+                return output::print_data(&serde_json::json!({
+                    "total": filtered.len(),
+                    "prefixes": prefixes,
+                }));
+            }
+
+            return output::print_data(&serde_json::json!({
+                "total": filtered.len(),
+                "metrics": filtered,
+            }));
+        }
+
         println!("{}", "Анализ метрик:".bold());
         println!();
 
-        let metrics = client.metrics().await?;
-
         if let Some(pattern) = pattern {
             let filtered: Vec<&String> = metrics
                 .data
                 .iter()
-                .filter(|m| m.contains(pattern))
+                .filter(|m| filter.matches(m))
                 .collect();
 
             println!("Метрики, соответствующие паттерну '{}': {}", pattern, filtered.len());
@@ -390,12 +779,941 @@ impl DebugCommand {
         }
 
         if let Some(export_path) = export {
-            let content = metrics.data.join("\n");
+            let content = render_metrics_export(&metrics.data, export_format)?;
             std::fs::write(export_path, content)
-                .map_err(|e| crate::error::VmCliError::IoError(e))?;
+                .map_err(crate::error::VmCliError::IoError)?;
             println!("Список метрик экспортирован в: {}", export_path);
         }
 
         Ok(())
     }
+
+    async fn analyze_series(
+        &self,
+        client: &VmClient,
+        selector: &str,
+        limit: Option<usize>,
+        window: SeriesWindow<'_>,
+        export: Option<&str>,
+        export_format: MetricsExportFormat,
+    ) -> Result<()> {
+        let series = fetch_series(client, selector, limit, window).await?;
+        let truncated = limit.is_some_and(|limit| series.data.len() >= limit);
+
+        let by_name = series_by_metric_name(&series);
+
+        if let Some(export_path) = export {
+            let names: Vec<String> = by_name.keys().map(|name| name.to_string()).collect();
+            std::fs::write(export_path, render_metrics_export(&names, export_format)?)?;
+            if !output::is_json_mode() {
+                println!("Список метрик экспортирован в: {}", export_path);
+            }
+        }
+
+        if output::is_json_mode() {
+            return output::print_data(&serde_json::json!({
+                "selector": selector,
+                "total_series": series.data.len(),
+                "truncated": truncated,
+                "metrics": by_name,
+            }));
+        }
+
+        println!("{}", "Анализ метрик:".bold());
+        println!();
+        println!(
+            "Серии, соответствующие селектору '{}': {} ({} метрик)",
+            selector,
+            series.data.len(),
+            by_name.len()
+        );
+        for (name, count) in by_name.iter().take(20) {
+            println!("  {}: {} серий", name, count);
+        }
+        if by_name.len() > 20 {
+            println!("  ... и еще {} метрик", by_name.len() - 20);
+        }
+        if truncated {
+            println!(
+                "{} результат ограничен {} сериями (--limit), часть серий может быть не показана",
+                messages::t(Msg::Warning).yellow().bold(),
+                series.data.len()
+            );
+        }
+
+        Ok(())
+    }
+
+    async fn debug_relabel(
+        &self,
+        client: &VmClient,
+        metric: &str,
+        config_file: &str,
+    ) -> Result<()> {
+        if !std::path::Path::new(config_file).exists() {
+            return Err(crate::error::VmCliError::FileNotFound(config_file.to_string()));
+        }
+
+        let relabel_configs = std::fs::read_to_string(config_file)?;
+        let result = client.relabel_debug(metric, &relabel_configs).await?;
+
+        if let Some(error_msg) = result.error_msg.as_deref().filter(|m| !m.is_empty()) {
+            return Err(crate::error::VmCliError::ApiError {
+                message: format!("Ошибка релейблинга: {}", error_msg),
+                status: None,
+            });
+        }
+
+        if output::is_json_mode() {
+            return output::print_data(&result);
+        }
+
+        println!("{}", "Отладка релейблинга:".bold());
+        println!("Метрика: {}", metric);
+        println!("Конфигурация: {}", config_file);
+        println!();
+
+        for (i, step) in result.steps.iter().enumerate() {
+            println!("{} {}", format!("Шаг {}:", i + 1).bold(), step.rule.trim());
+            println!("  {} {}", "До:".dimmed(), step.in_labels);
+            println!("  {} {}", "После:".dimmed(), step.out_labels);
+        }
+
+        if !result.steps.is_empty() {
+            println!();
+        }
+
+        println!("{} {}", "Исходные метки:".bold(), result.original_labels);
+        if result.resulting_labels.is_empty() {
+            println!("{} {}", "Результат:".bold(), "серия отброшена".red());
+        } else {
+            println!("{} {}", "Результат:".bold(), result.resulting_labels.green());
+        }
+
+        Ok(())
+    }
+
+    async fn debug_connect(&self, client: &VmClient) -> Result<()> {
+        let stages = run_connect_stages(client).await;
+
+        if output::is_json_mode() {
+            return output::print_data(&serde_json::json!({
+                "host": client.base_url(),
+                "stages": stages,
+            }));
+        }
+
+        println!("{} {}", "Диагностика подключения:".bold(), client.base_url());
+
+        for stage in &stages {
+            let mark = if stage.ok { "✓".green() } else { "✗".red() };
+            println!(
+                "  {} {:<5} {:>9.2} мс  {}",
+                mark,
+                stage.stage.to_uppercase(),
+                stage.duration_ms,
+                stage.detail
+            );
+        }
+
+        match stages.iter().find(|s| !s.ok) {
+            Some(failed) => println!(
+                "{} {}",
+                "Сбой на этапе:".red().bold(),
+                failed.stage.to_uppercase()
+            ),
+            None => println!("{}", "Подключение работает".green()),
+        }
+
+        Ok(())
+    }
+}
+
+enum MetricFilter {
+    All,
+    Substring(String),
+    Regex(Regex),
+}
+
+impl MetricFilter {
+    fn new(pattern: Option<&str>, regex: bool) -> Result<Self> {
+        match pattern {
+            None => Ok(MetricFilter::All),
+            Some(pattern) if regex => Regex::new(pattern).map(MetricFilter::Regex).map_err(|e| {
+                crate::error::VmCliError::InvalidQuery(format!("Неверное регулярное выражение '{}': {}", pattern, e))
+            }),
+            Some(pattern) => Ok(MetricFilter::Substring(pattern.to_string())),
+        }
+    }
+
+    fn matches(&self, metric: &str) -> bool {
+        match self {
+            MetricFilter::All => true,
+            MetricFilter::Substring(pattern) => metric.contains(pattern.as_str()),
+            MetricFilter::Regex(regex) => regex.is_match(metric),
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+struct SeriesWindow<'a> {
+    range: Option<&'a str>,
+    page_window: Option<Duration>,
+}
+
+fn series_by_metric_name(series: &SeriesResponse) -> BTreeMap<&str, usize> {
+    let mut by_name = BTreeMap::new();
+    for labels in &series.data {
+        let name = labels.get("__name__").map(String::as_str).unwrap_or("");
+        *by_name.entry(name).or_insert(0) += 1;
+    }
+    by_name
+}
+
+async fn fetch_series(
+    client: &VmClient,
+    selector: &str,
+    limit: Option<usize>,
+    window: SeriesWindow<'_>,
+) -> Result<SeriesResponse> {
+    let Some(range) = window.range else {
+        return client.series(&[selector], None, None, limit).await;
+    };
+    let (start, end) = parse_time_range(range).map_err(crate::error::VmCliError::TimeParseError)?;
+
+    let Some(page_window) = window.page_window else {
+        return client.series(&[selector], Some(&start), Some(&end), limit).await;
+    };
+    let (Ok(start), Ok(end)) = (start.parse::<i64>(), end.parse::<i64>()) else {
+        return Err(crate::error::VmCliError::TimeParseError(range.to_string()));
+    };
+
+    let progress_bar = output::window_progress_bar();
+
+    let series = client
+        .series_paged(&[selector], start, end, page_window, limit, |page, pages| {
+            progress_bar.set_length(pages as u64);
+            progress_bar.set_position(page as u64);
+        })
+        .await;
+    progress_bar.finish_and_clear();
+    series
+}
+
+fn render_metrics_export(metrics: &[String], format: MetricsExportFormat) -> Result<String> {
+    let unique: Vec<&String> = metrics.iter().collect::<BTreeSet<_>>().into_iter().collect();
+
+    match format {
+        MetricsExportFormat::Txt => Ok(unique
+            .iter()
+            .map(|m| m.as_str())
+            .collect::<Vec<_>>()
+            .join("\n")),
+        MetricsExportFormat::Json => Ok(serde_json::to_string_pretty(&serde_json::json!({
+            "metrics": unique,
+        }))?),
+        MetricsExportFormat::Csv => {
+            let mut writer = csv::Writer::from_writer(Vec::new());
+            writer.write_record(["metric"])?;
+            for metric in unique {
+                writer.write_record([metric])?;
+            }
+            let data = writer
+                .into_inner()
+                .map_err(|e| crate::error::VmCliError::IoError(e.into_error()))?;
+            Ok(String::from_utf8_lossy(&data).into_owned())
+        }
+    }
+}
+
+#[derive(Serialize)]
+pub struct ConnectStage {
+    pub stage: &'static str,
+    pub ok: bool,
+    pub duration_ms: f64,
+    pub detail: String,
+}
+
+impl ConnectStage {
+    fn new<T>(stage: &'static str, elapsed: Duration, result: &std::result::Result<T, String>, detail: impl FnOnce(&T) -> String) -> Self {
+        Self {
+            stage,
+            ok: result.is_ok(),
+            duration_ms: elapsed.as_secs_f64() * 1000.0,
+            detail: match result {
+                Ok(value) => detail(value),
+                Err(e) => e.clone(),
+            },
+        }
+    }
+}
+
+async fn timed<T, E, F>(future: F) -> (Duration, std::result::Result<T, String>)
+where
+    E: std::fmt::Display,
+    F: Future<Output = std::result::Result<T, E>>,
+{
+    let started = Instant::now();
+    let result = match tokio::time::timeout(CONNECT_TIMEOUT, future).await {
+        Ok(result) => result.map_err(|e| e.to_string()),
+        Err(_) => Err(format!("таймаут {} с", CONNECT_TIMEOUT.as_secs())),
+    };
+    (started.elapsed(), result)
+}
+
+pub async fn run_connect_stages(client: &VmClient) -> Vec<ConnectStage> {
+    let mut stages = Vec::new();
+
+    let url = match reqwest::Url::parse(client.base_url()) {
+        Ok(url) => url,
+        Err(e) => {
+            stages.push(ConnectStage::new::<()>("url", Duration::ZERO, &Err(e.to_string()), |_| String::new()));
+            return stages;
+        }
+    };
+    let host = url.host_str().unwrap_or_default().to_string();
+    let port = url.port_or_known_default().unwrap_or(80);
+
+    let (elapsed, dns) = timed(tokio::net::lookup_host((host.as_str(), port))).await;
+    let dns = dns.map(|addrs| addrs.collect::<Vec<SocketAddr>>());
+    stages.push(ConnectStage::new("dns", elapsed, &dns, |addrs| {
+        addrs.iter().map(|a| a.to_string()).collect::<Vec<_>>().join(", ")
+    }));
+    let Some(addr) = dns.ok().and_then(|addrs| addrs.first().copied()) else {
+        return stages;
+    };
+
+    let (elapsed, tcp) = timed(tokio::net::TcpStream::connect(addr)).await;
+    stages.push(ConnectStage::new("tcp", elapsed, &tcp, |_| addr.to_string()));
+    let Ok(stream) = tcp else {
+        return stages;
+    };
+
+    if url.scheme() == "https" {
+        let (elapsed, tls) = timed(async {
+            let connector = tokio_native_tls::TlsConnector::from(client.tls_connector().map_err(|e| e.to_string())?);
+            connector.connect(&host, stream).await.map_err(|e| e.to_string())
+        })
+        .await;
+        stages.push(ConnectStage::new("tls", elapsed, &tls, |_| {
+            if client.tls_insecure() {
+                format!("{} (сертификат не проверялся: --insecure)", host)
+            } else {
+                host.clone()
+            }
+        }));
+        if tls.is_err() {
+            return stages;
+        }
+    }
+
+    let (elapsed, health) = timed(client.health()).await;
+    stages.push(ConnectStage::new("http", elapsed, &health, |h| format!("/health: {}", h.status)));
+
+    stages
+}
+
+#[derive(Serialize)]
+struct DataGap {
+    start: String,
+    end: String,
+    duration: String,
+}
+
+fn collect_gaps(response: &QueryResponse, min_gap: u64) -> Vec<DataGap> {
+    let mut gaps = Vec::new();
+
+    for result in &response.data.result {
+        if let Some(values) = &result.values {
+            for pair in values.windows(2) {
+                let prev_time = pair[0].0;
+                let curr_time = pair[1].0;
+                let gap_duration = curr_time - prev_time;
+
+                if gap_duration > min_gap as f64 {
+                    let start = chrono::DateTime::from_timestamp(prev_time as i64, 0)
+                        .unwrap_or_default()
+                        .format("%Y-%m-%d %H:%M:%S")
+                        .to_string();
+                    let end = chrono::DateTime::from_timestamp(curr_time as i64, 0)
+                        .unwrap_or_default()
+                        .format("%Y-%m-%d %H:%M:%S")
+                        .to_string();
+
+                    let duration = if gap_duration > 3600.0 {
+                        format!("{:.0}h", gap_duration / 3600.0)
+                    } else if gap_duration > 60.0 {
+                        format!("{:.0}m", gap_duration / 60.0)
+                    } else {
+                        format!("{:.0}s", gap_duration)
+                    };
+
+                    gaps.push(DataGap { start, end, duration });
+                }
+            }
+        }
+    }
+
+    gaps
+}
+
+fn bench_level_stats(concurrency: usize, mut times: Vec<Duration>, errors: usize, elapsed: Duration) -> BenchLevel {
+    times.sort();
+    let as_ms = |d: Duration| d.as_secs_f64() * 1000.0;
+    let avg = (!times.is_empty()).then(|| times.iter().sum::<Duration>() / times.len() as u32);
+
+    BenchLevel {
+        concurrency,
+        requests: times.len(),
+        errors,
+        qps: times.len() as f64 / elapsed.as_secs_f64().max(f64::EPSILON),
+        avg_ms: avg.map(as_ms),
+        p50_ms: percentile(&times, 50.0).map(as_ms),
+        p90_ms: percentile(&times, 90.0).map(as_ms),
+        p99_ms: percentile(&times, 99.0).map(as_ms),
+        max_ms: times.last().copied().map(as_ms),
+    }
+}
+
+fn display_float(value: &f64) -> String {
+    format!("{:.2}", value)
+}
+
+fn display_ms(value: &Option<f64>) -> String {
+    value.map(|ms| format!("{:.1}", ms)).unwrap_or_else(|| "-".to_string())
+}
+
+fn percentile(sorted: &[Duration], p: f64) -> Option<Duration> {
+    if sorted.is_empty() {
+        return None;
+    }
+    let rank = ((p / 100.0) * sorted.len() as f64).ceil() as usize;
+    Some(sorted[rank.clamp(1, sorted.len()) - 1])
+}
+
+fn jitter(times: &[Duration]) -> Option<Duration> {
+    if times.len() < 2 {
+        return None;
+    }
+    let secs: Vec<f64> = times.iter().map(Duration::as_secs_f64).collect();
+    let mean = secs.iter().sum::<f64>() / secs.len() as f64;
+    let variance = secs.iter().map(|s| (s - mean).powi(2)).sum::<f64>() / secs.len() as f64;
+    Some(Duration::from_secs_f64(variance.sqrt()))
+}
+
+struct TsdbSection<'a> {
+    key: &'static str,
+    title: String,
+    table: CardinalityTable<'a>,
+}
+
+fn tsdb_sections<'a>(status: &'a TsdbStatus, focus_label: Option<&str>) -> Vec<TsdbSection<'a>> {
+    let series_total = status.total_series;
+    let section = |key, title: &str, entries: &'a [TsdbStatEntry], series_total| TsdbSection {
+        key,
+        title: title.to_string(),
+        table: CardinalityTable::new(entries, series_total),
+    };
+
+    let mut sections = vec![
+        section("metric_name", "Серии по имени метрики", &status.series_count_by_metric_name, series_total),
+        section("label_name", "Серии по имени метки", &status.series_count_by_label_name, series_total),
+        section("label_value_pair", "Серии по паре метка=значение", &status.series_count_by_label_value_pair, series_total),
+        section("label_value_count", "Число значений по имени метки", &status.label_value_count_by_label_name, None),
+    ];
+    if let Some(label) = focus_label {
+        let title = format!("Серии по значениям метки {}", label);
+        sections.push(section("focus_label_value", &title, &status.series_count_by_focus_label_value, series_total));
+    }
+    sections
+}
+
+/// Одна секция кардинальности: записи по убыванию значения и, если общее число
+/// серий известно, доля каждой записи.
+#[derive(Serialize)]
+#[serde(transparent)]
+struct CardinalityTable<'a> {
+    entries: Vec<&'a TsdbStatEntry>,
+    #[serde(skip)]
+    series_total: Option<u64>,
+}
+
+impl<'a> CardinalityTable<'a> {
+    fn new(entries: &'a [TsdbStatEntry], series_total: Option<u64>) -> Self {
+        let mut entries: Vec<&TsdbStatEntry> = entries.iter().collect();
+        entries.sort_by(|a, b| b.value.cmp(&a.value).then_with(|| a.name.cmp(&b.name)));
+        Self { entries, series_total }
+    }
+}
+
+impl Formatter for CardinalityTable<'_> {
+    fn headers(&self) -> Vec<String> {
+        match self.series_total {
+            Some(_) => ["name", "series", "share"].map(String::from).into(),
+            None => ["name", "values"].map(String::from).into(),
+        }
+    }
+
+    fn rows(&self) -> Vec<Vec<String>> {
+        self.entries
+            .iter()
+            .map(|entry| {
+                let mut row = vec![entry.name.clone(), entry.value.to_string()];
+                match self.series_total {
+                    Some(total) if total > 0 => row.push(format!("{:.2}%", entry.value as f64 * 100.0 / total as f64)),
+                    Some(_) => row.push("-".to_string()),
+                    None => {}
+                }
+                row
+            })
+            .collect()
+    }
+}
+
+/// Все секции debug tsdb одной таблицей для --format: секция указывается
+/// в первой колонке, JSON и YAML содержат ответ сервера как есть.
+#[derive(Serialize)]
+#[serde(transparent)]
+struct TsdbReport<'a> {
+    status: &'a TsdbStatus,
+    #[serde(skip)]
+    sections: Vec<TsdbSection<'a>>,
+}
+
+impl<'a> TsdbReport<'a> {
+    fn new(status: &'a TsdbStatus, focus_label: Option<&str>) -> Self {
+        Self {
+            status,
+            sections: tsdb_sections(status, focus_label),
+        }
+    }
+}
+
+impl Formatter for TsdbReport<'_> {
+    fn headers(&self) -> Vec<String> {
+        ["section", "name", "value", "share"].map(String::from).into()
+    }
+
+    fn rows(&self) -> Vec<Vec<String>> {
+        self.sections
+            .iter()
+            .flat_map(|section| {
+                section.table.rows().into_iter().map(move |mut row| {
+                    row.resize(3, String::new());
+                    row.insert(0, section.key.to_string());
+                    row
+                })
+            })
+            .collect()
+    }
+}
+
+fn build_instance_stats(
+    first: &serde_json::Value,
+    second: &serde_json::Value,
+    elapsed: f64,
+    tsdb: Option<&TsdbStatus>,
+) -> InstanceStats {
+    let rate = |matches: &dyn Fn(&str) -> bool| {
+        let before = sum_metric(first, matches)?;
+        let after = sum_metric(second, matches)?;
+        (elapsed > 0.0).then(|| (after - before).max(0.0) / elapsed)
+    };
+
+    InstanceStats {
+        series: tsdb.and_then(|t| t.total_series),
+        ingestion_rate: rate(&|key| metric_name(key) == "vm_rows_inserted_total"),
+        query_rate: rate(&|key| {
+            metric_name(key) == "vm_http_requests_total" && key.contains("/api/v1/query")
+        }),
+        memory_bytes: sum_metric(second, &|key| key == "process_resident_memory_bytes").map(|v| v as u64),
+        disk_bytes: sum_metric(second, &|key| metric_name(key) == "vm_data_size_bytes").map(|v| v as u64),
+    }
+}
+
+fn metric_name(key: &str) -> &str {
+    key.split('{').next().unwrap_or(key)
+}
+
+fn sum_metric(metrics: &serde_json::Value, matches: &dyn Fn(&str) -> bool) -> Option<f64> {
+    let values: Vec<f64> = metrics
+        .as_object()?
+        .iter()
+        .filter(|(key, _)| matches(key))
+        .filter_map(|(_, value)| value.as_f64())
+        .collect();
+
+    (!values.is_empty()).then(|| values.iter().sum())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{assert_consistent_formats, MockServer};
+
+    fn stage_names(stages: &[ConnectStage]) -> Vec<(&str, bool)> {
+        stages.iter().map(|s| (s.stage, s.ok)).collect()
+    }
+
+    #[tokio::test]
+    async fn connect_stages_report_each_step_for_http() {
+        let server = MockServer::start(vec![(200, "OK")]);
+        let client = VmClient::new(&server.url, 5, None, None, None).unwrap();
+
+        let stages = run_connect_stages(&client).await;
+
+        assert_eq!(stage_names(&stages), vec![("dns", true), ("tcp", true), ("http", true)]);
+        assert_eq!(stages[2].detail, "/health: OK");
+        assert!(stages.iter().all(|s| s.duration_ms >= 0.0));
+    }
+
+    #[tokio::test]
+    async fn connect_stages_stop_at_failed_tcp() {
+        let port = std::net::TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap().port();
+        let client = VmClient::new(&format!("http://127.0.0.1:{}", port), 5, None, None, None).unwrap();
+
+        let stages = run_connect_stages(&client).await;
+
+        assert_eq!(stage_names(&stages), vec![("dns", true), ("tcp", false)]);
+    }
+
+    #[tokio::test]
+    async fn connect_stages_report_tls_failure_before_http() {
+        let server = MockServer::start(vec![(200, "OK")]);
+        let https = server.url.replace("http://", "https://");
+        let client = VmClient::new(&https, 5, None, None, None).unwrap();
+
+        let stages = run_connect_stages(&client).await;
+
+        assert_eq!(stage_names(&stages), vec![("dns", true), ("tcp", true), ("tls", false)]);
+        assert!(!stages[2].detail.is_empty());
+    }
+
+    #[tokio::test]
+    async fn connect_stages_reject_invalid_url() {
+        let client = VmClient::new("not a url", 5, None, None, None).unwrap();
+
+        let stages = run_connect_stages(&client).await;
+
+        assert_eq!(stage_names(&stages), vec![("url", false)]);
+    }
+
+    #[tokio::test]
+    async fn connect_stages_use_configured_ca_bundle() {
+        let server = MockServer::start_tls(vec![(200, "OK")]);
+        let dir = tempfile::tempdir().unwrap();
+        let bundle = dir.path().join("bundle.pem");
+        let pem = [include_str!("../../testdata/other-ca.pem"), include_str!("../../testdata/ca.pem")].concat();
+        std::fs::write(&bundle, pem).unwrap();
+        let tls = crate::config::TlsConfig { ca_cert: Some(bundle.display().to_string()), insecure: false };
+        let client = VmClient::new(&server.url, 5, None, None, Some(tls)).unwrap();
+
+        let stages = run_connect_stages(&client).await;
+
+        assert_eq!(stage_names(&stages), vec![("dns", true), ("tcp", true), ("tls", true), ("http", true)]);
+    }
+
+    #[tokio::test]
+    async fn connect_stages_honor_insecure() {
+        let server = MockServer::start_tls(vec![(200, "OK")]);
+        let tls = crate::config::TlsConfig { ca_cert: None, insecure: true };
+        let client = VmClient::new(&server.url, 5, None, None, Some(tls)).unwrap();
+
+        let stages = run_connect_stages(&client).await;
+
+        assert_eq!(stage_names(&stages), vec![("dns", true), ("tcp", true), ("tls", true), ("http", true)]);
+        assert!(stages[2].detail.ends_with("(сертификат не проверялся: --insecure)"));
+    }
+
+    #[test]
+    fn instance_stats_without_total_series_report_unknown() {
+        let metrics = serde_json::json!({ "process_resident_memory_bytes": 1024.0 });
+        let tsdb: TsdbStatus = serde_json::from_str(r#"{"seriesCountByMetricName": []}"#).unwrap();
+
+        let stats = build_instance_stats(&metrics, &metrics, 1.0, Some(&tsdb));
+        assert_eq!(stats.series, None);
+        assert_eq!(stats.memory_bytes, Some(1024));
+
+        let tsdb: TsdbStatus = serde_json::from_str(r#"{"totalSeries": 42}"#).unwrap();
+        assert_eq!(build_instance_stats(&metrics, &metrics, 1.0, Some(&tsdb)).series, Some(42));
+    }
+
+    fn tsdb_status() -> TsdbStatus {
+        serde_json::from_str(
+            r#"{"totalSeries": 200, "totalLabelValuePairs": 5,
+                "seriesCountByMetricName": [{"name": "up", "value": 50}, {"name": "http_requests_total", "value": 150}],
+                "labelValueCountByLabelName": [{"name": "job", "value": 3}],
+                "seriesCountByFocusLabelValue": [{"name": "node", "value": 20}]}"#,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn cardinality_tables_sort_by_value_and_show_share() {
+        let status = tsdb_status();
+        let sections = tsdb_sections(&status, None);
+
+        assert_eq!(sections.len(), 4);
+        assert_eq!(
+            sections[0].table.rows(),
+            [["http_requests_total", "150", "75.00%"], ["up", "50", "25.00%"]]
+        );
+        assert_eq!(sections[3].table.headers(), ["name", "values"]);
+        assert_eq!(sections[3].table.rows(), [["job", "3"]]);
+    }
+
+    #[test]
+    fn tsdb_report_renders_consistently_across_formats() {
+        let status = tsdb_status();
+        let report = TsdbReport::new(&status, Some("job"));
+        assert_consistent_formats(&report);
+        for section in &report.sections {
+            assert_consistent_formats(&section.table);
+        }
+
+        let csv = report.render(&OutputFormat::Csv, true).unwrap();
+        assert!(csv.contains("label_value_count,job,3,\n"), "{}", csv);
+        assert!(csv.ends_with("focus_label_value,node,20,10.00%"), "{}", csv);
+    }
+
+    fn exported(format: MetricsExportFormat) -> String {
+        let metrics = ["up", "node_load1", "up", "go_gc_duration_seconds", "node_load1"].map(String::from);
+        render_metrics_export(&metrics, format).unwrap()
+    }
+
+    #[test]
+    fn metrics_export_is_sorted_and_deduplicated_in_every_format() {
+        assert_eq!(exported(MetricsExportFormat::Txt), "go_gc_duration_seconds\nnode_load1\nup");
+        assert_eq!(
+            serde_json::from_str::<serde_json::Value>(&exported(MetricsExportFormat::Json)).unwrap(),
+            serde_json::json!({ "metrics": ["go_gc_duration_seconds", "node_load1", "up"] })
+        );
+        assert_eq!(exported(MetricsExportFormat::Csv), "metric\ngo_gc_duration_seconds\nnode_load1\nup\n");
+    }
+
+    #[test]
+    fn percentiles_use_nearest_rank() {
+        let latencies: Vec<Duration> = (1..=100).map(Duration::from_millis).collect();
+
+        assert_eq!(percentile(&latencies, 50.0), Some(Duration::from_millis(50)));
+        assert_eq!(percentile(&latencies, 90.0), Some(Duration::from_millis(90)));
+        assert_eq!(percentile(&latencies, 99.0), Some(Duration::from_millis(99)));
+        assert_eq!(percentile(&latencies, 0.0), Some(Duration::from_millis(1)));
+        assert_eq!(percentile(&latencies, 100.0), Some(Duration::from_millis(100)));
+
+        let few: Vec<Duration> = [10, 20, 30].map(Duration::from_millis).into();
+        assert_eq!(percentile(&few, 50.0), Some(Duration::from_millis(20)));
+        assert_eq!(percentile(&few, 99.0), Some(Duration::from_millis(30)));
+        assert_eq!(percentile(&[], 50.0), None);
+    }
+
+    #[test]
+    fn jitter_is_standard_deviation_of_latencies() {
+        let times: Vec<Duration> = [2, 4, 4, 4, 5, 5, 7, 9].map(Duration::from_millis).into();
+
+        assert_eq!(jitter(&times), Some(Duration::from_millis(2)));
+        assert_eq!(jitter(&times[..1]), None);
+    }
+
+    #[tokio::test]
+    async fn warmup_queries_are_sent_but_not_measured() {
+        let server = MockServer::start(vec![(200, r#"{"status":"success","data":{"resultType":"vector","result":[]}}"#)]);
+        let client = VmClient::new(&server.url, 5, None, None, None).unwrap();
+        let command = DebugCommand::try_parse_from(["debug", "performance", "--query", "up"]).unwrap();
+
+        command.test_performance(&client, 3, "up", 2, 2).await.unwrap();
+
+        assert_eq!(server.requests().len(), 5);
+    }
+
+    #[test]
+    fn instance_stats_compute_rates_and_sizes_from_metric_maps() {
+        let first = serde_json::json!({
+            "vm_rows_inserted_total{type=\"promremotewrite\"}": 1000.0,
+            "vm_rows_inserted_total{type=\"vmimport\"}": 500.0,
+            "vm_http_requests_total{path=\"/api/v1/query\"}": 10.0,
+            "vm_http_requests_total{path=\"/api/v1/query_range\"}": 20.0,
+            "vm_http_requests_total{path=\"/api/v1/import\"}": 99.0,
+        });
+        let second = serde_json::json!({
+            "vm_rows_inserted_total{type=\"promremotewrite\"}": 1800.0,
+            "vm_rows_inserted_total{type=\"vmimport\"}": 700.0,
+            "vm_http_requests_total{path=\"/api/v1/query\"}": 30.0,
+            "vm_http_requests_total{path=\"/api/v1/query_range\"}": 40.0,
+            "vm_http_requests_total{path=\"/api/v1/import\"}": 500.0,
+            "process_resident_memory_bytes": 2048.0,
+            "vm_data_size_bytes{type=\"storage/big\"}": 3000.0,
+            "vm_data_size_bytes{type=\"indexdb\"}": 1000.0,
+        });
+
+        let stats = build_instance_stats(&first, &second, 10.0, Some(&tsdb_status()));
+
+        assert_eq!(stats.series, Some(200));
+        assert_eq!(stats.ingestion_rate, Some(100.0));
+        assert_eq!(stats.query_rate, Some(4.0));
+        assert_eq!(stats.memory_bytes, Some(2048));
+        assert_eq!(stats.disk_bytes, Some(4000));
+    }
+
+    #[test]
+    fn instance_stats_without_metrics_or_elapsed_time_are_unknown() {
+        let empty = serde_json::json!({});
+        let stats = build_instance_stats(&empty, &empty, 10.0, None);
+        assert!(stats.series.is_none() && stats.ingestion_rate.is_none() && stats.memory_bytes.is_none());
+
+        let counter = serde_json::json!({ "vm_rows_inserted_total": 5.0 });
+        assert_eq!(build_instance_stats(&counter, &counter, 0.0, None).ingestion_rate, None);
+    }
+
+    const SELECTED_SERIES: &str = r#"{"status":"success","data":[
+        {"__name__":"up","job":"x","instance":"a"},
+        {"__name__":"node_load1","job":"x"},
+        {"__name__":"up","job":"x","instance":"b"}
+    ]}"#;
+
+    #[test]
+    fn series_are_grouped_by_metric_name() {
+        let series: SeriesResponse = serde_json::from_str(SELECTED_SERIES).unwrap();
+
+        assert_eq!(series_by_metric_name(&series).into_iter().collect::<Vec<_>>(), [("node_load1", 1), ("up", 2)]);
+    }
+
+    #[tokio::test]
+    async fn metrics_selector_routes_to_series_endpoint() {
+        let server = MockServer::start(vec![(200, SELECTED_SERIES)]);
+        let client = VmClient::new(&server.url, 5, None, None, None).unwrap();
+        let dir = tempfile::tempdir().unwrap();
+        let export = dir.path().join("names.txt");
+        let command = DebugCommand::try_parse_from([
+            "debug",
+            "metrics",
+            "--match",
+            "{job=\"x\"}",
+            "--export",
+            export.to_str().unwrap(),
+        ])
+        .unwrap();
+
+        command.execute(&client).await.unwrap();
+
+        let requests = server.requests();
+        assert_eq!(requests.len(), 1);
+        assert_eq!(requests[0].path(), "/api/v1/series");
+        assert_eq!(requests[0].param("match[]"), ["{job=\"x\"}"]);
+        assert_eq!(std::fs::read_to_string(&export).unwrap(), "node_load1\nup");
+    }
+
+    #[tokio::test]
+    async fn metrics_selector_forwards_limit() {
+        let server = MockServer::start(vec![(200, SELECTED_SERIES)]);
+        let client = VmClient::new(&server.url, 5, None, None, None).unwrap();
+        let dir = tempfile::tempdir().unwrap();
+        let export = dir.path().join("names.txt");
+        let command = DebugCommand::try_parse_from([
+            "debug",
+            "metrics",
+            "--match",
+            "up",
+            "--limit",
+            "3",
+            "--export",
+            export.to_str().unwrap(),
+        ])
+        .unwrap();
+
+        command.execute(&client).await.unwrap();
+
+        assert_eq!(server.requests()[0].param("limit"), ["3"]);
+        assert!(DebugCommand::try_parse_from(["debug", "metrics", "--limit", "3"]).is_err());
+    }
+
+    const METRIC_NAMES: &str = r#"{"status":"success","data":["node_cpu_seconds_total","node_load1","go_gc_duration_seconds","node_network_receive_bytes_total"]}"#;
+
+    #[test]
+    fn metric_filter_matches_substring_or_regex() {
+        let names = ["node_cpu_seconds_total", "node_load1", "go_gc_duration_seconds", "anode_total"];
+        let filtered = |filter: MetricFilter| names.iter().copied().filter(|m| filter.matches(m)).collect::<Vec<_>>();
+
+        assert_eq!(filtered(MetricFilter::new(None, false).unwrap()), names);
+        assert_eq!(filtered(MetricFilter::new(Some("node_"), false).unwrap()), ["node_cpu_seconds_total", "node_load1", "anode_total"]);
+        assert_eq!(filtered(MetricFilter::new(Some("^node_.*_total$"), true).unwrap()), ["node_cpu_seconds_total"]);
+        assert!(filtered(MetricFilter::new(Some("^node_.*_total$"), false).unwrap()).is_empty());
+    }
+
+    #[tokio::test]
+    async fn metrics_invalid_regex_fails_before_request() {
+        let server = MockServer::start(vec![(200, METRIC_NAMES)]);
+        let client = VmClient::new(&server.url, 5, None, None, None).unwrap();
+
+        let result = DebugCommand::try_parse_from(["debug", "metrics", "node_(cpu", "--regex"])
+            .unwrap()
+            .execute(&client)
+            .await;
+
+        match result {
+            Err(crate::error::VmCliError::InvalidQuery(message)) => assert!(message.contains("node_(cpu"), "{}", message),
+            other => panic!("unexpected result: {:?}", other.err()),
+        }
+        assert!(server.requests().is_empty());
+        assert!(DebugCommand::try_parse_from(["debug", "metrics", "--regex"]).is_err());
+    }
+
+    #[test]
+    fn bench_level_stats_aggregates_latencies_and_qps() {
+        let times: Vec<Duration> = (1..=100).rev().map(Duration::from_millis).collect();
+
+        let level = bench_level_stats(4, times, 3, Duration::from_secs(10));
+
+        assert_eq!((level.concurrency, level.requests, level.errors), (4, 100, 3));
+        assert!((level.qps - 10.0).abs() < 1e-9);
+        assert_eq!(level.avg_ms, Some(50.5));
+        assert_eq!(level.p50_ms, Some(50.0));
+        assert_eq!(level.p90_ms, Some(90.0));
+        assert_eq!(level.p99_ms, Some(99.0));
+        assert_eq!(level.max_ms, Some(100.0));
+    }
+
+    #[test]
+    fn bench_level_stats_without_successful_requests() {
+        let level = bench_level_stats(8, Vec::new(), 5, Duration::from_secs(2));
+
+        assert_eq!((level.requests, level.errors, level.qps), (0, 5, 0.0));
+        assert_eq!((level.avg_ms, level.p50_ms, level.max_ms), (None, None, None));
+        assert_eq!(display_ms(&level.p99_ms), "-");
+        assert_eq!(display_float(&12.345), "12.35");
+    }
+
+    #[test]
+    fn bench_levels_are_parsed_from_a_list() {
+        let command = DebugCommand::try_parse_from(["debug", "bench", "--concurrency-levels", "1,3,16", "--duration", "2s"]).unwrap();
+        match command.command {
+            DebugSubcommand::Bench { concurrency_levels, duration, .. } => {
+                assert_eq!(concurrency_levels, [1, 3, 16]);
+                assert_eq!(duration, Duration::from_secs(2));
+            }
+            _ => panic!("expected bench subcommand"),
+        }
+        assert!(DebugCommand::try_parse_from(["debug", "bench", "--concurrency-levels", "1,0"]).is_err());
+    }
+
+    #[tokio::test]
+    async fn relabel_error_message_is_returned_as_api_error() {
+        let server = MockServer::start(vec![(200, r#"{"originalLabels":"up","errorMsg":"cannot parse relabel_configs"}"#)]);
+        let client = VmClient::new(&server.url, 5, None, None, None).unwrap();
+        let dir = tempfile::tempdir().unwrap();
+        let config_file = dir.path().join("relabel.yml");
+        std::fs::write(&config_file, "- action: bogus\n").unwrap();
+        let command = DebugCommand::try_parse_from([
+            "debug", "relabel", "--metric", "up", "--config-file", config_file.to_str().unwrap(),
+        ])
+        .unwrap();
+
+        match command.execute(&client).await {
+            Err(crate::error::VmCliError::ApiError { message, status: None }) => {
+                assert!(message.contains("cannot parse relabel_configs"), "{}", message);
+            }
+            other => panic!("unexpected result: {:?}", other),
+        }
+        assert_eq!(server.requests()[0].path(), "/metric-relabel-debug");
+    }
 }
@@ -0,0 +1,375 @@
+use crate::api::VmClient;
+use crate::commands::debug::{run_connect_stages, ConnectStage};
+use crate::commands::health::is_healthy;
+use crate::config::{Config, CURRENT_SCHEMA_VERSION};
+use crate::error::{Result, VmCliError};
+use crate::output;
+use clap::Parser;
+use colored::*;
+use serde::Serialize;
+use tracing::info;
+
+const MIN_SUPPORTED_VERSION: (u32, u32, u32) = (1, 87, 0);
+
+#[derive(Parser)]
+pub struct DoctorCommand {
+    #[arg(skip)]
+    config_checks: Vec<Check>,
+
+    #[arg(skip)]
+    cluster_hosts: Vec<(String, String)>,
+}
+
+#[derive(Debug, Serialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+enum CheckStatus {
+    Pass,
+    Warn,
+    Fail,
+}
+
+#[derive(Serialize, Clone)]
+struct Check {
+    name: String,
+    status: CheckStatus,
+    detail: String,
+    hint: Option<String>,
+}
+
+impl Check {
+    fn pass(name: &str, detail: impl Into<String>) -> Self {
+        Self {
+            name: name.to_string(),
+            status: CheckStatus::Pass,
+            detail: detail.into(),
+            hint: None,
+        }
+    }
+
+    fn warn(name: &str, detail: impl Into<String>, hint: impl Into<String>) -> Self {
+        Self {
+            name: name.to_string(),
+            status: CheckStatus::Warn,
+            detail: detail.into(),
+            hint: Some(hint.into()),
+        }
+    }
+
+    fn fail(name: &str, detail: impl Into<String>, hint: impl Into<String>) -> Self {
+        Self {
+            name: name.to_string(),
+            status: CheckStatus::Fail,
+            detail: detail.into(),
+            hint: Some(hint.into()),
+        }
+    }
+}
+
+impl DoctorCommand {
+    pub fn apply_config(&mut self, config: &Config, config_path: Option<&str>, load_error: Option<&VmCliError>) {
+        if let Some(e) = load_error {
+            self.config_checks.push(Check::fail(
+                "config",
+                e.to_string(),
+                "Исправьте файл конфигурации или переменные окружения VM_*; vm-cli config show покажет итоговые значения",
+            ));
+            return;
+        }
+
+        match Config::resolve_path(config_path).ok().flatten() {
+            Some(path) if config.schema_version < CURRENT_SCHEMA_VERSION => {
+                self.config_checks.push(Check::warn(
+                    "config",
+                    format!("{} (schema_version {})", path.display(), config.schema_version),
+                    format!("Выполните vm-cli config migrate, чтобы обновить файл до версии {}", CURRENT_SCHEMA_VERSION),
+                ));
+            }
+            Some(path) => self.config_checks.push(Check::pass("config", path.display().to_string())),
+            None => self.config_checks.push(Check::pass(
+                "config",
+                "файл не найден, используются значения по умолчанию и VM_*",
+            )),
+        }
+
+        if let Some(cluster) = &config.cluster {
+            let hosts = cluster
+                .vminsert_host
+                .iter()
+                .map(|host| ("vminsert".to_string(), host.clone()))
+                .chain(cluster.vmstorage_host.iter().map(|host| ("vmstorage".to_string(), host.clone())))
+                .chain(cluster.vmselect_hosts.iter().map(|host| ("vmselect".to_string(), host.clone())));
+            self.cluster_hosts = hosts.collect();
+        }
+    }
+
+    pub async fn execute(&self, client: &VmClient) -> Result<()> {
+        info!("Проверка окружения vm-cli");
+
+        let mut checks = self.config_checks.clone();
+        let stages = run_connect_stages(client).await;
+        let connected = stages.last().is_some_and(|stage| stage.ok && stage.stage == "http");
+        checks.extend(connect_checks(&stages, client.base_url(), client.tls_insecure()));
+
+        if connected {
+            checks.push(auth_check(client).await);
+            checks.push(version_check(client).await);
+            for (role, host) in &self.cluster_hosts {
+                checks.push(cluster_check(client, role, host).await);
+            }
+        } else {
+            checks.push(Check::warn(
+                "api",
+                "не проверялось",
+                "Проверки авторизации, версии и кластера выполняются после успешного подключения",
+            ));
+        }
+
+        let failed = checks.iter().filter(|c| c.status == CheckStatus::Fail).count();
+        let warnings = checks.iter().filter(|c| c.status == CheckStatus::Warn).count();
+
+        if output::is_json_mode() {
+            if failed > 0 {
+                return Err(checks_failed(&checks));
+            }
+            return output::print_data(&serde_json::json!({
+                "host": client.base_url(),
+                "ok": true,
+                "checks": checks,
+            }));
+        }
+
+        println!("{} {}", "Диагностика окружения:".bold(), client.base_url());
+        for check in &checks {
+            let mark = match check.status {
+                CheckStatus::Pass => "✓".green(),
+                CheckStatus::Warn => "!".yellow(),
+                CheckStatus::Fail => "✗".red(),
+            };
+            println!("  {} {:<10} {}", mark, check.name, check.detail);
+            if let Some(hint) = &check.hint {
+                println!("    {} {}", "→".dimmed(), hint.dimmed());
+            }
+        }
+
+        println!();
+        let summary = format!(
+            "Проверок: {}, предупреждений: {}, ошибок: {}",
+            checks.len(),
+            warnings,
+            failed
+        );
+        if failed > 0 {
+            println!("{}", summary.red().bold());
+            return Err(checks_failed(&checks));
+        } else if warnings > 0 {
+            println!("{}", summary.yellow().bold());
+        } else {
+            println!("{}", summary.green().bold());
+        }
+
+        Ok(())
+    }
+}
+
+fn checks_failed(checks: &[Check]) -> VmCliError {
+    VmCliError::ChecksFailed {
+        failed: checks
+            .iter()
+            .filter(|c| c.status == CheckStatus::Fail)
+            .map(|c| c.name.clone())
+            .collect(),
+        total: checks.len(),
+    }
+}
+
+fn connect_checks(stages: &[ConnectStage], host: &str, insecure: bool) -> Vec<Check> {
+    let mut checks = Vec::new();
+
+    for stage in stages {
+        if stage.stage == "http" && host.starts_with("http://") {
+            checks.push(Check::pass("tls", "не используется (http)"));
+        }
+        let check = match (stage.stage, stage.ok) {
+            ("url", _) => Check::fail("host", stage.detail.clone(), format!("Адрес '{}' некорректен: проверьте host или VM_HOST", host)),
+            ("dns", true) => Check::pass("dns", stage.detail.clone()),
+            ("dns", false) => Check::fail("dns", stage.detail.clone(), "Проверьте имя хоста и настройки DNS"),
+            ("tcp", true) => Check::pass("host", format!("{} доступен", stage.detail)),
+            ("tcp", false) => Check::fail("host", stage.detail.clone(), "Убедитесь, что VictoriaMetrics запущена и порт открыт"),
+            ("tls", true) if insecure => Check::warn(
+                "tls",
+                format!("рукопожатие с {} успешно", stage.detail),
+                "Включен --insecure/VM_INSECURE: не используйте этот режим в production",
+            ),
+            ("tls", true) => Check::pass("tls", format!("рукопожатие с {} успешно", stage.detail)),
+            ("tls", false) => Check::fail("tls", stage.detail.clone(), "Проверьте сертификат сервера и что по адресу действительно работает https"),
+            ("http", true) => Check::pass("health", stage.detail.clone()),
+            ("http", false) => Check::fail("health", stage.detail.clone(), "Проверьте base_path и что по адресу отвечает VictoriaMetrics"),
+            _ => continue,
+        };
+        checks.push(check);
+    }
+
+    checks
+}
+
+async fn auth_check(client: &VmClient) -> Check {
+    match client.query("1", None).await {
+        Ok(_) => Check::pass("auth", "запросы к API принимаются"),
+        Err(VmCliError::ApiError { status: Some(status @ (401 | 403)), .. }) => Check::fail(
+            "auth",
+            format!("сервер отклонил запрос (HTTP {})", status),
+            "Сервер требует авторизацию: проверьте учетные данные прокси (например, vmauth)",
+        ),
+        Err(e) => Check::fail(
+            "auth",
+            e.to_string(),
+            "Проверьте, что endpoint запросов доступен (base_path, cluster.query_endpoint)",
+        ),
+    }
+}
+
+async fn version_check(client: &VmClient) -> Check {
+    let minimum = format!("v{}.{}.{}", MIN_SUPPORTED_VERSION.0, MIN_SUPPORTED_VERSION.1, MIN_SUPPORTED_VERSION.2);
+
+    match client.app_version().await {
+        Ok(Some(version)) => match parse_version(&version) {
+            Some(parsed) if parsed >= MIN_SUPPORTED_VERSION => Check::pass("version", version),
+            Some(_) => Check::warn(
+                "version",
+                format!("{} старше {}", version, minimum),
+                format!("Часть команд может не работать: обновите VictoriaMetrics до {} или новее", minimum),
+            ),
+            None => Check::warn("version", version, "Не удалось разобрать версию, совместимость не проверена"),
+        },
+        Ok(None) => Check::warn(
+            "version",
+            "vm_app_version не найден в /metrics",
+            "Совместимость не проверена: убедитесь, что host указывает на компонент VictoriaMetrics",
+        ),
+        Err(e) => Check::warn("version", e.to_string(), "Совместимость не проверена: /metrics недоступен"),
+    }
+}
+
+async fn cluster_check(client: &VmClient, role: &str, host: &str) -> Check {
+    match client.probe_health(host).await {
+        Ok(status) if is_healthy(&status) => Check::pass(role, format!("{} ({})", host, status)),
+        Ok(status) => Check::warn(role, format!("{} ({})", host, status), "Компонент отвечает, но сообщает о проблемах"),
+        Err(e) => Check::fail(
+            role,
+            format!("{}: {}", host, e),
+            format!("Проверьте адрес {} в секции [cluster]", role),
+        ),
+    }
+}
+
+fn parse_version(version: &str) -> Option<(u32, u32, u32)> {
+    let mut parts = version.trim_start_matches('v').split(['.', '-']);
+    let major = parts.next()?.parse().ok()?;
+    let minor = parts.next()?.parse().ok()?;
+    let patch = parts.next().and_then(|p| p.parse().ok()).unwrap_or(0);
+    Some((major, minor, patch))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn stage(name: &'static str, ok: bool, detail: &str) -> ConnectStage {
+        ConnectStage { stage: name, ok, duration_ms: 1.0, detail: detail.to_string() }
+    }
+
+    fn summary(checks: &[Check]) -> Vec<(&str, CheckStatus)> {
+        checks.iter().map(|c| (c.name.as_str(), c.status)).collect()
+    }
+
+    #[test]
+    fn connect_checks_for_plain_http() {
+        let stages = [
+            stage("dns", true, "127.0.0.1:8428"),
+            stage("tcp", true, "127.0.0.1:8428"),
+            stage("http", true, "/health: OK"),
+        ];
+
+        let checks = connect_checks(&stages, "http://localhost:8428", false);
+
+        assert_eq!(
+            summary(&checks),
+            vec![
+                ("dns", CheckStatus::Pass),
+                ("host", CheckStatus::Pass),
+                ("tls", CheckStatus::Pass),
+                ("health", CheckStatus::Pass),
+            ]
+        );
+        assert_eq!(checks[2].detail, "не используется (http)");
+    }
+
+    #[test]
+    fn connect_checks_report_failed_stage_with_hint() {
+        let stages = [stage("dns", true, "10.0.0.1:8428"), stage("tcp", false, "connection refused")];
+
+        let checks = connect_checks(&stages, "http://vm:8428", false);
+
+        assert_eq!(summary(&checks), vec![("dns", CheckStatus::Pass), ("host", CheckStatus::Fail)]);
+        assert_eq!(checks[1].detail, "connection refused");
+        assert!(checks[1].hint.is_some());
+    }
+
+    #[test]
+    fn connect_checks_warn_about_insecure_tls() {
+        let stages = [stage("tls", true, "vm"), stage("http", true, "/health: OK")];
+
+        let secure = connect_checks(&stages, "https://vm", false);
+        let insecure = connect_checks(&stages, "https://vm", true);
+
+        assert_eq!(secure[0].status, CheckStatus::Pass);
+        assert_eq!(insecure[0].status, CheckStatus::Warn);
+    }
+
+    #[test]
+    fn apply_config_reports_load_error() {
+        let mut doctor = DoctorCommand::try_parse_from(["doctor"]).unwrap();
+        let error = VmCliError::ConfigError(config::ConfigError::Message("bad toml".to_string()));
+
+        doctor.apply_config(&Config::default(), None, Some(&error));
+
+        assert_eq!(summary(&doctor.config_checks), vec![("config", CheckStatus::Fail)]);
+    }
+
+    #[test]
+    fn checks_failed_lists_failed_check_names() {
+        let checks = [
+            Check::pass("config", "ok"),
+            Check::fail("dns", "no such host", "fix dns"),
+            Check::warn("version", "old", "upgrade"),
+            Check::fail("host", "refused", "start vm"),
+        ];
+
+        match checks_failed(&checks) {
+            VmCliError::ChecksFailed { failed, total } => {
+                assert_eq!(failed, vec!["dns", "host"]);
+                assert_eq!(total, 4);
+            }
+            other => panic!("unexpected error: {}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn execute_fails_when_a_check_fails() {
+        let port = std::net::TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap().port();
+        let client = VmClient::new(&format!("http://127.0.0.1:{}", port), 5, None, None, None).unwrap();
+        let doctor = DoctorCommand::try_parse_from(["doctor"]).unwrap();
+
+        let error = doctor.execute(&client).await.unwrap_err();
+
+        assert!(matches!(&error, VmCliError::ChecksFailed { failed, .. } if failed == &["host"]));
+        assert_eq!(error.exit_code(), 13);
+    }
+
+    #[test]
+    fn parse_version_accepts_prefixed_and_suffixed_versions() {
+        assert_eq!(parse_version("v1.93.4"), Some((1, 93, 4)));
+        assert_eq!(parse_version("1.87-cluster"), Some((1, 87, 0)));
+        assert_eq!(parse_version("latest"), None);
+    }
+}
@@ -1,18 +1,43 @@
-use crate::api::VmClient;
+use crate::api::{MetricMetadata, VmClient};
+use crate::batch::{BatchErrors, ErrorPolicy};
+use crate::config::ExportConfig;
 use crate::error::Result;
-use crate::utils::parse_time_range;
+use crate::messages::{self, Msg};
+use crate::output;
+use crate::utils::{
+    checksum_path, format_float, format_sample_value, format_series, format_uptime, parse_bytes, parse_label,
+    parse_time_range, parse_timestamp, sha256_hex, write_checksum, HashingWriter,
+};
 use clap::Parser;
 use colored::*;
 use indicatif::{ProgressBar, ProgressStyle};
+use reqwest::Response;
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap, VecDeque};
 use std::fs::File;
-use std::io::Write;
+use std::borrow::Cow;
+use std::io::{BufRead, BufReader, BufWriter, Read, Seek, SeekFrom, Write};
+use std::path::Path;
+use std::time::{Duration, Instant};
 
-use tracing::info;
+use tracing::{info, warn};
 
 #[derive(Parser)]
 pub struct ExportCommand {
-    #[arg(value_name = "MATCH")]
-    match_: String,
+    #[arg(value_name = "MATCH", required_unless_present = "profile")]
+    match_: Option<String>,
+
+    #[arg(long)]
+    profile: Option<String>,
+
+    #[arg(skip)]
+    profile_matches: Vec<String>,
+
+    #[arg(skip)]
+    profile_range: Option<String>,
+
+    #[arg(long = "match", value_name = "MATCH")]
+    extra_matches: Vec<String>,
 
     #[arg(short, long)]
     start: Option<String>,
@@ -26,11 +51,90 @@ pub struct ExportCommand {
     #[arg(short, long)]
     output: Option<String>,
 
+    #[arg(long, value_name = "DIR", conflicts_with_all = ["output", "format"])]
+    output_dir: Option<String>,
+
+    #[arg(long, requires = "output")]
+    append: bool,
+
+    #[arg(long, requires = "output")]
+    checksum: bool,
+
+    #[arg(long, value_name = "PATH", conflicts_with = "output_dir")]
+    metadata_file: Option<String>,
+
     #[arg(short, long, value_enum, default_value = "prometheus")]
     format: ExportFormat,
 
     #[arg(long)]
     progress: bool,
+
+    #[arg(long, value_name = "BYTES_PER_SEC", value_parser = parse_rate)]
+    max_rate: Option<u64>,
+
+    #[arg(long, value_name = "N", value_parser = clap::value_parser!(u64).range(1..), conflicts_with_all = ["tail", "dry_run"])]
+    head: Option<u64>,
+
+    #[arg(long, value_name = "N", value_parser = clap::value_parser!(u64).range(1..), conflicts_with = "dry_run")]
+    tail: Option<u64>,
+
+    #[arg(long, value_name = "DURATION", requires = "aggr")]
+    step: Option<String>,
+
+    #[arg(long, value_enum, requires = "step", conflicts_with_all = ["head", "tail", "max_rate", "dry_run"])]
+    aggr: Option<ExportAggr>,
+
+    #[arg(long = "drop-label", value_name = "NAME")]
+    drop_labels: Vec<String>,
+
+    #[arg(long = "rename-label", value_name = "OLD=NEW", value_parser = parse_label)]
+    rename_labels: Vec<(String, String)>,
+
+    #[arg(long = "add-label", value_name = "NAME=VALUE", value_parser = parse_label)]
+    add_labels: Vec<(String, String)>,
+
+    #[arg(long)]
+    dry_run: bool,
+
+    #[arg(long, requires = "aggr")]
+    skip_errors: bool,
+}
+
+#[derive(clap::ValueEnum, Clone, Copy)]
+pub enum ExportAggr {
+    Avg,
+    Min,
+    Max,
+    Sum,
+}
+
+impl ExportAggr {
+    fn function(self) -> &'static str {
+        match self {
+            ExportAggr::Avg => "avg_over_time",
+            ExportAggr::Min => "min_over_time",
+            ExportAggr::Max => "max_over_time",
+            ExportAggr::Sum => "sum_over_time",
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+enum Preview {
+    Head(usize),
+    Tail(usize),
+}
+
+enum ExportSource {
+    Buffered(String),
+    Stream(Response),
+}
+
+#[derive(Serialize)]
+struct MetricFile {
+    metric: String,
+    path: String,
+    lines: usize,
 }
 
 #[derive(clap::ValueEnum, Clone)]
@@ -38,21 +142,33 @@ pub enum ExportFormat {
     Prometheus,
     Json,
     Csv,
+    #[value(name = "openmetrics")]
+    OpenMetrics,
+    Parquet,
 }
 
 impl ExportCommand {
     pub async fn execute(&self, client: &VmClient) -> Result<()> {
-        info!("Экспорт данных: {}", self.match_);
+        let matches = self.matches();
+        info!("Экспорт данных: {}", matches.join(", "));
 
         let (start, end) = self.determine_time_range()?;
 
         info!("Временной диапазон: {} - {}", start, end);
 
+        self.validate_relabel()?;
+        self.validate_parquet()?;
+        self.validate_append()?;
+
+        if self.dry_run {
+            return self.estimate_export(client, &start, &end).await;
+        }
+
         let progress_bar = if self.progress {
             let pb = ProgressBar::new_spinner();
             pb.set_style(
                 ProgressStyle::default_spinner()
-                    .template("{spinner:.green} {wide_msg}")
+                    .template("{spinner:.green} {bytes} ({binary_bytes_per_sec}) {wide_msg}")
                     .unwrap(),
             );
             pb.set_message("Экспорт данных...");
@@ -61,23 +177,394 @@ impl ExportCommand {
             None
         };
 
-        let export_data = client.export(&self.match_, Some(&start), Some(&end)).await?;
+        let preview = self
+            .head
+            .map(|n| Preview::Head(n as usize))
+            .or(self.tail.map(|n| Preview::Tail(n as usize)));
+
+        let mut errors = BatchErrors::new(ErrorPolicy::from_flags(self.skip_errors));
+        let source = if let (Some(aggr), Some(step)) = (self.aggr, &self.step) {
+            ExportSource::Buffered(downsample(client, &matches, &start, &end, step, aggr, &mut errors).await?)
+        } else if let Some(preview) = preview {
+            let response = client.export_stream(&matches, Some(&start), Some(&end)).await?;
+            ExportSource::Buffered(download_preview(response, preview, self.max_rate, progress_bar.as_ref()).await?)
+        } else {
+            ExportSource::Stream(client.export_stream(&matches, Some(&start), Some(&end)).await?)
+        };
+
+        self.write_export(client, source, progress_bar.as_ref()).await?;
+        errors.finish()
+    }
+
+    async fn write_export(&self, client: &VmClient, source: ExportSource, progress: Option<&ProgressBar>) -> Result<()> {
+        if let (ExportFormat::Parquet, Some(output_path)) = (&self.format, &self.output) {
+            return self.export_parquet(source, output_path, progress).await;
+        }
+
+        let export_data = match source {
+            ExportSource::Buffered(data) => data,
+            ExportSource::Stream(response) => match self.streamed_output() {
+                Some(output_path) => {
+                    let (bytes, hash) = self.stream_to_file(response, output_path, progress).await?;
+                    if let Some(pb) = progress {
+                        pb.finish_with_message("Экспорт завершен");
+                    }
+                    return self.report_saved(output_path, bytes, &hash);
+                }
+                None => download(response, self.max_rate, progress).await?,
+            },
+        };
 
-        if let Some(pb) = &progress_bar {
+        if let Some(pb) = progress {
             pb.finish_with_message("Экспорт завершен");
         }
 
-        let formatted_data = self.format_data(&export_data)?;
+        let export_data = self.relabel(export_data)?;
 
-        if let Some(output_path) = &self.output {
-            self.save_to_file(&formatted_data, output_path)?;
+        if let Some(output_dir) = &self.output_dir {
+            let files = split_by_metric(&export_data, Path::new(output_dir))?;
+            if output::is_json_mode() {
+                return output::print_data(&serde_json::json!({
+                    "output_dir": output_dir,
+                    "files": files,
+                }));
+            }
+            for file in &files {
+                println!("  {} ({} строк)", file.path, file.lines);
+            }
             println!(
-                "{} {}",
+                "{} {} ({} файлов)",
                 "Экспорт сохранен в:".green().bold(),
-                output_path
+                output_dir,
+                files.len()
             );
+            return Ok(());
+        }
+
+        let metadata = if self.metadata_file.is_some() {
+            client.metadata(None, None).await?.data
+        } else if matches!(self.format, ExportFormat::OpenMetrics) {
+            match client.metadata(None, None).await {
+                Ok(response) => response.data,
+                Err(e) => {
+                    warn!("Не удалось получить метаданные, типы метрик будут unknown: {}", e);
+                    HashMap::new()
+                }
+            }
+        } else {
+            HashMap::new()
+        };
+
+        let formatted_data = self.format_data(&export_data, &metadata)?;
+
+        if let Some(metadata_path) = &self.metadata_file {
+            let sidecar = metadata_for_export(&export_data, &metadata);
+            std::fs::write(metadata_path, serde_json::to_string_pretty(&sidecar)?)?;
+            let note = format!("Метаданные ({} метрик) сохранены в: {}", sidecar.len(), metadata_path);
+            if self.output.is_some() && !output::is_json_mode() {
+                println!("{}", note);
+            } else {
+                eprintln!("{}", note);
+            }
+        }
+
+        if let Some(output_path) = &self.output {
+            let (bytes, hash) = self.save_to_file(&formatted_data, output_path)?;
+            return self.report_saved(output_path, bytes, &hash);
+        } else if output::is_json_mode() {
+            output::print_data(&formatted_data)?;
+        } else {
+            output::print_text(&formatted_data);
+        }
+
+        Ok(())
+    }
+
+    fn report_saved(&self, output_path: &str, bytes: u64, hash: &str) -> Result<()> {
+        let checksum = self.write_checksum(output_path, hash)?;
+        if output::is_json_mode() {
+            return output::print_data(&serde_json::json!({
+                "output": output_path,
+                "bytes": bytes,
+                "sha256": checksum,
+            }));
+        }
+        println!(
+            "{} {}",
+            "Экспорт сохранен в:".green().bold(),
+            output_path
+        );
+        Ok(())
+    }
+
+    /// Файл, в который экспорт в формате prometheus пишется по мере загрузки, не
+    /// собирая ответ в памяти. Для остальных форматов и для --metadata-file нужен
+    /// весь экспорт целиком.
+    fn streamed_output(&self) -> Option<&str> {
+        match self.format {
+            ExportFormat::Prometheus if self.metadata_file.is_none() => self.output.as_deref(),
+            _ => None,
+        }
+    }
+
+    /// Возвращает число записанных байт и их SHA-256: с --append хеш покрывает
+    /// только дописанную часть, а не весь файл.
+    async fn stream_to_file(&self, response: Response, path: &str, progress: Option<&ProgressBar>) -> Result<(u64, String)> {
+        let file = if self.append {
+            let needs_newline = missing_trailing_newline(path)?;
+            let mut file = std::fs::OpenOptions::new().create(true).append(true).open(path)?;
+            if needs_newline {
+                file.write_all(b"\n")?;
+            }
+            file
         } else {
-            println!("{}", formatted_data);
+            output::track_partial_file(Path::new(path));
+            File::create(path)?
+        };
+
+        let mut writer = HashingWriter::new(BufWriter::new(file));
+        let mut written = 0u64;
+        stream_lines(response, self.max_rate, progress, |line| {
+            let line = self.relabel_sample(line)?;
+            writer.write_all(line.as_bytes())?;
+            writer.write_all(b"\n")?;
+            written += line.len() as u64 + 1;
+            Ok(())
+        })
+        .await?;
+        writer.flush()?;
+        let (_, hash) = writer.finish();
+
+        if !self.append {
+            output::complete_partial_file(Path::new(path));
+        }
+        Ok((written, hash))
+    }
+
+    pub fn apply_profile(&mut self, export_config: Option<&ExportConfig>) -> Result<()> {
+        let Some(name) = &self.profile else {
+            return Ok(());
+        };
+
+        let profile = export_config
+            .and_then(|c| c.profiles.get(name))
+            .ok_or_else(|| {
+                crate::error::VmCliError::ConfigError(config::ConfigError::Message(format!(
+                    "Профиль экспорта '{}' не найден в конфигурации",
+                    name
+                )))
+            })?;
+
+        self.profile_matches = profile.matches.clone();
+        self.profile_range = profile.range.clone();
+        Ok(())
+    }
+
+    fn validate_relabel(&self) -> Result<()> {
+        let names = self
+            .drop_labels
+            .iter()
+            .chain(self.rename_labels.iter().flat_map(|(old, new)| [old, new]))
+            .chain(self.add_labels.iter().map(|(name, _)| name));
+
+        for name in names {
+            if name == "__name__" || name.trim().is_empty() {
+                return Err(crate::error::VmCliError::InvalidQuery(format!(
+                    "Недопустимое имя метки для перемаркировки: '{}'",
+                    name
+                )));
+            }
+        }
+        Ok(())
+    }
+
+    fn validate_parquet(&self) -> Result<()> {
+        if !matches!(self.format, ExportFormat::Parquet) {
+            return Ok(());
+        }
+        if self.output.is_none() {
+            return Err(crate::error::VmCliError::InvalidQuery(
+                "Для формата parquet укажите файл через --output".to_string(),
+            ));
+        }
+        Ok(())
+    }
+
+    /// Дописывать можно только построчные форматы: JSON-массив, OpenMetrics с
+    /// завершающим `# EOF` и Parquet после склейки перестают быть корректными.
+    fn validate_append(&self) -> Result<()> {
+        let format = match self.format {
+            _ if !self.append => return Ok(()),
+            ExportFormat::Prometheus | ExportFormat::Csv => return Ok(()),
+            ExportFormat::Json => "json",
+            ExportFormat::OpenMetrics => "openmetrics",
+            ExportFormat::Parquet => "parquet",
+        };
+        Err(crate::error::VmCliError::InvalidQuery(format!(
+            "--append не поддерживается для формата {}: используйте prometheus или csv",
+            format
+        )))
+    }
+
+    async fn export_parquet(&self, source: ExportSource, output_path: &str, progress: Option<&ProgressBar>) -> Result<()> {
+        let mut sink = ParquetSink::new()?;
+        match source {
+            ExportSource::Buffered(data) => {
+                for line in data.lines() {
+                    sink.push_line(&self.relabel_sample(line)?)?;
+                }
+            }
+            ExportSource::Stream(response) => {
+                stream_lines(response, self.max_rate, progress, |line| {
+                    sink.push_line(&self.relabel_sample(line)?)
+                })
+                .await?;
+            }
+        }
+
+        if let Some(pb) = progress {
+            pb.finish_with_message("Экспорт завершен");
+        }
+
+        output::track_partial_file(Path::new(output_path));
+        let (rows, hash) = sink.finish(output_path)?;
+        output::complete_partial_file(Path::new(output_path));
+        let checksum = self.write_checksum(output_path, &hash)?;
+
+        if output::is_json_mode() {
+            return output::print_data(&serde_json::json!({
+                "output": output_path,
+                "rows": rows,
+                "sha256": checksum,
+            }));
+        }
+        println!(
+            "{} {} ({} {})",
+            "Экспорт сохранен в:".green().bold(),
+            output_path,
+            rows,
+            messages::t(Msg::Records)
+        );
+        Ok(())
+    }
+
+    fn relabel(&self, data: String) -> Result<String> {
+        if !self.has_relabel() {
+            return Ok(data);
+        }
+
+        let mut result = String::with_capacity(data.len());
+        for line in data.lines() {
+            result.push_str(&self.relabel_sample(line)?);
+            result.push('\n');
+        }
+        Ok(result)
+    }
+
+    fn has_relabel(&self) -> bool {
+        !self.drop_labels.is_empty() || !self.rename_labels.is_empty() || !self.add_labels.is_empty()
+    }
+
+    fn relabel_sample<'a>(&self, line: &'a str) -> Result<Cow<'a, str>> {
+        let trimmed = line.trim();
+        if !self.has_relabel() || trimmed.is_empty() || trimmed.starts_with('#') {
+            return Ok(Cow::Borrowed(line));
+        }
+        self.relabel_line(trimmed).map(Cow::Owned)
+    }
+
+    fn relabel_line(&self, line: &str) -> Result<String> {
+        let parsed = split_sample_line(line)
+            .map(|(metric_part, value, timestamp)| (parse_series_labels(metric_part), value, timestamp))
+            .filter(|((name, _), _, _)| !name.is_empty());
+        let Some(((name, labels), value, timestamp)) = parsed else {
+            return Err(crate::error::VmCliError::InvalidQuery(format!(
+                "Не удалось разобрать строку экспорта для перемаркировки: '{}'",
+                line
+            )));
+        };
+        let mut metric: HashMap<String, String> = labels.into_iter().collect();
+
+        for label in &self.drop_labels {
+            metric.remove(label);
+        }
+        for (old, new) in &self.rename_labels {
+            if let Some(label_value) = metric.remove(old) {
+                metric.insert(new.clone(), label_value);
+            }
+        }
+        for (label, label_value) in &self.add_labels {
+            metric.insert(label.clone(), label_value.clone());
+        }
+        metric.insert("__name__".to_string(), name);
+
+        Ok(format!("{} {} {}", format_series(&metric), value, timestamp))
+    }
+
+    fn matches(&self) -> Vec<&str> {
+        self.match_
+            .iter()
+            .chain(self.profile_matches.iter())
+            .chain(self.extra_matches.iter())
+            .map(String::as_str)
+            .collect()
+    }
+
+    async fn estimate_export(&self, client: &VmClient, start: &str, end: &str) -> Result<()> {
+        let matches = self.matches();
+        let series = client.series(&matches, Some(start), Some(end), None).await?;
+
+        let range_seconds = match (parse_timestamp(start), parse_timestamp(end)) {
+            (Some(s), Some(e)) if e > s => Some((e - s) as u64),
+            _ => None,
+        };
+
+        let samples = match range_seconds {
+            Some(seconds) if !series.data.is_empty() => {
+                let mut total = 0.0;
+                for selector in &matches {
+                    let query = format!("sum(count_over_time({}[{}s]))", selector, seconds);
+                    let response = client.query(&query, Some(end)).await?;
+                    total += response
+                        .data
+                        .result
+                        .first()
+                        .and_then(|r| r.value.as_ref())
+                        .and_then(|(_, v)| v.parse::<f64>().ok())
+                        .unwrap_or(0.0);
+                }
+                Some(total.to_string())
+            }
+            _ => None,
+        };
+
+        if output::is_json_mode() {
+            return output::print_data(&serde_json::json!({
+                "matches": matches,
+                "start": start,
+                "end": end,
+                "series": series.data.len(),
+                "samples": samples,
+            }));
+        }
+
+        println!("{}", "Режим проверки (dry-run)".yellow().bold());
+        println!("Селектор: {}", matches.join(", "));
+        println!("Временной диапазон: {} - {}", format_timestamp(start), format_timestamp(end));
+        println!("{} {}", "Серий:".bold(), series.data.len());
+
+        if series.data.is_empty() {
+            println!("{}", "Нет данных для экспорта".yellow());
+            return Ok(());
+        }
+
+        if let Some(seconds) = range_seconds {
+            println!("Длительность диапазона: {}", format_uptime(&seconds.to_string()));
+        }
+
+        match samples {
+            Some(samples) => println!("{} {}", "Оценка количества точек:".bold(), samples),
+            None => println!("{}", "Оценка количества точек недоступна для нечислового диапазона".yellow()),
         }
 
         Ok(())
@@ -86,18 +573,23 @@ impl ExportCommand {
     fn determine_time_range(&self) -> Result<(String, String)> {
         if let Some(range) = &self.range {
             parse_time_range(range)
-                .map_err(|e| crate::error::VmCliError::TimeParseError(e))
+                .map_err(crate::error::VmCliError::TimeParseError)
         } else if let (Some(start), Some(end)) = (&self.start, &self.end) {
             Ok((start.clone(), end.clone()))
+        } else if let Some(range) = &self.profile_range {
+            parse_time_range(range)
+                .map_err(crate::error::VmCliError::TimeParseError)
         } else {
             parse_time_range("1h")
-                .map_err(|e| crate::error::VmCliError::TimeParseError(e))
+                .map_err(crate::error::VmCliError::TimeParseError)
         }
     }
 
-    fn format_data(&self, data: &str) -> Result<String> {
+    fn format_data(&self, data: &str, metadata: &HashMap<String, Vec<MetricMetadata>>) -> Result<String> {
         match self.format {
             ExportFormat::Prometheus => Ok(data.to_string()),
+            ExportFormat::OpenMetrics => Ok(to_openmetrics(data, metadata)),
+            ExportFormat::Parquet => unreachable!("parquet записывается через write_parquet"),
             ExportFormat::Json => {
                 let lines: Vec<&str> = data.lines().collect();
                 let mut json_data = Vec::new();
@@ -107,8 +599,8 @@ impl ExportCommand {
                         continue;
                     }
 
-                    if let Some((metric_part, value_part)) = line.rsplit_once(' ') {
-                        let mut metric_info = std::collections::HashMap::new();
+                    if let Some((metric_part, value, timestamp)) = split_sample_line(line) {
+                        let mut metric_info = BTreeMap::new();
                         
                         if let Some((name, labels)) = metric_part.split_once('{') {
                             metric_info.insert("__name__".to_string(), name.to_string());
@@ -125,18 +617,16 @@ impl ExportCommand {
                             metric_info.insert("__name__".to_string(), metric_part.to_string());
                         }
 
-                        if let Some((timestamp, value)) = value_part.rsplit_once(' ') {
-                            let entry = serde_json::json!({
-                                "metric": metric_info,
-                                "value": [timestamp.parse::<f64>().unwrap_or(0.0), value.parse::<f64>().unwrap_or(0.0)]
-                            });
-                            json_data.push(entry);
-                        }
+                        let entry = serde_json::json!({
+                            "metric": metric_info,
+                            "value": [json_number(timestamp), json_number(value)]
+                        });
+                        json_data.push(entry);
                     }
                 }
 
                 serde_json::to_string_pretty(&json_data)
-                    .map_err(|e| crate::error::VmCliError::JsonError(e))
+                    .map_err(crate::error::VmCliError::JsonError)
             }
             ExportFormat::Csv => {
                 let mut csv_data = String::new();
@@ -148,16 +638,14 @@ impl ExportCommand {
                         continue;
                     }
 
-                    if let Some((metric_part, value_part)) = line.rsplit_once(' ') {
+                    if let Some((metric_part, value, timestamp)) = split_sample_line(line) {
                         let metric_name = if let Some((name, _)) = metric_part.split_once('{') {
                             name
                         } else {
                             metric_part
                         };
 
-                        if let Some((timestamp, value)) = value_part.rsplit_once(' ') {
-                            csv_data.push_str(&format!("\n{},{},{}", timestamp, value, metric_name));
-                        }
+                        csv_data.push_str(&format!("\n{},{},{}", timestamp, format_sample_value(value), metric_name));
                     }
                 }
 
@@ -166,13 +654,1205 @@ impl ExportCommand {
         }
     }
 
-    fn save_to_file(&self, data: &str, path: &str) -> Result<()> {
+    /// Возвращает число записанных байт и их SHA-256.
+    fn save_to_file(&self, data: &str, path: &str) -> Result<(u64, String)> {
+        if self.append {
+            let payload = self.append_payload(data, path)?;
+            let mut file = std::fs::OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(path)
+                .map_err(crate::error::VmCliError::IoError)?;
+            file.write_all(payload.as_bytes())
+                .map_err(crate::error::VmCliError::IoError)?;
+            return Ok((payload.len() as u64, sha256_hex(payload.as_bytes())));
+        }
+
+        output::track_partial_file(Path::new(path));
         let mut file = File::create(path)
-            .map_err(|e| crate::error::VmCliError::IoError(e))?;
+            .map_err(crate::error::VmCliError::IoError)?;
         
         file.write_all(data.as_bytes())
-            .map_err(|e| crate::error::VmCliError::IoError(e))?;
+            .map_err(crate::error::VmCliError::IoError)?;
         
+        output::complete_partial_file(Path::new(path));
+        Ok((data.len() as u64, sha256_hex(data.as_bytes())))
+    }
+
+    fn write_checksum(&self, path: &str, hash: &str) -> Result<Option<String>> {
+        if !self.checksum {
+            return Ok(None);
+        }
+
+        write_checksum(Path::new(path), hash)?;
+        if !output::is_json_mode() {
+            println!("SHA-256 сохранен в: {}", checksum_path(Path::new(path)).display());
+        }
+        Ok(Some(hash.to_string()))
+    }
+
+    fn append_payload<'a>(&self, data: &'a str, path: &str) -> Result<Cow<'a, str>> {
+        let file = match File::open(path) {
+            Ok(file) if file.metadata()?.len() > 0 => file,
+            _ => return Ok(Cow::Borrowed(data)),
+        };
+
+        let mut payload = data;
+        if matches!(self.format, ExportFormat::Csv) {
+            let mut existing_header = String::new();
+            BufReader::new(&file).read_line(&mut existing_header)?;
+            let (header, rows) = data.split_once('\n').unwrap_or((data, ""));
+
+            if existing_header.trim_end() == header {
+                payload = rows;
+            } else {
+                eprintln!(
+                    "{} заголовок CSV в '{}' отличается от текущего экспорта ({}), данные дописываются как есть",
+                    messages::t(Msg::Warning).yellow(),
+                    path,
+                    header
+                );
+            }
+        }
+
+        if !missing_trailing_newline(path)? || payload.is_empty() {
+            Ok(Cow::Borrowed(payload))
+        } else {
+            Ok(Cow::Owned(format!("\n{}", payload)))
+        }
+    }
+}
+
+fn parse_rate(value: &str) -> std::result::Result<u64, String> {
+    match parse_bytes(value) {
+        Some(rate) if rate > 0 => Ok(rate),
+        _ => Err(format!("неверная скорость '{}', ожидается число байт в секунду (например, 512K, 2M)", value)),
+    }
+}
+
+struct RateLimiter {
+    rate: f64,
+    tokens: f64,
+    last: Instant,
+}
+
+impl RateLimiter {
+    fn new(rate: u64) -> Self {
+        Self {
+            rate: rate as f64,
+            tokens: rate as f64,
+            last: Instant::now(),
+        }
+    }
+
+    async fn acquire(&mut self, bytes: usize) {
+        let now = Instant::now();
+        self.tokens = (self.tokens + now.duration_since(self.last).as_secs_f64() * self.rate).min(self.rate);
+        self.last = now;
+
+        self.tokens -= bytes as f64;
+        if self.tokens < 0.0 {
+            tokio::time::sleep(Duration::from_secs_f64(-self.tokens / self.rate)).await;
+        }
+    }
+}
+
+async fn download(response: Response, max_rate: Option<u64>, progress: Option<&ProgressBar>) -> Result<String> {
+    let mut body = String::new();
+    stream_lines(response, max_rate, progress, |line| {
+        body.push_str(line);
+        body.push('\n');
         Ok(())
+    })
+    .await?;
+    Ok(body)
+}
+
+/// Читает тело ответа по мере поступления с учетом --max-rate и передает
+/// каждую строку (без перевода строки) в `on_line`.
+async fn stream_lines(
+    mut response: Response,
+    max_rate: Option<u64>,
+    progress: Option<&ProgressBar>,
+    mut on_line: impl FnMut(&str) -> Result<()>,
+) -> Result<u64> {
+    let mut limiter = max_rate.map(RateLimiter::new);
+    let mut pending: Vec<u8> = Vec::new();
+    let mut received = 0u64;
+
+    while let Some(chunk) = response.chunk().await? {
+        if let Some(limiter) = &mut limiter {
+            limiter.acquire(chunk.len()).await;
+        }
+        received += chunk.len() as u64;
+        if let Some(pb) = progress {
+            pb.set_position(received);
+        }
+
+        pending.extend_from_slice(&chunk);
+        let Some(last) = pending.iter().rposition(|b| *b == b'\n') else {
+            continue;
+        };
+        let complete: Vec<u8> = pending.drain(..=last).collect();
+        for line in utf8(&complete)?.lines() {
+            on_line(line)?;
+        }
+    }
+
+    if !pending.is_empty() {
+        on_line(utf8(&pending)?.trim_end_matches('\r'))?;
+    }
+    Ok(received)
+}
+
+/// Непустой файл, последняя строка которого не завершена переводом строки.
+fn missing_trailing_newline(path: &str) -> Result<bool> {
+    let mut file = match File::open(path) {
+        Ok(file) if file.metadata()?.len() > 0 => file,
+        _ => return Ok(false),
+    };
+
+    let mut last = [0u8; 1];
+    file.seek(SeekFrom::End(-1))?;
+    file.read_exact(&mut last)?;
+    Ok(last[0] != b'\n')
+}
+
+fn utf8(bytes: &[u8]) -> Result<&str> {
+    std::str::from_utf8(bytes).map_err(|e| crate::error::VmCliError::Unknown(e.to_string()))
+}
+
+async fn downsample(
+    client: &VmClient,
+    matches: &[&str],
+    start: &str,
+    end: &str,
+    step: &str,
+    aggr: ExportAggr,
+    errors: &mut BatchErrors,
+) -> Result<String> {
+    let mut data = String::new();
+
+    for selector in matches {
+        let query = format!("{}({}[{}]) keep_metric_names", aggr.function(), selector, step);
+        info!("Прореживание на стороне сервера: {}", query);
+        let Some(response) = errors.record(selector, client.query_range(&query, start, end, step).await)? else {
+            continue;
+        };
+
+        for result in &response.data.result {
+            let series = format_series(&result.metric);
+            for (timestamp, value) in result.value.iter().chain(result.values.iter().flatten()) {
+                data.push_str(&format!("{} {} {}\n", series, value, (timestamp * 1000.0).round() as i64));
+            }
+        }
+    }
+
+    Ok(data)
+}
+
+async fn download_preview(
+    mut response: Response,
+    preview: Preview,
+    max_rate: Option<u64>,
+    progress: Option<&ProgressBar>,
+) -> Result<String> {
+    let mut limiter = max_rate.map(RateLimiter::new);
+    let mut pending: Vec<u8> = Vec::new();
+    let mut lines: VecDeque<String> = VecDeque::new();
+    let mut received = 0u64;
+
+    loop {
+        let chunk = response.chunk().await?;
+        let finished = chunk.is_none();
+
+        if let Some(chunk) = &chunk {
+            if let Some(limiter) = &mut limiter {
+                limiter.acquire(chunk.len()).await;
+            }
+            received += chunk.len() as u64;
+            if let Some(pb) = progress {
+                pb.set_position(received);
+            }
+            pending.extend_from_slice(chunk);
+        }
+
+        while let Some(pos) = pending.iter().position(|b| *b == b'\n') {
+            let line: Vec<u8> = pending.drain(..=pos).collect();
+            if push_preview_line(&mut lines, &line, preview) {
+                return Ok(join_lines(lines));
+            }
+        }
+
+        if finished {
+            if !pending.is_empty() {
+                push_preview_line(&mut lines, &pending, preview);
+            }
+            return Ok(join_lines(lines));
+        }
+    }
+}
+
+fn push_preview_line(lines: &mut VecDeque<String>, raw: &[u8], preview: Preview) -> bool {
+    let line = String::from_utf8_lossy(raw).trim_end().to_string();
+    if line.trim().is_empty() || line.starts_with('#') {
+        return false;
+    }
+
+    lines.push_back(line);
+    match preview {
+        Preview::Head(limit) => lines.len() >= limit,
+        Preview::Tail(limit) => {
+            if lines.len() > limit {
+                lines.pop_front();
+            }
+            false
+        }
+    }
+}
+
+fn join_lines(lines: VecDeque<String>) -> String {
+    let mut data = Vec::from(lines).join("\n");
+    data.push('\n');
+    data
+}
+
+fn split_by_metric(data: &str, dir: &Path) -> Result<Vec<MetricFile>> {
+    std::fs::create_dir_all(dir)?;
+
+    let mut writers: BTreeMap<String, (String, BufWriter<File>, usize)> = BTreeMap::new();
+
+    for line in data.lines() {
+        let trimmed = line.trim();
+        if trimmed.is_empty() || trimmed.starts_with('#') {
+            continue;
+        }
+
+        let name_end = trimmed.find(['{', ' ']).unwrap_or(trimmed.len());
+        let file_name = format!("{}.prom", sanitize_file_name(&trimmed[..name_end]));
+
+        if !writers.contains_key(&file_name) {
+            output::track_partial_file(&dir.join(&file_name));
+            let file = File::create(dir.join(&file_name))?;
+            writers.insert(
+                file_name.clone(),
+                (trimmed[..name_end].to_string(), BufWriter::new(file), 0),
+            );
+        }
+
+        if let Some((_, writer, lines)) = writers.get_mut(&file_name) {
+            writeln!(writer, "{}", trimmed)?;
+            *lines += 1;
+        }
+    }
+
+    let mut files = Vec::with_capacity(writers.len());
+    for (file_name, (metric, mut writer, lines)) in writers {
+        writer.flush()?;
+        output::complete_partial_file(&dir.join(&file_name));
+        files.push(MetricFile {
+            metric,
+            path: dir.join(file_name).display().to_string(),
+            lines,
+        });
+    }
+
+    Ok(files)
+}
+
+fn sanitize_file_name(metric: &str) -> String {
+    let sanitized: String = metric
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() || c == '_' || c == '-' || c == '.' { c } else { '_' })
+        .collect();
+
+    if sanitized.is_empty() || sanitized.chars().all(|c| c == '.') {
+        "_".to_string()
+    } else {
+        sanitized
+    }
+}
+
+const OPENMETRICS_SUFFIXES: [&str; 6] = ["_total", "_bucket", "_sum", "_count", "_created", "_info"];
+
+struct MetricFamily<'a> {
+    name: String,
+    metadata: Option<&'a MetricMetadata>,
+    samples: Vec<String>,
+}
+
+fn to_openmetrics(data: &str, metadata: &HashMap<String, Vec<MetricMetadata>>) -> String {
+    let mut families: Vec<MetricFamily> = Vec::new();
+    let mut index: HashMap<String, usize> = HashMap::new();
+
+    for line in data.lines() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let name_end = line.find(['{', ' ']).unwrap_or(line.len());
+        let (family_name, family_metadata) = resolve_family(&line[..name_end], metadata);
+
+        let position = *index.entry(family_name.clone()).or_insert_with(|| {
+            families.push(MetricFamily {
+                name: family_name,
+                metadata: family_metadata,
+                samples: Vec::new(),
+            });
+            families.len() - 1
+        });
+
+        let sample = match split_sample_line(line) {
+            Some((metric, value, timestamp)) => {
+                format!("{} {} {}", metric, format_sample_value(value), millis_to_seconds(timestamp))
+            }
+            None => line.to_string(),
+        };
+        families[position].samples.push(sample);
+    }
+
+    let mut output = String::new();
+    for family in &families {
+        let metric_type = family
+            .metadata
+            .map(|m| openmetrics_type(&m.metric_type))
+            .unwrap_or("unknown");
+        output.push_str(&format!("# TYPE {} {}\n", family.name, metric_type));
+
+        if let Some(meta) = family.metadata {
+            if !meta.unit.is_empty() {
+                output.push_str(&format!("# UNIT {} {}\n", family.name, meta.unit));
+            }
+            if !meta.help.is_empty() {
+                output.push_str(&format!("# HELP {} {}\n", family.name, escape_help(&meta.help)));
+            }
+        }
+
+        for sample in &family.samples {
+            output.push_str(sample);
+            output.push('\n');
+        }
+    }
+    output.push_str("# EOF\n");
+    output
+}
+
+fn resolve_family<'a>(
+    name: &str,
+    metadata: &'a HashMap<String, Vec<MetricMetadata>>,
+) -> (String, Option<&'a MetricMetadata>) {
+    let candidates = OPENMETRICS_SUFFIXES
+        .iter()
+        .filter_map(|suffix| name.strip_suffix(suffix));
+
+    for candidate in std::iter::once(name).chain(candidates) {
+        if let Some(meta) = metadata.get(candidate).and_then(|m| m.first()) {
+            let family = match (meta.metric_type.as_str(), candidate.strip_suffix("_total")) {
+                ("counter", Some(base)) => base,
+                _ => candidate,
+            };
+            return (family.to_string(), Some(meta));
+        }
+    }
+
+    (name.to_string(), None)
+}
+
+fn metadata_for_export(
+    data: &str,
+    metadata: &HashMap<String, Vec<MetricMetadata>>,
+) -> BTreeMap<String, Vec<MetricMetadata>> {
+    let mut sidecar = BTreeMap::new();
+
+    for line in data.lines() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let name = &line[..line.find(['{', ' ']).unwrap_or(line.len())];
+        let candidates = OPENMETRICS_SUFFIXES.iter().filter_map(|suffix| name.strip_suffix(suffix));
+        if let Some((family, entries)) = std::iter::once(name)
+            .chain(candidates)
+            .find_map(|candidate| metadata.get_key_value(candidate))
+        {
+            sidecar.entry(family.clone()).or_insert_with(|| entries.clone());
+        }
+    }
+
+    sidecar
+}
+
+fn openmetrics_type(metric_type: &str) -> &'static str {
+    match metric_type {
+        "counter" => "counter",
+        "gauge" => "gauge",
+        "histogram" => "histogram",
+        "gaugehistogram" => "gaugehistogram",
+        "summary" => "summary",
+        "info" => "info",
+        "stateset" => "stateset",
+        _ => "unknown",
+    }
+}
+
+fn escape_help(help: &str) -> String {
+    help.replace('\\', "\\\\").replace('\n', "\\n").replace('"', "\\\"")
+}
+
+fn millis_to_seconds(timestamp: &str) -> String {
+    match timestamp.parse::<i64>() {
+        Ok(ms) => format!("{}.{:03}", ms.div_euclid(1000), ms.rem_euclid(1000)),
+        Err(_) => timestamp.to_string(),
+    }
+}
+
+fn parse_series_labels(metric: &str) -> (String, BTreeMap<String, String>) {
+    let mut labels = BTreeMap::new();
+    let Some(open) = metric.find('{') else {
+        return (metric.to_string(), labels);
+    };
+    let mut name = metric[..open].to_string();
+    let inner = &metric[open + 1..metric.rfind('}').unwrap_or(metric.len())];
+
+    let mut chars = inner.chars().peekable();
+    loop {
+        while chars.next_if(|c| *c == ',' || c.is_whitespace()).is_some() {}
+        let key: String = chars.by_ref().take_while(|c| *c != '=').collect();
+        if key.is_empty() || chars.next() != Some('"') {
+            break;
+        }
+
+        let mut value = String::new();
+        while let Some(c) = chars.next() {
+            match c {
+                '\\' => match chars.next() {
+                    Some('n') => value.push('\n'),
+                    Some(other) => value.push(other),
+                    None => break,
+                },
+                '"' => break,
+                _ => value.push(c),
+            }
+        }
+
+        let key = key.trim().to_string();
+        if key == "__name__" {
+            if name.is_empty() {
+                name = value;
+            }
+        } else {
+            labels.insert(key, value);
+        }
+    }
+
+    (name, labels)
+}
+
+#[cfg(feature = "parquet")]
+struct ParquetSink {
+    timestamps: arrow_array::builder::TimestampMillisecondBuilder,
+    values: arrow_array::builder::Float64Builder,
+    names: arrow_array::builder::StringDictionaryBuilder<arrow_array::types::Int32Type>,
+    labels: BTreeMap<String, arrow_array::builder::StringDictionaryBuilder<arrow_array::types::Int32Type>>,
+    rows: usize,
+}
+
+/// Разбирает строки экспорта по одной сразу в колонки Arrow, не держа в памяти
+/// текст ответа; метки, впервые встреченные на середине, дополняются null-ами.
+#[cfg(feature = "parquet")]
+impl ParquetSink {
+    fn new() -> Result<Self> {
+        use arrow_array::builder::{Float64Builder, StringDictionaryBuilder, TimestampMillisecondBuilder};
+
+        Ok(Self {
+            timestamps: TimestampMillisecondBuilder::new().with_timezone("UTC"),
+            values: Float64Builder::new(),
+            names: StringDictionaryBuilder::new(),
+            labels: BTreeMap::new(),
+            rows: 0,
+        })
+    }
+
+    fn push_line(&mut self, line: &str) -> Result<()> {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            return Ok(());
+        }
+
+        let Some((metric, value, timestamp)) = split_sample_line(line) else {
+            return Err(crate::error::VmCliError::InvalidQuery(format!(
+                "Не удалось разобрать строку экспорта: '{}'",
+                line
+            )));
+        };
+        let (name, mut labels) = parse_series_labels(metric);
+
+        self.timestamps.append_value(timestamp.parse::<i64>().unwrap_or_default());
+        self.values.append_value(value.parse::<f64>().unwrap_or(f64::NAN));
+        self.names.append_value(name);
+
+        for (label, column) in &mut self.labels {
+            match labels.remove(label) {
+                Some(value) => column.append_value(value),
+                None => column.append_null(),
+            }
+        }
+        for (label, value) in labels {
+            let mut column = arrow_array::builder::StringDictionaryBuilder::new();
+            column.append_nulls(self.rows);
+            column.append_value(value);
+            self.labels.insert(label, column);
+        }
+
+        self.rows += 1;
+        Ok(())
+    }
+
+    /// Записывает файл и возвращает число строк и SHA-256 записанного.
+    fn finish(mut self, path: &str) -> Result<(usize, String)> {
+        use arrow_array::{ArrayRef, RecordBatch};
+        use arrow_schema::{DataType, Field, Schema, TimeUnit};
+        use parquet::arrow::ArrowWriter;
+        use parquet::basic::Compression;
+        use parquet::file::properties::WriterProperties;
+        use std::sync::Arc;
+
+        if self.rows == 0 {
+            return Err(crate::error::VmCliError::EmptyResult);
+        }
+
+        let parquet_error = |e: &dyn std::fmt::Display| crate::error::VmCliError::Unknown(format!("Parquet: {}", e));
+
+        let dictionary = DataType::Dictionary(Box::new(DataType::Int32), Box::new(DataType::Utf8));
+        let mut fields = vec![
+            Field::new("timestamp", DataType::Timestamp(TimeUnit::Millisecond, Some("UTC".into())), false),
+            Field::new("value", DataType::Float64, false),
+            Field::new("__name__", dictionary.clone(), false),
+        ];
+        fields.extend(self.labels.keys().map(|label| Field::new(label.as_str(), dictionary.clone(), true)));
+
+        let mut columns: Vec<ArrayRef> = vec![
+            Arc::new(self.timestamps.finish()),
+            Arc::new(self.values.finish()),
+            Arc::new(self.names.finish()),
+        ];
+        columns.extend(self.labels.values_mut().map(|column| Arc::new(column.finish()) as ArrayRef));
+
+        let schema = Arc::new(Schema::new(fields));
+        let batch = RecordBatch::try_new(schema.clone(), columns).map_err(|e| parquet_error(&e))?;
+
+        let properties = WriterProperties::builder().set_compression(Compression::SNAPPY).build();
+        let mut file = HashingWriter::new(File::create(path)?);
+        let mut writer = ArrowWriter::try_new(&mut file, schema, Some(properties))
+            .map_err(|e| parquet_error(&e))?;
+        writer.write(&batch).map_err(|e| parquet_error(&e))?;
+        writer.close().map_err(|e| parquet_error(&e))?;
+
+        let (_, hash) = file.finish();
+        Ok((self.rows, hash))
+    }
+}
+
+#[cfg(not(feature = "parquet"))]
+struct ParquetSink;
+
+#[cfg(not(feature = "parquet"))]
+impl ParquetSink {
+    fn new() -> Result<Self> {
+        Err(crate::error::VmCliError::InvalidQuery(
+            "vm-cli собран без поддержки Parquet, пересоберите с --features parquet".to_string(),
+        ))
+    }
+
+    fn push_line(&mut self, _line: &str) -> Result<()> {
+        Ok(())
+    }
+
+    fn finish(self, _path: &str) -> Result<(usize, String)> {
+        Ok((0, String::new()))
+    }
+}
+
+fn split_sample_line(line: &str) -> Option<(&str, &str, &str)> {
+    let (rest, timestamp) = line.trim_end().rsplit_once(' ')?;
+    let (metric, value) = rest.trim_end().rsplit_once(' ')?;
+    Some((metric, value, timestamp))
+}
+
+fn json_number(value: &str) -> serde_json::Value {
+    match value.parse::<f64>() {
+        Ok(number) => serde_json::Number::from_f64(number)
+            .map(serde_json::Value::Number)
+            .unwrap_or_else(|| serde_json::Value::String(format_float(number))),
+        Err(_) => serde_json::Value::String(value.to_string()),
+    }
+}
+
+fn format_timestamp(value: &str) -> String {
+    value
+        .parse::<i64>()
+        .ok()
+        .and_then(|ts| chrono::DateTime::from_timestamp(ts, 0))
+        .map(|dt| dt.format("%Y-%m-%d %H:%M:%S").to_string())
+        .unwrap_or_else(|| value.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::StreamingServer;
+    use crate::config::{ExportConfig, ExportProfile};
+    use crate::testing::MockServer;
+
+    #[test]
+    fn split_by_metric_routes_lines_to_metric_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let data = "up{job=\"a\"} 1 1700000000000\n\
+                    # comment\n\
+                    node_load1{instance=\"x\"} 0.5 1700000000000\n\
+                    up{job=\"b\"} 0 1700000000000\n";
+
+        let files = split_by_metric(data, dir.path()).unwrap();
+
+        let summary: Vec<(&str, usize)> = files.iter().map(|f| (f.metric.as_str(), f.lines)).collect();
+        assert_eq!(summary, vec![("node_load1", 1), ("up", 2)]);
+        assert_eq!(
+            std::fs::read_to_string(dir.path().join("up.prom")).unwrap(),
+            "up{job=\"a\"} 1 1700000000000\nup{job=\"b\"} 0 1700000000000\n"
+        );
+        assert_eq!(
+            std::fs::read_to_string(dir.path().join("node_load1.prom")).unwrap(),
+            "node_load1{instance=\"x\"} 0.5 1700000000000\n"
+        );
+    }
+
+    #[test]
+    fn split_by_metric_sanitizes_file_names() {
+        let dir = tempfile::tempdir().unwrap();
+        let files = split_by_metric("ns:rule/rate 1 1700000000000\n", dir.path()).unwrap();
+
+        assert_eq!(files.len(), 1);
+        assert_eq!(files[0].metric, "ns:rule/rate");
+        assert!(dir.path().join("ns_rule_rate.prom").exists());
+        assert_eq!(sanitize_file_name(".."), "_");
+    }
+
+    #[test]
+    fn split_sample_line_separates_value_and_timestamp() {
+        assert_eq!(
+            split_sample_line("up{job=\"a b\"} 1 1700000000000"),
+            Some(("up{job=\"a b\"}", "1", "1700000000000"))
+        );
+        assert_eq!(split_sample_line("garbage"), None);
+    }
+
+    fn metadata(name: &str, metric_type: &str, help: &str) -> (String, Vec<MetricMetadata>) {
+        let entry = MetricMetadata {
+            metric_type: metric_type.to_string(),
+            help: help.to_string(),
+            unit: String::new(),
+        };
+        (name.to_string(), vec![entry])
+    }
+
+    #[test]
+    fn to_openmetrics_emits_type_help_and_eof() {
+        let data = "http_requests_total{code=\"200\"} 10 1700000000123\n\
+                    node_load1 0.5 1700000000000\n\
+                    unknown_metric 1 1700000000000\n";
+        let metadata = HashMap::from([
+            metadata("http_requests_total", "counter", "Total requests"),
+            metadata("node_load1", "gauge", "1m load average"),
+        ]);
+
+        let output = to_openmetrics(data, &metadata);
+
+        assert_eq!(
+            output,
+            "# TYPE http_requests counter\n\
+             # HELP http_requests Total requests\n\
+             http_requests_total{code=\"200\"} 10 1700000000.123\n\
+             # TYPE node_load1 gauge\n\
+             # HELP node_load1 1m load average\n\
+             node_load1 0.5 1700000000.000\n\
+             # TYPE unknown_metric unknown\n\
+             unknown_metric 1 1700000000.000\n\
+             # EOF\n"
+        );
+    }
+
+    #[test]
+    fn to_openmetrics_without_samples_still_ends_with_eof() {
+        assert_eq!(to_openmetrics("", &HashMap::new()), "# EOF\n");
+    }
+
+    fn export_command(args: &[&str]) -> ExportCommand {
+        ExportCommand::try_parse_from(std::iter::once("export").chain(args.iter().copied())).unwrap()
+    }
+
+    const SAMPLE: &str = "up{instance=\"host:9100\",job=\"node\"} 1 1700000000000\n";
+
+    #[test]
+    fn relabel_drops_label() {
+        let command = export_command(&["up", "--drop-label", "instance"]);
+        assert_eq!(command.relabel(SAMPLE.to_string()).unwrap(), "up{job=\"node\"} 1 1700000000000\n");
+    }
+
+    #[test]
+    fn relabel_renames_label() {
+        let command = export_command(&["up", "--rename-label", "job=service"]);
+        assert_eq!(
+            command.relabel(SAMPLE.to_string()).unwrap(),
+            "up{instance=\"host:9100\",service=\"node\"} 1 1700000000000\n"
+        );
+    }
+
+    #[test]
+    fn relabel_adds_label() {
+        let command = export_command(&["up", "--add-label", "env=staging"]);
+        assert_eq!(
+            command.relabel(SAMPLE.to_string()).unwrap(),
+            "up{env=\"staging\",instance=\"host:9100\",job=\"node\"} 1 1700000000000\n"
+        );
+    }
+
+    #[test]
+    fn relabel_keeps_escaped_values_and_comments() {
+        let command = export_command(&["up", "--drop-label", "job"]);
+        let data = "# HELP up\nup{path=\"a\\\"b\",job=\"x\"} 1 1700000000000\n";
+        assert_eq!(
+            command.relabel(data.to_string()).unwrap(),
+            "# HELP up\nup{path=\"a\\\"b\"} 1 1700000000000\n"
+        );
+    }
+
+    #[test]
+    fn relabel_rejects_unparsable_lines() {
+        let command = export_command(&["up", "--drop-label", "instance"]);
+        let json_line = "{\"metric\":{\"__name__\":\"up\",\"instance\":\"h\"},\"values\":[1],\"timestamps\":[1]}\n";
+        assert!(matches!(
+            command.relabel(json_line.to_string()),
+            Err(crate::error::VmCliError::InvalidQuery(_))
+        ));
+    }
+
+    #[test]
+    fn relabel_without_transforms_passes_data_through() {
+        let command = export_command(&["up"]);
+        assert_eq!(command.relabel("anything".to_string()).unwrap(), "anything");
+    }
+
+    fn chunked_response(chunks: &[&'static str]) -> Response {
+        let chunks: Vec<std::io::Result<&'static str>> = chunks.iter().map(|chunk| Ok(*chunk)).collect();
+        let stream = futures::stream::iter(chunks);
+        Response::from(http::Response::new(reqwest::Body::wrap_stream(stream)))
+    }
+
+    #[tokio::test]
+    async fn stream_lines_reassembles_lines_split_across_chunks() {
+        let response = chunked_response(&["up{job=\"a\"} 1 17", "00000000000\nnode_lo", "ad1 0.5 1700000000000"]);
+        let mut lines = Vec::new();
+
+        let received = stream_lines(response, None, None, |line| {
+            lines.push(line.to_string());
+            Ok(())
+        })
+        .await
+        .unwrap();
+
+        assert_eq!(lines, vec!["up{job=\"a\"} 1 1700000000000", "node_load1 0.5 1700000000000"]);
+        assert_eq!(received, 56);
+    }
+
+    #[cfg(not(feature = "parquet"))]
+    #[test]
+    fn parquet_requires_feature() {
+        assert!(matches!(ParquetSink::new(), Err(crate::error::VmCliError::InvalidQuery(_))));
+    }
+
+    #[cfg(feature = "parquet")]
+    #[test]
+    fn parquet_round_trip_with_dictionary_label_columns() {
+        use arrow_array::cast::AsArray;
+        use arrow_array::Array;
+        use arrow_array::types::{Float64Type, Int32Type};
+        use arrow_schema::DataType;
+        use parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("export.parquet");
+        let mut sink = ParquetSink::new().unwrap();
+        for line in [
+            "up{job=\"node\"} 1 1700000000000",
+            "# comment",
+            "up{instance=\"h:9100\",job=\"node\"} 0 1700000015000",
+            "node_load1 NaN 1700000030000",
+        ] {
+            sink.push_line(line).unwrap();
+        }
+        assert_eq!(sink.finish(path.to_str().unwrap()).unwrap().0, 3);
+
+        let reader = ParquetRecordBatchReaderBuilder::try_new(File::open(&path).unwrap())
+            .unwrap()
+            .build()
+            .unwrap();
+        let batches: Vec<_> = reader.map(|batch| batch.unwrap()).collect();
+        let batch = &batches[0];
+        let schema = batch.schema();
+
+        let fields: Vec<&str> = schema.fields().iter().map(|f| f.name().as_str()).collect();
+        assert_eq!(fields, vec!["timestamp", "value", "__name__", "instance", "job"]);
+        assert!(matches!(schema.field_with_name("job").unwrap().data_type(), DataType::Dictionary(_, _)));
+        assert_eq!(batch.num_rows(), 3);
+
+        let values = batch.column(1).as_primitive::<Float64Type>();
+        assert_eq!(values.value(0), 1.0);
+        assert!(values.value(2).is_nan());
+
+        let instance = batch.column_by_name("instance").unwrap().as_dictionary::<Int32Type>();
+        assert!(instance.is_null(0));
+        assert!(!instance.is_null(1));
+        assert!(instance.is_null(2));
+    }
+
+    #[cfg(feature = "parquet")]
+    #[test]
+    fn parquet_rejects_empty_and_unparsable_exports() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("empty.parquet");
+
+        let empty = ParquetSink::new().unwrap();
+        assert!(matches!(empty.finish(path.to_str().unwrap()), Err(crate::error::VmCliError::EmptyResult)));
+        assert!(!path.exists());
+
+        let mut sink = ParquetSink::new().unwrap();
+        assert!(matches!(
+            sink.push_line("{\"metric\":{\"__name__\":\"up\"},\"values\":[1]}"),
+            Err(crate::error::VmCliError::InvalidQuery(_))
+        ));
+    }
+
+    const RANGE_RESPONSE: &str = r#"{"status":"success","data":{"resultType":"matrix","result":[{"metric":{"__name__":"m"},"values":[[1700000000,"1"]]}]}}"#;
+
+    async fn downsample_three(policy: ErrorPolicy) -> (Result<String>, BatchErrors, usize) {
+        let server = MockServer::start(vec![(200, RANGE_RESPONSE), (400, "bad query"), (200, RANGE_RESPONSE)]);
+        let client = VmClient::new(&server.url, 5, None, None, None).unwrap();
+        let mut errors = BatchErrors::new(policy);
+
+        let result = downsample(&client, &["a", "b", "c"], "0", "1", "1h", ExportAggr::Avg, &mut errors).await;
+        (result, errors, server.requests().len())
+    }
+
+    #[tokio::test]
+    async fn downsample_fail_fast_stops_at_middle_selector() {
+        let (result, _, requests) = downsample_three(ErrorPolicy::FailFast).await;
+        assert!(matches!(result, Err(crate::error::VmCliError::ApiError { status: Some(400), .. })));
+        assert_eq!(requests, 2);
+    }
+
+    #[tokio::test]
+    async fn downsample_continue_keeps_other_selectors() {
+        let (result, errors, requests) = downsample_three(ErrorPolicy::Continue).await;
+        assert_eq!(result.unwrap(), "m 1 1700000000000\nm 1 1700000000000\n");
+        assert_eq!(requests, 3);
+        assert!(matches!(errors.finish(), Err(crate::error::VmCliError::BatchFailed { total: 3, .. })));
+    }
+
+    #[test]
+    fn append_concatenates_prometheus_exports() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("data.txt").to_string_lossy().into_owned();
+        let command = export_command(&["up", "--output", &path, "--append"]);
+
+        command.save_to_file("a 1 1", &path).unwrap();
+        command.save_to_file("b 2 2\n", &path).unwrap();
+
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "a 1 1\nb 2 2\n");
+    }
+
+    #[test]
+    fn append_skips_repeated_csv_header() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("data.csv").to_string_lossy().into_owned();
+        let command = export_command(&["up", "--output", &path, "--append", "--format", "csv"]);
+
+        command.save_to_file("timestamp,value,metric_name\n1,1,a", &path).unwrap();
+        command.save_to_file("timestamp,value,metric_name\n2,2,b", &path).unwrap();
+
+        assert_eq!(
+            std::fs::read_to_string(&path).unwrap(),
+            "timestamp,value,metric_name\n1,1,a\n2,2,b"
+        );
+    }
+
+    #[test]
+    fn append_rejects_document_formats() {
+        for format in ["json", "openmetrics", "parquet"] {
+            let command = export_command(&["up", "--output", "out", "--append", "--format", format]);
+            assert!(matches!(command.validate_append(), Err(crate::error::VmCliError::InvalidQuery(_))), "{}", format);
+        }
+        for format in ["prometheus", "csv"] {
+            assert!(export_command(&["up", "--output", "out", "--append", "--format", format]).validate_append().is_ok());
+        }
+        assert!(export_command(&["up", "--format", "json"]).validate_append().is_ok());
+    }
+
+    #[tokio::test]
+    async fn stream_to_file_stays_within_max_rate() {
+        let line = format!("m{{job=\"{}\"}} 1 1700000000000\n", "x".repeat(74));
+        let chunk = line.repeat(20);
+        assert_eq!(chunk.len(), 2000);
+        let chunks: Vec<std::io::Result<String>> = (0..4).map(|_| Ok(chunk.clone())).collect();
+        let response = Response::from(http::Response::new(reqwest::Body::wrap_stream(futures::stream::iter(chunks))));
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("export.txt").to_string_lossy().into_owned();
+        let command = export_command(&["m", "--output", &path, "--max-rate", "4000", "--drop-label", "job"]);
+
+        let started = Instant::now();
+        let (written, _) = command.stream_to_file(response, &path, None).await.unwrap();
+        let elapsed = started.elapsed().as_secs_f64();
+
+        // первые 4000 байт проходят сразу (запас ограничителя), остальные 4000 — за секунду
+        assert!(elapsed >= 0.9, "elapsed {}", elapsed);
+        assert!((8000.0 - 4000.0) / elapsed <= 4000.0 * 1.1);
+        let content = std::fs::read_to_string(&path).unwrap();
+        assert_eq!(content.lines().count(), 80);
+        assert!(content.lines().all(|l| l == "m 1 1700000000000"));
+        assert_eq!(written, content.len() as u64);
+    }
+
+    #[tokio::test]
+    async fn stream_to_file_appends_after_unterminated_line() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("export.txt").to_string_lossy().into_owned();
+        std::fs::write(&path, "a 1 1").unwrap();
+        let command = export_command(&["m", "--output", &path, "--append"]);
+
+        command.stream_to_file(chunked_response(&["b 2 2\n"]), &path, None).await.unwrap();
+
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "a 1 1\nb 2 2\n");
+    }
+
+    #[tokio::test]
+    async fn dry_run_estimates_from_series_and_count_query() {
+        let server = MockServer::start(vec![
+            (200, r#"{"status":"success","data":[{"__name__":"up","job":"a"},{"__name__":"up","job":"b"}]}"#),
+            (200, r#"{"status":"success","data":{"resultType":"vector","result":[{"metric":{},"value":[1700003600,"240"]}]}}"#),
+        ]);
+        let client = VmClient::new(&server.url, 5, None, None, None).unwrap();
+
+        export_command(&["up", "--start", "1700000000", "--end", "1700003600", "--dry-run"])
+            .execute(&client)
+            .await
+            .unwrap();
+
+        let requests = server.requests();
+        assert_eq!(requests.len(), 2);
+        assert_eq!(requests[0].path(), "/api/v1/series");
+        assert_eq!(requests[0].param("match[]"), ["up"]);
+        assert_eq!(requests[0].param("start"), ["1700000000"]);
+        assert_eq!(requests[1].path(), "/api/v1/query");
+        assert_eq!(requests[1].param("query"), ["sum(count_over_time(up[3600s]))"]);
+    }
+
+    #[tokio::test]
+    async fn dry_run_estimates_rfc3339_range() {
+        let server = MockServer::start(vec![
+            (200, r#"{"status":"success","data":[{"__name__":"up","job":"a"}]}"#),
+            (200, r#"{"status":"success","data":{"resultType":"vector","result":[{"metric":{},"value":[1700003600,"120"]}]}}"#),
+        ]);
+        let client = VmClient::new(&server.url, 5, None, None, None).unwrap();
+
+        export_command(&["up", "--start", "2023-11-14T22:13:20Z", "--end", "2023-11-15T00:13:20+01:00", "--dry-run"])
+            .execute(&client)
+            .await
+            .unwrap();
+
+        let requests = server.requests();
+        assert_eq!(requests.len(), 2);
+        assert_eq!(requests[1].param("query"), ["sum(count_over_time(up[3600s]))"]);
+        assert_eq!(requests[1].param("time"), ["2023-11-15T00:13:20+01:00"]);
+    }
+
+    #[tokio::test]
+    async fn dry_run_skips_sample_count_without_series() {
+        let server = MockServer::start(vec![(200, r#"{"status":"success","data":[]}"#)]);
+        let client = VmClient::new(&server.url, 5, None, None, None).unwrap();
+
+        export_command(&["up", "--start", "1700000000", "--end", "1700003600", "--dry-run"])
+            .execute(&client)
+            .await
+            .unwrap();
+
+        assert_eq!(server.requests().len(), 1);
+    }
+
+    #[test]
+    fn positional_match_comes_first_then_repeated_match_flags() {
+        let command = export_command(&["up", "--match", "node_load1", "--match", "{job=\"api\"}"]);
+
+        assert_eq!(command.matches(), ["up", "node_load1", "{job=\"api\"}"]);
+    }
+
+    fn profile_config() -> ExportConfig {
+        let profile = ExportProfile { matches: vec!["up".to_string(), "node_load1".to_string()], range: Some("7d".to_string()) };
+        ExportConfig { default_format: "prometheus".to_string(), chunk_size: 1000, profiles: [("nodes".to_string(), profile)].into() }
+    }
+
+    fn range_width(command: &ExportCommand) -> i64 {
+        let (start, end) = command.determine_time_range().unwrap();
+        end.parse::<i64>().unwrap() - start.parse::<i64>().unwrap()
+    }
+
+    #[test]
+    fn profile_selectors_and_range_are_applied() {
+        let mut command = export_command(&["--profile", "nodes", "--match", "{job=\"api\"}"]);
+        command.apply_profile(Some(&profile_config())).unwrap();
+
+        assert_eq!(command.matches(), ["up", "node_load1", "{job=\"api\"}"]);
+        assert_eq!(range_width(&command), 7 * 86400);
+    }
+
+    #[test]
+    fn cli_range_overrides_profile_range() {
+        let mut command = export_command(&["--profile", "nodes", "--range", "1h"]);
+        command.apply_profile(Some(&profile_config())).unwrap();
+        assert_eq!(range_width(&command), 3600);
+
+        let mut command = export_command(&["--profile", "nodes", "--start", "100", "--end", "200"]);
+        command.apply_profile(Some(&profile_config())).unwrap();
+        assert_eq!(command.determine_time_range().unwrap(), ("100".to_string(), "200".to_string()));
+    }
+
+    #[test]
+    fn unknown_profile_is_a_config_error() {
+        let mut command = export_command(&["--profile", "missing"]);
+
+        assert!(matches!(
+            command.apply_profile(Some(&profile_config())),
+            Err(crate::error::VmCliError::ConfigError(_))
+        ));
+        assert!(command.apply_profile(None).is_err());
+    }
+
+    #[test]
+    fn export_keeps_non_finite_values_instead_of_zero() {
+        let data = "a 1 1700000000\nb NaN 1700000000\nc +Inf 1700000000\nd -Inf 1700000000\n";
+
+        let json = export_command(&["up", "--format", "json"]).format_data(data, &HashMap::new()).unwrap();
+        let values: Vec<serde_json::Value> = serde_json::from_str::<Vec<serde_json::Value>>(&json)
+            .unwrap()
+            .into_iter()
+            .map(|entry| entry["value"][1].clone())
+            .collect();
+        assert_eq!(values, [serde_json::json!(1.0), serde_json::json!("NaN"), serde_json::json!("+Inf"), serde_json::json!("-Inf")]);
+
+        let csv = export_command(&["up", "--format", "csv"]).format_data(data, &HashMap::new()).unwrap();
+        assert_eq!(csv, "timestamp,value,metric_name\n1700000000,1,a\n1700000000,NaN,b\n1700000000,+Inf,c\n1700000000,-Inf,d");
+    }
+
+    #[tokio::test]
+    async fn head_preview_stops_after_five_data_lines() {
+        let line = "up{job=\"a\"} 1 1700000000000\n";
+        let count = 200_000;
+        let server = StreamingServer::start("# HELP up\n# TYPE up gauge\n\n", line, count, "");
+        let client = VmClient::new(&server.url, 30, None, None, None).unwrap();
+
+        let response = client.export_stream(&["up"], None, None).await.unwrap();
+        let preview = download_preview(response, Preview::Head(5), None, None).await.unwrap();
+
+        assert_eq!(preview, line.repeat(5));
+        tokio::time::sleep(Duration::from_millis(200)).await;
+        assert!(server.sent() < (line.len() * count / 2) as u64, "отправлено {}", server.sent());
+    }
+
+    #[tokio::test]
+    async fn tail_preview_keeps_last_lines() {
+        let server = MockServer::start(vec![(200, "a 1 1\n# comment\nb 2 2\nc 3 3\n\nd 4 4")]);
+        let client = VmClient::new(&server.url, 5, None, None, None).unwrap();
+
+        let response = client.export_stream(&["up"], None, None).await.unwrap();
+        let preview = download_preview(response, Preview::Tail(2), None, None).await.unwrap();
+
+        assert_eq!(preview, "c 3 3\nd 4 4\n");
+    }
+
+    #[tokio::test]
+    async fn aggr_exports_through_query_range_with_step() {
+        let server = MockServer::start(vec![(200, RANGE_RESPONSE)]);
+        let client = VmClient::new(&server.url, 5, None, None, None).unwrap();
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("export.txt").to_string_lossy().into_owned();
+
+        export_command(&["up", "--start", "1700000000", "--end", "1700003600", "--step", "5m", "--aggr", "max", "--output", &path])
+            .execute(&client)
+            .await
+            .unwrap();
+
+        let requests = server.requests();
+        assert_eq!(requests.len(), 1);
+        assert_eq!(requests[0].path(), "/api/v1/query_range");
+        assert_eq!(requests[0].param("query"), ["max_over_time(up[5m]) keep_metric_names"]);
+        assert_eq!(requests[0].param("step"), ["5m"]);
+        assert_eq!(requests[0].param("start"), ["1700000000"]);
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "m 1 1700000000000\n");
+    }
+
+    #[test]
+    fn aggr_requires_step() {
+        assert!(ExportCommand::try_parse_from(["export", "up", "--aggr", "avg"]).is_err());
+        assert!(ExportCommand::try_parse_from(["export", "up", "--step", "5m"]).is_err());
+    }
+
+    #[tokio::test]
+    async fn checksum_sidecar_matches_streamed_export() {
+        let server = MockServer::start(vec![(200, "up 1 1700000000000\n")]);
+        let client = VmClient::new(&server.url, 5, None, None, None).unwrap();
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("export.txt");
+
+        export_command(&["up", "--output", path.to_str().unwrap(), "--checksum"])
+            .execute(&client)
+            .await
+            .unwrap();
+
+        let expected = crate::utils::sha256_hex(&std::fs::read(&path).unwrap());
+        assert_eq!(crate::utils::read_checksum(&path).unwrap(), Some(expected));
+    }
+
+    #[tokio::test]
+    async fn checksum_with_append_covers_only_appended_export() {
+        let server = MockServer::start(vec![(200, "a 1 1\n"), (200, "b 2 2\n")]);
+        let client = VmClient::new(&server.url, 5, None, None, None).unwrap();
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("export.txt");
+
+        for _ in 0..2 {
+            export_command(&["up", "--output", path.to_str().unwrap(), "--append", "--checksum"])
+                .execute(&client)
+                .await
+                .unwrap();
+        }
+
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "a 1 1\nb 2 2\n");
+        assert_eq!(crate::utils::read_checksum(&path).unwrap(), Some(sha256_hex(b"b 2 2\n")));
+    }
+
+    #[test]
+    fn save_to_file_with_append_hashes_payload_without_repeated_header() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("export.csv").to_string_lossy().into_owned();
+        let command = export_command(&["up", "--output", &path, "--append", "--format", "csv"]);
+
+        command.save_to_file("timestamp,value,metric_name\n1,1,a", &path).unwrap();
+        let (bytes, hash) = command.save_to_file("timestamp,value,metric_name\n2,2,b", &path).unwrap();
+
+        let content = std::fs::read_to_string(&path).unwrap();
+        let appended = &content[content.len() - bytes as usize..];
+        assert!(!appended.contains("timestamp"));
+        assert_eq!(hash, sha256_hex(appended.as_bytes()));
     }
 }
@@ -1,8 +1,13 @@
-use crate::api::VmClient;
-use crate::error::Result;
-use crate::utils::{format_health_status, format_uptime};
+use crate::api::{HealthResponse, VmClient};
+use crate::config::OutputFormat;
+use crate::error::{Result, VmCliError};
+use crate::messages::{self, Msg};
+use crate::output;
+use crate::utils::{format_health_status, format_uptime, print_formatted, Formatter};
 use clap::Parser;
 use colored::*;
+use indicatif::{ProgressBar, ProgressStyle};
+use std::time::{Duration, Instant};
 use tracing::info;
 
 #[derive(Parser)]
@@ -10,15 +15,35 @@ pub struct HealthCommand {
     #[arg(short, long)]
     verbose: bool,
 
-    #[arg(long)]
+    #[arg(long, conflicts_with = "format")]
     status_only: bool,
+
+    #[arg(short, long, value_enum)]
+    format: Option<OutputFormat>,
+
+    #[arg(long, value_name = "TIMEOUT")]
+    wait: Option<String>,
+
+    #[arg(long, default_value = "1s", requires = "wait")]
+    interval: String,
 }
 
 impl HealthCommand {
     pub async fn execute(&self, client: &VmClient) -> Result<()> {
         info!("Проверка здоровья VictoriaMetrics");
 
-        let health = client.health().await?;
+        let health = match &self.wait {
+            Some(wait) => self.wait_until_healthy(client, wait).await?,
+            None => client.health().await?,
+        };
+
+        if output::is_json_mode() {
+            return output::print_data(&health);
+        }
+
+        if let Some(format) = &self.format {
+            return print_formatted(&health, format);
+        }
 
         if self.status_only {
             println!("{}", health.status);
@@ -26,16 +51,16 @@ impl HealthCommand {
         }
 
         let status_display = format_health_status(&health.status);
-        println!("{} {}", "Статус:".bold(), status_display);
+        println!("{} {}", messages::t(Msg::Status).bold(), status_display);
 
         if self.verbose {
             if let Some(version) = &health.version {
-                println!("{} {}", "Версия:".bold(), version);
+                println!("{} {}", messages::t(Msg::Version).bold(), version);
             }
 
             if let Some(uptime) = &health.uptime {
                 let formatted_uptime = format_uptime(uptime);
-                println!("{} {}", "Время работы:".bold(), formatted_uptime);
+                println!("{} {}", messages::t(Msg::Uptime).bold(), formatted_uptime);
             }
 
             self.check_additional_health(client).await?;
@@ -43,19 +68,79 @@ impl HealthCommand {
 
         match health.status.to_lowercase().as_str() {
             "ok" | "healthy" => {
-                println!("{}", "✓ VictoriaMetrics работает нормально".green());
+                println!("{}", messages::t(Msg::HealthOk).green());
             }
             "error" | "unhealthy" => {
-                println!("{}", "✗ VictoriaMetrics имеет проблемы".red());
+                println!("{}", messages::t(Msg::HealthProblem).red());
             }
             _ => {
-                println!("{}", "? Статус VictoriaMetrics неопределен".yellow());
+                println!("{}", messages::t(Msg::HealthUnknown).yellow());
             }
         }
 
         Ok(())
     }
 
+    async fn wait_until_healthy(&self, client: &VmClient, wait: &str) -> Result<HealthResponse> {
+        let timeout = humantime::parse_duration(wait)
+            .map_err(|_| VmCliError::TimeParseError(format!("Неверный таймаут --wait: {}", wait)))?;
+        let interval = humantime::parse_duration(&self.interval)
+            .ok()
+            .filter(|i| !i.is_zero())
+            .ok_or_else(|| VmCliError::TimeParseError(format!("Неверный интервал --interval: {}", self.interval)))?;
+
+        info!("Ожидание готовности VictoriaMetrics (таймаут {:?})", timeout);
+
+        let progress_bar = if output::is_json_mode() {
+            ProgressBar::hidden()
+        } else {
+            let pb = ProgressBar::new_spinner();
+            pb.set_style(
+                ProgressStyle::default_spinner()
+                    .template("{spinner:.green} [{elapsed}] {wide_msg}")
+                    .unwrap(),
+            );
+            pb.enable_steady_tick(Duration::from_millis(100));
+            pb
+        };
+
+        let deadline = Instant::now() + timeout;
+        let mut attempt = 0u64;
+
+        loop {
+            attempt += 1;
+            let last_state = match client.health().await {
+                Ok(health) if is_healthy(&health.status) => {
+                    progress_bar.finish_and_clear();
+                    return Ok(health);
+                }
+                Ok(health) => health.status,
+                Err(e) => e.to_string(),
+            };
+
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            if remaining.is_zero() {
+                progress_bar.abandon_with_message(format!(
+                    "VictoriaMetrics не готова после {} попыток: {}",
+                    attempt, last_state
+                ));
+                return Err(VmCliError::Timeout);
+            }
+
+            progress_bar.set_message(format!(
+                "Ожидание VictoriaMetrics, попытка {}: {}",
+                attempt, last_state
+            ));
+            tokio::select! {
+                _ = tokio::time::sleep(interval.min(remaining)) => {}
+                _ = client.cancelled() => {
+                    progress_bar.finish_and_clear();
+                    return Err(VmCliError::Cancelled);
+                }
+            }
+        }
+    }
+
     async fn check_additional_health(&self, client: &VmClient) -> Result<()> {
         info!("Выполнение дополнительных проверок");
 
@@ -78,3 +163,85 @@ impl HealthCommand {
         Ok(())
     }
 }
+
+impl Formatter for HealthResponse {
+    fn headers(&self) -> Vec<String> {
+        ["status", "version", "uptime"].map(String::from).into()
+    }
+
+    fn rows(&self) -> Vec<Vec<String>> {
+        vec![vec![
+            self.status.clone(),
+            self.version.clone().unwrap_or_default(),
+            self.uptime.clone().unwrap_or_default(),
+        ]]
+    }
+}
+
+pub fn is_healthy(status: &str) -> bool {
+    matches!(status.to_lowercase().as_str(), "ok" | "healthy")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::MockServer;
+    use crate::testing::assert_consistent_formats;
+
+    #[test]
+    fn health_renders_consistently_across_formats() {
+        let health = HealthResponse {
+            status: "ok".to_string(),
+            version: Some("v1.93.0".to_string()),
+            uptime: None,
+        };
+        assert_consistent_formats(&health);
+    }
+
+    fn health_command(args: &[&str]) -> HealthCommand {
+        HealthCommand::try_parse_from(std::iter::once("health").chain(args.iter().copied())).unwrap()
+    }
+
+    #[tokio::test]
+    async fn wait_polls_until_server_becomes_healthy() {
+        let server = MockServer::start(vec![(200, "starting"), (503, "unavailable"), (200, "OK")]);
+        let client = VmClient::new(&server.url, 5, None, None, None).unwrap();
+        let command = health_command(&["--wait", "10s", "--interval", "10ms"]);
+
+        let health = command.wait_until_healthy(&client, "10s").await.unwrap();
+
+        assert_eq!(health.status, "OK");
+        assert!(server.requests().len() >= 3);
+        assert!(server.requests().iter().all(|r| r.path() == "/health"));
+    }
+
+    #[tokio::test]
+    async fn wait_times_out_when_server_stays_unhealthy() {
+        let server = MockServer::start(vec![(200, "starting")]);
+        let client = VmClient::new(&server.url, 5, None, None, None).unwrap();
+        let command = health_command(&["--wait", "100ms", "--interval", "20ms"]);
+
+        let result = command.wait_until_healthy(&client, "100ms").await;
+
+        assert!(matches!(result, Err(VmCliError::Timeout)));
+        assert!(server.requests().len() > 1);
+    }
+
+    #[tokio::test]
+    async fn wait_stops_as_soon_as_cancelled() {
+        let server = MockServer::start(vec![(200, "starting")]);
+        let cancel = tokio_util::sync::CancellationToken::new();
+        let client = VmClient::new(&server.url, 5, None, None, None).unwrap().with_cancellation(cancel.clone());
+        let command = health_command(&["--wait", "1m", "--interval", "30s"]);
+
+        let canceller = async {
+            tokio::time::sleep(Duration::from_millis(100)).await;
+            cancel.cancel();
+        };
+        let wait = tokio::time::timeout(Duration::from_secs(5), command.wait_until_healthy(&client, "1m"));
+        let (result, ()) = tokio::join!(wait, canceller);
+
+        assert!(matches!(result.expect("ожидание должно прерываться сразу"), Err(VmCliError::Cancelled)));
+        assert_eq!(server.requests().len(), 1);
+    }
+}
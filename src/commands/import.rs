@@ -1,19 +1,53 @@
-use crate::api::VmClient;
+use crate::api::{MetricMetadata, VmClient};
+use crate::batch::{BatchErrors, CircuitBreaker, ErrorPolicy};
+use crate::commands::health::is_healthy;
 use crate::error::Result;
+use crate::output;
+use crate::utils::{checksum_path, parse_label, read_checksum, sha256_hex};
 use clap::Parser;
 use colored::*;
 use indicatif::{ProgressBar, ProgressStyle};
-use std::fs;
-use tracing::info;
+use std::collections::{HashMap, HashSet};
+use std::fs::{self, File};
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use tracing::{info, warn};
+
+const METRIC_SUFFIXES: [&str; 6] = ["_total", "_bucket", "_sum", "_count", "_created", "_info"];
 
 #[derive(Parser)]
 pub struct ImportCommand {
-    #[arg(value_name = "FILE")]
-    file: String,
+    #[arg(value_name = "FILE", required = true)]
+    files: Vec<String>,
 
     #[arg(short, long, value_enum, default_value = "prometheus")]
     format: ImportFormat,
 
+    #[arg(long = "add-label", value_name = "NAME=VALUE", value_parser = parse_label)]
+    add_labels: Vec<(String, String)>,
+
+    #[arg(long, value_name = "PATH")]
+    metadata_file: Option<String>,
+
+    #[arg(long, value_name = "KEY")]
+    idempotency_key: Option<String>,
+
+    #[arg(long)]
+    force: bool,
+
+    #[arg(long, conflicts_with = "follow")]
+    verify_checksum: bool,
+
+    #[arg(long, value_name = "N", conflicts_with = "follow")]
+    skip_lines: Option<usize>,
+
+    #[arg(long)]
+    skip_preflight: bool,
+
+    #[arg(long, value_name = "ACCOUNT:PROJECT", value_parser = parse_tenant)]
+    tenant: Option<String>,
+
     #[arg(long)]
     progress: bool,
     #[arg(long)]
@@ -21,28 +55,146 @@ pub struct ImportCommand {
 
     #[arg(long)]
     skip_errors: bool,
+
+    #[arg(long, value_name = "N", num_args = 0..=1, default_missing_value = "10")]
+    summary: Option<usize>,
+
+    #[arg(long)]
+    follow: bool,
+
+    #[arg(long, default_value = "1000")]
+    poll_interval: u64,
+
+    #[arg(long, default_value = "1000")]
+    batch_size: usize,
+
+    #[arg(long, value_name = "N", value_parser = clap::value_parser!(u64).range(1..))]
+    breaker_threshold: Option<u64>,
+
+    #[arg(long, value_name = "DURATION", default_value = "5m", value_parser = humantime::parse_duration)]
+    breaker_max_wait: Duration,
 }
 
 #[derive(clap::ValueEnum, Clone)]
 pub enum ImportFormat {
     Prometheus,
+    PrometheusExposition,
     Json,
     Csv,
 }
 
 impl ImportCommand {
     pub async fn execute(&self, client: &VmClient) -> Result<()> {
-        info!("Импорт данных из файла: {}", self.file);
+        let policy = ErrorPolicy::from_flags(self.skip_errors);
+
+        if !self.add_labels.is_empty() && !matches!(self.format, ImportFormat::PrometheusExposition) {
+            return Err(crate::error::VmCliError::InvalidQuery(
+                "--add-label поддерживается только для формата prometheus-exposition".to_string(),
+            ));
+        }
+
+        if self.metadata_file.is_some() && !matches!(self.format, ImportFormat::PrometheusExposition) {
+            return Err(crate::error::VmCliError::InvalidQuery(
+                "--metadata-file поддерживается только для формата prometheus-exposition".to_string(),
+            ));
+        }
+        let metadata = match &self.metadata_file {
+            Some(path) => load_metadata(path)?,
+            None => HashMap::new(),
+        };
+
+        if self.idempotency_key.is_some() && (self.follow || self.files.len() > 1) {
+            return Err(crate::error::VmCliError::InvalidQuery(
+                "--idempotency-key можно указать только для импорта одного файла".to_string(),
+            ));
+        }
 
-        if !std::path::Path::new(&self.file).exists() {
-            return Err(crate::error::VmCliError::FileNotFound(self.file.clone()));
+        if self.skip_lines.is_some() {
+            if !matches!(self.format, ImportFormat::Prometheus) {
+                return Err(crate::error::VmCliError::InvalidQuery(
+                    "--skip-lines поддерживается только для формата prometheus".to_string(),
+                ));
+            }
+            if self.files.len() > 1 {
+                return Err(crate::error::VmCliError::InvalidQuery(
+                    "--skip-lines можно указать только для импорта одного файла".to_string(),
+                ));
+            }
         }
 
-        let file_content = fs::read_to_string(&self.file)
-            .map_err(|e| crate::error::VmCliError::IoError(e))?;
+        client.import_url(self.tenant.as_deref())?;
+
+        if !self.skip_preflight && !self.dry_run && !client.is_dry_run() {
+            preflight(client).await?;
+        }
+
+        if self.follow {
+            if self.files.len() > 1 {
+                return Err(crate::error::VmCliError::InvalidQuery(
+                    "Режим --follow поддерживает только один файл".to_string(),
+                ));
+            }
+            let file = &self.files[0];
+            if !std::path::Path::new(file).exists() {
+                return Err(crate::error::VmCliError::FileNotFound(file.clone()));
+            }
+            return self.follow_file(client, file, policy).await;
+        }
+
+        let mut errors = BatchErrors::new(policy);
+        let mut breaker = self.circuit_breaker();
+        for file in &self.files {
+            let result = self.import_file(client, file, &metadata).await;
+            breaker.observe(client, result.is_ok()).await?;
+            errors.record(file, result)?;
+        }
+
+        errors.finish()
+    }
+
+    async fn import_file(
+        &self,
+        client: &VmClient,
+        file: &str,
+        metadata: &HashMap<String, Vec<MetricMetadata>>,
+    ) -> Result<()> {
+        info!("Импорт данных из файла: {}", file);
+
+        if !std::path::Path::new(file).exists() {
+            return Err(crate::error::VmCliError::FileNotFound(file.to_string()));
+        }
+
+        let file_content = fs::read_to_string(file)
+            .map_err(crate::error::VmCliError::IoError)?;
 
         info!("Размер файла: {} байт", file_content.len());
 
+        let hash = sha256_hex(file_content.as_bytes());
+        if self.verify_checksum {
+            verify_checksum(file, &hash)?;
+        }
+
+        let marker = marker_path(Path::new(file));
+        let marker_key = self.idempotency_key.as_deref().unwrap_or(&hash);
+        let target = client.import_url(self.tenant.as_deref())?;
+        if !self.force && is_marked(&marker, marker_key, &target) {
+            if output::is_json_mode() {
+                return output::print_data(&serde_json::json!({
+                    "file": file,
+                    "hash": hash,
+                    "skipped": true,
+                }));
+            }
+            println!(
+                "{} {} (уже импортирован в {}, ключ {}; используйте --force для повторного импорта)",
+                "Пропущен:".yellow().bold(),
+                file,
+                target,
+                &marker_key[..marker_key.len().min(12)]
+            );
+            return Ok(());
+        }
+
         let progress_bar = if self.progress {
             let pb = ProgressBar::new_spinner();
             pb.set_style(
@@ -56,32 +208,255 @@ impl ImportCommand {
             None
         };
 
-        let import_data = self.prepare_data(&file_content)?;
+        let skip_lines = self.skip_lines.unwrap_or(0);
+        let skip_bytes = line_offset(&file_content, skip_lines);
+        if skip_lines > 0 {
+            info!("Пропуск первых {} строк ({} байт)", skip_lines, skip_bytes);
+        }
+
+        let mut import_data = self.prepare_data(&file_content[skip_bytes..])?;
+        if !metadata.is_empty() {
+            import_data = attach_metadata(&import_data, metadata);
+        }
+        let summary = self.summary.map(|top| {
+            let mut counts = count_samples_by_metric(&import_data);
+            counts.truncate(top);
+            counts
+        });
 
         if self.dry_run {
+            if output::is_json_mode() {
+                return output::print_data(&serde_json::json!({
+                    "file": file,
+                    "dry_run": true,
+                    "lines": import_data.lines().count(),
+                    "summary": summary,
+                }));
+            }
             println!("{}", "Режим проверки (dry-run)".yellow().bold());
             println!("{} строк данных готово к импорту", import_data.lines().count());
+            if let Some(summary) = &summary {
+                print_summary(summary);
+            }
             return Ok(());
         }
 
-        client.import(&import_data).await?;
+        match self.format {
+            ImportFormat::PrometheusExposition => {
+                let key = self.idempotency_key.as_deref();
+                client
+                    .import_prometheus_exposition(&import_data, &self.add_labels, self.tenant.as_deref(), key)
+                    .await?
+            }
+            ImportFormat::Prometheus => {
+                let start = ImportPosition { line: skip_lines, byte: skip_bytes };
+                self.import_batches(client, file, &import_data, start, progress_bar.as_ref())
+                    .await?
+            }
+            _ => {
+                let key = self.idempotency_key.as_deref();
+                client.import(&import_data, self.tenant.as_deref(), key).await?
+            }
+        }
+
+        if !client.is_dry_run() {
+            if let Err(e) = mark_imported(&marker, marker_key, &target, file) {
+                warn!("Не удалось записать маркер импорта {}: {}", marker.display(), e);
+            }
+        }
 
         if let Some(pb) = &progress_bar {
             pb.finish_with_message("Импорт завершен");
         }
 
+        if output::is_json_mode() {
+            return output::print_data(&serde_json::json!({
+                "file": file,
+                "hash": hash,
+                "lines": import_data.lines().count(),
+                "summary": summary,
+                "dry_run": client.is_dry_run(),
+            }));
+        }
+
+        if client.is_dry_run() {
+            println!("{} {} был бы импортирован", "[dry-run]".yellow().bold(), file);
+        } else {
+            println!(
+                "{} {}",
+                "Импорт успешно завершен:".green().bold(),
+                file
+            );
+        }
+        if let Some(summary) = &summary {
+            print_summary(summary);
+        }
+
+        Ok(())
+    }
+
+    async fn import_batches(
+        &self,
+        client: &VmClient,
+        file: &str,
+        data: &str,
+        start: ImportPosition,
+        progress: Option<&ProgressBar>,
+    ) -> Result<()> {
+        let mut sent = ImportProgress::default();
+        match self.send_batches(client, file, data, start, &mut sent, progress).await {
+            Ok(errors) => errors.finish(),
+            Err(e) => {
+                eprintln!("{}", sent.failure_report(file, start));
+                Err(e)
+            }
+        }
+    }
+
+    /// Отправляет пакеты, пока не встретится ошибка, прерывающая импорт; `sent`
+    /// к этому моменту описывает все обработанные пакеты, включая пропущенные.
+    async fn send_batches(
+        &self,
+        client: &VmClient,
+        file: &str,
+        data: &str,
+        start: ImportPosition,
+        sent: &mut ImportProgress,
+        progress: Option<&ProgressBar>,
+    ) -> Result<BatchErrors> {
+        let lines: Vec<&str> = data.split_inclusive('\n').collect();
+        let mut errors = BatchErrors::new(ErrorPolicy::from_flags(self.skip_errors));
+        let mut breaker = self.circuit_breaker();
+        let mut first_line = start.line + 1;
+
+        for batch in lines.chunks(self.batch_size.max(1)) {
+            let payload = batch.concat();
+            let item = format!("{}: строки {}-{}", file, first_line, first_line + batch.len() - 1);
+            // у каждого пакета свой ключ, одинаковый при повторных попытках
+            let key = self.idempotency_key.as_ref().map(|key| format!("{}/{}", key, first_line));
+            first_line += batch.len();
+
+            let result = client.import(&payload, self.tenant.as_deref(), key.as_deref()).await;
+            let recorded = match breaker.observe(client, result.is_ok()).await {
+                Ok(()) => errors.record(&item, result),
+                Err(e) => Err(e),
+            };
+            if recorded?.is_none() {
+                sent.skipped_batches += 1;
+                sent.skipped_lines += batch.len();
+                sent.skipped_bytes += payload.len();
+                continue;
+            }
+
+            sent.batches += 1;
+            sent.lines += batch.len();
+            sent.bytes += payload.len();
+            if let Some(pb) = progress {
+                pb.set_message(format!("Импорт данных: {} строк, {} пакетов", sent.lines, sent.batches));
+            }
+        }
+
+        Ok(errors)
+    }
+
+    async fn follow_file(&self, client: &VmClient, path: &str, policy: ErrorPolicy) -> Result<()> {
+        if !matches!(self.format, ImportFormat::Prometheus) {
+            return Err(crate::error::VmCliError::InvalidQuery(
+                "Режим --follow поддерживается только для формата prometheus".to_string(),
+            ));
+        }
+
+        if !output::is_json_mode() {
+            println!("{} {}", "Отслеживание файла:".blue().bold(), path);
+            println!("Для остановки нажмите Ctrl+C");
+        }
+
+        let mut file = File::open(path)?;
+        let mut offset = 0u64;
+        let mut pending: Vec<u8> = Vec::new();
+        let mut imported_lines = 0usize;
+        let interval = Duration::from_millis(self.poll_interval);
+        let mut errors = BatchErrors::new(policy);
+        let mut breaker = self.circuit_breaker();
+
+        loop {
+            let len = fs::metadata(path)?.len();
+
+            if len < offset {
+                warn!("Файл {} был усечен, чтение с начала", path);
+                file = File::open(path)?;
+                offset = 0;
+                pending.clear();
+            }
+
+            if len > offset {
+                file.seek(SeekFrom::Start(offset))?;
+                let read = (&mut file).take(len - offset).read_to_end(&mut pending)?;
+                offset += read as u64;
+
+                if let Some(complete) = take_complete_lines(&mut pending) {
+                    let lines: Vec<&str> = complete
+                        .lines()
+                        .filter(|l| !l.trim().is_empty())
+                        .collect();
+
+                    for batch in lines.chunks(self.batch_size.max(1)) {
+                        let data = format!("{}\n", batch.join("\n"));
+                        let item = format!("пакет из {} строк", batch.len());
+                        if let Err(e) = self.validate_prometheus_format(&data) {
+                            errors.record::<()>(&item, Err(e))?;
+                            continue;
+                        }
+
+                        if self.dry_run {
+                            if !output::is_json_mode() {
+                                println!("{} строк данных готово к импорту", batch.len());
+                            }
+                            imported_lines += batch.len();
+                            continue;
+                        }
+
+                        let result = client.import(&data, self.tenant.as_deref(), None).await;
+                        breaker.observe(client, result.is_ok()).await?;
+                        if errors.record(&item, result)?.is_some() {
+                            imported_lines += batch.len();
+                            info!("Импортировано {} строк (всего {})", batch.len(), imported_lines);
+                        }
+                    }
+                }
+            }
+
+            tokio::select! {
+                _ = tokio::time::sleep(interval) => {}
+                _ = client.cancelled() => break,
+            }
+        }
+
+        if output::is_json_mode() {
+            errors.finish()?;
+            return output::print_data(&serde_json::json!({
+                "file": path,
+                "lines": imported_lines,
+            }));
+        }
+
         println!(
-            "{} {}",
-            "Импорт успешно завершен:".green().bold(),
-            self.file
+            "{} {} строк из {}",
+            "Импорт остановлен, импортировано:".green().bold(),
+            imported_lines,
+            path
         );
 
-        Ok(())
+        errors.finish()
+    }
+
+    fn circuit_breaker(&self) -> CircuitBreaker {
+        CircuitBreaker::new(self.breaker_threshold.map(|n| n as usize), self.breaker_max_wait)
     }
 
     fn prepare_data(&self, content: &str) -> Result<String> {
         match self.format {
-            ImportFormat::Prometheus => {
+            ImportFormat::Prometheus | ImportFormat::PrometheusExposition => {
                 self.validate_prometheus_format(content)?;
                 Ok(content.to_string())
             }
@@ -126,7 +501,7 @@ impl ImportCommand {
         }
 
         if error_count > 0 {
-            println!(
+            eprintln!(
                 "{} {} ошибок из {} строк",
                 "Найдено:".yellow().bold(),
                 error_count,
@@ -139,10 +514,8 @@ impl ImportCommand {
 
     fn is_valid_prometheus_line(&self, line: &str) -> bool {
         if let Some((metric_part, value_part)) = line.rsplit_once(' ') {
-            if metric_part.contains('{') {
-                if !metric_part.contains('}') {
-                    return false;
-                }
+            if metric_part.contains('{') && !metric_part.contains('}') {
+                return false;
             }
 
             if let Some((timestamp, value)) = value_part.rsplit_once(' ') {
@@ -157,42 +530,45 @@ impl ImportCommand {
 
     fn convert_json_to_prometheus(&self, content: &str) -> Result<String> {
         let json_data: serde_json::Value = serde_json::from_str(content)
-            .map_err(|e| crate::error::VmCliError::JsonError(e))?;
+            .map_err(crate::error::VmCliError::JsonError)?;
+
+        let Some(array) = json_data.as_array() else {
+            return Err(crate::error::VmCliError::InvalidQuery(
+                "JSON для импорта должен быть массивом объектов".to_string(),
+            ));
+        };
 
         let mut prometheus_data = String::new();
+        let mut error_count = 0;
 
-        if let Some(array) = json_data.as_array() {
-            for item in array {
-                if let (Some(metric), Some(value)) = (item.get("metric"), item.get("value")) {
-                    if let (Some(metric_obj), Some(value_array)) = (metric.as_object(), value.as_array()) {
-                        if value_array.len() == 2 {
-                            if let (Some(timestamp), Some(value)) = (value_array[0].as_f64(), value_array[1].as_f64()) {
-                                let mut metric_str = String::new();
-                                
-                                if let Some(name) = metric_obj.get("__name__") {
-                                    metric_str.push_str(&name.as_str().unwrap_or("unknown"));
-                                }
-
-                                let labels: Vec<String> = metric_obj
-                                    .iter()
-                                    .filter(|(k, _)| *k != "__name__")
-                                    .map(|(k, v)| format!("{}=\"{}\"", k, v.as_str().unwrap_or("")))
-                                    .collect();
-
-                                if !labels.is_empty() {
-                                    metric_str.push('{');
-                                    metric_str.push_str(&labels.join(","));
-                                    metric_str.push('}');
-                                }
-
-                                prometheus_data.push_str(&format!("{} {} {}\n", metric_str, value, timestamp as i64));
-                            }
-                        }
-                    }
+        for (index, item) in array.iter().enumerate() {
+            match json_element_to_prometheus(item) {
+                Ok(line) => {
+                    prometheus_data.push_str(&line);
+                    prometheus_data.push('\n');
+                }
+                Err(reason) if self.skip_errors => {
+                    error_count += 1;
+                    eprintln!("{} Элемент {}: {}", "ПРЕДУПРЕЖДЕНИЕ:".yellow(), index, reason);
+                }
+                Err(reason) => {
+                    return Err(crate::error::VmCliError::InvalidQuery(format!(
+                        "Неверный элемент {}: {}",
+                        index, reason
+                    )));
                 }
             }
         }
 
+        if error_count > 0 {
+            eprintln!(
+                "{} {} ошибок из {} элементов",
+                "Найдено:".yellow().bold(),
+                error_count,
+                array.len()
+            );
+        }
+
         Ok(prometheus_data)
     }
 
@@ -201,7 +577,7 @@ impl ImportCommand {
         let mut prometheus_data = String::new();
 
         for result in reader.records() {
-            let record = result.map_err(|e| crate::error::VmCliError::CsvError(e))?;
+            let record = result.map_err(crate::error::VmCliError::CsvError)?;
             
             if record.len() >= 3 {
                 let timestamp = record.get(0).unwrap_or("");
@@ -217,3 +593,756 @@ impl ImportCommand {
         Ok(prometheus_data)
     }
 }
+
+#[derive(serde::Serialize)]
+struct MetricSamples {
+    metric: String,
+    samples: usize,
+}
+
+fn load_metadata(path: &str) -> Result<HashMap<String, Vec<MetricMetadata>>> {
+    if !Path::new(path).exists() {
+        return Err(crate::error::VmCliError::FileNotFound(path.to_string()));
+    }
+    Ok(serde_json::from_str(&fs::read_to_string(path)?)?)
+}
+
+fn attach_metadata(data: &str, metadata: &HashMap<String, Vec<MetricMetadata>>) -> String {
+    let mut described: HashSet<String> = data
+        .lines()
+        .filter_map(|line| line.trim().strip_prefix("# TYPE "))
+        .filter_map(|rest| rest.split_whitespace().next())
+        .map(str::to_string)
+        .collect();
+
+    let mut result = String::with_capacity(data.len());
+    for line in data.lines() {
+        let trimmed = line.trim();
+        if !trimmed.is_empty() && !trimmed.starts_with('#') {
+            let name = &trimmed[..trimmed.find(['{', ' ']).unwrap_or(trimmed.len())];
+            let candidates = METRIC_SUFFIXES.iter().filter_map(|suffix| name.strip_suffix(suffix));
+            let family = std::iter::once(name)
+                .chain(candidates)
+                .find_map(|candidate| metadata.get_key_value(candidate))
+                .and_then(|(family, entries)| entries.first().map(|meta| (family, meta)));
+
+            if let Some((family, meta)) = family {
+                if described.insert(family.clone()) {
+                    if !meta.help.is_empty() {
+                        let help = meta.help.replace('\\', "\\\\").replace('\n', "\\n");
+                        result.push_str(&format!("# HELP {} {}\n", family, help));
+                    }
+                    result.push_str(&format!("# TYPE {} {}\n", family, exposition_type(&meta.metric_type)));
+                }
+            }
+        }
+        result.push_str(line);
+        result.push('\n');
+    }
+    result
+}
+
+fn exposition_type(metric_type: &str) -> &'static str {
+    match metric_type {
+        "counter" => "counter",
+        "gauge" => "gauge",
+        "histogram" => "histogram",
+        "summary" => "summary",
+        _ => "untyped",
+    }
+}
+
+fn verify_checksum(file: &str, actual: &str) -> Result<()> {
+    let sidecar = checksum_path(Path::new(file));
+    if !sidecar.exists() {
+        return Err(crate::error::VmCliError::FileNotFound(sidecar.display().to_string()));
+    }
+
+    match read_checksum(Path::new(file))? {
+        Some(expected) if expected == actual => {
+            info!("Контрольная сумма {} совпадает", file);
+            Ok(())
+        }
+        Some(expected) => Err(crate::error::VmCliError::ChecksumMismatch {
+            file: file.to_string(),
+            expected,
+            actual: actual.to_string(),
+        }),
+        None => Err(crate::error::VmCliError::InvalidQuery(format!(
+            "Файл {} не содержит SHA-256 в формате sha256sum",
+            sidecar.display()
+        ))),
+    }
+}
+
+#[derive(Clone, Copy)]
+struct ImportPosition {
+    line: usize,
+    byte: usize,
+}
+
+/// Пакеты, пропущенные с --skip-errors, в импорт не попали, но позицию для
+/// продолжения тоже сдвигают: они уже перечислены в сводке ошибок.
+#[derive(Default)]
+struct ImportProgress {
+    batches: usize,
+    lines: usize,
+    bytes: usize,
+    skipped_batches: usize,
+    skipped_lines: usize,
+    skipped_bytes: usize,
+}
+
+impl ImportProgress {
+    fn failure_report(&self, file: &str, start: ImportPosition) -> String {
+        let stopped_line = start.line + self.lines + self.skipped_lines;
+        let skipped = if self.skipped_batches > 0 {
+            format!(
+                ", пропущено из-за ошибок {} пакетов, {} строк",
+                self.skipped_batches, self.skipped_lines
+            )
+        } else {
+            String::new()
+        };
+        format!(
+            "{} импорт {} прерван: успешно отправлено {} пакетов, {} строк ({} байт){}; \
+             остановка на строке {} (смещение {} байт). Для продолжения: --skip-lines {}",
+            "ПРЕДУПРЕЖДЕНИЕ:".yellow(),
+            file,
+            self.batches,
+            self.lines,
+            self.bytes,
+            skipped,
+            stopped_line + 1,
+            start.byte + self.bytes + self.skipped_bytes,
+            stopped_line
+        )
+    }
+}
+
+fn line_offset(content: &str, lines: usize) -> usize {
+    if lines == 0 {
+        return 0;
+    }
+    content
+        .match_indices('\n')
+        .nth(lines - 1)
+        .map_or(content.len(), |(pos, _)| pos + 1)
+}
+
+fn marker_path(file: &Path) -> PathBuf {
+    file.parent()
+        .filter(|dir| !dir.as_os_str().is_empty())
+        .unwrap_or_else(|| Path::new("."))
+        .join(".imported")
+}
+
+/// Запись в .imported: `<ключ> <адрес импорта> <файл>`. Адрес включает хост vminsert
+/// и тенанта, поэтому импорт того же файла в другую инсталляцию не пропускается.
+fn is_marked(marker: &Path, key: &str, target: &str) -> bool {
+    fs::read_to_string(marker)
+        .map(|content| {
+            content.lines().any(|line| {
+                let mut fields = line.split_whitespace();
+                fields.next() == Some(key) && fields.next() == Some(target)
+            })
+        })
+        .unwrap_or(false)
+}
+
+fn mark_imported(marker: &Path, key: &str, target: &str, file: &str) -> std::io::Result<()> {
+    if is_marked(marker, key, target) {
+        return Ok(());
+    }
+    let mut out = fs::OpenOptions::new().create(true).append(true).open(marker)?;
+    writeln!(out, "{} {} {}", key, target, file)
+}
+
+fn parse_tenant(value: &str) -> std::result::Result<String, String> {
+    let (account, project) = value.split_once(':').unwrap_or((value, "0"));
+    match (account.parse::<u32>(), project.parse::<u32>()) {
+        (Ok(account), Ok(project)) => Ok(format!("{}:{}", account, project)),
+        _ => Err(format!(
+            "ожидается ACCOUNT или ACCOUNT:PROJECT из неотрицательных целых чисел, получено '{}'",
+            value
+        )),
+    }
+}
+
+async fn preflight(client: &VmClient) -> Result<()> {
+    let host = client.insert_host();
+    let status = client.probe_health(host).await?;
+    if !is_healthy(&status) {
+        return Err(crate::error::VmCliError::ApiError {
+            message: format!(
+                "{} не готов к записи (статус /health: {}); используйте --skip-preflight, чтобы пропустить проверку",
+                host, status
+            ),
+            status: None,
+        });
+    }
+
+    let flags = match client.get_flags(host).await {
+        Ok(flags) => flags,
+        Err(e) => {
+            warn!("Не удалось получить флаги сервера, проверка режима записи пропущена: {}", e);
+            return Ok(());
+        }
+    };
+
+    for flag in ["readonly", "maintenance"] {
+        if flags.get(flag).is_some_and(|value| value == "true") {
+            return Err(crate::error::VmCliError::ApiError {
+                message: format!(
+                    "{} запущен с -{}=true, запись отклоняется; используйте --skip-preflight, чтобы пропустить проверку",
+                    host, flag
+                ),
+                status: None,
+            });
+        }
+    }
+
+    Ok(())
+}
+
+fn count_samples_by_metric(data: &str) -> Vec<MetricSamples> {
+    let mut counts: std::collections::HashMap<&str, usize> = std::collections::HashMap::new();
+
+    for line in data.lines() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let name_end = line.find(['{', ' ']).unwrap_or(line.len());
+        *counts.entry(&line[..name_end]).or_insert(0) += 1;
+    }
+
+    let mut counts: Vec<MetricSamples> = counts
+        .into_iter()
+        .map(|(metric, samples)| MetricSamples { metric: metric.to_string(), samples })
+        .collect();
+    counts.sort_by(|a, b| b.samples.cmp(&a.samples).then_with(|| a.metric.cmp(&b.metric)));
+    counts
+}
+
+fn print_summary(summary: &[MetricSamples]) {
+    println!("{}", "Сводка по метрикам:".bold());
+    for entry in summary {
+        println!("  {}: {} samples", entry.metric, entry.samples);
+    }
+}
+
+fn json_element_to_prometheus(item: &serde_json::Value) -> std::result::Result<String, String> {
+    let metric_obj = item
+        .get("metric")
+        .ok_or("отсутствует поле 'metric'")?
+        .as_object()
+        .ok_or("поле 'metric' должно быть объектом")?;
+    let value_array = item
+        .get("value")
+        .ok_or("отсутствует поле 'value'")?
+        .as_array()
+        .filter(|v| v.len() == 2)
+        .ok_or("поле 'value' должно быть массивом [timestamp, value]")?;
+
+    let timestamp = json_number(&value_array[0]).ok_or("value[0] (timestamp) не является числом")?;
+    let value = json_number(&value_array[1]).ok_or("value[1] не является числом")?;
+
+    let mut metric_str = String::new();
+
+    if let Some(name) = metric_obj.get("__name__") {
+        metric_str.push_str(name.as_str().ok_or("поле 'metric.__name__' должно быть строкой")?);
+    }
+
+    let labels: Vec<String> = metric_obj
+        .iter()
+        .filter(|(k, _)| *k != "__name__")
+        .map(|(k, v)| format!("{}=\"{}\"", k, v.as_str().unwrap_or("")))
+        .collect();
+
+    if !labels.is_empty() {
+        metric_str.push('{');
+        metric_str.push_str(&labels.join(","));
+        metric_str.push('}');
+    }
+
+    Ok(format!("{} {} {}", metric_str, value, timestamp as i64))
+}
+
+fn json_number(value: &serde_json::Value) -> Option<f64> {
+    value
+        .as_f64()
+        .or_else(|| value.as_str().and_then(|s| s.parse().ok()))
+}
+
+fn take_complete_lines(pending: &mut Vec<u8>) -> Option<String> {
+    let last_newline = pending.iter().rposition(|&b| b == b'\n')?;
+    let complete: Vec<u8> = pending.drain(..=last_newline).collect();
+    Some(String::from_utf8_lossy(&complete).into_owned())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::MockServer;
+
+    fn import_command(args: &[&str]) -> ImportCommand {
+        ImportCommand::try_parse_from(std::iter::once("import").chain(args.iter().copied())).unwrap()
+    }
+
+    #[test]
+    fn line_offset_counts_bytes_of_skipped_lines() {
+        let content = "a 1\nbb 2\nccc 3\n";
+        assert_eq!(line_offset(content, 0), 0);
+        assert_eq!(line_offset(content, 1), 4);
+        assert_eq!(line_offset(content, 2), 9);
+        assert_eq!(line_offset(content, 3), content.len());
+        assert_eq!(line_offset(content, 10), content.len());
+        assert_eq!(line_offset("a 1\nb 2", 2), 7);
+    }
+
+    #[test]
+    fn failure_report_points_to_resume_position() {
+        let sent = ImportProgress { batches: 2, lines: 2000, bytes: 30000, ..Default::default() };
+        let report = sent.failure_report("data.txt", ImportPosition { line: 100, byte: 1500 });
+
+        assert!(report.contains("импорт data.txt прерван: успешно отправлено 2 пакетов, 2000 строк (30000 байт)"));
+        assert!(report.contains("остановка на строке 2101 (смещение 31500 байт)"));
+        assert!(report.ends_with("--skip-lines 2100"));
+    }
+
+    #[tokio::test]
+    async fn import_batches_sends_prometheus_text_and_stops_on_failure() {
+        let server = MockServer::start(vec![(204, ""), (400, "cannot parse")]);
+        let client = VmClient::new(&server.url, 5, None, None, None).unwrap();
+        let command = import_command(&["data.txt", "--batch-size", "2"]);
+        let data = "a 1 1\nb 2 2\nc 3 3\nd 4 4\ne 5 5\n";
+
+        let result = command
+            .import_batches(&client, "data.txt", data, ImportPosition { line: 0, byte: 0 }, None)
+            .await;
+
+        assert!(matches!(result, Err(crate::error::VmCliError::ApiError { status: Some(400), .. })));
+        let requests = server.requests();
+        assert_eq!(requests.len(), 2);
+        assert!(requests.iter().all(|r| r.method == "POST" && r.path() == "/api/v1/import/prometheus"));
+        assert_eq!(requests[0].body, "a 1 1\nb 2 2\n");
+        assert_eq!(requests[1].body, "c 3 3\nd 4 4\n");
+    }
+
+    #[test]
+    fn content_hash_is_stable_sha256() {
+        assert_eq!(
+            sha256_hex(b"abc"),
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+        assert_eq!(sha256_hex(b"a 1 1\n"), sha256_hex(b"a 1 1\n"));
+    }
+
+    #[test]
+    fn marker_is_keyed_by_host_and_tenant() {
+        let dir = tempfile::tempdir().unwrap();
+        let marker = marker_path(&dir.path().join("data.txt"));
+        let first = VmClient::new("http://vm-a:8428", 5, None, None, None).unwrap();
+        let second = VmClient::new("http://vm-b:8428", 5, None, None, None).unwrap();
+        let target = first.import_url(None).unwrap();
+
+        mark_imported(&marker, "hash", &target, "data.txt").unwrap();
+        mark_imported(&marker, "hash", &target, "data.txt").unwrap();
+
+        assert!(is_marked(&marker, "hash", &target));
+        assert!(!is_marked(&marker, "other", &target));
+        assert!(!is_marked(&marker, "hash", &second.import_url(None).unwrap()));
+        assert_eq!(fs::read_to_string(&marker).unwrap().lines().count(), 1);
+
+        let cluster = cluster_client("http://vm-a:8481", "http://vm-a:8480");
+        mark_imported(&marker, "hash", &cluster.import_url(None).unwrap(), "data.txt").unwrap();
+        assert!(is_marked(&marker, "hash", &cluster.import_url(Some("0:0")).unwrap()));
+        assert!(!is_marked(&marker, "hash", &cluster.import_url(Some("1:0")).unwrap()));
+    }
+
+    async fn import_three_files(policy_args: &[&str]) -> (Result<()>, Vec<String>) {
+        let dir = tempfile::tempdir().unwrap();
+        let files: Vec<String> = ["day1.txt", "day2.txt", "day3.txt"]
+            .iter()
+            .map(|name| dir.path().join(name).to_string_lossy().into_owned())
+            .collect();
+        fs::write(&files[0], "a 1 1\n").unwrap();
+        fs::write(&files[2], "c 3 3\n").unwrap();
+
+        let server = MockServer::start(vec![(204, "")]);
+        let client = VmClient::new(&server.url, 5, None, None, None).unwrap();
+        let mut args: Vec<&str> = files.iter().map(String::as_str).collect();
+        args.push("--skip-preflight");
+        args.extend_from_slice(policy_args);
+
+        let result = import_command(&args).execute(&client).await;
+        (result, server.requests().into_iter().map(|r| r.body).collect())
+    }
+
+    #[tokio::test]
+    async fn default_policy_aborts_on_missing_middle_file() {
+        let (result, bodies) = import_three_files(&[]).await;
+        assert!(matches!(result, Err(crate::error::VmCliError::FileNotFound(_))));
+        assert_eq!(bodies, ["a 1 1\n"]);
+    }
+
+    #[tokio::test]
+    async fn skip_errors_imports_remaining_files_and_fails() {
+        let (result, bodies) = import_three_files(&["--skip-errors"]).await;
+        match result {
+            Err(crate::error::VmCliError::BatchFailed { failed, total }) => {
+                assert_eq!(failed.len(), 1);
+                assert!(failed[0].ends_with("day2.txt"));
+                assert_eq!(total, 3);
+            }
+            other => panic!("unexpected result: {:?}", other.err()),
+        }
+        assert_eq!(bodies, ["a 1 1\n", "c 3 3\n"]);
+    }
+
+    #[test]
+    fn import_has_no_separate_fail_fast_flag() {
+        assert!(ImportCommand::try_parse_from(["import", "a.txt", "--stop-on-error"]).is_err());
+        assert!(ImportCommand::try_parse_from(["import", "a.txt", "--fail-fast"]).is_err());
+    }
+
+    #[tokio::test]
+    async fn breaker_waits_for_health_after_consecutive_batch_failures() {
+        let server = MockServer::start(vec![
+            (204, ""),
+            (503, "unavailable"),
+            (503, "unavailable"),
+            (200, "OK"),
+            (204, ""),
+        ]);
+        let client = VmClient::new(&server.url, 5, None, None, None).unwrap();
+        let command = import_command(&["data.txt", "--batch-size", "1", "--skip-errors", "--breaker-threshold", "2"]);
+
+        let result = command
+            .import_batches(&client, "data.txt", "a 1 1\nb 2 2\nc 3 3\nd 4 4\n", ImportPosition { line: 0, byte: 0 }, None)
+            .await;
+
+        match result {
+            Err(crate::error::VmCliError::BatchFailed { failed, total }) => {
+                assert_eq!(failed, ["data.txt: строки 2-2", "data.txt: строки 3-3"]);
+                assert_eq!(total, 4);
+            }
+            other => panic!("unexpected result: {:?}", other),
+        }
+        let paths: Vec<String> = server.requests().iter().map(|r| r.path().to_string()).collect();
+        assert_eq!(
+            paths,
+            [
+                "/api/v1/import/prometheus",
+                "/api/v1/import/prometheus",
+                "/api/v1/import/prometheus",
+                "/health",
+                "/api/v1/import/prometheus",
+            ]
+        );
+        assert_eq!(server.requests()[4].body, "d 4 4\n");
+    }
+
+    #[tokio::test]
+    async fn breaker_observes_failure_before_fail_fast_returns() {
+        let server = MockServer::start(vec![(503, "unavailable"), (200, "OK")]);
+        let client = VmClient::new(&server.url, 5, None, None, None).unwrap();
+        let command = import_command(&["data.txt", "--batch-size", "1", "--breaker-threshold", "1"]);
+
+        let result = command
+            .import_batches(&client, "data.txt", "a 1 1\nb 2 2\n", ImportPosition { line: 0, byte: 0 }, None)
+            .await;
+
+        assert!(matches!(result, Err(crate::error::VmCliError::ApiError { status: Some(503), .. })));
+        let paths: Vec<String> = server.requests().iter().map(|r| r.path().to_string()).collect();
+        assert_eq!(paths, ["/api/v1/import/prometheus", "/health"]);
+    }
+
+    #[test]
+    fn take_complete_lines_keeps_partial_tail() {
+        let mut pending = b"a 1 1\nb 2".to_vec();
+        assert_eq!(take_complete_lines(&mut pending).as_deref(), Some("a 1 1\n"));
+        assert_eq!(pending, b"b 2");
+        assert_eq!(take_complete_lines(&mut pending), None);
+    }
+
+    #[tokio::test]
+    async fn follow_imports_only_complete_lines_of_appended_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("spool.txt");
+        let path_str = path.to_string_lossy().into_owned();
+        fs::write(&path, "a 1 1\nb 2 2\nc 3").unwrap();
+
+        let server = MockServer::start(vec![(204, "")]);
+        let cancel = tokio_util::sync::CancellationToken::new();
+        let client = VmClient::new(&server.url, 5, None, None, None)
+            .unwrap()
+            .with_cancellation(cancel.clone());
+        let command = import_command(&[&path_str, "--follow", "--poll-interval", "20"]);
+
+        let appender = async {
+            tokio::time::sleep(Duration::from_millis(200)).await;
+            let mut file = fs::OpenOptions::new().append(true).open(&path).unwrap();
+            file.write_all(b" 3\nd 4").unwrap();
+            tokio::time::sleep(Duration::from_millis(200)).await;
+            cancel.cancel();
+        };
+        let (result, ()) = tokio::join!(command.follow_file(&client, &path_str, ErrorPolicy::FailFast), appender);
+
+        result.unwrap();
+        let bodies: Vec<String> = server.requests().into_iter().map(|r| r.body).collect();
+        assert_eq!(bodies, ["a 1 1\nb 2 2\n", "c 3 3\n"]);
+    }
+
+    fn cluster_client(base_url: &str, vminsert: &str) -> VmClient {
+        let cluster = toml::from_str(&format!("vminsert_host = \"{}\"", vminsert)).unwrap();
+        VmClient::new(base_url, 5, Some(cluster), None, None).unwrap()
+    }
+
+    #[tokio::test]
+    async fn preflight_probes_vminsert_host() {
+        let select = MockServer::start(vec![(500, "wrong host")]);
+        let insert = MockServer::start(vec![(200, "OK"), (200, "-readonly=\"false\"\n")]);
+
+        preflight(&cluster_client(&select.url, &insert.url)).await.unwrap();
+
+        assert!(select.requests().is_empty());
+        let paths: Vec<String> = insert.requests().iter().map(|r| r.path().to_string()).collect();
+        assert_eq!(paths, ["/health", "/flags"]);
+    }
+
+    #[tokio::test]
+    async fn preflight_rejects_readonly_vminsert() {
+        let insert = MockServer::start(vec![(200, "OK"), (200, "-readonly=\"true\"\n")]);
+
+        let result = preflight(&cluster_client("http://127.0.0.1:1", &insert.url)).await;
+
+        match result {
+            Err(crate::error::VmCliError::ApiError { message, .. }) => assert!(message.contains("-readonly=true")),
+            other => panic!("unexpected result: {:?}", other),
+        }
+    }
+
+    const JSON_WITH_BAD_ELEMENT: &str = r#"[
+        {"metric":{"__name__":"up","job":"a"},"value":[1700000000,"1"]},
+        {"metric":{"__name__":"up","job":"b"},"value":[1700000000,"oops"]},
+        {"metric":{"__name__":"up","job":"c"},"value":[1700000000,0]}
+    ]"#;
+
+    #[test]
+    fn bad_json_element_is_reported_by_index_and_field() {
+        let command = import_command(&["data.json", "--format", "json"]);
+
+        match command.convert_json_to_prometheus(JSON_WITH_BAD_ELEMENT) {
+            Err(crate::error::VmCliError::InvalidQuery(message)) => {
+                assert_eq!(message, "Неверный элемент 1: value[1] не является числом");
+            }
+            other => panic!("unexpected result: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn skip_errors_continues_past_bad_json_element() {
+        let command = import_command(&["data.json", "--format", "json", "--skip-errors"]);
+
+        let converted = command.convert_json_to_prometheus(JSON_WITH_BAD_ELEMENT).unwrap();
+
+        assert_eq!(converted, "up{job=\"a\"} 1 1700000000\nup{job=\"c\"} 0 1700000000\n");
+    }
+
+    #[test]
+    fn json_element_errors_name_the_offending_field() {
+        let cases = [
+            (r#"{"value":[1,2]}"#, "отсутствует поле 'metric'"),
+            (r#"{"metric":[],"value":[1,2]}"#, "поле 'metric' должно быть объектом"),
+            (r#"{"metric":{}}"#, "отсутствует поле 'value'"),
+            (r#"{"metric":{},"value":[1]}"#, "поле 'value' должно быть массивом [timestamp, value]"),
+            (r#"{"metric":{},"value":["x",1]}"#, "value[0] (timestamp) не является числом"),
+            (r#"{"metric":{"__name__":1},"value":[1,2]}"#, "поле 'metric.__name__' должно быть строкой"),
+        ];
+        for (element, expected) in cases {
+            let item: serde_json::Value = serde_json::from_str(element).unwrap();
+            assert_eq!(json_element_to_prometheus(&item).unwrap_err(), expected, "{}", element);
+        }
+    }
+
+    #[test]
+    fn summary_counts_samples_per_metric_sorted_by_count() {
+        let command = import_command(&["data.txt", "--summary", "5"]);
+        let content = "# HELP node_cpu_seconds_total CPU time\n\
+                       # TYPE node_cpu_seconds_total counter\n\
+                       node_cpu_seconds_total{cpu=\"0\"} 1 1700000000000\n\
+                       up{job=\"a\"} 1 1700000000000\n\
+                       node_cpu_seconds_total{cpu=\"1\"} 2 1700000000000\n\
+                       \n\
+                       node_load1 0.5 1700000000000\n\
+                       up{job=\"b\"} 0 1700000000000\n\
+                       node_cpu_seconds_total{cpu=\"0\"} 3 1700000060000\n";
+
+        let data = command.prepare_data(content).unwrap();
+        let counts: Vec<(String, usize)> =
+            count_samples_by_metric(&data).into_iter().map(|entry| (entry.metric, entry.samples)).collect();
+
+        assert_eq!(
+            counts,
+            [("node_cpu_seconds_total".to_string(), 3), ("up".to_string(), 2), ("node_load1".to_string(), 1)]
+        );
+    }
+
+    #[tokio::test]
+    async fn metadata_sidecar_round_trips_from_export_to_exposition_import() {
+        let dir = tempfile::tempdir().unwrap();
+        let data = dir.path().join("data.txt").to_string_lossy().into_owned();
+        let sidecar = dir.path().join("metadata.json").to_string_lossy().into_owned();
+
+        let source = MockServer::start(vec![
+            (200, "http_requests_total{code=\"200\"} 5 1700000000000\nup 1 1700000000000\n"),
+            (
+                200,
+                r#"{"status":"success","data":{"http_requests":[{"type":"counter","help":"Requests served.","unit":""}],"unused":[{"type":"gauge","help":"","unit":""}]}}"#,
+            ),
+        ]);
+        let source_client = VmClient::new(&source.url, 5, None, None, None).unwrap();
+        crate::commands::export::ExportCommand::try_parse_from([
+            "export", "{__name__!=\"\"}", "--output", &data, "--metadata-file", &sidecar,
+        ])
+        .unwrap()
+        .execute(&source_client)
+        .await
+        .unwrap();
+
+        let saved: HashMap<String, Vec<MetricMetadata>> = serde_json::from_str(&fs::read_to_string(&sidecar).unwrap()).unwrap();
+        assert_eq!(saved.keys().collect::<Vec<_>>(), ["http_requests"]);
+
+        let target = MockServer::start(vec![(204, "")]);
+        let target_client = VmClient::new(&target.url, 5, None, None, None).unwrap();
+        import_command(&[&data, "--format", "prometheus-exposition", "--metadata-file", &sidecar, "--skip-preflight"])
+            .execute(&target_client)
+            .await
+            .unwrap();
+
+        let requests = target.requests();
+        assert_eq!(requests.len(), 1);
+        assert_eq!(
+            requests[0].body,
+            "# HELP http_requests Requests served.\n# TYPE http_requests counter\nhttp_requests_total{code=\"200\"} 5 1700000000000\nup 1 1700000000000\n"
+        );
+    }
+
+    #[test]
+    fn metadata_file_requires_exposition_format() {
+        let dir = tempfile::tempdir().unwrap();
+        let sidecar = dir.path().join("metadata.json");
+        fs::write(&sidecar, "{}").unwrap();
+
+        let result = tokio_test::block_on(
+            import_command(&["data.txt", "--metadata-file", sidecar.to_str().unwrap()])
+                .execute(&VmClient::new("http://127.0.0.1:1", 1, None, None, None).unwrap()),
+        );
+
+        assert!(matches!(result, Err(crate::error::VmCliError::InvalidQuery(_))));
+    }
+
+    #[test]
+    fn verify_checksum_detects_mismatch() {
+        let dir = tempfile::tempdir().unwrap();
+        let file = dir.path().join("data.txt");
+        fs::write(&file, "a 1 1\n").unwrap();
+        let file = file.to_string_lossy().into_owned();
+        crate::utils::write_checksum(Path::new(&file), &sha256_hex(b"a 1 1\n")).unwrap();
+
+        assert!(verify_checksum(&file, &sha256_hex(b"a 1 1\n")).is_ok());
+
+        let tampered = sha256_hex(b"a 1 2\n");
+        match verify_checksum(&file, &tampered) {
+            Err(crate::error::VmCliError::ChecksumMismatch { expected, actual, .. }) => {
+                assert_eq!(expected, sha256_hex(b"a 1 1\n"));
+                assert_eq!(actual, tampered);
+            }
+            other => panic!("unexpected result: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn verify_checksum_requires_a_valid_sidecar() {
+        let dir = tempfile::tempdir().unwrap();
+        let file = dir.path().join("data.txt").to_string_lossy().into_owned();
+
+        assert!(matches!(verify_checksum(&file, "x"), Err(crate::error::VmCliError::FileNotFound(_))));
+
+        fs::write(format!("{}.sha256", file), "garbage\n").unwrap();
+        assert!(matches!(verify_checksum(&file, "x"), Err(crate::error::VmCliError::InvalidQuery(_))));
+    }
+
+    #[tokio::test]
+    async fn import_with_mismatched_checksum_sends_nothing() {
+        let dir = tempfile::tempdir().unwrap();
+        let file = dir.path().join("data.txt");
+        fs::write(&file, "a 1 1\n").unwrap();
+        fs::write(dir.path().join("data.txt.sha256"), format!("{}  data.txt\n", sha256_hex(b"other"))).unwrap();
+        let server = MockServer::start(vec![(204, "")]);
+        let client = VmClient::new(&server.url, 5, None, None, None).unwrap();
+
+        let result = import_command(&[file.to_str().unwrap(), "--verify-checksum", "--skip-preflight"])
+            .execute(&client)
+            .await;
+
+        assert!(matches!(result, Err(crate::error::VmCliError::ChecksumMismatch { .. })));
+        assert!(server.requests().is_empty());
+    }
+
+    #[tokio::test]
+    async fn idempotency_key_is_sent_as_header() {
+        let dir = tempfile::tempdir().unwrap();
+        let file = dir.path().join("data.txt");
+        fs::write(&file, "a 1 1\nb 2 2\nc 3 3\n").unwrap();
+        let file = file.to_str().unwrap();
+        let server = MockServer::start(vec![(204, "")]);
+        let client = VmClient::new(&server.url, 5, None, None, None).unwrap();
+
+        import_command(&[file, "--format", "prometheus-exposition", "--idempotency-key", "backfill", "--skip-preflight"])
+            .execute(&client)
+            .await
+            .unwrap();
+        import_command(&[file, "--batch-size", "2", "--idempotency-key", "backfill", "--force", "--skip-preflight"])
+            .execute(&client)
+            .await
+            .unwrap();
+        import_command(&[file, "--force", "--skip-preflight"]).execute(&client).await.unwrap();
+
+        let keys: Vec<Option<String>> = server
+            .requests()
+            .iter()
+            .map(|r| r.header("x-idempotency-key").map(String::from))
+            .collect();
+        assert_eq!(
+            keys,
+            [Some("backfill".to_string()), Some("backfill/1".to_string()), Some("backfill/3".to_string()), None]
+        );
+    }
+
+    #[tokio::test]
+    async fn skipped_batches_advance_resume_position() {
+        let server = MockServer::start(vec![(204, ""), (400, "cannot parse"), (400, "cannot parse"), (503, "unavailable")]);
+        let client = VmClient::new(&server.url, 5, None, None, None).unwrap();
+        let command = import_command(&[
+            "data.txt", "--batch-size", "1", "--skip-errors", "--breaker-threshold", "2", "--breaker-max-wait", "0s",
+        ]);
+        let data = "a 1 1\nb 2 2\nc 3 3\nd 4 4\n";
+        let start = ImportPosition { line: 10, byte: 60 };
+        let mut sent = ImportProgress::default();
+
+        let result = command.send_batches(&client, "data.txt", data, start, &mut sent, None).await;
+
+        assert!(matches!(result, Err(crate::error::VmCliError::Timeout)));
+        assert_eq!((sent.batches, sent.lines, sent.bytes), (1, 1, 6));
+        assert_eq!((sent.skipped_batches, sent.skipped_lines, sent.skipped_bytes), (1, 1, 6));
+
+        let report = sent.failure_report("data.txt", start);
+        assert!(report.contains("успешно отправлено 1 пакетов, 1 строк (6 байт), пропущено из-за ошибок 1 пакетов, 1 строк"));
+        assert!(report.contains("остановка на строке 13 (смещение 72 байт)"));
+        assert!(report.ends_with("--skip-lines 12"));
+        assert_eq!(&data[72 - 60..], "c 3 3\nd 4 4\n");
+    }
+}
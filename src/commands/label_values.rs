@@ -0,0 +1,250 @@
+use crate::api::VmClient;
+use crate::config::OutputFormat;
+use crate::error::{Result, VmCliError};
+use crate::messages::{self, Msg};
+use crate::output;
+use crate::utils::{parse_time_range, print_formatted, Formatter};
+use clap::Parser;
+use colored::*;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::time::Duration;
+use tracing::info;
+
+#[derive(Parser)]
+pub struct LabelValuesCommand {
+    #[arg(value_name = "LABEL", required_unless_present = "labels", conflicts_with = "labels")]
+    label: Option<String>,
+
+    #[arg(long, value_name = "LABEL,...", value_delimiter = ',')]
+    labels: Vec<String>,
+
+    #[arg(long = "match", value_name = "MATCH")]
+    matches: Vec<String>,
+
+    #[arg(short, long, conflicts_with = "range")]
+    start: Option<String>,
+
+    #[arg(short, long, conflicts_with = "range")]
+    end: Option<String>,
+
+    #[arg(short, long)]
+    range: Option<String>,
+
+    #[arg(short, long)]
+    limit: Option<usize>,
+
+    #[arg(long, value_name = "DURATION", requires = "range", value_parser = humantime::parse_duration)]
+    page_window: Option<Duration>,
+
+    #[arg(short, long, value_enum, default_value = "table")]
+    format: OutputFormat,
+}
+
+#[derive(Serialize)]
+#[serde(transparent)]
+struct GroupedLabelValues<'a>(BTreeMap<&'a str, Vec<String>>);
+
+impl Formatter for GroupedLabelValues<'_> {
+    fn headers(&self) -> Vec<String> {
+        ["label", "value"].map(String::from).into()
+    }
+
+    fn rows(&self) -> Vec<Vec<String>> {
+        self.0
+            .iter()
+            .flat_map(|(label, values)| values.iter().map(move |value| vec![label.to_string(), value.clone()]))
+            .collect()
+    }
+}
+
+impl LabelValuesCommand {
+    pub async fn execute(&self, client: &VmClient) -> Result<()> {
+        let (grouped, truncated) = self.fetch(client).await?;
+
+        let count = grouped.0.values().map(Vec::len).sum::<usize>();
+        let table = self.format == OutputFormat::Table && !output::is_json_mode();
+
+        if table && count == 0 {
+            println!("{}", messages::t(Msg::NoData).yellow());
+            return Ok(());
+        }
+
+        print_formatted(&grouped, &self.format)?;
+
+        if table {
+            println!(
+                "\n{} {} {}",
+                messages::t(Msg::Found).blue().bold(),
+                count,
+                messages::t(Msg::Records)
+            );
+        }
+
+        for label in truncated {
+            eprintln!(
+                "{} {}: показаны первые {} значений, найдено больше; увеличьте --limit или уточните селектор",
+                messages::t(Msg::Warning).yellow(),
+                label,
+                self.limit.unwrap_or_default()
+            );
+        }
+
+        Ok(())
+    }
+
+    /// Значения по меткам и метки, чьи значения обрезаны по --limit.
+    async fn fetch(&self, client: &VmClient) -> Result<(GroupedLabelValues<'_>, Vec<&str>)> {
+        let labels: Vec<&str> = self.label.iter().chain(&self.labels).map(String::as_str).collect();
+        info!("Получение значений меток: {}", labels.join(", "));
+
+        let (start, end) = match &self.range {
+            Some(range) => {
+                let (start, end) = parse_time_range(range).map_err(VmCliError::TimeParseError)?;
+                (Some(start), Some(end))
+            }
+            None => (self.start.clone(), self.end.clone()),
+        };
+        let matches: Vec<&str> = self.matches.iter().map(String::as_str).collect();
+
+        let limit = self.limit.map(|limit| limit + 1);
+
+        let responses = match (self.page_window, &start, &end) {
+            (Some(page_window), Some(start), Some(end)) => {
+                let (Ok(start), Ok(end)) = (start.parse::<i64>(), end.parse::<i64>()) else {
+                    return Err(VmCliError::TimeParseError(self.range.clone().unwrap_or_default()));
+                };
+                // окна по каждой метке идут по очереди, а сами метки — параллельно
+                let progress_bar = output::window_progress_bar();
+                let pages_done = std::cell::Cell::new(0);
+                let responses = futures::future::try_join_all(labels.iter().map(|label| {
+                    client.label_values_paged(label, &matches, start, end, page_window, limit, |_, pages| {
+                        pages_done.set(pages_done.get() + 1);
+                        progress_bar.set_length((pages * labels.len()) as u64);
+                        progress_bar.set_position(pages_done.get());
+                    })
+                }))
+                .await;
+                progress_bar.finish_and_clear();
+                responses?
+            }
+            _ => {
+                futures::future::try_join_all(
+                    labels.iter().map(|label| client.label_values(label, &matches, start.as_deref(), end.as_deref(), limit)),
+                )
+                .await?
+            }
+        };
+
+        let mut truncated = Vec::new();
+        let mut grouped = BTreeMap::new();
+        for (label, response) in labels.into_iter().zip(responses) {
+            let mut values = response.data;
+            if let Some(limit) = self.limit.filter(|limit| values.len() > *limit) {
+                values.truncate(limit);
+                truncated.push(label);
+            }
+            grouped.insert(label, values);
+        }
+        Ok((GroupedLabelValues(grouped), truncated))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::MockServer;
+    use std::io::{BufRead, BufReader, Write};
+    use std::net::TcpListener;
+    use std::time::Duration;
+
+    /// Отвечает только после того, как открыты все `expected` соединения,
+    /// поэтому последовательные запросы не дождутся ответа.
+    fn start_gated_server(expected: usize) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+
+        std::thread::spawn(move || {
+            let mut pending = Vec::new();
+            for stream in listener.incoming().take(expected) {
+                let mut reader = BufReader::new(stream.unwrap());
+                let mut request_line = String::new();
+                reader.read_line(&mut request_line).unwrap();
+                let mut line = String::new();
+                while reader.read_line(&mut line).unwrap() > 2 {
+                    line.clear();
+                }
+                let label = request_line.split('/').nth(4).unwrap_or_default().to_string();
+                pending.push((reader, label));
+            }
+            for (mut reader, label) in pending {
+                let body = format!(r#"{{"status":"success","data":["{}-1","{}-2"]}}"#, label, label);
+                let response = format!("HTTP/1.1 200 OK\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}", body.len(), body);
+                reader.get_mut().write_all(response.as_bytes()).unwrap();
+            }
+        });
+
+        url
+    }
+
+    #[tokio::test]
+    async fn labels_are_fetched_concurrently_and_merged() {
+        let url = start_gated_server(3);
+        let client = VmClient::new(&url, 5, None, None, None).unwrap();
+        let command = LabelValuesCommand::try_parse_from(["label-values", "--labels", "job,instance,region"]).unwrap();
+
+        let (grouped, truncated) = tokio::time::timeout(Duration::from_secs(5), command.fetch(&client))
+            .await
+            .expect("запросы должны идти параллельно")
+            .unwrap();
+
+        assert_eq!(
+            serde_json::to_value(&grouped).unwrap(),
+            serde_json::json!({
+                "instance": ["instance-1", "instance-2"],
+                "job": ["job-1", "job-2"],
+                "region": ["region-1", "region-2"],
+            })
+        );
+        assert_eq!(grouped.rows().len(), 6);
+        assert!(truncated.is_empty());
+    }
+
+    #[test]
+    fn label_and_labels_are_mutually_exclusive() {
+        assert!(LabelValuesCommand::try_parse_from(["label-values", "job", "--labels", "instance"]).is_err());
+        assert!(LabelValuesCommand::try_parse_from(["label-values"]).is_err());
+    }
+
+    #[tokio::test]
+    async fn limit_is_forwarded_and_truncated_labels_reported() {
+        let server = MockServer::start(vec![(200, r#"{"status":"success","data":["a","b","c"]}"#)]);
+        let client = VmClient::new(&server.url, 5, None, None, None).unwrap();
+        let command = LabelValuesCommand::try_parse_from(["label-values", "job", "--limit", "2"]).unwrap();
+
+        let (grouped, truncated) = command.fetch(&client).await.unwrap();
+
+        assert_eq!(grouped.0["job"], ["a", "b"]);
+        assert_eq!(truncated, ["job"]);
+        assert_eq!(server.requests()[0].param("limit"), ["3"]);
+    }
+
+    #[tokio::test]
+    async fn page_window_merges_values_across_windows() {
+        let server = MockServer::start(vec![
+            (200, r#"{"status":"success","data":["a","b"]}"#),
+            (200, r#"{"status":"success","data":["b","c"]}"#),
+        ]);
+        let client = VmClient::new(&server.url, 5, None, None, None).unwrap();
+        let command =
+            LabelValuesCommand::try_parse_from(["label-values", "job", "--range", "1d", "--page-window", "12h"]).unwrap();
+
+        let (grouped, truncated) = command.fetch(&client).await.unwrap();
+
+        assert_eq!(grouped.0["job"], ["a", "b", "c"]);
+        assert!(truncated.is_empty());
+        let requests = server.requests();
+        assert_eq!(requests.len(), 2);
+        assert_eq!(requests[0].param("end"), requests[1].param("start"));
+    }
+}
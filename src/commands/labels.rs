@@ -0,0 +1,151 @@
+use crate::api::VmClient;
+use crate::config::OutputFormat;
+use crate::error::{Result, VmCliError};
+use crate::messages::{self, Msg};
+use crate::output;
+use crate::utils::{parse_time_range, print_formatted, Formatter};
+use clap::Parser;
+use colored::*;
+use serde::Serialize;
+use tracing::info;
+
+#[derive(Parser)]
+pub struct LabelsCommand {
+    #[arg(long = "match", value_name = "MATCH")]
+    match_: Option<String>,
+
+    #[arg(short, long, conflicts_with = "range")]
+    start: Option<String>,
+
+    #[arg(short, long, conflicts_with = "range")]
+    end: Option<String>,
+
+    #[arg(short, long)]
+    range: Option<String>,
+
+    #[arg(short, long, value_parser = clap::value_parser!(u64).range(1..))]
+    limit: Option<u64>,
+
+    #[arg(long)]
+    count: bool,
+
+    #[arg(short, long, value_enum)]
+    format: Option<OutputFormat>,
+}
+
+#[derive(Serialize)]
+#[serde(transparent)]
+struct LabelNames(Vec<String>);
+
+impl Formatter for LabelNames {
+    fn headers(&self) -> Vec<String> {
+        ["label"].map(String::from).into()
+    }
+
+    fn rows(&self) -> Vec<Vec<String>> {
+        self.0.iter().map(|label| vec![label.clone()]).collect()
+    }
+}
+
+impl LabelsCommand {
+    pub fn apply_default_format(&mut self, format: &OutputFormat) {
+        self.format.get_or_insert_with(|| format.clone());
+    }
+
+    pub async fn execute(&self, client: &VmClient) -> Result<()> {
+        let (labels, truncated) = self.fetch(client).await?;
+
+        if self.count {
+            if output::is_json_mode() {
+                return output::print_data(&serde_json::json!({ "count": labels.0.len() }));
+            }
+            println!("{}", labels.0.len());
+            return Ok(());
+        }
+
+        let format = self.format.clone().unwrap_or(OutputFormat::Table);
+        let table = format == OutputFormat::Table && !output::is_json_mode();
+
+        if table && labels.0.is_empty() {
+            println!("{}", messages::t(Msg::NoData).yellow());
+            return Ok(());
+        }
+
+        print_formatted(&labels, &format)?;
+
+        if table {
+            println!(
+                "\n{} {} {}",
+                messages::t(Msg::Found).blue().bold(),
+                labels.0.len(),
+                messages::t(Msg::Records)
+            );
+        }
+
+        if truncated {
+            eprintln!(
+                "{} показаны первые {} меток, найдено больше; увеличьте --limit или уточните селектор",
+                messages::t(Msg::Warning).yellow(),
+                labels.0.len()
+            );
+        }
+
+        Ok(())
+    }
+
+    /// Имена меток и признак того, что ответ обрезан по --limit.
+    async fn fetch(&self, client: &VmClient) -> Result<(LabelNames, bool)> {
+        let (start, end) = match &self.range {
+            Some(range) => {
+                let (start, end) = parse_time_range(range).map_err(VmCliError::TimeParseError)?;
+                (Some(start), Some(end))
+            }
+            None => (self.start.clone(), self.end.clone()),
+        };
+
+        info!("Получение имен меток: match={:?}, start={:?}, end={:?}", self.match_, start, end);
+
+        let limit = self.limit.map(|limit| limit as usize);
+        let mut response = client
+            .label_names(self.match_.as_deref(), start.as_deref(), end.as_deref(), limit.map(|limit| limit + 1))
+            .await?;
+
+        let truncated = limit.is_some_and(|limit| response.data.len() > limit);
+        if let Some(limit) = limit {
+            response.data.truncate(limit);
+        }
+        Ok((LabelNames(response.data), truncated))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::MockServer;
+    use crate::testing::assert_consistent_formats;
+
+    #[test]
+    fn labels_render_consistently_across_formats() {
+        assert_consistent_formats(&LabelNames(vec!["job".to_string(), "odd,\"name\"".to_string()]));
+    }
+
+    #[tokio::test]
+    async fn limit_is_forwarded_and_truncation_detected() {
+        let server = MockServer::start(vec![(200, r#"{"status":"success","data":["instance","job","zone"]}"#)]);
+        let client = VmClient::new(&server.url, 5, None, None, None).unwrap();
+
+        let (labels, truncated) = LabelsCommand::try_parse_from(["labels", "--limit", "2"]).unwrap().fetch(&client).await.unwrap();
+        assert_eq!(labels.0, ["instance", "job"]);
+        assert!(truncated);
+
+        let (labels, truncated) = LabelsCommand::try_parse_from(["labels", "--limit", "3"]).unwrap().fetch(&client).await.unwrap();
+        assert_eq!(labels.0.len(), 3);
+        assert!(!truncated);
+
+        let (_, truncated) = LabelsCommand::try_parse_from(["labels"]).unwrap().fetch(&client).await.unwrap();
+        assert!(!truncated);
+
+        let limits: Vec<Vec<String>> = server.requests().iter().map(|r| r.param("limit")).collect();
+        assert_eq!(limits, [vec!["3".to_string()], vec!["4".to_string()], vec![]]);
+    }
+}
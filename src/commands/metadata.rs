@@ -0,0 +1,101 @@
+use crate::api::{MetricMetadata, VmClient};
+use crate::config::OutputFormat;
+use crate::error::Result;
+use crate::messages::{self, Msg};
+use crate::output;
+use crate::utils::{print_formatted, Formatter};
+use clap::Parser;
+use colored::*;
+use serde::Serialize;
+use std::collections::HashMap;
+use tracing::info;
+
+#[derive(Parser)]
+pub struct MetadataCommand {
+    #[arg(value_name = "METRIC")]
+    metric: Option<String>,
+
+    #[arg(short, long)]
+    limit: Option<usize>,
+
+    #[arg(short, long, value_enum, default_value = "table")]
+    format: OutputFormat,
+}
+
+#[derive(Serialize)]
+#[serde(transparent)]
+struct MetadataListing<'a>(&'a HashMap<String, Vec<MetricMetadata>>);
+
+impl MetadataListing<'_> {
+    fn entries(&self) -> Vec<(&String, &MetricMetadata)> {
+        let mut entries: Vec<(&String, &MetricMetadata)> = self
+            .0
+            .iter()
+            .flat_map(|(name, items)| items.iter().map(move |item| (name, item)))
+            .collect();
+        entries.sort_by(|a, b| a.0.cmp(b.0));
+        entries
+    }
+}
+
+impl Formatter for MetadataListing<'_> {
+    fn headers(&self) -> Vec<String> {
+        ["metric", "type", "help", "unit"].map(String::from).into()
+    }
+
+    fn rows(&self) -> Vec<Vec<String>> {
+        self.entries()
+            .into_iter()
+            .map(|(name, item)| vec![name.clone(), item.metric_type.clone(), item.help.clone(), item.unit.clone()])
+            .collect()
+    }
+}
+
+impl MetadataCommand {
+    pub async fn execute(&self, client: &VmClient) -> Result<()> {
+        info!("Получение метаданных метрик: {:?}", self.metric);
+
+        let response = client.metadata(self.metric.as_deref(), self.limit).await?;
+        let listing = MetadataListing(&response.data);
+        let count = listing.entries().len();
+
+        if count == 0 && !output::is_json_mode() {
+            match &self.metric {
+                Some(metric) => println!("{} {}", "Метаданные не найдены для метрики:".yellow(), metric),
+                None => println!("{}", "Метаданные не найдены".yellow()),
+            }
+            return Ok(());
+        }
+
+        print_formatted(&listing, &self.format)?;
+
+        if self.format == OutputFormat::Table && !output::is_json_mode() {
+            println!(
+                "\n{} {} {}",
+                messages::t(Msg::Found).blue().bold(),
+                count,
+                messages::t(Msg::Records)
+            );
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn metadata_rows_are_sorted_by_metric() {
+        let data: HashMap<String, Vec<MetricMetadata>> = serde_json::from_str(
+            r#"{"up":[{"type":"gauge","help":"Target is up"}],"go_gc_duration_seconds":[{"type":"summary","help":"GC pauses","unit":"seconds"}]}"#,
+        )
+        .unwrap();
+
+        assert_eq!(
+            MetadataListing(&data).rows(),
+            [["go_gc_duration_seconds", "summary", "GC pauses", "seconds"], ["up", "gauge", "Target is up", ""]]
+        );
+    }
+}
@@ -1,8 +1,14 @@
 pub mod admin;
+pub mod config;
 pub mod debug;
+pub mod doctor;
 pub mod export;
 pub mod health;
 pub mod import;
+pub mod label_values;
+pub mod labels;
+pub mod metadata;
 pub mod query;
+pub mod series;
 
  
\ No newline at end of file
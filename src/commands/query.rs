@@ -1,61 +1,249 @@
-use crate::api::VmClient;
+use crate::api::{MetricMetadata, QueryResponse, QueryResult, VmClient};
 use crate::config::OutputFormat;
 use crate::error::Result;
-use crate::utils::{format_output, parse_time_range, validate_promql_query};
+use crate::messages::{self, Msg};
+use crate::output;
+use crate::utils::{
+    auto_step, cap_step, derive_values, extract_selectors, format_float, format_output, format_sample_value, format_series, format_table_annotated, format_table_with_thresholds, parse_label,
+    parse_time_range, range_points, round_sample_value, substitute_vars, suggest_names, suggests_range_query, validate_promql_query, DeriveMode, LabelColumns,
+};
 use clap::Parser;
 use colored::*;
-use tracing::info;
+use serde::Serialize;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::fs::File;
+use std::io::{BufWriter, Read, Write};
+use std::path::Path;
+use tracing::{info, warn};
 
 #[derive(Parser)]
 pub struct QueryCommand {
 
     #[arg(value_name = "QUERY")]
-    query: String,
+    query: Option<String>,
+
+    #[arg(long)]
+    query_file: Option<String>,
+
+    #[arg(long = "var", value_name = "NAME=VALUE", value_parser = parse_label)]
+    vars: Vec<(String, String)>,
+
+    #[arg(long)]
+    allow_undefined: bool,
 
     #[arg(short, long)]
     time: Option<String>,
 
+    #[arg(long = "at", value_name = "TIME", conflicts_with_all = ["time", "range", "server_count"])]
+    at: Vec<String>,
+
     #[arg(short, long)]
     range: Option<String>,
 
     #[arg(short, long, default_value = "1m")]
     step: String,
 
+    #[arg(long, value_name = "N", value_parser = clap::value_parser!(u64).range(2..), requires = "range")]
+    max_points: Option<u64>,
+
     #[arg(short, long, value_enum, default_value = "table")]
     format: OutputFormat,
 
     #[arg(long)]
     count: bool,
 
+    #[arg(long, conflicts_with_all = ["count", "derive"])]
+    server_count: bool,
+
     #[arg(long)]
     metrics_only: bool,
+
+    #[arg(long, value_enum)]
+    derive: Option<DeriveMode>,
+
+    #[arg(long)]
+    warn: Option<f64>,
+
+    #[arg(long)]
+    crit: Option<f64>,
+
+    #[arg(long)]
+    compact: bool,
+
+    #[arg(long, value_name = "BOOL", default_value_t = true, action = clap::ArgAction::Set)]
+    show_name: bool,
+
+    #[arg(long)]
+    hide_labels: bool,
+
+    #[arg(long)]
+    fail_on_empty: bool,
+
+    #[arg(long)]
+    explain_schema: bool,
+
+    #[arg(long)]
+    suggest: bool,
+
+    #[arg(long, value_name = "TIME", conflicts_with_all = ["range", "at", "count", "server_count", "derive"])]
+    compare: Option<String>,
+
+    #[arg(long, value_name = "LABEL", conflicts_with_all = ["range", "at", "server_count", "compare", "metrics_only"])]
+    group_by: Option<String>,
+
+    #[arg(long, value_enum, default_value = "sum", requires = "group_by")]
+    agg: GroupAgg,
+
+    #[arg(long, value_name = "N")]
+    round: Option<u32>,
+
+    #[arg(long, conflicts_with_all = ["count", "server_count", "metrics_only", "compare", "group_by"])]
+    annotate: bool,
+
+    #[arg(
+        long,
+        value_name = "FIELD,...",
+        value_delimiter = ',',
+        conflicts_with_all = ["count", "server_count", "metrics_only", "compare", "annotate"]
+    )]
+    fields: Vec<String>,
+
+    #[arg(long, conflicts_with_all = ["at", "count", "server_count", "metrics_only", "derive", "compare", "group_by", "suggest", "annotate"])]
+    stream: bool,
+
+    #[arg(long, value_name = "PATH", requires = "stream")]
+    output_file: Option<String>,
+}
+
+#[derive(clap::ValueEnum, Clone, Copy)]
+enum GroupAgg {
+    Sum,
+    Avg,
+    Max,
+    Min,
+}
+
+#[derive(Serialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+enum DiffKind {
+    Unchanged,
+    Increased,
+    Decreased,
+    Changed,
+    Added,
+    Removed,
+}
+
+#[derive(Serialize)]
+struct SeriesDiff {
+    series: String,
+    before: Option<String>,
+    after: Option<String>,
+    delta: Option<f64>,
+    percent: Option<f64>,
+    change: DiffKind,
 }
 
 impl QueryCommand {
     pub async fn execute(&self, client: &VmClient) -> Result<()> {
-        info!("Выполнение запроса: {}", self.query);
+        if self.compact && self.format == OutputFormat::Yaml {
+            return Err(crate::error::VmCliError::InvalidQuery(
+                "--compact поддерживается только для JSON: YAML всегда выводится в блочном стиле".to_string(),
+            ));
+        }
 
-        validate_promql_query(&self.query)
-            .map_err(|e| crate::error::VmCliError::InvalidQuery(e))?;
+        let query = self.resolve_query()?;
+        let client = &client.clone().with_round_digits(self.round);
 
-        let response = if let Some(range) = &self.range {
+        info!("Выполнение запроса: {}", query);
+
+        validate_promql_query(&query)
+            .map_err(crate::error::VmCliError::InvalidQuery)?;
+
+        if self.range.is_none() && suggests_range_query(&query) {
+            eprintln!(
+                "{} запрос содержит range-вектор без агрегации; для графика по времени используйте --range (например, --range 1h)",
+                "ПОДСКАЗКА:".yellow().bold()
+            );
+        }
+
+        if self.explain_schema {
+            self.explain_schema(client, &query).await?;
+        }
+
+        if self.server_count {
+            return self.execute_server_count(client, &query).await;
+        }
+
+        if let Some(baseline) = &self.compare {
+            return self.execute_compare(client, &query, baseline).await;
+        }
+
+        if self.stream {
+            return self.execute_stream(client, &query).await;
+        }
+
+        if self.derive.is_some() && self.range.is_none() {
+            return Err(crate::error::VmCliError::InvalidQuery(
+                "--derive работает только с range-запросами, укажите --range".to_string(),
+            ));
+        }
+
+        let mut response = if let Some(range) = &self.range {
             let (start, end) = parse_time_range(range)
-                .map_err(|e| crate::error::VmCliError::TimeParseError(e))?;
+                .map_err(crate::error::VmCliError::TimeParseError)?;
             
-            info!("Range запрос: {} - {}", start, end);
-            client.query_range(&self.query, &start, &end, &self.step).await?
+            let step = self.resolve_step(&start, &end);
+
+            info!("Range запрос: {} - {}, шаг {}", start, end, step);
+            client.query_range(&query, &start, &end, &step).await?
+        } else if !self.at.is_empty() {
+            self.query_at(client, &query).await?
         } else {
-            client.query(&self.query, self.time.as_deref()).await?
+            client.query(&query, self.time.as_deref()).await?
         };
 
+        if let Some(mode) = self.derive {
+            for result in &mut response.data.result {
+                if let Some(values) = &result.values {
+                    result.values = Some(derive_values(values, mode));
+                }
+            }
+        }
+
+        if self.suggest && response.data.result.is_empty() {
+            self.suggest_metrics(client, &query).await;
+        }
+
+        if self.fail_on_empty && response.data.result.is_empty() {
+            return Err(crate::error::VmCliError::EmptyResult);
+        }
+
+        if let Some(label) = &self.group_by {
+            response.data.result = group_results(&response.data.result, label, self.agg);
+        }
+
+        if let Some(digits) = self.round {
+            round_results(&mut response, digits);
+        }
+
         if self.count {
+            if output::is_json_mode() {
+                return output::print_data(&serde_json::json!({ "count": response.data.result.len() }));
+            }
             println!("{}", response.data.result.len());
             return Ok(());
         }
 
         if self.metrics_only {
+            if output::is_json_mode() {
+                let label_sets: Vec<BTreeMap<&String, &String>> =
+                    response.data.result.iter().map(|r| r.metric.iter().collect()).collect();
+                return output::print_data(&label_sets);
+            }
             for result in &response.data.result {
-                for (key, value) in &result.metric {
+                let sorted: BTreeMap<&String, &String> = result.metric.iter().collect();
+                for (key, value) in sorted {
                     if key != "__name__" {
                         println!("{}={}", key, value);
                     }
@@ -64,17 +252,970 @@ impl QueryCommand {
             return Ok(());
         }
 
-        let output = format_output(&response, &self.format);
-        println!("{}", output);
+        if !self.fields.is_empty() {
+            let projected = project_fields(&response.data.result, &self.fields);
+            if output::is_json_mode() {
+                return output::print_data(&projected);
+            }
+            if self.format == OutputFormat::Json {
+                let text = if !self.compact && output::is_pretty() {
+                    serde_json::to_string_pretty(&projected)?
+                } else {
+                    serde_json::to_string(&projected)?
+                };
+                output::print_text(&text);
+                return Ok(());
+            }
+            warn!("--fields применяется только к JSON-выводу (--format json или --json)");
+        }
+
+        let metadata = if self.annotate {
+            Some(fetch_metadata(client, &response).await)
+        } else {
+            None
+        };
+
+        if output::is_json_mode() {
+            return match &metadata {
+                Some(metadata) => output::print_data(&annotate_json(&response, metadata)?),
+                None => output::print_data(&response),
+            };
+        }
+
+        let columns = LabelColumns {
+            show_name: self.show_name,
+            hide_labels: self.hide_labels,
+        };
+        let formatted = if let Some(metadata) = &metadata {
+            self.format_annotated(&response, metadata, columns)?
+        } else if self.format == OutputFormat::Table && (self.warn.is_some() || self.crit.is_some()) {
+            format_table_with_thresholds(&response, self.warn, self.crit, columns)
+        } else {
+            format_output(&response, &self.format, !self.compact && output::is_pretty(), columns)
+        };
+        output::print_text(&formatted);
 
-        if self.format == OutputFormat::Table {
+        if self.format == OutputFormat::Table && !response.data.result.is_empty() {
             println!(
-                "\n{} {} результатов",
-                "Найдено:".blue().bold(),
-                response.data.result.len()
+                "\n{} {} {}",
+                messages::t(Msg::Found).blue().bold(),
+                response.data.result.len(),
+                messages::t(Msg::Results)
             );
         }
 
         Ok(())
     }
+
+    fn format_annotated(
+        &self,
+        response: &QueryResponse,
+        metadata: &HashMap<String, MetricMetadata>,
+        columns: LabelColumns,
+    ) -> Result<String> {
+        let pretty = !self.compact && output::is_pretty();
+        let formatted = match self.format {
+            OutputFormat::Table => format_table_annotated(response, metadata, self.warn, self.crit, columns),
+            OutputFormat::Json if pretty => serde_json::to_string_pretty(&annotate_json(response, metadata)?)?,
+            OutputFormat::Json => serde_json::to_string(&annotate_json(response, metadata)?)?,
+            OutputFormat::Yaml => serde_yaml::to_string(&annotate_json(response, metadata)?)
+                .map_err(|e| crate::error::VmCliError::Unknown(e.to_string()))?,
+            _ => {
+                warn!("--annotate поддерживается только для форматов table, json и yaml");
+                format_output(response, &self.format, pretty, columns)
+            }
+        };
+        Ok(formatted)
+    }
+
+    async fn suggest_metrics(&self, client: &VmClient, query: &str) {
+        let names: Vec<String> = extract_selectors(query)
+            .into_iter()
+            .filter_map(|selector| {
+                let name = selector.split('{').next().unwrap_or("").trim().to_string();
+                (!name.is_empty()).then_some(name)
+            })
+            .collect();
+        if names.is_empty() {
+            return;
+        }
+
+        let metrics = match client.metrics().await {
+            Ok(metrics) => metrics.data,
+            Err(e) => {
+                warn!("Не удалось получить список метрик для подсказок: {}", e);
+                return;
+            }
+        };
+
+        for name in names.iter().filter(|name| !metrics.contains(name)) {
+            let suggestions = suggest_names(name, &metrics, 3);
+            if suggestions.is_empty() {
+                continue;
+            }
+            eprintln!(
+                "{} метрика '{}' не найдена, возможно, вы имели в виду: {}?",
+                "ПОДСКАЗКА:".yellow().bold(),
+                name,
+                suggestions.join(", ")
+            );
+        }
+    }
+
+    async fn explain_schema(&self, client: &VmClient, query: &str) -> Result<()> {
+        let (start, end) = match &self.range {
+            Some(range) => {
+                let (start, end) = parse_time_range(range)
+                    .map_err(crate::error::VmCliError::TimeParseError)?;
+                (Some(start), Some(end))
+            }
+            None => (None, None),
+        };
+
+        let selectors = extract_selectors(query);
+        let mut counts = Vec::with_capacity(selectors.len());
+        for selector in &selectors {
+            let series = client.series(&[selector.as_str()], start.as_deref(), end.as_deref(), None).await?;
+            counts.push((selector.as_str(), series.data.len()));
+        }
+
+        let json = output::is_json_mode();
+        if !json {
+            println!("{}", "Селекторы запроса:".bold());
+        }
+        for (selector, count) in &counts {
+            if *count == 0 {
+                eprintln!(
+                    "{} селектор '{}' не находит ни одной серии (возможна опечатка в имени метрики или метке)",
+                    messages::t(Msg::Warning).yellow(),
+                    selector
+                );
+            } else if !json {
+                println!("  {} — {} серий", selector, count);
+            }
+        }
+        if !json {
+            println!();
+        }
+
+        Ok(())
+    }
+
+    async fn execute_server_count(&self, client: &VmClient, query: &str) -> Result<()> {
+        let count = self.fetch_server_count(client, query).await?;
+
+        if output::is_json_mode() {
+            return output::print_data(&serde_json::json!({ "count": count }));
+        }
+
+        println!("{}", count);
+        Ok(())
+    }
+
+    /// count(<query>) через тот же endpoint, что и основной запрос: с --range это
+    /// query_range, и берется число серий в последней точке диапазона.
+    async fn fetch_server_count(&self, client: &VmClient, query: &str) -> Result<String> {
+        let count_query = format!("count({})", query);
+        info!("Подсчет на сервере: {}", count_query);
+
+        let response = if let Some(range) = &self.range {
+            let (start, end) = parse_time_range(range)
+                .map_err(crate::error::VmCliError::TimeParseError)?;
+            let step = self.resolve_step(&start, &end);
+            client.query_range(&count_query, &start, &end, &step).await?
+        } else {
+            client.query(&count_query, self.time.as_deref()).await?
+        };
+
+        Ok(response
+            .data
+            .result
+            .first()
+            .and_then(|r| r.value.as_ref().or_else(|| r.values.as_ref()?.last()))
+            .map(|(_, v)| v.clone())
+            .unwrap_or_else(|| "0".to_string()))
+    }
+
+    async fn execute_stream(&self, client: &VmClient, query: &str) -> Result<()> {
+        let out: Box<dyn Write + Send> = match &self.output_file {
+            Some(path) => {
+                output::track_partial_file(Path::new(path));
+                Box::new(BufWriter::new(File::create(path)?))
+            }
+            None => Box::new(BufWriter::new(std::io::stdout())),
+        };
+
+        let written = if let Some(range) = &self.range {
+            let (start, end) = parse_time_range(range)
+                .map_err(crate::error::VmCliError::TimeParseError)?;
+            let step = self.resolve_step(&start, &end);
+
+            info!("Потоковый range запрос: {} - {}, шаг {}", start, end, step);
+            client.stream_query_range(query, &start, &end, &step, out).await?
+        } else {
+            client.stream_query(query, self.time.as_deref(), out).await?
+        };
+
+        if let Some(path) = &self.output_file {
+            output::complete_partial_file(Path::new(path));
+            if output::is_json_mode() {
+                output::print_data(&serde_json::json!({ "series": written, "output": path }))?;
+            } else {
+                println!("Записано {} серий в {}", written, path);
+            }
+        }
+
+        if self.fail_on_empty && written == 0 {
+            return Err(crate::error::VmCliError::EmptyResult);
+        }
+        Ok(())
+    }
+
+    async fn execute_compare(&self, client: &VmClient, query: &str, baseline: &str) -> Result<()> {
+        info!("Сравнение результатов: {} и {}", baseline, self.time.as_deref().unwrap_or("сейчас"));
+
+        let (mut before, mut after) = futures::try_join!(
+            client.query(query, Some(baseline)),
+            client.query(query, self.time.as_deref())
+        )?;
+        if let Some(digits) = self.round {
+            round_results(&mut before, digits);
+            round_results(&mut after, digits);
+        }
+        let diffs = diff_results(&before, &after);
+
+        if output::is_json_mode() {
+            return output::print_data(&diffs);
+        }
+        if self.format == OutputFormat::Json {
+            output::print_text(&serde_json::to_string_pretty(&diffs)?);
+            return Ok(());
+        }
+
+        let mut text = String::new();
+        for diff in &diffs {
+            text.push_str(&format_diff_line(diff));
+            text.push('\n');
+        }
+        output::print_text(text.trim_end());
+
+        let count = |kind: DiffKind| diffs.iter().filter(|d| d.change == kind).count();
+        println!(
+            "\nИзменилось: {}, без изменений: {}, добавлено: {}, удалено: {}",
+            count(DiffKind::Increased) + count(DiffKind::Decreased) + count(DiffKind::Changed),
+            count(DiffKind::Unchanged),
+            count(DiffKind::Added),
+            count(DiffKind::Removed)
+        );
+        Ok(())
+    }
+
+    fn resolve_step(&self, start: &str, end: &str) -> String {
+        let step = if self.step != "auto" {
+            self.step.clone()
+        } else {
+            let range_seconds = match (start.parse::<i64>(), end.parse::<i64>()) {
+                (Ok(s), Ok(e)) if e > s => (e - s) as u64,
+                _ => 0,
+            };
+            auto_step(range_seconds)
+        };
+
+        let Some(max_points) = self.max_points else {
+            return step;
+        };
+        match cap_step(start, end, &step, max_points) {
+            Some(capped) => {
+                eprintln!(
+                    "{} шаг {} дает {} точек на серию при лимите --max-points {}, шаг увеличен до {}",
+                    messages::t(Msg::Warning).yellow(),
+                    step,
+                    range_points(start, end, &step).unwrap_or_default(),
+                    max_points,
+                    capped
+                );
+                capped
+            }
+            None => step,
+        }
+    }
+
+    async fn query_at(&self, client: &VmClient, query: &str) -> Result<QueryResponse> {
+        info!("Запрос в {} моментов времени", self.at.len());
+
+        let responses =
+            futures::future::try_join_all(self.at.iter().map(|time| client.query(query, Some(time)))).await?;
+
+        let mut responses = responses.into_iter();
+        let Some(mut merged) = responses.next() else {
+            return Err(crate::error::VmCliError::InvalidQuery("Не указано ни одного --at".to_string()));
+        };
+        for response in responses {
+            merged.data.result.extend(response.data.result);
+        }
+
+        Ok(merged)
+    }
+
+    fn resolve_query(&self) -> Result<String> {
+        self.resolve_query_from(std::io::stdin())
+    }
+
+    fn resolve_query_from(&self, mut stdin: impl Read) -> Result<String> {
+        let raw = match (&self.query, &self.query_file) {
+            (Some(_), Some(_)) => {
+                return Err(crate::error::VmCliError::InvalidQuery(
+                    "Укажите запрос либо аргументом, либо через --query-file, но не одновременно".to_string(),
+                ));
+            }
+            (None, None) => {
+                return Err(crate::error::VmCliError::InvalidQuery(
+                    "Не указан запрос: передайте QUERY, '-' для чтения из stdin или --query-file".to_string(),
+                ));
+            }
+            (Some(query), None) if query == "-" => {
+                let mut buffer = String::new();
+                stdin.read_to_string(&mut buffer)?;
+                buffer
+            }
+            (Some(query), None) => query.clone(),
+            (None, Some(path)) => {
+                if !std::path::Path::new(path).exists() {
+                    return Err(crate::error::VmCliError::FileNotFound(path.clone()));
+                }
+                std::fs::read_to_string(path)?
+            }
+        };
+
+        substitute_vars(raw.trim_end_matches(['\n', '\r']), &self.vars, self.allow_undefined)
+            .map_err(crate::error::VmCliError::InvalidQuery)
+    }
+}
+
+async fn fetch_metadata(client: &VmClient, response: &QueryResponse) -> HashMap<String, MetricMetadata> {
+    let names: BTreeSet<&str> = response
+        .data
+        .result
+        .iter()
+        .filter_map(|r| r.metric.get("__name__").map(String::as_str))
+        .collect();
+
+    let fetched = futures::future::join_all(names.into_iter().map(|name| async move {
+        match client.metadata(Some(name), Some(1)).await {
+            Ok(metadata) => metadata
+                .data
+                .into_values()
+                .flatten()
+                .next()
+                .map(|meta| (name.to_string(), meta)),
+            Err(e) => {
+                warn!("Не удалось получить метаданные метрики {}: {}", name, e);
+                None
+            }
+        }
+    }))
+    .await;
+
+    fetched.into_iter().flatten().collect()
+}
+
+fn project_fields(results: &[QueryResult], fields: &[String]) -> Vec<serde_json::Map<String, serde_json::Value>> {
+    results
+        .iter()
+        .map(|result| {
+            let mut projected = serde_json::Map::new();
+            for field in fields {
+                let value = match field.as_str() {
+                    "value" => result.value.as_ref().map(|(_, value)| value.clone().into()),
+                    "timestamp" => result.value.as_ref().map(|(timestamp, _)| (*timestamp).into()),
+                    "values" => result.values.as_ref().and_then(|values| serde_json::to_value(values).ok()),
+                    label => result.metric.get(label).map(|value| value.clone().into()),
+                };
+                if let Some(value) = value {
+                    projected.insert(field.clone(), value);
+                }
+            }
+            projected
+        })
+        .collect()
+}
+
+fn annotate_json(response: &QueryResponse, metadata: &HashMap<String, MetricMetadata>) -> Result<serde_json::Value> {
+    let mut value = serde_json::to_value(response)?;
+    if let Some(results) = value["data"]["result"].as_array_mut() {
+        for result in results {
+            let meta = result["metric"]["__name__"].as_str().and_then(|name| metadata.get(name));
+            if let (Some(meta), Some(fields)) = (meta, result.as_object_mut()) {
+                fields.insert("type".to_string(), meta.metric_type.clone().into());
+                fields.insert("help".to_string(), meta.help.clone().into());
+            }
+        }
+    }
+    Ok(value)
+}
+
+fn round_results(response: &mut QueryResponse, digits: u32) {
+    for result in &mut response.data.result {
+        if let Some((_, value)) = &mut result.value {
+            *value = round_sample_value(value, digits);
+        }
+        for (_, value) in result.values.iter_mut().flatten() {
+            *value = round_sample_value(value, digits);
+        }
+    }
+}
+
+/// Сворачивает instant-результаты по значению метки. NaN и нечисловые значения
+/// не участвуют ни в одной агрегации (как в VictoriaMetrics); группа, где
+/// других значений нет, получает NaN.
+fn group_results(results: &[QueryResult], label: &str, agg: GroupAgg) -> Vec<QueryResult> {
+    let mut groups: BTreeMap<String, (f64, Vec<f64>)> = BTreeMap::new();
+
+    for result in results {
+        let Some((timestamp, value)) = &result.value else {
+            continue;
+        };
+        let key = result.metric.get(label).cloned().unwrap_or_default();
+        let (latest, values) = groups.entry(key).or_insert((*timestamp, Vec::new()));
+        *latest = latest.max(*timestamp);
+        if let Some(number) = value.parse::<f64>().ok().filter(|number| !number.is_nan()) {
+            values.push(number);
+        }
+    }
+
+    groups
+        .into_iter()
+        .map(|(key, (timestamp, values))| {
+            let aggregated = match agg {
+                _ if values.is_empty() => f64::NAN,
+                GroupAgg::Sum => values.iter().sum(),
+                GroupAgg::Avg => values.iter().sum::<f64>() / values.len() as f64,
+                GroupAgg::Max => values.iter().copied().fold(f64::NAN, f64::max),
+                GroupAgg::Min => values.iter().copied().fold(f64::NAN, f64::min),
+            };
+            QueryResult {
+                metric: HashMap::from([(label.to_string(), key)]),
+                value: Some((timestamp, format_float(aggregated))),
+                values: None,
+            }
+        })
+        .collect()
+}
+
+fn diff_results(before: &QueryResponse, after: &QueryResponse) -> Vec<SeriesDiff> {
+    let values = |response: &QueryResponse| -> BTreeMap<String, String> {
+        response
+            .data
+            .result
+            .iter()
+            .filter_map(|r| r.value.as_ref().map(|(_, value)| (format_series(&r.metric), value.clone())))
+            .collect()
+    };
+    let before = values(before);
+    let mut after = values(after);
+
+    let mut diffs = Vec::new();
+    for (series, old) in before {
+        let new = after.remove(&series);
+        diffs.push(classify_diff(series, Some(old), new));
+    }
+    for (series, new) in after {
+        diffs.push(classify_diff(series, None, Some(new)));
+    }
+    diffs.sort_by(|a, b| a.series.cmp(&b.series));
+    diffs
+}
+
+fn classify_diff(series: String, before: Option<String>, after: Option<String>) -> SeriesDiff {
+    let number = |value: &Option<String>| {
+        value
+            .as_deref()
+            .and_then(|v| v.trim().parse::<f64>().ok())
+            .filter(|n| n.is_finite())
+    };
+
+    let (change, delta, percent) = match (&before, &after) {
+        (None, _) => (DiffKind::Added, None, None),
+        (_, None) => (DiffKind::Removed, None, None),
+        (Some(old), Some(new)) => match (number(&before), number(&after)) {
+            (Some(old), Some(new)) => {
+                let delta = new - old;
+                let percent = (old != 0.0).then(|| delta / old.abs() * 100.0);
+                let change = if delta > 0.0 {
+                    DiffKind::Increased
+                } else if delta < 0.0 {
+                    DiffKind::Decreased
+                } else {
+                    DiffKind::Unchanged
+                };
+                (change, Some(delta), percent)
+            }
+            _ if old == new => (DiffKind::Unchanged, None, None),
+            _ => (DiffKind::Changed, None, None),
+        },
+    };
+
+    SeriesDiff {
+        series,
+        before: before.map(|v| format_sample_value(&v)),
+        after: after.map(|v| format_sample_value(&v)),
+        delta,
+        percent,
+        change,
+    }
+}
+
+fn format_diff_line(diff: &SeriesDiff) -> String {
+    let before = diff.before.as_deref().unwrap_or("");
+    let after = diff.after.as_deref().unwrap_or("");
+    let delta = match (diff.delta, diff.percent) {
+        (Some(delta), Some(percent)) => format!("{:+} ({:+.2}%)", delta, percent),
+        (Some(delta), None) => format!("{:+}", delta),
+        _ => String::new(),
+    };
+
+    match diff.change {
+        DiffKind::Added => format!("+ {} {}", diff.series, after).green().bold().to_string(),
+        DiffKind::Removed => format!("- {} {}", diff.series, before).red().bold().to_string(),
+        DiffKind::Unchanged => format!("  {} {}", diff.series, after).dimmed().to_string(),
+        DiffKind::Increased => format!("  {} {} -> {} {}", diff.series, before, after, delta).green().to_string(),
+        DiffKind::Decreased => format!("  {} {} -> {} {}", diff.series, before, after, delta).red().to_string(),
+        DiffKind::Changed => format!("  {} {} -> {}", diff.series, before, after).yellow().to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::MockServer;
+
+    fn query_command(args: &[&str]) -> QueryCommand {
+        QueryCommand::try_parse_from(std::iter::once("query").chain(args.iter().copied())).unwrap()
+    }
+
+    fn sample_response() -> QueryResponse {
+        serde_json::from_str(
+            r#"{"status":"success","data":{"resultType":"vector","result":[{"metric":{"__name__":"up","job":"node"},"value":[1700000000,"1"]}]}}"#,
+        )
+        .unwrap()
+    }
+
+    const COLUMNS: LabelColumns = LabelColumns { show_name: true, hide_labels: false };
+
+    #[test]
+    fn compact_json_is_single_line_and_default_is_indented() {
+        let compact = format_output(&sample_response(), &OutputFormat::Json, false, COLUMNS);
+        let pretty = format_output(&sample_response(), &OutputFormat::Json, true, COLUMNS);
+
+        assert_eq!(compact.trim_end().lines().count(), 1);
+        assert!(pretty.lines().count() > 1);
+        assert!(pretty.contains("\n  \"status\""));
+        assert_eq!(
+            serde_json::from_str::<serde_json::Value>(&compact).unwrap(),
+            serde_json::from_str::<serde_json::Value>(&pretty).unwrap()
+        );
+    }
+
+    #[test]
+    fn yaml_stays_block_style_without_pretty() {
+        let yaml = format_output(&sample_response(), &OutputFormat::Yaml, false, COLUMNS);
+
+        assert!(yaml.starts_with("status: success\n"), "{}", yaml);
+        assert_eq!(yaml, format_output(&sample_response(), &OutputFormat::Yaml, true, COLUMNS));
+    }
+
+    #[tokio::test]
+    async fn compact_yaml_is_rejected() {
+        let client = VmClient::new("http://127.0.0.1:1", 5, None, None, None).unwrap();
+        let result = query_command(&["up", "--format", "yaml", "--compact"]).execute(&client).await;
+
+        assert!(matches!(result, Err(crate::error::VmCliError::InvalidQuery(_))));
+    }
+
+    #[test]
+    fn query_is_read_from_stdin_file_or_argument() {
+        let stdin = std::io::Cursor::new("sum(rate(x[5m]))\n");
+        assert_eq!(query_command(&["-"]).resolve_query_from(stdin).unwrap(), "sum(rate(x[5m]))");
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("query.promql");
+        std::fs::write(&path, "up{job=\"$job\"}\r\n").unwrap();
+        let command = query_command(&["--query-file", path.to_str().unwrap(), "--var", "job=node"]);
+        assert_eq!(command.resolve_query_from(std::io::empty()).unwrap(), "up{job=\"node\"}");
+
+        assert_eq!(query_command(&["up"]).resolve_query_from(std::io::empty()).unwrap(), "up");
+    }
+
+    #[test]
+    fn query_argument_and_file_conflict() {
+        let command = query_command(&["up", "--query-file", "query.promql"]);
+        assert!(matches!(
+            command.resolve_query_from(std::io::empty()),
+            Err(crate::error::VmCliError::InvalidQuery(_))
+        ));
+        assert!(matches!(
+            query_command(&[]).resolve_query_from(std::io::empty()),
+            Err(crate::error::VmCliError::InvalidQuery(_))
+        ));
+        assert!(matches!(
+            query_command(&["--query-file", "/nonexistent/query.promql"]).resolve_query_from(std::io::empty()),
+            Err(crate::error::VmCliError::FileNotFound(_))
+        ));
+    }
+
+    #[test]
+    fn auto_step_is_resolved_from_range_and_explicit_step_is_kept() {
+        assert_eq!(query_command(&["up", "--step", "auto"]).resolve_step("1700000000", "1702592000"), "2592s");
+        assert_eq!(query_command(&["up", "--step", "auto"]).resolve_step("1700000000", "1700003600"), "15s");
+        assert_eq!(query_command(&["up", "--step", "1m"]).resolve_step("1700000000", "1702592000"), "1m");
+    }
+
+    #[tokio::test]
+    async fn server_count_wraps_query_in_count_and_returns_scalar() {
+        let server = MockServer::start(vec![(
+            200,
+            r#"{"status":"success","data":{"resultType":"vector","result":[{"metric":{},"value":[1700000000,"42"]}]}}"#,
+        )]);
+        let client = VmClient::new(&server.url, 5, None, None, None).unwrap();
+        let command = query_command(&["up{job=\"node\"}", "--server-count"]);
+
+        assert_eq!(command.fetch_server_count(&client, "up{job=\"node\"}").await.unwrap(), "42");
+        assert_eq!(server.requests()[0].path(), "/api/v1/query");
+        assert_eq!(server.requests()[0].param("query"), ["count(up{job=\"node\"})"]);
+    }
+
+    #[tokio::test]
+    async fn server_count_with_range_uses_query_range_and_latest_point() {
+        let server = MockServer::start(vec![(
+            200,
+            r#"{"status":"success","data":{"resultType":"matrix","result":[{"metric":{},"values":[[1700000000,"40"],[1700000060,"42"]]}]}}"#,
+        )]);
+        let client = VmClient::new(&server.url, 5, None, None, None).unwrap();
+        let command = query_command(&["rate(http_requests_total[5m])", "--server-count", "--range", "1h", "--step", "1m"]);
+
+        assert_eq!(command.fetch_server_count(&client, "rate(http_requests_total[5m])").await.unwrap(), "42");
+        let request = &server.requests()[0];
+        assert_eq!(request.path(), "/api/v1/query_range");
+        assert_eq!(request.param("query"), ["count(rate(http_requests_total[5m]))"]);
+        assert_eq!(request.param("step"), ["1m"]);
+    }
+
+    #[tokio::test]
+    async fn each_at_time_sends_own_query_and_results_are_merged() {
+        let server = MockServer::start(vec![(
+            200,
+            r#"{"status":"success","data":{"resultType":"vector","result":[{"metric":{"__name__":"up"},"value":[1700000000,"1"]}]}}"#,
+        )]);
+        let client = VmClient::new(&server.url, 5, None, None, None).unwrap();
+        let command = query_command(&["up", "--at", "1700000000", "--at", "1700000600", "--at", "1700001200"]);
+
+        let response = command.query_at(&client, "up").await.unwrap();
+
+        assert_eq!(response.data.result.len(), 3);
+        let mut times: Vec<String> = server.requests().iter().flat_map(|r| r.param("time")).collect();
+        times.sort();
+        assert_eq!(times, ["1700000000", "1700000600", "1700001200"]);
+        assert!(server.requests().iter().all(|r| r.param("query") == ["up"]));
+    }
+
+    #[tokio::test]
+    async fn empty_result_succeeds_unless_fail_on_empty() {
+        let server = MockServer::start(vec![(200, r#"{"status":"success","data":{"resultType":"vector","result":[]}}"#)]);
+        let client = VmClient::new(&server.url, 5, None, None, None).unwrap();
+
+        query_command(&["up", "--format", "csv"]).execute(&client).await.unwrap();
+        let result = query_command(&["up", "--format", "csv", "--fail-on-empty"]).execute(&client).await;
+
+        assert!(matches!(result, Err(crate::error::VmCliError::EmptyResult)));
+        assert_ne!(crate::error::VmCliError::EmptyResult.exit_code(), 0);
+    }
+
+    #[tokio::test]
+    async fn explain_schema_looks_up_series_for_each_selector() {
+        let server = MockServer::start(vec![(200, r#"{"status":"success","data":[]}"#)]);
+        let client = VmClient::new(&server.url, 5, None, None, None).unwrap();
+        let query = "sum(rate(http_requests_total{job=\"api\"}[5m])) / sum(up)";
+
+        query_command(&[query, "--explain-schema"]).explain_schema(&client, query).await.unwrap();
+
+        let selectors: Vec<Vec<String>> = server.requests().iter().map(|r| r.param("match[]")).collect();
+        assert_eq!(selectors, [vec!["http_requests_total{job=\"api\"}".to_string()], vec!["up".to_string()]]);
+        assert!(server.requests().iter().all(|r| r.path() == "/api/v1/series"));
+    }
+
+    #[test]
+    fn label_column_flags_are_parsed() {
+        let command = query_command(&["up"]);
+        assert!(command.show_name && !command.hide_labels);
+
+        let command = query_command(&["up", "--show-name", "false", "--hide-labels"]);
+        assert!(!command.show_name && command.hide_labels);
+    }
+
+    fn diff(before: Option<&str>, after: Option<&str>) -> SeriesDiff {
+        classify_diff("up{job=\"node\"}".to_string(), before.map(String::from), after.map(String::from))
+    }
+
+    #[test]
+    fn classify_diff_computes_delta_and_percent() {
+        let increased = diff(Some("4"), Some("5"));
+        assert!(increased.change == DiffKind::Increased);
+        assert_eq!((increased.delta, increased.percent), (Some(1.0), Some(25.0)));
+
+        let decreased = diff(Some("-4"), Some("-5"));
+        assert!(decreased.change == DiffKind::Decreased);
+        assert_eq!((decreased.delta, decreased.percent), (Some(-1.0), Some(-25.0)));
+
+        let from_zero = diff(Some("0"), Some("3"));
+        assert!(from_zero.change == DiffKind::Increased);
+        assert_eq!((from_zero.delta, from_zero.percent), (Some(3.0), None));
+
+        assert!(diff(Some("2"), Some("2.0")).change == DiffKind::Unchanged);
+    }
+
+    #[test]
+    fn classify_diff_marks_one_sided_and_non_numeric_series() {
+        assert!(diff(None, Some("1")).change == DiffKind::Added);
+        assert!(diff(Some("1"), None).change == DiffKind::Removed);
+        assert!(diff(Some("NaN"), Some("NaN")).change == DiffKind::Unchanged);
+        let changed = diff(Some("1"), Some("NaN"));
+        assert!(changed.change == DiffKind::Changed);
+        assert_eq!(changed.delta, None);
+    }
+
+    #[test]
+    fn format_diff_line_marks_and_colors_changes() {
+        assert_eq!(
+            format_diff_line(&diff(Some("4"), Some("5"))),
+            "  up{job=\"node\"} 4 -> 5 +1 (+25.00%)".green().to_string()
+        );
+        assert_eq!(
+            format_diff_line(&diff(Some("4"), Some("3"))),
+            "  up{job=\"node\"} 4 -> 3 -1 (-25.00%)".red().to_string()
+        );
+        assert_eq!(format_diff_line(&diff(Some("0"), Some("2"))), "  up{job=\"node\"} 0 -> 2 +2".green().to_string());
+        assert_eq!(format_diff_line(&diff(Some("1"), Some("1"))), "  up{job=\"node\"} 1".dimmed().to_string());
+        assert_eq!(format_diff_line(&diff(None, Some("1"))), "+ up{job=\"node\"} 1".green().bold().to_string());
+        assert_eq!(format_diff_line(&diff(Some("1"), None)), "- up{job=\"node\"} 1".red().bold().to_string());
+    }
+
+    #[test]
+    fn diff_results_pairs_series_by_labels() {
+        let response = |json: &str| -> QueryResponse {
+            serde_json::from_str(&format!(r#"{{"status":"success","data":{{"resultType":"vector","result":[{}]}}}}"#, json)).unwrap()
+        };
+        let before = response(r#"{"metric":{"job":"a"},"value":[1,"1"]},{"metric":{"job":"b"},"value":[1,"1"]}"#);
+        let after = response(r#"{"metric":{"job":"b"},"value":[2,"2"]},{"metric":{"job":"c"},"value":[2,"1"]}"#);
+
+        let diffs = diff_results(&before, &after);
+
+        let kinds: Vec<(&str, bool, bool)> = diffs
+            .iter()
+            .map(|d| (d.series.as_str(), d.change == DiffKind::Removed, d.change == DiffKind::Added))
+            .collect();
+        assert_eq!(kinds, [("{job=\"a\"}", true, false), ("{job=\"b\"}", false, false), ("{job=\"c\"}", false, true)]);
+        assert!(diffs[1].change == DiffKind::Increased);
+    }
+
+    fn grouping_input() -> Vec<QueryResult> {
+        let response: QueryResponse = serde_json::from_str(
+            r#"{"status":"success","data":{"resultType":"vector","result":[
+                {"metric":{"job":"api","instance":"a"},"value":[1700000000,"1"]},
+                {"metric":{"job":"api","instance":"b"},"value":[1700000010,"4"]},
+                {"metric":{"job":"db","instance":"c"},"value":[1700000000,"2"]},
+                {"metric":{"instance":"d"},"value":[1700000000,"7"]},
+                {"metric":{"job":"db","instance":"e"},"value":[1700000000,"NaN"]}
+            ]}}"#,
+        )
+        .unwrap();
+        response.data.result
+    }
+
+    fn grouped(agg: GroupAgg) -> Vec<(String, f64, String)> {
+        group_results(&grouping_input(), "job", agg)
+            .into_iter()
+            .map(|r| {
+                let (timestamp, value) = r.value.unwrap();
+                (r.metric["job"].clone(), timestamp, value)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn group_results_sums_by_label_with_latest_timestamp() {
+        assert_eq!(
+            grouped(GroupAgg::Sum),
+            [
+                (String::new(), 1700000000.0, "7".to_string()),
+                ("api".to_string(), 1700000010.0, "5".to_string()),
+                ("db".to_string(), 1700000000.0, "2".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn group_results_supports_each_aggregation() {
+        let values = |agg| grouped(agg).into_iter().map(|(job, _, value)| (job, value)).collect::<Vec<_>>();
+        let pairs = |items: [(&str, &str); 3]| items.map(|(job, value)| (job.to_string(), value.to_string())).to_vec();
+
+        assert_eq!(values(GroupAgg::Avg), pairs([("", "7"), ("api", "2.5"), ("db", "2")]));
+        assert_eq!(values(GroupAgg::Max), pairs([("", "7"), ("api", "4"), ("db", "2")]));
+        assert_eq!(values(GroupAgg::Min), pairs([("", "7"), ("api", "1"), ("db", "2")]));
+    }
+
+    #[test]
+    fn group_results_ignore_nan_in_every_aggregation() {
+        let response: QueryResponse = serde_json::from_str(
+            r#"{"status":"success","data":{"resultType":"vector","result":[
+                {"metric":{"job":"api"},"value":[1700000000,"NaN"]},
+                {"metric":{"job":"api"},"value":[1700000000,"3"]},
+                {"metric":{"job":"api"},"value":[1700000000,"1"]},
+                {"metric":{"job":"idle"},"value":[1700000000,"NaN"]},
+                {"metric":{"job":"idle"},"value":[1700000000,"bad"]}
+            ]}}"#,
+        )
+        .unwrap();
+
+        for (agg, api) in [(GroupAgg::Sum, "4"), (GroupAgg::Avg, "2"), (GroupAgg::Max, "3"), (GroupAgg::Min, "1")] {
+            let values: Vec<String> = group_results(&response.data.result, "job", agg)
+                .into_iter()
+                .map(|r| r.value.unwrap().1)
+                .collect();
+            assert_eq!(values, [api, "NaN"]);
+        }
+    }
+
+    #[test]
+    fn group_by_conflicts_with_range_queries() {
+        assert!(QueryCommand::try_parse_from(["query", "up", "--group-by", "job", "--range", "1h"]).is_err());
+        assert!(QueryCommand::try_parse_from(["query", "up", "--agg", "max"]).is_err());
+    }
+
+    #[test]
+    fn round_results_rounds_instant_and_range_values() {
+        let mut response: QueryResponse = serde_json::from_str(
+            r#"{"status":"success","data":{"resultType":"matrix","result":[
+                {"metric":{},"value":[1700000000,"0.123456"]},
+                {"metric":{},"values":[[1700000000,"9.999"],[1700000060,"NaN"]]}
+            ]}}"#,
+        )
+        .unwrap();
+
+        round_results(&mut response, 2);
+
+        assert_eq!(response.data.result[0].value.as_ref().unwrap().1, "0.12");
+        let values: Vec<&str> = response.data.result[1].values.iter().flatten().map(|(_, v)| v.as_str()).collect();
+        assert_eq!(values, ["10", "NaN"]);
+    }
+
+    fn annotated_response() -> QueryResponse {
+        serde_json::from_str(
+            r#"{"status":"success","data":{"resultType":"vector","result":[
+                {"metric":{"__name__":"up","job":"a"},"value":[1700000000,"1"]},
+                {"metric":{"__name__":"up","job":"b"},"value":[1700000000,"0"]},
+                {"metric":{"__name__":"node_load1"},"value":[1700000000,"0.5"]},
+                {"metric":{"job":"c"},"value":[1700000000,"2"]}
+            ]}}"#,
+        )
+        .unwrap()
+    }
+
+    fn up_metadata() -> HashMap<String, MetricMetadata> {
+        let meta = MetricMetadata { metric_type: "gauge".to_string(), help: "Target is up.".to_string(), unit: String::new() };
+        HashMap::from([("up".to_string(), meta)])
+    }
+
+    #[test]
+    fn annotate_json_adds_type_and_help_to_known_metrics() {
+        let value = annotate_json(&annotated_response(), &up_metadata()).unwrap();
+        let results = value["data"]["result"].as_array().unwrap();
+
+        assert_eq!(results[0]["type"], "gauge");
+        assert_eq!(results[1]["help"], "Target is up.");
+        assert!(results[2].get("type").is_none());
+        assert!(results[3].get("type").is_none());
+    }
+
+    #[test]
+    fn annotated_table_has_type_and_help_columns() {
+        let table = crate::utils::format_table_annotated(&annotated_response(), &up_metadata(), None, None, COLUMNS);
+        let lines: Vec<&str> = table.lines().collect();
+
+        assert!(lines.iter().any(|line| line.contains("type") && line.contains("help")), "{}", table);
+        assert_eq!(lines.iter().filter(|line| line.contains("gauge") && line.contains("Target is up.")).count(), 2, "{}", table);
+    }
+
+    #[tokio::test]
+    async fn fetch_metadata_requests_each_metric_name_once() {
+        let server = MockServer::start(vec![(
+            200,
+            r#"{"status":"success","data":{"up":[{"type":"gauge","help":"Target is up.","unit":""}]}}"#,
+        )]);
+        let client = VmClient::new(&server.url, 5, None, None, None).unwrap();
+
+        let metadata = fetch_metadata(&client, &annotated_response()).await;
+
+        let mut names: Vec<Vec<String>> = server.requests().iter().map(|r| r.param("metric")).collect();
+        names.sort();
+        assert_eq!(names, [vec!["node_load1".to_string()], vec!["up".to_string()]]);
+        assert_eq!(metadata["up"].metric_type, "gauge");
+    }
+
+    #[test]
+    fn max_points_widens_explicit_and_auto_steps() {
+        let command = query_command(&["up", "--range", "7d", "--step", "1s", "--max-points", "5000"]);
+        assert_eq!(command.resolve_step("1700000000", "1700604800"), "121s");
+
+        let command = query_command(&["up", "--range", "1h", "--step", "1m", "--max-points", "5000"]);
+        assert_eq!(command.resolve_step("1700000000", "1700003600"), "1m");
+
+        let command = query_command(&["up", "--range", "30d", "--max-points", "100"]);
+        assert_eq!(command.resolve_step("1700000000", "1702592000"), "26182s");
+    }
+
+    #[test]
+    fn max_points_requires_range_and_at_least_two_points() {
+        assert!(QueryCommand::try_parse_from(["query", "up", "--max-points", "100"]).is_err());
+        assert!(QueryCommand::try_parse_from(["query", "up", "--range", "1h", "--max-points", "1"]).is_err());
+    }
+
+    #[test]
+    fn fields_projection_keeps_only_requested_keys() {
+        let fields: Vec<String> = ["__name__", "instance", "value", "missing"].map(String::from).into();
+        let command = query_command(&["up", "--fields", "__name__,instance,value,missing"]);
+        assert_eq!(command.fields, fields);
+
+        let response: QueryResponse = serde_json::from_str(
+            r#"{"status":"success","data":{"resultType":"vector","result":[
+                {"metric":{"__name__":"up","instance":"h:9100","job":"node"},"value":[1700000000,"1"]},
+                {"metric":{"__name__":"up","job":"api"},"value":[1700000000,"0"]}
+            ]}}"#,
+        )
+        .unwrap();
+
+        let projected = serde_json::to_value(project_fields(&response.data.result, &fields)).unwrap();
+
+        assert_eq!(
+            projected,
+            serde_json::json!([
+                {"__name__": "up", "instance": "h:9100", "value": "1"},
+                {"__name__": "up", "value": "0"},
+            ])
+        );
+    }
+
+    #[test]
+    fn fields_projection_reads_timestamp_and_range_values() {
+        let response: QueryResponse = serde_json::from_str(
+            r#"{"status":"success","data":{"resultType":"matrix","result":[
+                {"metric":{"job":"node"},"values":[[1700000000,"1"],[1700000060,"2"]]}
+            ]}}"#,
+        )
+        .unwrap();
+        let fields: Vec<String> = ["job", "values", "timestamp"].map(String::from).into();
+
+        let projected = serde_json::to_value(project_fields(&response.data.result, &fields)).unwrap();
+
+        assert_eq!(projected, serde_json::json!([{"job": "node", "values": [[1700000000.0, "1"], [1700000060.0, "2"]]}]));
+    }
 }
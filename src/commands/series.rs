@@ -0,0 +1,184 @@
+use crate::api::{SeriesResponse, VmClient};
+use crate::config::OutputFormat;
+use crate::error::{Result, VmCliError};
+use crate::messages::{self, Msg};
+use crate::output;
+use crate::utils::{format_series, parse_time_range, print_formatted, Formatter};
+use clap::Parser;
+use colored::*;
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap};
+use std::time::Duration;
+use tracing::info;
+
+#[derive(Parser)]
+pub struct SeriesCommand {
+    #[arg(long = "match", value_name = "MATCH", required = true)]
+    matches: Vec<String>,
+
+    #[arg(short, long, conflicts_with = "range")]
+    start: Option<String>,
+
+    #[arg(short, long, conflicts_with = "range")]
+    end: Option<String>,
+
+    #[arg(short, long)]
+    range: Option<String>,
+
+    #[arg(short, long, default_value = "1000", value_parser = clap::value_parser!(u64).range(1..))]
+    limit: u64,
+
+    #[arg(long, value_name = "DURATION", requires = "range", value_parser = humantime::parse_duration)]
+    page_window: Option<Duration>,
+
+    #[arg(short, long, value_enum)]
+    format: Option<OutputFormat>,
+}
+
+#[derive(Serialize)]
+#[serde(transparent)]
+struct SeriesList(Vec<BTreeMap<String, String>>);
+
+impl Formatter for SeriesList {
+    fn headers(&self) -> Vec<String> {
+        ["series"].map(String::from).into()
+    }
+
+    fn rows(&self) -> Vec<Vec<String>> {
+        self.0
+            .iter()
+            .map(|labels| {
+                let metric: HashMap<String, String> = labels.clone().into_iter().collect();
+                vec![format_series(&metric)]
+            })
+            .collect()
+    }
+}
+
+impl SeriesCommand {
+    pub fn apply_default_format(&mut self, format: &OutputFormat) {
+        self.format.get_or_insert_with(|| format.clone());
+    }
+
+    pub async fn execute(&self, client: &VmClient) -> Result<()> {
+        let limit = self.limit as usize;
+        let response = self.fetch(client).await?;
+
+        let truncated = response.data.len() > limit;
+        let series = SeriesList(
+            response
+                .data
+                .into_iter()
+                .take(limit)
+                .map(|labels| labels.into_iter().collect())
+                .collect(),
+        );
+
+        let format = self.format.clone().unwrap_or(OutputFormat::Table);
+        let table = format == OutputFormat::Table && !output::is_json_mode();
+
+        if table && series.0.is_empty() {
+            println!("{}", messages::t(Msg::NoData).yellow());
+            return Ok(());
+        }
+
+        print_formatted(&series, &format)?;
+
+        if table {
+            println!(
+                "\n{} {} {}",
+                messages::t(Msg::Found).blue().bold(),
+                series.0.len(),
+                messages::t(Msg::Records)
+            );
+        }
+
+        if truncated {
+            eprintln!(
+                "{} показаны первые {} серий, найдено больше; увеличьте --limit или уточните селектор",
+                messages::t(Msg::Warning).yellow(),
+                limit
+            );
+        }
+
+        Ok(())
+    }
+
+    /// Серии с запасом в одну сверх --limit, чтобы было видно обрезку;
+    /// с --page-window диапазон запрашивается по окнам.
+    async fn fetch(&self, client: &VmClient) -> Result<SeriesResponse> {
+        let (start, end) = match &self.range {
+            Some(range) => {
+                let (start, end) = parse_time_range(range).map_err(VmCliError::TimeParseError)?;
+                (Some(start), Some(end))
+            }
+            None => (self.start.clone(), self.end.clone()),
+        };
+
+        info!("Получение серий: match={:?}, start={:?}, end={:?}", self.matches, start, end);
+
+        let limit = Some(self.limit as usize + 1);
+        let matches: Vec<&str> = self.matches.iter().map(String::as_str).collect();
+
+        let (Some(page_window), Some(start), Some(end)) = (self.page_window, &start, &end) else {
+            return client.series(&matches, start.as_deref(), end.as_deref(), limit).await;
+        };
+        let (Ok(start), Ok(end)) = (start.parse::<i64>(), end.parse::<i64>()) else {
+            return Err(VmCliError::TimeParseError(self.range.clone().unwrap_or_default()));
+        };
+
+        let progress_bar = output::window_progress_bar();
+        let series = client
+            .series_paged(&matches, start, end, page_window, limit, |page, pages| {
+                progress_bar.set_length(pages as u64);
+                progress_bar.set_position(page as u64);
+            })
+            .await;
+        progress_bar.finish_and_clear();
+        series
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::MockServer;
+    use crate::testing::assert_consistent_formats;
+
+    #[test]
+    fn series_render_consistently_across_formats() {
+        let series = BTreeMap::from([
+            ("__name__".to_string(), "up".to_string()),
+            ("path".to_string(), "a|b\tc".to_string()),
+        ]);
+        assert_consistent_formats(&SeriesList(vec![series]));
+    }
+
+    #[tokio::test]
+    async fn page_window_requests_consecutive_windows_and_merges_series() {
+        let server = MockServer::start(vec![
+            (200, r#"{"status":"success","data":[{"__name__":"up","job":"a"},{"__name__":"up","job":"b"}]}"#),
+            (200, r#"{"status":"success","data":[{"__name__":"up","job":"b"},{"__name__":"up","job":"c"}]}"#),
+        ]);
+        let client = VmClient::new(&server.url, 5, None, None, None).unwrap();
+        let command = SeriesCommand::try_parse_from([
+            "series", "--match", "up", "--range", "1d", "--page-window", "12h", "--limit", "2",
+        ])
+        .unwrap();
+
+        let series = command.fetch(&client).await.unwrap();
+
+        let jobs: Vec<&str> = series.data.iter().map(|labels| labels["job"].as_str()).collect();
+        assert_eq!(jobs, ["a", "b", "c"]);
+
+        let requests = server.requests();
+        assert_eq!(requests.len(), 2);
+        assert_eq!(requests[0].param("end"), requests[1].param("start"));
+        assert!(requests.iter().all(|request| request.param("limit") == ["3"]));
+    }
+
+    #[test]
+    fn page_window_requires_range() {
+        assert!(SeriesCommand::try_parse_from(["series", "--match", "up", "--page-window", "1h"]).is_err());
+    }
+}
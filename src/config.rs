@@ -1,16 +1,27 @@
 use serde::{Deserialize, Serialize};
-use std::path::Path;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
 use config::builder::DefaultState;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Config {
+    #[serde(default)]
+    pub schema_version: u32,
     pub host: String,
+    #[serde(default)]
+    pub base_path: Option<String>,
+    #[serde(default)]
+    pub extra_filters: Vec<String>,
+    #[serde(default)]
+    pub extra_labels: Vec<String>,
     pub timeout: u64,
     pub auth: Option<AuthConfig>,
     pub output: OutputConfig,
     pub cluster: Option<ClusterConfig>,
     pub logging: Option<LoggingConfig>,
     pub export: Option<ExportConfig>,
+    pub http: Option<HttpConfig>,
+    pub tls: Option<TlsConfig>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -37,6 +48,10 @@ pub enum OutputFormat {
     Table,
     #[serde(rename = "csv")]
     Csv,
+    #[serde(rename = "tsv")]
+    Tsv,
+    #[serde(rename = "markdown")]
+    Markdown,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -47,7 +62,8 @@ pub struct ClusterConfig {
     pub query_range_endpoint: String,
     #[serde(default = "default_health_endpoint")]
     pub health_endpoint: String,
-    #[serde(default = "default_metrics_endpoint")]
+    #[serde(default = "default_metrics_endpoint", skip_serializing)]
+    #[allow(dead_code)]
     pub metrics_endpoint: String,
     #[serde(default)]
     pub use_select_endpoint: bool,
@@ -57,6 +73,34 @@ pub struct ClusterConfig {
     pub select_project_id: String,
     pub vminsert_host: Option<String>,
     pub vmstorage_host: Option<String>,
+    #[serde(default)]
+    pub vmselect_hosts: Vec<String>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct HttpConfig {
+    pub pool_max_idle_per_host: Option<usize>,
+    pub pool_idle_timeout: Option<u64>,
+    #[serde(default)]
+    pub http2_prior_knowledge: bool,
+    pub max_response_bytes: Option<u64>,
+    pub trace_file: Option<String>,
+    #[serde(default)]
+    pub post_queries: bool,
+    pub proxy: Option<String>,
+    #[serde(default)]
+    pub no_proxy: Vec<String>,
+    pub retries: Option<u32>,
+    pub retry_base_ms: Option<u64>,
+    #[serde(default)]
+    pub retry_writes: bool,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct TlsConfig {
+    pub ca_cert: Option<String>,
+    #[serde(default)]
+    pub insecure: bool,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -70,10 +114,21 @@ pub struct LoggingConfig {
 pub struct ExportConfig {
     #[serde(default = "default_export_format")]
     pub default_format: String,
-    #[serde(default = "default_chunk_size")]
+    #[serde(default = "default_chunk_size", skip_serializing)]
+    #[allow(dead_code)]
     pub chunk_size: usize,
+    #[serde(default)]
+    pub profiles: HashMap<String, ExportProfile>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ExportProfile {
+    pub matches: Vec<String>,
+    pub range: Option<String>,
 }
 
+pub const CURRENT_SCHEMA_VERSION: u32 = 1;
+
 fn default_query_endpoint() -> String { "/api/v1/query".to_string() }
 fn default_query_range_endpoint() -> String { "/api/v1/query_range".to_string() }
 fn default_health_endpoint() -> String { "/health".to_string() }
@@ -87,7 +142,11 @@ fn default_chunk_size() -> usize { 1000 }
 impl Default for Config {
     fn default() -> Self {
         Self {
+            schema_version: CURRENT_SCHEMA_VERSION,
             host: "http://localhost:8428".to_string(),
+            base_path: None,
+            extra_filters: Vec::new(),
+            extra_labels: Vec::new(),
             timeout: 30,
             auth: None,
             output: OutputConfig {
@@ -98,42 +157,30 @@ impl Default for Config {
             cluster: None,
             logging: None,
             export: None,
+            http: None,
+            tls: None,
         }
     }
 }
 
 impl Config {
     pub fn load(config_path: Option<&str>) -> crate::error::Result<Self> {
-        let mut builder: config::ConfigBuilder<DefaultState> = config::ConfigBuilder::default();
+        Self::load_with_env(config_path, None)
+    }
 
-        builder = builder.set_default("host", "http://localhost:8428")?;
-        builder = builder.set_default("timeout", 30)?;
-        builder = builder.set_default("output.format", "table")?;
-        builder = builder.set_default("output.color", true)?;
-        builder = builder.set_default("output.pretty", true)?;
+    /// Как `load`, но переменные VM_* берутся из `env`, а не из окружения процесса.
+    pub fn load_with_env(config_path: Option<&str>, env: Option<config::Map<String, String>>) -> crate::error::Result<Self> {
+        let mut builder = Self::default_builder()?;
 
         if let Some(path) = config_path {
             if Path::new(path).exists() {
                 builder = builder.add_source(config::File::with_name(path));
             }
-        } else {
-            let config_dirs = vec![
-                dirs::config_dir().map(|p| p.join("vm-cli").join("config.toml")),
-                Some(std::env::current_dir()?.join(".vm-cli.toml")),
-                Some(std::env::current_dir()?.join("vm-cli.toml")),
-            ];
-
-            for config_path in config_dirs {
-                if let Some(path) = config_path {
-                    if path.exists() {
-                        builder = builder.add_source(config::File::from(path));
-                        break;
-                    }
-                }
-            }
+        } else if let Some(path) = Self::resolve_path(None)? {
+            builder = builder.add_source(config::File::from(path));
         }
 
-        builder = builder.add_source(config::Environment::with_prefix("VM"));
+        builder = builder.add_source(config::Environment::with_prefix("VM").source(env));
 
         let config = builder.build()?;
         let config: Config = config.try_deserialize()?;
@@ -143,6 +190,39 @@ impl Config {
         Ok(config)
     }
 
+    pub fn load_file(path: &Path) -> crate::error::Result<Self> {
+        let config = Self::default_builder()?
+            .add_source(config::File::from(path))
+            .build()?;
+        Ok(config.try_deserialize()?)
+    }
+
+    fn default_builder() -> crate::error::Result<config::ConfigBuilder<DefaultState>> {
+        let mut builder: config::ConfigBuilder<DefaultState> = config::ConfigBuilder::default();
+
+        builder = builder.set_default("host", "http://localhost:8428")?;
+        builder = builder.set_default("timeout", 30)?;
+        builder = builder.set_default("output.format", "table")?;
+        builder = builder.set_default("output.color", true)?;
+        builder = builder.set_default("output.pretty", true)?;
+
+        Ok(builder)
+    }
+
+    pub fn resolve_path(config_path: Option<&str>) -> crate::error::Result<Option<PathBuf>> {
+        if let Some(path) = config_path {
+            return Ok(Some(PathBuf::from(path)).filter(|p| p.exists()));
+        }
+
+        let config_dirs = vec![
+            dirs::config_dir().map(|p| p.join("vm-cli").join("config.toml")),
+            Some(std::env::current_dir()?.join(".vm-cli.toml")),
+            Some(std::env::current_dir()?.join("vm-cli.toml")),
+        ];
+
+        Ok(config_dirs.into_iter().flatten().find(|path| path.exists()))
+    }
+
     pub fn save(&self, path: &str) -> crate::error::Result<()> {
         let content = toml::to_string_pretty(self)
             .map_err(|e| crate::error::VmCliError::Unknown(e.to_string()))?;
@@ -29,16 +29,86 @@ pub enum VmCliError {
     #[error("Файл не найден: {0}")]
     FileNotFound(String),
 
+    #[allow(dead_code)]
     #[error("Недостаточно прав для выполнения операции")]
     PermissionDenied,
 
     #[error("Таймаут операции")]
     Timeout,
 
+    #[error("Ответ сервера превышает лимит {limit} байт")]
+    ResultTooLarge { limit: u64 },
+
+    #[error("Запрос не вернул данных")]
+    EmptyResult,
+
+    #[error("Контрольная сумма файла {file} не совпадает: ожидалась {expected}, получена {actual}")]
+    ChecksumMismatch { file: String, expected: String, actual: String },
+
+    #[error("Не удалось загрузить CA-сертификат {path}: {reason}")]
+    TlsError { path: String, reason: String },
+
+    #[error("Не пройдены проверки ({} из {total}): {}", failed.len(), failed.join(", "))]
+    ChecksFailed { failed: Vec<String>, total: usize },
+
+    #[error("Завершено с ошибками ({} из {total}): {}", failed.len(), failed.join(", "))]
+    BatchFailed { failed: Vec<String>, total: usize },
+
+    #[error("Операция прервана")]
+    Cancelled,
+
     #[error("Неизвестная ошибка: {0}")]
     Unknown(String),
 }
 
+impl VmCliError {
+    pub fn kind(&self) -> &'static str {
+        match self {
+            VmCliError::HttpError(_) => "http",
+            VmCliError::JsonError(_) => "json",
+            VmCliError::ConfigError(_) => "config",
+            VmCliError::IoError(_) => "io",
+            VmCliError::CsvError(_) => "csv",
+            VmCliError::ApiError { .. } => "api",
+            VmCliError::TimeParseError(_) => "time_parse",
+            VmCliError::InvalidQuery(_) => "invalid_query",
+            VmCliError::FileNotFound(_) => "file_not_found",
+            VmCliError::PermissionDenied => "permission_denied",
+            VmCliError::Timeout => "timeout",
+            VmCliError::ResultTooLarge { .. } => "result_too_large",
+            VmCliError::EmptyResult => "empty_result",
+            VmCliError::ChecksumMismatch { .. } => "checksum_mismatch",
+            VmCliError::TlsError { .. } => "tls",
+            VmCliError::ChecksFailed { .. } => "checks_failed",
+            VmCliError::BatchFailed { .. } => "batch_failed",
+            VmCliError::Cancelled => "cancelled",
+            VmCliError::Unknown(_) => "unknown",
+        }
+    }
+
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            VmCliError::ConfigError(_) | VmCliError::TlsError { .. } => 2,
+            VmCliError::PermissionDenied => 3,
+            VmCliError::ApiError { status: Some(401 | 403), .. } => 3,
+            VmCliError::Timeout => 4,
+            VmCliError::HttpError(e) if e.is_timeout() => 4,
+            VmCliError::ApiError { .. } => 5,
+            VmCliError::IoError(_) | VmCliError::FileNotFound(_) => 6,
+            VmCliError::HttpError(_) => 7,
+            VmCliError::JsonError(_) | VmCliError::CsvError(_) => 8,
+            VmCliError::InvalidQuery(_) | VmCliError::TimeParseError(_) => 9,
+            VmCliError::ResultTooLarge { .. } => 10,
+            VmCliError::EmptyResult => 11,
+            VmCliError::ChecksumMismatch { .. } => 12,
+            VmCliError::ChecksFailed { .. } => 13,
+            VmCliError::BatchFailed { .. } => 14,
+            VmCliError::Cancelled => 130,
+            VmCliError::Unknown(_) => 1,
+        }
+    }
+}
+
 impl From<anyhow::Error> for VmCliError {
     fn from(err: anyhow::Error) -> Self {
         VmCliError::Unknown(err.to_string())
@@ -46,3 +116,43 @@ impl From<anyhow::Error> for VmCliError {
 }
 
 pub type Result<T> = std::result::Result<T, VmCliError>;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn each_variant_maps_to_its_exit_code() {
+        let io = || std::io::Error::other("disk");
+        let api = |status| VmCliError::ApiError { message: String::new(), status };
+        let http = reqwest::Client::new().get("not a url").build().unwrap_err();
+        let cases = [
+            (VmCliError::Unknown(String::new()), 1),
+            (VmCliError::ConfigError(config::ConfigError::Message(String::new())), 2),
+            (VmCliError::TlsError { path: String::new(), reason: String::new() }, 2),
+            (VmCliError::PermissionDenied, 3),
+            (api(Some(401)), 3),
+            (api(Some(403)), 3),
+            (VmCliError::Timeout, 4),
+            (api(Some(500)), 5),
+            (api(None), 5),
+            (VmCliError::IoError(io()), 6),
+            (VmCliError::FileNotFound(String::new()), 6),
+            (VmCliError::HttpError(http), 7),
+            (VmCliError::JsonError(serde_json::from_str::<u8>("x").unwrap_err()), 8),
+            (VmCliError::CsvError(csv::Error::from(io())), 8),
+            (VmCliError::InvalidQuery(String::new()), 9),
+            (VmCliError::TimeParseError(String::new()), 9),
+            (VmCliError::ResultTooLarge { limit: 1 }, 10),
+            (VmCliError::EmptyResult, 11),
+            (VmCliError::ChecksumMismatch { file: String::new(), expected: String::new(), actual: String::new() }, 12),
+            (VmCliError::ChecksFailed { failed: Vec::new(), total: 0 }, 13),
+            (VmCliError::BatchFailed { failed: Vec::new(), total: 0 }, 14),
+            (VmCliError::Cancelled, 130),
+        ];
+
+        for (error, code) in cases {
+            assert_eq!(error.exit_code(), code, "{:?}", error);
+        }
+    }
+}
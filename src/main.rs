@@ -1,16 +1,24 @@
 use clap::{Parser, Subcommand};
 use colored::*;
+use tokio_util::sync::CancellationToken;
 use tracing::{error, info};
 
 mod api;
+mod batch;
 mod commands;
 mod config;
 mod error;
+mod messages;
+mod output;
+#[cfg(test)]
+mod testing;
+mod trace;
 mod utils;
 
 use commands::{
-    admin::AdminCommand, debug::DebugCommand, export::ExportCommand, health::HealthCommand,
-    import::ImportCommand, query::QueryCommand,
+    admin::AdminCommand, config::ConfigCommand, debug::DebugCommand, doctor::DoctorCommand,
+    export::ExportCommand, health::HealthCommand, import::ImportCommand, label_values::LabelValuesCommand,
+    labels::LabelsCommand, metadata::MetadataCommand, query::QueryCommand, series::SeriesCommand,
 };
 use config::Config;
 use error::VmCliError;
@@ -26,6 +34,9 @@ struct Cli {
     #[arg(long, default_value = "http://localhost:8428")]
     host: String,
 
+    #[arg(long, value_name = "PATH")]
+    base_path: Option<String>,
+
     #[arg(short, long, default_value = "30")]
     timeout: u64,
 
@@ -35,6 +46,60 @@ struct Cli {
     #[arg(long)]
     verbose: bool,
 
+    #[arg(long)]
+    json: bool,
+
+    #[arg(long)]
+    pager: bool,
+
+    #[arg(long, value_name = "ZONE", value_parser = output::parse_timezone)]
+    tz: Option<output::DisplayTimezone>,
+
+    #[arg(long, value_enum)]
+    lang: Option<messages::Lang>,
+
+    #[arg(long, value_name = "DURATION")]
+    every: Option<String>,
+
+    #[arg(long, requires = "every")]
+    fail_fast: bool,
+
+    #[arg(long)]
+    pool_max_idle_per_host: Option<usize>,
+
+    #[arg(long)]
+    pool_idle_timeout: Option<u64>,
+
+    #[arg(long)]
+    http2_prior_knowledge: bool,
+
+    #[arg(long)]
+    max_response_bytes: Option<u64>,
+
+    #[arg(long, value_name = "PATH")]
+    trace_file: Option<String>,
+
+    #[arg(long)]
+    post_queries: bool,
+
+    #[arg(long)]
+    insecure: bool,
+
+    #[arg(long, value_name = "URL")]
+    proxy: Option<String>,
+
+    #[arg(long, value_name = "N")]
+    retries: Option<u32>,
+
+    #[arg(long)]
+    dry_run: bool,
+
+    #[arg(long, value_name = "DURATION", value_parser = humantime::parse_duration)]
+    cache_ttl: Option<std::time::Duration>,
+
+    #[arg(long = "select-host", value_name = "URL")]
+    select_hosts: Vec<String>,
+
     #[command(subcommand)]
     command: Commands,
 }
@@ -53,11 +118,30 @@ enum Commands {
     Admin(AdminCommand),
 
     Debug(DebugCommand),
+
+    Metadata(MetadataCommand),
+
+    LabelValues(LabelValuesCommand),
+
+    Labels(LabelsCommand),
+
+    Series(SeriesCommand),
+
+    Config(ConfigCommand),
+
+    Doctor(DoctorCommand),
 }
 
 #[tokio::main]
 async fn main() -> Result<(), VmCliError> {
-    let mut cli = Cli::parse();
+    let mut cli = match Cli::try_parse() {
+        Ok(cli) => cli,
+        Err(e) if e.use_stderr() && json_requested() => {
+            output::print_usage_error(&e.to_string());
+            std::process::exit(e.exit_code());
+        }
+        Err(e) => e.exit(),
+    };
 
     if cli.host == "http://localhost:8428" {
         if let Ok(env_host) = std::env::var("VM_HOST") {
@@ -85,12 +169,124 @@ async fn main() -> Result<(), VmCliError> {
         }
     }
 
-    let config = Config::load(cli.config.as_deref())?;
+    if !cli.json {
+        if let Ok(env_json) = std::env::var("VM_JSON") {
+            cli.json = env_json == "1" || env_json.to_lowercase() == "true";
+        }
+    }
+
+    if !cli.insecure {
+        if let Ok(env_insecure) = std::env::var("VM_INSECURE") {
+            cli.insecure = env_insecure == "1" || env_insecure.to_lowercase() == "true";
+        }
+    }
+
+    if cli.lang.is_none() {
+        if let Ok(env_lang) = std::env::var("VM_LANG") {
+            cli.lang = messages::Lang::parse(&env_lang);
+        }
+    }
+
+    messages::set_lang(cli.lang.unwrap_or_else(messages::Lang::from_locale));
+    output::set_json_mode(cli.json);
+    output::set_pager(cli.pager);
+    if let Some(tz) = cli.tz {
+        output::set_timezone(tz);
+    }
+
+    let (mut config, load_error) = match Config::load(cli.config.as_deref()) {
+        Ok(config) => (config, None),
+        Err(e) if matches!(cli.command, Commands::Doctor(_)) => (Config::default(), Some(e)),
+        Err(e) => exit_with_error(&e, cli.json),
+    };
     
+    let mut flag_keys = Vec::new();
+
+    if cli.pool_max_idle_per_host.is_some()
+        || cli.pool_idle_timeout.is_some()
+        || cli.http2_prior_knowledge
+        || cli.max_response_bytes.is_some()
+        || cli.trace_file.is_some()
+        || cli.post_queries
+        || cli.proxy.is_some()
+        || cli.retries.is_some()
+    {
+        let http = config.http.get_or_insert_with(Default::default);
+        if let Some(max_idle) = cli.pool_max_idle_per_host {
+            http.pool_max_idle_per_host = Some(max_idle);
+            flag_keys.push("http.pool_max_idle_per_host");
+        }
+        if let Some(idle_timeout) = cli.pool_idle_timeout {
+            http.pool_idle_timeout = Some(idle_timeout);
+            flag_keys.push("http.pool_idle_timeout");
+        }
+        if cli.http2_prior_knowledge {
+            http.http2_prior_knowledge = true;
+            flag_keys.push("http.http2_prior_knowledge");
+        }
+        if let Some(limit) = cli.max_response_bytes {
+            http.max_response_bytes = Some(limit);
+            flag_keys.push("http.max_response_bytes");
+        }
+        if let Some(trace_file) = &cli.trace_file {
+            http.trace_file = Some(trace_file.clone());
+            flag_keys.push("http.trace_file");
+        }
+        if cli.post_queries {
+            http.post_queries = true;
+            flag_keys.push("http.post_queries");
+        }
+        if let Some(proxy) = &cli.proxy {
+            http.proxy = Some(proxy.clone());
+            flag_keys.push("http.proxy");
+        }
+        if let Some(retries) = cli.retries {
+            http.retries = Some(retries);
+            flag_keys.push("http.retries");
+        }
+    }
+
+    if cli.insecure {
+        config.tls.get_or_insert_with(Default::default).insecure = true;
+        flag_keys.push("tls.insecure");
+    }
+
+    if cli.base_path.is_some() {
+        config.base_path = cli.base_path.clone();
+        flag_keys.push("base_path");
+    }
+
+    output::set_pretty(config.output.pretty);
+
+    if let Commands::Config(cmd) = &cli.command {
+        if let Err(e) = cmd.execute(&config, cli.config.as_deref(), &flag_keys) {
+            exit_with_error(&e, cli.json);
+        }
+        return Ok(());
+    }
+
+    if let Commands::Export(cmd) = &mut cli.command {
+        if let Err(e) = cmd.apply_profile(config.export.as_ref()) {
+            exit_with_error(&e, cli.json);
+        }
+    }
+
+    if let Commands::Labels(cmd) = &mut cli.command {
+        cmd.apply_default_format(&config.output.format);
+    }
+
+    if let Commands::Series(cmd) = &mut cli.command {
+        cmd.apply_default_format(&config.output.format);
+    }
+
+    if let Commands::Doctor(cmd) = &mut cli.command {
+        cmd.apply_config(&config, cli.config.as_deref(), load_error.as_ref());
+    }
+
     let log_level = if cli.verbose {
         "debug"
     } else {
-        &config.logging.as_ref().and_then(|l| Some(l.level.clone())).unwrap_or_else(|| "info".to_string())
+        &config.logging.as_ref().map(|l| l.level.clone()).unwrap_or_else(|| "info".to_string())
     };
     
     if let Some(logging_config) = &config.logging {
@@ -110,14 +306,10 @@ async fn main() -> Result<(), VmCliError> {
                 .with_writer(file)
                 .init();
         } else {
-            tracing_subscriber::fmt()
-                .with_env_filter(format!("vm_cli={}", log_level))
-                .init();
+            init_console_logging(log_level, cli.json);
         }
     } else {
-        tracing_subscriber::fmt()
-            .with_env_filter(format!("vm_cli={}", log_level))
-            .init();
+        init_console_logging(log_level, cli.json);
     }
 
     info!("Запуск vm-cli v{}", env!("CARGO_PKG_VERSION"));
@@ -129,15 +321,39 @@ async fn main() -> Result<(), VmCliError> {
         }
     }
     
-    let api_client = api::VmClient::new(&config.host, config.timeout, config.cluster)?;
-
-    let result = match cli.command {
-        Commands::Query(cmd) => cmd.execute(&api_client).await,
-        Commands::Health(cmd) => cmd.execute(&api_client).await,
-        Commands::Export(cmd) => cmd.execute(&api_client).await,
-        Commands::Import(cmd) => cmd.execute(&api_client).await,
-        Commands::Admin(cmd) => cmd.execute(&api_client).await,
-        Commands::Debug(cmd) => cmd.execute(&api_client).await,
+    let cancel = CancellationToken::new();
+    let signal_cancel = cancel.clone();
+    tokio::spawn(async move {
+        if tokio::signal::ctrl_c().await.is_ok() {
+            signal_cancel.cancel();
+        }
+    });
+
+    let api_client = match api::VmClient::new(&config.host, config.timeout, config.cluster, config.http, config.tls) {
+        Ok(client) => client
+            .with_auth(config.auth.clone())
+            .with_select_hosts(cli.select_hosts.clone())
+            .with_base_path(config.base_path.clone())
+            .with_enforced_labels(config.extra_filters.clone(), config.extra_labels.clone())
+            .with_query_cache(cli.cache_ttl)
+            .with_dry_run(cli.dry_run)
+            .with_cancellation(cancel.clone()),
+        Err(e) => exit_with_error(&e, cli.json),
+    };
+
+    let result = if let Some(every) = &cli.every {
+        match humantime::parse_duration(every) {
+            Ok(interval) if !interval.is_zero() => {
+                run_every(&cli.command, &api_client, interval, cli.fail_fast, cli.json, &cancel).await
+            }
+            _ => Err(VmCliError::TimeParseError(format!("Неверный интервал --every: {}", every))),
+        }
+    } else {
+        tokio::select! {
+            biased;
+            result = dispatch(&cli.command, &api_client) => result,
+            _ = cancel.cancelled() => Err(VmCliError::Cancelled),
+        }
     };
 
     match result {
@@ -146,9 +362,265 @@ async fn main() -> Result<(), VmCliError> {
             Ok(())
         }
         Err(e) => {
+            if matches!(e, VmCliError::Cancelled) {
+                output::remove_partial_files();
+            }
             error!("Ошибка выполнения команды: {}", e);
-            eprintln!("{} {}", "ОШИБКА:".red().bold(), e);
-            Err(e)
+            exit_with_error(&e, cli.json)
+        }
+    }
+}
+
+async fn dispatch(command: &Commands, api_client: &api::VmClient) -> Result<(), VmCliError> {
+    match command {
+        Commands::Query(cmd) => cmd.execute(api_client).await,
+        Commands::Health(cmd) => cmd.execute(api_client).await,
+        Commands::Export(cmd) => cmd.execute(api_client).await,
+        Commands::Import(cmd) => cmd.execute(api_client).await,
+        Commands::Admin(cmd) => cmd.execute(api_client).await,
+        Commands::Debug(cmd) => cmd.execute(api_client).await,
+        Commands::Metadata(cmd) => cmd.execute(api_client).await,
+        Commands::LabelValues(cmd) => cmd.execute(api_client).await,
+        Commands::Labels(cmd) => cmd.execute(api_client).await,
+        Commands::Series(cmd) => cmd.execute(api_client).await,
+        Commands::Doctor(cmd) => cmd.execute(api_client).await,
+        Commands::Config(_) => unreachable!("config обрабатывается до создания клиента"),
+    }
+}
+
+/// Источник тиков для --every; в тестах заменяется фиктивными часами.
+trait Clock {
+    async fn tick(&mut self);
+}
+
+impl Clock for tokio::time::Interval {
+    async fn tick(&mut self) {
+        tokio::time::Interval::tick(self).await;
+    }
+}
+
+async fn run_every(
+    command: &Commands,
+    api_client: &api::VmClient,
+    interval: std::time::Duration,
+    fail_fast: bool,
+    json: bool,
+    cancel: &CancellationToken,
+) -> Result<(), VmCliError> {
+    let mut ticker = tokio::time::interval(interval);
+    ticker.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+    every_loop(&mut ticker, |_| dispatch(command, api_client), fail_fast, json, cancel).await
+}
+
+async fn every_loop<F>(
+    clock: &mut impl Clock,
+    mut run: impl FnMut(u64) -> F,
+    fail_fast: bool,
+    json: bool,
+    cancel: &CancellationToken,
+) -> Result<(), VmCliError>
+where
+    F: std::future::Future<Output = Result<(), VmCliError>>,
+{
+    let mut iteration = 0u64;
+
+    loop {
+        tokio::select! {
+            _ = clock.tick() => {}
+            _ = cancel.cancelled() => break,
+        }
+
+        iteration += 1;
+        if !json {
+            if iteration > 1 {
+                println!();
+            }
+            println!(
+                "{} {} #{}",
+                "───".dimmed(),
+                chrono::Local::now().format("%Y-%m-%d %H:%M:%S"),
+                iteration
+            );
+        }
+
+        let result = tokio::select! {
+            biased;
+            result = run(iteration) => result,
+            _ = cancel.cancelled() => Err(VmCliError::Cancelled),
+        };
+
+        if let Err(e) = result {
+            if matches!(e, VmCliError::Cancelled) {
+                output::remove_partial_files();
+                break;
+            }
+            if fail_fast {
+                return Err(e);
+            }
+            error!("Ошибка выполнения команды (итерация {}): {}", iteration, e);
+            if json {
+                output::print_error(&e);
+            } else {
+                eprintln!("{} {}", messages::t(messages::Msg::Error).red().bold(), messages::error_message(&e));
+            }
+        }
+    }
+
+    Ok(())
+}
+
+fn json_requested() -> bool {
+    std::env::args().any(|arg| arg == "--json")
+        || std::env::var("VM_JSON").is_ok_and(|v| v == "1" || v.to_lowercase() == "true")
+}
+
+fn exit_with_error(e: &VmCliError, json: bool) -> ! {
+    if json {
+        output::print_error(e);
+    } else {
+        eprintln!("{} {}", messages::t(messages::Msg::Error).red().bold(), messages::error_message(e));
+    }
+    std::process::exit(e.exit_code())
+}
+
+fn init_console_logging(log_level: &str, json: bool) {
+    let builder = tracing_subscriber::fmt().with_env_filter(format!("vm_cli={}", log_level));
+
+    if json {
+        builder.with_writer(std::io::stderr).init();
+    } else {
+        builder.init();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::MockServer;
+
+    /// Фиктивные часы: отсчитывают виртуальное время и отменяют цикл после
+    /// заданного числа тиков.
+    struct FakeClock {
+        interval: std::time::Duration,
+        now: std::time::Duration,
+        ticks: Vec<std::time::Duration>,
+        limit: usize,
+        cancel: CancellationToken,
+    }
+
+    impl FakeClock {
+        fn new(limit: usize, cancel: &CancellationToken) -> Self {
+            Self {
+                interval: std::time::Duration::from_secs(30),
+                now: std::time::Duration::ZERO,
+                ticks: Vec::new(),
+                limit,
+                cancel: cancel.clone(),
+            }
+        }
+    }
+
+    impl Clock for FakeClock {
+        async fn tick(&mut self) {
+            if self.ticks.len() == self.limit {
+                self.cancel.cancel();
+                return std::future::pending().await;
+            }
+            self.ticks.push(self.now);
+            self.now += self.interval;
         }
     }
+
+    async fn run_loop(limit: usize, fail_fast: bool, failing: u64) -> (Vec<u64>, Vec<std::time::Duration>, Result<(), VmCliError>) {
+        let cancel = CancellationToken::new();
+        let mut clock = FakeClock::new(limit, &cancel);
+        let mut runs = Vec::new();
+
+        let result = every_loop(
+            &mut clock,
+            |iteration| {
+                runs.push(iteration);
+                async move {
+                    if iteration == failing {
+                        Err(VmCliError::Timeout)
+                    } else {
+                        Ok(())
+                    }
+                }
+            },
+            fail_fast,
+            true,
+            &cancel,
+        )
+        .await;
+
+        (runs, clock.ticks, result)
+    }
+
+    #[tokio::test]
+    async fn every_runs_once_per_tick_until_cancelled() {
+        let (runs, ticks, result) = run_loop(3, false, 0).await;
+
+        assert!(result.is_ok());
+        assert_eq!(runs, [1, 2, 3]);
+        assert_eq!(ticks, [0, 30, 60].map(std::time::Duration::from_secs));
+    }
+
+    #[tokio::test]
+    async fn every_continues_after_failed_iteration() {
+        let (runs, _, result) = run_loop(3, false, 2).await;
+
+        assert!(result.is_ok());
+        assert_eq!(runs, [1, 2, 3]);
+    }
+
+    #[tokio::test]
+    async fn every_stops_on_failure_with_fail_fast() {
+        let (runs, _, result) = run_loop(3, true, 2).await;
+
+        assert!(matches!(result, Err(VmCliError::Timeout)));
+        assert_eq!(runs, [1, 2]);
+    }
+
+    #[tokio::test]
+    async fn every_stops_when_cancelled_during_iteration() {
+        let cancel = CancellationToken::new();
+        let mut clock = FakeClock::new(5, &cancel);
+        let mut runs = 0;
+
+        let result = every_loop(
+            &mut clock,
+            |_| {
+                runs += 1;
+                let cancel = cancel.clone();
+                async move {
+                    cancel.cancel();
+                    std::future::pending().await
+                }
+            },
+            false,
+            true,
+            &cancel,
+        )
+        .await;
+
+        assert!(result.is_ok());
+        assert_eq!(runs, 1);
+    }
+
+    #[tokio::test]
+    async fn failing_command_with_json_flag_reports_envelope_and_specific_exit_code() {
+        let server = MockServer::start(vec![(422, "unknown function: rat")]);
+        let cli = Cli::try_parse_from(["vm-cli", "--json", "--host", &server.url, "query", "up"]).unwrap();
+        assert!(cli.json);
+        let client = api::VmClient::new(&cli.host, cli.timeout, None, None, None).unwrap();
+
+        let err = dispatch(&cli.command, &client).await.unwrap_err();
+
+        let envelope: serde_json::Value = serde_json::from_str(&output::error_envelope(&err)).unwrap();
+        assert_eq!(envelope["ok"], false);
+        assert_eq!(envelope["error"]["error_kind"], "api");
+        assert_eq!(envelope["error"]["status"], 422);
+        assert_eq!(err.exit_code(), 5);
+    }
 }
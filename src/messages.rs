@@ -0,0 +1,166 @@
+use crate::error::VmCliError;
+use clap::ValueEnum;
+use std::sync::OnceLock;
+
+static LANG: OnceLock<Lang> = OnceLock::new();
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Lang {
+    Ru,
+    En,
+}
+
+impl Lang {
+    pub fn parse(value: &str) -> Option<Self> {
+        match value.trim().to_lowercase().as_str() {
+            "ru" => Some(Lang::Ru),
+            "en" => Some(Lang::En),
+            _ => None,
+        }
+    }
+
+    pub fn from_locale() -> Self {
+        let locale = ["LC_ALL", "LC_MESSAGES", "LANG"]
+            .iter()
+            .filter_map(|name| std::env::var(name).ok())
+            .find(|value| !value.is_empty());
+
+        Self::for_locale(locale.as_deref())
+    }
+
+    fn for_locale(locale: Option<&str>) -> Self {
+        match locale {
+            Some(value) if value != "C" && value != "POSIX" && !value.starts_with("ru") => Lang::En,
+            _ => Lang::Ru,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum Msg {
+    Error,
+    Warning,
+    Errors,
+    Found,
+    Results,
+    Records,
+    NoData,
+    Status,
+    Version,
+    Uptime,
+    HealthOk,
+    HealthProblem,
+    HealthUnknown,
+}
+
+pub fn set_lang(lang: Lang) {
+    let _ = LANG.set(lang);
+}
+
+pub fn lang() -> Lang {
+    LANG.get().copied().unwrap_or(Lang::Ru)
+}
+
+pub fn t(msg: Msg) -> &'static str {
+    text(lang(), msg)
+}
+
+fn text(lang: Lang, msg: Msg) -> &'static str {
+    match lang {
+        Lang::Ru => match msg {
+            Msg::Error => "ОШИБКА:",
+            Msg::Warning => "ПРЕДУПРЕЖДЕНИЕ:",
+            Msg::Errors => "Ошибок:",
+            Msg::Found => "Найдено:",
+            Msg::Results => "результатов",
+            Msg::Records => "записей",
+            Msg::NoData => "Нет данных для отображения",
+            Msg::Status => "Статус:",
+            Msg::Version => "Версия:",
+            Msg::Uptime => "Время работы:",
+            Msg::HealthOk => "✓ VictoriaMetrics работает нормально",
+            Msg::HealthProblem => "✗ VictoriaMetrics имеет проблемы",
+            Msg::HealthUnknown => "? Статус VictoriaMetrics неопределен",
+        },
+        Lang::En => match msg {
+            Msg::Error => "ERROR:",
+            Msg::Warning => "WARNING:",
+            Msg::Errors => "Errors:",
+            Msg::Found => "Found:",
+            Msg::Results => "results",
+            Msg::Records => "records",
+            Msg::NoData => "No data to display",
+            Msg::Status => "Status:",
+            Msg::Version => "Version:",
+            Msg::Uptime => "Uptime:",
+            Msg::HealthOk => "✓ VictoriaMetrics is healthy",
+            Msg::HealthProblem => "✗ VictoriaMetrics has problems",
+            Msg::HealthUnknown => "? VictoriaMetrics status is unknown",
+        },
+    }
+}
+
+pub fn error_message(err: &VmCliError) -> String {
+    error_message_in(lang(), err)
+}
+
+fn error_message_in(lang: Lang, err: &VmCliError) -> String {
+    if lang == Lang::Ru {
+        return err.to_string();
+    }
+
+    match err {
+        VmCliError::HttpError(e) => format!("HTTP request failed: {}", e),
+        VmCliError::JsonError(e) => format!("JSON parse error: {}", e),
+        VmCliError::ConfigError(e) => format!("Configuration error: {}", e),
+        VmCliError::IoError(e) => format!("I/O error: {}", e),
+        VmCliError::CsvError(e) => format!("CSV error: {}", e),
+        VmCliError::ApiError { message, .. } => format!("VictoriaMetrics API error: {}", message),
+        VmCliError::TimeParseError(value) => format!("Invalid time format: {}", value),
+        VmCliError::InvalidQuery(value) => format!("Invalid query: {}", value),
+        VmCliError::FileNotFound(path) => format!("File not found: {}", path),
+        VmCliError::PermissionDenied => "Permission denied".to_string(),
+        VmCliError::Timeout => "Operation timed out".to_string(),
+        VmCliError::ResultTooLarge { limit } => {
+            format!("Server response exceeds the {} byte limit", limit)
+        }
+        VmCliError::EmptyResult => "Query returned no data".to_string(),
+        VmCliError::ChecksumMismatch { file, expected, actual } => {
+            format!("Checksum mismatch for {}: expected {}, got {}", file, expected, actual)
+        }
+        VmCliError::TlsError { path, reason } => format!("Failed to load CA certificate {}: {}", path, reason),
+        VmCliError::ChecksFailed { failed, total } => {
+            format!("Checks failed ({} of {}): {}", failed.len(), total, failed.join(", "))
+        }
+        VmCliError::BatchFailed { failed, total } => {
+            format!("Completed with errors ({} of {}): {}", failed.len(), total, failed.join(", "))
+        }
+        VmCliError::Cancelled => "Aborted".to_string(),
+        VmCliError::Unknown(value) => format!("Unknown error: {}", value),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn switching_language_changes_messages() {
+        assert_eq!(text(Lang::Ru, Msg::NoData), "Нет данных для отображения");
+        assert_eq!(text(Lang::En, Msg::NoData), "No data to display");
+        assert_eq!(error_message_in(Lang::Ru, &VmCliError::Timeout), VmCliError::Timeout.to_string());
+        assert_eq!(error_message_in(Lang::En, &VmCliError::Timeout), "Operation timed out");
+    }
+
+    #[test]
+    fn language_is_parsed_from_flag_and_locale() {
+        assert_eq!(Lang::parse(" EN "), Some(Lang::En));
+        assert_eq!(Lang::parse("ru"), Some(Lang::Ru));
+        assert_eq!(Lang::parse("de"), None);
+
+        assert_eq!(Lang::for_locale(Some("en_US.UTF-8")), Lang::En);
+        assert_eq!(Lang::for_locale(Some("ru_RU.UTF-8")), Lang::Ru);
+        assert_eq!(Lang::for_locale(Some("C")), Lang::Ru);
+        assert_eq!(Lang::for_locale(None), Lang::Ru);
+    }
+}
@@ -0,0 +1,230 @@
+use crate::error::{Result, VmCliError};
+use indicatif::{ProgressBar, ProgressStyle};
+use serde::Serialize;
+use std::io::{IsTerminal, Write};
+use std::process::{Command, Stdio};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, OnceLock};
+
+static JSON_MODE: AtomicBool = AtomicBool::new(false);
+static PAGER: AtomicBool = AtomicBool::new(false);
+static PRETTY: AtomicBool = AtomicBool::new(true);
+static TIMEZONE: OnceLock<DisplayTimezone> = OnceLock::new();
+static PARTIAL_FILES: Mutex<Vec<PathBuf>> = Mutex::new(Vec::new());
+
+const DEFAULT_PAGER: &str = "less -R";
+
+#[derive(Serialize)]
+struct DataEnvelope<'a, T: Serialize + ?Sized> {
+    ok: bool,
+    data: &'a T,
+}
+
+#[derive(Serialize)]
+struct ErrorEnvelope<'a> {
+    ok: bool,
+    error: ErrorBody<'a>,
+}
+
+#[derive(Serialize)]
+struct ErrorBody<'a> {
+    error_kind: &'a str,
+    message: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    status: Option<u16>,
+}
+
+pub fn set_json_mode(enabled: bool) {
+    JSON_MODE.store(enabled, Ordering::Relaxed);
+}
+
+pub fn is_json_mode() -> bool {
+    JSON_MODE.load(Ordering::Relaxed)
+}
+
+/// Прогресс по окнам `--page-window`; в JSON-режиме скрыт.
+pub fn window_progress_bar() -> ProgressBar {
+    if is_json_mode() {
+        return ProgressBar::hidden();
+    }
+    let pb = ProgressBar::new(0);
+    pb.set_style(
+        ProgressStyle::default_bar()
+            .template("{bar:30.green} {pos}/{len} окон {wide_msg}")
+            .unwrap(),
+    );
+    pb
+}
+
+pub fn set_pager(enabled: bool) {
+    PAGER.store(enabled, Ordering::Relaxed);
+}
+
+pub fn set_pretty(enabled: bool) {
+    PRETTY.store(enabled, Ordering::Relaxed);
+}
+
+pub fn is_pretty() -> bool {
+    PRETTY.load(Ordering::Relaxed)
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum DisplayTimezone {
+    Utc,
+    Local,
+    Named(chrono_tz::Tz),
+}
+
+pub fn parse_timezone(value: &str) -> std::result::Result<DisplayTimezone, String> {
+    match value.to_lowercase().as_str() {
+        "utc" => Ok(DisplayTimezone::Utc),
+        "local" => Ok(DisplayTimezone::Local),
+        _ => value
+            .parse::<chrono_tz::Tz>()
+            .map(DisplayTimezone::Named)
+            .map_err(|_| format!("Неизвестный часовой пояс: {}", value)),
+    }
+}
+
+pub fn set_timezone(timezone: DisplayTimezone) {
+    let _ = TIMEZONE.set(timezone);
+}
+
+pub fn timezone() -> Option<DisplayTimezone> {
+    TIMEZONE.get().copied()
+}
+
+pub fn track_partial_file(path: &Path) {
+    if let Ok(mut files) = PARTIAL_FILES.lock() {
+        files.push(path.to_path_buf());
+    }
+}
+
+pub fn complete_partial_file(path: &Path) {
+    if let Ok(mut files) = PARTIAL_FILES.lock() {
+        files.retain(|p| p != path);
+    }
+}
+
+pub fn remove_partial_files() {
+    if let Ok(mut files) = PARTIAL_FILES.lock() {
+        for path in files.drain(..) {
+            let _ = std::fs::remove_file(path);
+        }
+    }
+}
+
+pub fn print_text(text: &str) {
+    if PAGER.load(Ordering::Relaxed) && !is_json_mode() && std::io::stdout().is_terminal() {
+        let pager = resolve_pager(std::env::var("PAGER").ok().as_deref());
+        if let Some((program, args)) = pager.split_first() {
+            if page_through(program, args, text).is_ok() {
+                return;
+            }
+        }
+    }
+
+    println!("{}", text);
+}
+
+fn resolve_pager(env_pager: Option<&str>) -> Vec<String> {
+    let command = env_pager
+        .map(str::trim)
+        .filter(|p| !p.is_empty())
+        .unwrap_or(DEFAULT_PAGER);
+    command.split_whitespace().map(String::from).collect()
+}
+
+fn page_through(program: &str, args: &[String], text: &str) -> std::io::Result<()> {
+    let mut child = Command::new(program)
+        .args(args)
+        .stdin(Stdio::piped())
+        .spawn()?;
+
+    if let Some(mut stdin) = child.stdin.take() {
+        let _ = writeln!(stdin, "{}", text);
+    }
+
+    child.wait()?;
+    Ok(())
+}
+
+pub fn print_data<T: Serialize + ?Sized>(data: &T) -> Result<()> {
+    let envelope = DataEnvelope { ok: true, data };
+    println!("{}", serde_json::to_string(&envelope)?);
+    Ok(())
+}
+
+pub fn print_error(err: &VmCliError) {
+    println!("{}", error_envelope(err));
+}
+
+pub fn print_usage_error(message: &str) {
+    println!("{}", render_error_envelope("usage", message.trim_end().to_string(), None));
+}
+
+pub fn error_envelope(err: &VmCliError) -> String {
+    let status = match err {
+        VmCliError::ApiError { status, .. } => *status,
+        _ => None,
+    };
+
+    render_error_envelope(err.kind(), crate::messages::error_message(err), status)
+}
+
+fn render_error_envelope(error_kind: &str, message: String, status: Option<u16>) -> String {
+    let envelope = ErrorEnvelope {
+        ok: false,
+        error: ErrorBody {
+            error_kind,
+            message,
+            status,
+        },
+    };
+
+    serde_json::to_string(&envelope)
+        .unwrap_or_else(|_| format!("{{\"ok\":false,\"error\":{{\"error_kind\":\"{}\"}}}}", error_kind))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::VmClient;
+    use crate::testing::MockServer;
+
+    #[tokio::test]
+    async fn failing_command_reports_json_error_envelope_and_nonzero_exit() {
+        let server = MockServer::start(vec![(400, "cannot parse query")]);
+        let client = VmClient::new(&server.url, 5, None, None, None).unwrap();
+
+        let err = <crate::commands::query::QueryCommand as clap::Parser>::try_parse_from(["query", "up"])
+            .unwrap()
+            .execute(&client)
+            .await
+            .unwrap_err();
+
+        let envelope: serde_json::Value = serde_json::from_str(&error_envelope(&err)).unwrap();
+        assert_eq!(envelope["ok"], false);
+        assert_eq!(envelope["error"]["error_kind"], "api");
+        assert_eq!(envelope["error"]["status"], 400);
+        assert!(envelope["error"]["message"].as_str().unwrap().contains("cannot parse query"));
+        assert_ne!(err.exit_code(), 0);
+    }
+
+    #[test]
+    fn error_envelope_omits_missing_status() {
+        let envelope: serde_json::Value = serde_json::from_str(&error_envelope(&VmCliError::Timeout)).unwrap();
+
+        assert_eq!(envelope["error"]["error_kind"], "timeout");
+        assert!(envelope["error"].get("status").is_none());
+    }
+
+    #[test]
+    fn pager_comes_from_env_with_less_as_fallback() {
+        assert_eq!(resolve_pager(None), ["less", "-R"]);
+        assert_eq!(resolve_pager(Some("  ")), ["less", "-R"]);
+        assert_eq!(resolve_pager(Some("more")), ["more"]);
+        assert_eq!(resolve_pager(Some(" bat --paging=always -p ")), ["bat", "--paging=always", "-p"]);
+    }
+}
@@ -0,0 +1,235 @@
+use crate::config::OutputFormat;
+use crate::utils::Formatter;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::TcpListener;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+
+/// Минимальный HTTP-сервер для тестов клиента: отвечает заготовленными ответами
+/// по очереди (последний повторяется) и запоминает полученные запросы.
+/// Каждое соединение обслуживается в своем потоке, поэтому простаивающие
+/// соединения (например, TCP-проба debug connect) не блокируют остальные.
+pub struct MockServer {
+    pub url: String,
+    requests: Arc<Mutex<Vec<RecordedRequest>>>,
+}
+
+#[derive(Debug, Clone)]
+pub struct RecordedRequest {
+    pub method: String,
+    pub target: String,
+    pub headers: Vec<(String, String)>,
+    pub body: String,
+}
+
+impl RecordedRequest {
+    pub fn path(&self) -> &str {
+        self.target.split('?').next().unwrap_or_default()
+    }
+
+    /// Значение заголовка без учета регистра имени.
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(key, _)| key.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// Значения параметра из строки запроса и тела формы в порядке отправки.
+    pub fn param(&self, name: &str) -> Vec<String> {
+        let query = self.target.split_once('?').map(|(_, query)| query).unwrap_or_default();
+        query
+            .split('&')
+            .chain(self.body.split('&'))
+            .filter_map(|pair| pair.split_once('='))
+            .map(|(key, value)| (decode(key), decode(value)))
+            .filter(|(key, _)| key == name)
+            .map(|(_, value)| value)
+            .collect()
+    }
+}
+
+fn decode(value: &str) -> String {
+    percent_encoding::percent_decode_str(&value.replace('+', " "))
+        .decode_utf8_lossy()
+        .into_owned()
+}
+
+impl MockServer {
+    pub fn start(responses: Vec<(u16, &str)>) -> Self {
+        Self::spawn(responses, None)
+    }
+
+    /// HTTPS-вариант с сертификатом testdata/server.pem (localhost, 127.0.0.1),
+    /// выпущенным тестовым CA testdata/ca.pem.
+    pub fn start_tls(responses: Vec<(u16, &str)>) -> Self {
+        let identity = native_tls::Identity::from_pkcs8(
+            include_bytes!("../testdata/server.pem"),
+            include_bytes!("../testdata/server.key"),
+        )
+        .unwrap();
+        Self::spawn(responses, Some(native_tls::TlsAcceptor::new(identity).unwrap()))
+    }
+
+    fn spawn(responses: Vec<(u16, &str)>, acceptor: Option<native_tls::TlsAcceptor>) -> Self {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let scheme = if acceptor.is_some() { "https" } else { "http" };
+        let url = format!("{}://{}", scheme, listener.local_addr().unwrap());
+        let requests = Arc::new(Mutex::new(Vec::new()));
+        let responses: Vec<(u16, String)> = responses.into_iter().map(|(s, b)| (s, b.to_string())).collect();
+
+        let recorded = requests.clone();
+        std::thread::spawn(move || {
+            for (index, stream) in listener.incoming().enumerate() {
+                let Ok(stream) = stream else { break };
+                let (status, body) = responses[index.min(responses.len() - 1)].clone();
+                let recorded = recorded.clone();
+                let acceptor = acceptor.clone();
+                std::thread::spawn(move || match acceptor {
+                    Some(acceptor) => serve(acceptor.accept(stream).ok()?, status, &body, &recorded),
+                    None => serve(stream, status, &body, &recorded),
+                });
+            }
+        });
+
+        Self { url, requests }
+    }
+
+    pub fn requests(&self) -> Vec<RecordedRequest> {
+        self.requests.lock().unwrap().clone()
+    }
+}
+
+fn serve<S: Read + Write>(stream: S, status: u16, body: &str, recorded: &Mutex<Vec<RecordedRequest>>) -> Option<()> {
+    let mut reader = BufReader::new(stream);
+    let request = read_request(&mut reader)?;
+    recorded.lock().unwrap().push(request);
+
+    let response = format!(
+        "HTTP/1.1 {} Mock\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        body.len(),
+        body
+    );
+    reader.get_mut().write_all(response.as_bytes()).ok()
+}
+
+fn read_request<R: BufRead>(reader: &mut R) -> Option<RecordedRequest> {
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line).ok()?;
+    let mut parts = request_line.split_whitespace();
+    let method = parts.next()?.to_string();
+    let target = parts.next()?.to_string();
+
+    let mut headers = Vec::new();
+    loop {
+        let mut line = String::new();
+        reader.read_line(&mut line).ok()?;
+        let line = line.trim_end();
+        if line.is_empty() {
+            break;
+        }
+        if let Some((name, value)) = line.split_once(':') {
+            headers.push((name.trim().to_string(), value.trim().to_string()));
+        }
+    }
+
+    let length = headers
+        .iter()
+        .find(|(name, _)| name.eq_ignore_ascii_case("content-length"))
+        .and_then(|(_, value)| value.parse::<usize>().ok())
+        .unwrap_or(0);
+    let mut request_body = vec![0; length];
+    reader.read_exact(&mut request_body).ok()?;
+
+    Some(RecordedRequest {
+        method,
+        target,
+        headers,
+        body: String::from_utf8_lossy(&request_body).into_owned(),
+    })
+}
+
+/// Сервер с одним ответом 200 без Content-Length: тело `prefix + chunk * count + suffix`
+/// отправляется по частям, пока клиент читает. `sent()` показывает, сколько байт
+/// тела ушло до закрытия соединения.
+pub struct StreamingServer {
+    pub url: String,
+    sent: Arc<AtomicU64>,
+}
+
+impl StreamingServer {
+    pub fn start(prefix: &str, chunk: &str, count: usize, suffix: &str) -> Self {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let sent = Arc::new(AtomicU64::new(0));
+        let (prefix, chunk, suffix) = (prefix.to_string(), chunk.to_string(), suffix.to_string());
+
+        let counter = sent.clone();
+        std::thread::spawn(move || {
+            let Ok((stream, _)) = listener.accept() else { return };
+            let mut reader = BufReader::new(stream);
+            if read_request(&mut reader).is_none() {
+                return;
+            }
+            let stream = reader.get_mut();
+            let head = "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nTransfer-Encoding: chunked\r\nConnection: close\r\n\r\n";
+            if stream.write_all(head.as_bytes()).is_err() {
+                return;
+            }
+            let parts = std::iter::once(&prefix).chain(std::iter::repeat_n(&chunk, count)).chain(std::iter::once(&suffix));
+            for part in parts {
+                if part.is_empty() {
+                    continue;
+                }
+                if stream.write_all(format!("{:x}\r\n{}\r\n", part.len(), part).as_bytes()).is_err() {
+                    return;
+                }
+                counter.fetch_add(part.len() as u64, Ordering::Relaxed);
+            }
+            let _ = stream.write_all(b"0\r\n\r\n");
+        });
+
+        Self { url, sent }
+    }
+
+    pub fn sent(&self) -> u64 {
+        self.sent.load(Ordering::Relaxed)
+    }
+}
+
+/// Проверяет, что команда выводит payload одинаково во всех форматах --format:
+/// JSON и YAML совпадают с сериализацией, CSV и TSV содержат одни и те же ячейки,
+/// а markdown — по строке на запись.
+pub fn assert_consistent_formats<T: Formatter + ?Sized>(payload: &T) {
+    let expected = serde_json::to_value(payload).unwrap();
+    let json = payload.render(&OutputFormat::Json, false).unwrap();
+    assert_eq!(serde_json::from_str::<serde_json::Value>(&json).unwrap(), expected);
+    let yaml = payload.render(&OutputFormat::Yaml, false).unwrap();
+    assert_eq!(serde_yaml::from_str::<serde_json::Value>(&yaml).unwrap(), expected);
+
+    let (headers, rows) = payload.delimited();
+    let record_count = rows.len();
+    let cells: Vec<Vec<String>> = std::iter::once(headers).chain(rows).collect();
+
+    let csv = payload.render(&OutputFormat::Csv, false).unwrap();
+    let parsed: Vec<Vec<String>> = csv::ReaderBuilder::new()
+        .has_headers(false)
+        .from_reader(csv.as_bytes())
+        .records()
+        .map(|record| record.unwrap().iter().map(String::from).collect())
+        .collect();
+    assert_eq!(parsed, cells, "{}", csv);
+
+    let tsv = payload.render(&OutputFormat::Tsv, false).unwrap();
+    let parsed: Vec<Vec<String>> = tsv.lines().map(|line| line.split('\t').map(String::from).collect()).collect();
+    let flattened: Vec<Vec<String>> = cells
+        .iter()
+        .map(|row| row.iter().map(|cell| cell.replace(['\t', '\n', '\r'], " ")).collect())
+        .collect();
+    assert_eq!(parsed, flattened, "{}", tsv);
+
+    let markdown = payload.render(&OutputFormat::Markdown, false).unwrap();
+    assert_eq!(markdown.lines().count(), payload.rows().len() + 2, "{}", markdown);
+    assert_eq!(payload.rows().len(), record_count);
+}
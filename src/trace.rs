@@ -0,0 +1,131 @@
+use crate::error::Result;
+use futures::StreamExt;
+use reqwest::header::HeaderMap;
+use reqwest::{Request, Response, StatusCode};
+use std::fs::OpenOptions;
+use std::io::Write;
+use tracing::warn;
+
+const TRACE_BODY_LIMIT: usize = 64 * 1024;
+
+const REDACTED_HEADERS: [&str; 4] = ["authorization", "proxy-authorization", "cookie", "set-cookie"];
+
+pub fn format_request(request: &Request) -> String {
+    let mut entry = format!(
+        "=== {} ===\n> {} {}\n",
+        chrono::Local::now().format("%Y-%m-%d %H:%M:%S%.3f"),
+        request.method(),
+        redact_url(request.url())
+    );
+    push_headers(&mut entry, '>', request.headers());
+
+    if let Some(body) = request.body().and_then(|b| b.as_bytes()) {
+        entry.push('\n');
+        push_body(&mut entry, &body[..body.len().min(TRACE_BODY_LIMIT)], body.len(), true);
+    }
+
+    entry
+}
+
+pub fn format_response(status: StatusCode, headers: &HeaderMap) -> String {
+    let mut entry = format!("< {}\n", status);
+    push_headers(&mut entry, '<', headers);
+    entry.push('\n');
+    entry
+}
+
+/// Тело ответа, которое по мере чтения копирует не более TRACE_BODY_LIMIT байт в
+/// запись трассировки. Запись дописывается в файл, когда тело дочитано или
+/// отброшено, поэтому ответ не буферизуется целиком и лимиты чтения
+/// (--max-response-bytes, --head) продолжают работать.
+pub fn traced_body(path: &str, entry: String, response: Response) -> reqwest::Body {
+    let recorder = BodyRecorder {
+        path: path.to_string(),
+        entry,
+        captured: Vec::new(),
+        total: 0,
+        complete: false,
+    };
+
+    let stream = futures::stream::unfold((response.bytes_stream(), recorder), |(mut inner, mut recorder)| async move {
+        match inner.next().await {
+            Some(chunk) => {
+                if let Ok(bytes) = &chunk {
+                    recorder.push(bytes);
+                }
+                Some((chunk, (inner, recorder)))
+            }
+            None => {
+                recorder.complete = true;
+                drop(recorder);
+                None
+            }
+        }
+    });
+    reqwest::Body::wrap_stream(stream)
+}
+
+struct BodyRecorder {
+    path: String,
+    entry: String,
+    captured: Vec<u8>,
+    total: usize,
+    complete: bool,
+}
+
+impl BodyRecorder {
+    fn push(&mut self, bytes: &[u8]) {
+        let room = TRACE_BODY_LIMIT.saturating_sub(self.captured.len());
+        self.captured.extend_from_slice(&bytes[..bytes.len().min(room)]);
+        self.total += bytes.len();
+    }
+}
+
+impl Drop for BodyRecorder {
+    fn drop(&mut self) {
+        push_body(&mut self.entry, &self.captured, self.total, self.complete);
+        if let Err(e) = append(&self.path, &self.entry) {
+            warn!("Не удалось записать трассировку в {}: {}", self.path, e);
+        }
+    }
+}
+
+pub fn append(path: &str, entry: &str) -> Result<()> {
+    let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+    writeln!(file, "{}", entry)?;
+    Ok(())
+}
+
+fn push_headers(entry: &mut String, direction: char, headers: &HeaderMap) {
+    for (name, value) in headers {
+        let value = if REDACTED_HEADERS.contains(&name.as_str()) {
+            "***".to_string()
+        } else {
+            String::from_utf8_lossy(value.as_bytes()).to_string()
+        };
+        entry.push_str(&format!("{} {}: {}\n", direction, name, value));
+    }
+}
+
+fn push_body(entry: &mut String, shown: &[u8], total: usize, complete: bool) {
+    entry.push_str(&String::from_utf8_lossy(shown));
+    if !complete {
+        entry.push_str(&format!(
+            "\n... [тело прочитано не полностью: показано {} из полученных {} байт]\n",
+            shown.len(),
+            total
+        ));
+    } else if total > shown.len() {
+        entry.push_str(&format!("\n... [обрезано: показано {} из {} байт]\n", shown.len(), total));
+    } else {
+        entry.push('\n');
+    }
+}
+
+fn redact_url(url: &reqwest::Url) -> String {
+    let mut url = url.clone();
+    if url.password().is_some() {
+        let _ = url.set_password(Some("***"));
+    }
+    url.to_string()
+}
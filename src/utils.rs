@@ -1,88 +1,416 @@
-use crate::api::QueryResponse;
+use crate::api::{MetricMetadata, QueryResponse, QueryResult};
 use crate::config::OutputFormat;
+use crate::error::VmCliError;
+use crate::output::{self, DisplayTimezone};
+use serde::Serialize;
 use colored::*;
+use sha2::{Digest, Sha256};
 
-use std::collections::HashMap;
-use tabled::{Table, Tabled};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::path::{Path, PathBuf};
+use tabled::{builder::Builder, settings::Style, Table};
 
-pub fn format_output(data: &QueryResponse, format: &OutputFormat) -> String {
-    match format {
-        OutputFormat::Json => format_json(data),
-        OutputFormat::Table => format_table(data),
-        OutputFormat::Csv => format_csv(data),
-        OutputFormat::Yaml => format_yaml(data),
+#[derive(Debug, Clone, Copy)]
+pub struct LabelColumns {
+    pub show_name: bool,
+    pub hide_labels: bool,
+}
+
+impl Default for LabelColumns {
+    fn default() -> Self {
+        Self {
+            show_name: true,
+            hide_labels: false,
+        }
     }
 }
 
-fn format_json(data: &QueryResponse) -> String {
-    serde_json::to_string_pretty(data).unwrap_or_else(|_| "Ошибка форматирования JSON".to_string())
+impl LabelColumns {
+    fn headers(self, name: &str) -> Vec<String> {
+        let mut headers = vec!["timestamp".to_string(), "value".to_string()];
+        if self.show_name {
+            headers.push(name.to_string());
+        }
+        if !self.hide_labels {
+            headers.push("labels".to_string());
+        }
+        headers
+    }
 }
 
-fn format_yaml(data: &QueryResponse) -> String {
-    serde_yaml::to_string(data).unwrap_or_else(|_| "Ошибка форматирования YAML".to_string())
+/// Ответ query как Formatter: в table и markdown метки собраны в одну колонку,
+/// в CSV/TSV каждая метка выводится своей колонкой.
+#[derive(Serialize)]
+#[serde(transparent)]
+pub struct QueryTable<'a> {
+    response: &'a QueryResponse,
+    #[serde(skip)]
+    columns: LabelColumns,
 }
 
-fn format_csv(data: &QueryResponse) -> String {
-    let mut csv_data = String::new();
-    
-    if let Some(first_result) = data.data.result.first() {
+impl<'a> QueryTable<'a> {
+    pub fn new(response: &'a QueryResponse, columns: LabelColumns) -> Self {
+        Self { response, columns }
+    }
+}
+
+impl Formatter for QueryTable<'_> {
+    fn headers(&self) -> Vec<String> {
+        self.columns.headers("name")
+    }
+
+    fn rows(&self) -> Vec<Vec<String>> {
+        build_metric_rows(self.response, self.columns)
+            .into_iter()
+            .map(|row| row.cells(self.columns))
+            .collect()
+    }
+
+    fn delimited(&self) -> (Vec<String>, Vec<Vec<String>>) {
+        let columns = self.columns;
+        let label_keys: BTreeSet<&String> = if columns.hide_labels {
+            BTreeSet::new()
+        } else {
+            self.response
+                .data
+                .result
+                .iter()
+                .flat_map(|r| r.metric.keys())
+                .filter(|key| !columns.show_name || key.as_str() != "__name__")
+                .collect()
+        };
+
         let mut headers = vec!["timestamp".to_string(), "value".to_string()];
-        headers.extend(first_result.metric.keys().cloned());
-        csv_data.push_str(&headers.join(","));
-        csv_data.push('\n');
-        
-        for result in &data.data.result {
-            if let Some((timestamp, value)) = &result.value {
-                let mut row = vec![timestamp.to_string(), value.to_string()];
-                for header in headers.iter().skip(2) {
-                    row.push(result.metric.get(header).unwrap_or(&"".to_string()).clone());
+        if columns.show_name {
+            headers.push("__name__".to_string());
+        }
+        headers.extend(label_keys.iter().map(|k| k.to_string()));
+
+        let mut rows = Vec::new();
+        for result in &self.response.data.result {
+            for (timestamp, value) in result_samples(result) {
+                let mut row = vec![render_timestamp(*timestamp), format_sample_value(value)];
+                if columns.show_name {
+                    row.push(result.metric.get("__name__").cloned().unwrap_or_default());
                 }
-                csv_data.push_str(&row.join(","));
-                csv_data.push('\n');
+                for key in &label_keys {
+                    row.push(result.metric.get(*key).cloned().unwrap_or_default());
+                }
+                rows.push(row);
             }
         }
+        (headers, rows)
+    }
+}
+
+pub fn format_output(data: &QueryResponse, format: &OutputFormat, pretty: bool, columns: LabelColumns) -> String {
+    let table = QueryTable::new(data, columns);
+    if *format == OutputFormat::Table && data.data.result.is_empty() {
+        return crate::messages::t(crate::messages::Msg::NoData).yellow().to_string();
+    }
+
+    table
+        .render(format, pretty)
+        .unwrap_or_else(|e| format!("Ошибка форматирования: {}", e))
+}
+
+fn serialize_json<T: Serialize + ?Sized>(data: &T, pretty: bool) -> crate::error::Result<String> {
+    let formatted = if pretty {
+        serde_json::to_string_pretty(data)?
+    } else {
+        serde_json::to_string(data)?
+    };
+    Ok(formatted)
+}
+
+/// YAML всегда выводится в блочном стиле: настройка pretty влияет только на JSON.
+fn serialize_yaml<T: Serialize + ?Sized>(data: &T) -> crate::error::Result<String> {
+    serde_yaml::to_string(data).map_err(|e| VmCliError::Unknown(e.to_string()))
+}
+
+fn result_samples(result: &QueryResult) -> impl Iterator<Item = &(f64, String)> {
+    result.value.iter().chain(result.values.iter().flatten())
+}
+
+pub fn render_timestamp(timestamp: f64) -> String {
+    match output::timezone() {
+        Some(timezone) => render_timestamp_in(timestamp, timezone),
+        None => timestamp.to_string(),
+    }
+}
+
+fn render_timestamp_in(timestamp: f64, timezone: DisplayTimezone) -> String {
+    let seconds = timestamp.floor();
+    let nanos = ((timestamp - seconds) * 1e9).round() as u32;
+    let Some(utc) = chrono::DateTime::from_timestamp(seconds as i64, nanos.min(999_999_999)) else {
+        return timestamp.to_string();
+    };
+
+    const FORMAT: &str = "%Y-%m-%d %H:%M:%S%.f %Z";
+    match timezone {
+        DisplayTimezone::Utc => utc.format(FORMAT).to_string(),
+        DisplayTimezone::Local => utc.with_timezone(&chrono::Local).format(FORMAT).to_string(),
+        DisplayTimezone::Named(tz) => utc.with_timezone(&tz).format(FORMAT).to_string(),
     }
-    
-    csv_data
 }
 
-#[derive(Tabled)]
 struct MetricRow {
     timestamp: String,
     value: String,
-    #[tabled(rename = "labels")]
+    name: String,
     labels: String,
 }
 
-fn format_table(data: &QueryResponse) -> String {
+impl MetricRow {
+    fn cells(self, columns: LabelColumns) -> Vec<String> {
+        let mut cells = vec![self.timestamp, self.value];
+        if columns.show_name {
+            cells.push(self.name);
+        }
+        if !columns.hide_labels {
+            cells.push(self.labels);
+        }
+        cells
+    }
+}
+
+fn build_metric_rows(data: &QueryResponse, columns: LabelColumns) -> Vec<MetricRow> {
     let mut rows = Vec::new();
     
     for result in &data.data.result {
-        if let Some((timestamp, value)) = &result.value {
-            let labels = format_labels(&result.metric);
+        let name = result.metric.get("__name__").cloned().unwrap_or_default();
+        let labels = if columns.show_name {
+            let mut rest = result.metric.clone();
+            rest.remove("__name__");
+            format_labels(&rest)
+        } else {
+            format_labels(&result.metric)
+        };
+        for (timestamp, value) in result_samples(result) {
             rows.push(MetricRow {
-                timestamp: timestamp.to_string(),
-                value: value.to_string(),
-                labels,
+                timestamp: render_timestamp(*timestamp),
+                value: format_sample_value(value),
+                name: name.clone(),
+                labels: labels.clone(),
             });
         }
     }
-    
+
+    rows
+}
+
+fn metric_table(rows: Vec<MetricRow>, columns: LabelColumns) -> Table {
+    let mut builder = Builder::default();
+    builder.push_record(columns.headers("name"));
+    for row in rows {
+        builder.push_record(row.cells(columns));
+    }
+    builder.build()
+}
+
+pub fn format_table_with_thresholds(
+    data: &QueryResponse,
+    warn: Option<f64>,
+    crit: Option<f64>,
+    columns: LabelColumns,
+) -> String {
+    let rows: Vec<MetricRow> = build_metric_rows(data, columns)
+        .into_iter()
+        .map(|row| MetricRow {
+            value: colorize_value(&row.value, warn, crit),
+            ..row
+        })
+        .collect();
+
     if rows.is_empty() {
-        return "Нет данных для отображения".yellow().to_string();
+        return crate::messages::t(crate::messages::Msg::NoData).yellow().to_string();
+    }
+
+    metric_table(rows, columns).to_string()
+}
+
+pub fn format_table_annotated(
+    data: &QueryResponse,
+    metadata: &HashMap<String, MetricMetadata>,
+    warn: Option<f64>,
+    crit: Option<f64>,
+    columns: LabelColumns,
+) -> String {
+    let rows = build_metric_rows(data, columns);
+    if rows.is_empty() {
+        return crate::messages::t(crate::messages::Msg::NoData).yellow().to_string();
+    }
+
+    let mut builder = Builder::default();
+    let mut headers = columns.headers("name");
+    headers.extend(["type".to_string(), "help".to_string()]);
+    builder.push_record(headers);
+    for row in rows {
+        let (metric_type, help) = metadata
+            .get(&row.name)
+            .map(|meta| (meta.metric_type.clone(), meta.help.clone()))
+            .unwrap_or_default();
+        let row = MetricRow {
+            value: colorize_value(&row.value, warn, crit),
+            ..row
+        };
+        let mut cells = row.cells(columns);
+        cells.extend([metric_type, help]);
+        builder.push_record(cells);
+    }
+    builder.build().to_string()
+}
+
+pub fn colorize_value(value: &str, warn: Option<f64>, crit: Option<f64>) -> String {
+    let Some(number) = value.parse::<f64>().ok().filter(|n| n.is_finite()) else {
+        return value.to_string();
+    };
+
+    match (warn, crit) {
+        (None, None) => value.to_string(),
+        (_, Some(crit)) if number > crit => value.red().to_string(),
+        (Some(warn), _) if number > warn => value.yellow().to_string(),
+        _ => value.green().to_string(),
+    }
+}
+
+pub fn format_float(value: f64) -> String {
+    if value.is_nan() {
+        "NaN".to_string()
+    } else if value == f64::INFINITY {
+        "+Inf".to_string()
+    } else if value == f64::NEG_INFINITY {
+        "-Inf".to_string()
+    } else {
+        value.to_string()
+    }
+}
+
+pub fn format_sample_value(value: &str) -> String {
+    match value.trim().parse::<f64>() {
+        Ok(number) if !number.is_finite() => format_float(number),
+        _ => value.to_string(),
+    }
+}
+
+pub fn round_sample_value(value: &str, digits: u32) -> String {
+    match value.trim().parse::<f64>() {
+        Ok(number) if number.is_finite() => {
+            let scale = 10f64.powi(digits.min(15) as i32);
+            format_float((number * scale).round() / scale + 0.0)
+        }
+        _ => value.to_string(),
+    }
+}
+
+pub fn escape_markdown(value: &str) -> String {
+    value.replace('|', "\\|").replace('\n', " ")
+}
+
+pub trait Formatter: Serialize {
+    fn headers(&self) -> Vec<String>;
+
+    fn rows(&self) -> Vec<Vec<String>>;
+
+    /// Колонки для CSV и TSV; по умолчанию совпадают с таблицей.
+    fn delimited(&self) -> (Vec<String>, Vec<Vec<String>>) {
+        (self.headers(), self.rows())
+    }
+
+    fn render(&self, format: &OutputFormat, pretty: bool) -> crate::error::Result<String> {
+        match format {
+            OutputFormat::Json => serialize_json(self, pretty),
+            OutputFormat::Yaml => serialize_yaml(self),
+            OutputFormat::Csv => render_delimited(self, b','),
+            OutputFormat::Tsv => render_delimited(self, b'\t'),
+            OutputFormat::Markdown => {
+                let rows = self
+                    .rows()
+                    .into_iter()
+                    .map(|row| row.iter().map(|cell| escape_markdown(cell)).collect());
+                Ok(render_table(self.headers(), rows).with(Style::markdown()).to_string())
+            }
+            OutputFormat::Table => Ok(render_table(self.headers(), self.rows()).to_string()),
+        }
+    }
+}
+
+pub fn print_formatted<T: Formatter + ?Sized>(payload: &T, format: &OutputFormat) -> crate::error::Result<()> {
+    if output::is_json_mode() {
+        return output::print_data(payload);
+    }
+    output::print_text(&payload.render(format, output::is_pretty())?);
+    Ok(())
+}
+
+fn render_table(headers: Vec<String>, rows: impl IntoIterator<Item = Vec<String>>) -> Table {
+    let mut builder = Builder::default();
+    builder.push_record(headers);
+    for row in rows {
+        builder.push_record(row);
+    }
+    builder.build()
+}
+
+fn render_delimited<T: Formatter + ?Sized>(payload: &T, delimiter: u8) -> crate::error::Result<String> {
+    let mut builder = csv::WriterBuilder::new();
+    builder.delimiter(delimiter);
+    if delimiter == b'\t' {
+        builder.quote_style(csv::QuoteStyle::Never);
+    }
+    let mut writer = builder.from_writer(Vec::new());
+    let (headers, rows) = payload.delimited();
+    writer.write_record(headers.iter().map(|cell| escape_delimited(cell, delimiter)))?;
+    for row in rows {
+        writer.write_record(row.iter().map(|cell| escape_delimited(cell, delimiter)))?;
+    }
+    let bytes = writer.into_inner().map_err(|e| VmCliError::Unknown(e.to_string()))?;
+    let text = String::from_utf8(bytes).map_err(|e| VmCliError::Unknown(e.to_string()))?;
+    Ok(text.trim_end_matches(['\r', '\n']).to_string())
+}
+
+/// TSV не экранирует значения кавычками: табуляции и переводы строк
+/// внутри значения заменяются пробелами.
+fn escape_delimited(value: &str, delimiter: u8) -> String {
+    if delimiter == b'\t' {
+        value.replace(['\t', '\n', '\r'], " ")
+    } else {
+        value.to_string()
     }
-    
-    Table::new(rows).to_string()
 }
 
 fn format_labels(labels: &HashMap<String, String>) -> String {
+    let sorted: BTreeMap<&String, &String> = labels.iter().collect();
     let mut formatted = Vec::new();
-    for (key, value) in labels {
+    for (key, value) in sorted {
         formatted.push(format!("{}={}", key, value));
     }
     formatted.join(", ")
 }
 
+pub fn format_series(metric: &HashMap<String, String>) -> String {
+    let name = metric.get("__name__").map(String::as_str).unwrap_or("");
+    let labels: BTreeMap<&String, &String> = metric.iter().filter(|(key, _)| *key != "__name__").collect();
+    if labels.is_empty() {
+        return name.to_string();
+    }
+
+    let labels: Vec<String> = labels
+        .iter()
+        .map(|(key, value)| format!("{}=\"{}\"", key, value.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n")))
+        .collect();
+    format!("{}{{{}}}", name, labels.join(","))
+}
+
+pub fn parse_label(value: &str) -> std::result::Result<(String, String), String> {
+    match value.split_once('=') {
+        Some((name, label_value)) if !name.trim().is_empty() => {
+            Ok((name.trim().to_string(), label_value.to_string()))
+        }
+        _ => Err(format!("ожидается NAME=VALUE, получено '{}'", value)),
+    }
+}
+
 pub fn format_health_status(status: &str) -> String {
     match status.to_lowercase().as_str() {
         "ok" | "healthy" => status.green().to_string(),
@@ -111,7 +439,7 @@ pub fn format_uptime(uptime: &str) -> String {
 }
 
 pub fn format_bytes(bytes: u64) -> String {
-    const UNITS: [&str; 4] = ["B", "KB", "MB", "GB"];
+    const UNITS: [&str; 5] = ["B", "KB", "MB", "GB", "TB"];
     let mut size = bytes as f64;
     let mut unit_index = 0;
     
@@ -131,6 +459,78 @@ pub fn format_percentage(value: f64, total: f64) -> String {
     }
 }
 
+pub fn parse_bytes(value: &str) -> Option<u64> {
+    let value = value.trim();
+    let split = value
+        .find(|c: char| !(c.is_ascii_digit() || c == '.'))
+        .unwrap_or(value.len());
+    let (number, unit) = value.split_at(split);
+    let number: f64 = number.parse().ok()?;
+
+    let multiplier: u64 = match unit.trim().to_uppercase().as_str() {
+        "" | "B" => 1,
+        "K" | "KB" | "KIB" => 1 << 10,
+        "M" | "MB" | "MIB" => 1 << 20,
+        "G" | "GB" | "GIB" => 1 << 30,
+        "T" | "TB" | "TIB" => 1 << 40,
+        "P" | "PB" | "PIB" => 1 << 50,
+        _ => return None,
+    };
+
+    Some((number * multiplier as f64) as u64)
+}
+
+pub fn parse_retention(value: &str) -> Option<std::time::Duration> {
+    const HOUR: f64 = 3600.0;
+    const DAY: f64 = 24.0 * HOUR;
+
+    let value = value.trim();
+    let split = value
+        .find(|c: char| !(c.is_ascii_digit() || c == '.'))
+        .unwrap_or(value.len());
+    let (number, unit) = value.split_at(split);
+    let number: f64 = number.parse().ok()?;
+
+    let seconds = match unit.trim() {
+        "h" => HOUR,
+        "d" => DAY,
+        "w" => 7.0 * DAY,
+        "y" => 365.0 * DAY,
+        "" | "M" => 31.0 * DAY,
+        _ => return None,
+    };
+
+    Some(std::time::Duration::from_secs_f64(number * seconds))
+}
+
+pub fn format_retention(duration: std::time::Duration) -> String {
+    let days = duration.as_secs() / 86400;
+    let hours = (duration.as_secs() % 86400) / 3600;
+
+    match (days, hours) {
+        (0, hours) => format!("{}h", hours),
+        (days, 0) if days >= 365 && days % 365 == 0 => format!("{}y ({} дней)", days / 365, days),
+        (days, 0) => format!("{} дней", days),
+        (days, hours) => format!("{} дней {}h", days, hours),
+    }
+}
+
+pub fn format_usage_bar(used: f64, total: f64, width: usize) -> String {
+    let ratio = if total > 0.0 { (used / total).clamp(0.0, 1.0) } else { 0.0 };
+    let filled = (ratio * width as f64).round() as usize;
+    let bar = format!("{}{}", "█".repeat(filled), "░".repeat(width - filled));
+
+    let bar = if ratio > 0.9 {
+        bar.red()
+    } else if ratio > 0.75 {
+        bar.yellow()
+    } else {
+        bar.green()
+    };
+
+    format!("[{}] {}", bar, format_percentage(used, total))
+}
+
 pub fn parse_time_range(range: &str) -> Result<(String, String), String> {
     let now = chrono::Utc::now();
     
@@ -159,6 +559,166 @@ pub fn parse_time_range(range: &str) -> Result<(String, String), String> {
     }
 }
 
+pub fn validate_range_params(start: &str, end: &str, step: &str) -> Result<(), String> {
+    match parse_step_seconds(step) {
+        None => {
+            return Err(format!(
+                "Неверный шаг '{}': укажите положительную длительность, например 30s, 5m или 1h",
+                step
+            ))
+        }
+        Some(seconds) if seconds <= 0.0 => {
+            return Err(format!("Шаг должен быть больше нуля, получено '{}'", step));
+        }
+        Some(_) => {}
+    }
+
+    if let (Some(from), Some(to)) = (parse_timestamp(start), parse_timestamp(end)) {
+        if from > to {
+            return Err(format!("Начало диапазона ({}) позже его конца ({})", start, end));
+        }
+    }
+
+    Ok(())
+}
+
+fn parse_step_seconds(step: &str) -> Option<f64> {
+    let step = step.trim();
+    if let Ok(seconds) = step.parse::<f64>() {
+        return Some(seconds).filter(|s| s.is_finite());
+    }
+    humantime::parse_duration(step).ok().map(|d| d.as_secs_f64())
+}
+
+/// Unix-время в секундах или RFC3339, как принимают --start/--end.
+pub fn parse_timestamp(value: &str) -> Option<f64> {
+    value.trim().parse::<f64>().ok().or_else(|| {
+        chrono::DateTime::parse_from_rfc3339(value.trim())
+            .ok()
+            .map(|t| t.timestamp_millis() as f64 / 1000.0)
+    })
+}
+
+pub fn substitute_vars(
+    template: &str,
+    vars: &[(String, String)],
+    allow_undefined: bool,
+) -> Result<String, String> {
+    let mut result = String::with_capacity(template.len());
+    let mut rest = template;
+
+    while let Some(pos) = rest.find('$') {
+        result.push_str(&rest[..pos]);
+        let after = &rest[pos + 1..];
+
+        if let Some(tail) = after.strip_prefix('$') {
+            result.push('$');
+            rest = tail;
+            continue;
+        }
+
+        let (name, raw, tail) = if let Some(braced) = after.strip_prefix('{') {
+            let end = braced
+                .find('}')
+                .ok_or_else(|| format!("Незакрытая подстановка '${{' в запросе: {}", template))?;
+            (&braced[..end], &rest[pos..pos + end + 3], &braced[end + 1..])
+        } else {
+            let end = after
+                .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+                .unwrap_or(after.len());
+            (&after[..end], &rest[pos..pos + end + 1], &after[end..])
+        };
+
+        let is_identifier = name.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_')
+            && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_');
+        if !is_identifier && raw.starts_with("${") {
+            return Err(format!("Неверное имя переменной в подстановке '{}'", raw));
+        }
+        if !is_identifier {
+            result.push('$');
+            rest = after;
+            continue;
+        }
+
+        match vars.iter().rev().find(|(key, _)| key == name) {
+            Some((_, value)) => result.push_str(value),
+            None if allow_undefined => result.push_str(raw),
+            None => {
+                return Err(format!(
+                    "Переменная ${} не задана: передайте --var {}=VALUE или укажите --allow-undefined",
+                    name, name
+                ))
+            }
+        }
+        rest = tail;
+    }
+
+    result.push_str(rest);
+    Ok(result)
+}
+
+const AUTO_STEP_TARGET_POINTS: u64 = 1000;
+const AUTO_STEP_MIN_SECONDS: u64 = 15;
+
+pub fn range_points(start: &str, end: &str, step: &str) -> Option<u64> {
+    let (from, to) = (parse_timestamp(start)?, parse_timestamp(end)?);
+    let step = parse_step_seconds(step).filter(|s| *s > 0.0)?;
+    (to >= from).then(|| ((to - from) / step).floor() as u64 + 1)
+}
+
+pub fn cap_step(start: &str, end: &str, step: &str, max_points: u64) -> Option<String> {
+    let points = range_points(start, end, step)?;
+    if points <= max_points || max_points < 2 {
+        return None;
+    }
+
+    let range = parse_timestamp(end)? - parse_timestamp(start)?;
+    let seconds = (range / (max_points - 1) as f64).ceil() as u64;
+    Some(format!("{}s", seconds.max(1)))
+}
+
+pub fn auto_step(range_seconds: u64) -> String {
+    let step = range_seconds.div_ceil(AUTO_STEP_TARGET_POINTS).max(AUTO_STEP_MIN_SECONDS);
+    format!("{}s", step)
+}
+
+#[derive(clap::ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DeriveMode {
+    Rate,
+    Delta,
+    Increase,
+}
+
+pub fn derive_values(values: &[(f64, String)], mode: DeriveMode) -> Vec<(f64, String)> {
+    let points: Vec<(f64, f64)> = values
+        .iter()
+        .filter_map(|(ts, v)| v.parse::<f64>().ok().map(|v| (*ts, v)))
+        .collect();
+
+    points
+        .windows(2)
+        .filter_map(|pair| {
+            let (prev_ts, prev) = pair[0];
+            let (ts, curr) = pair[1];
+            let increase = if curr < prev { curr } else { curr - prev };
+
+            let derived = match mode {
+                DeriveMode::Delta => curr - prev,
+                DeriveMode::Increase => increase,
+                DeriveMode::Rate => {
+                    let elapsed = ts - prev_ts;
+                    if elapsed <= 0.0 {
+                        return None;
+                    }
+                    increase / elapsed
+                }
+            };
+
+            Some((ts, format_float(derived)))
+        })
+        .collect()
+}
+
 pub fn validate_promql_query(query: &str) -> Result<(), String> {
     if query.trim().is_empty() {
         return Err("Запрос не может быть пустым".to_string());
@@ -178,3 +738,839 @@ pub fn validate_promql_query(query: &str) -> Result<(), String> {
     
     Ok(())
 }
+
+pub fn broad_matcher_reason(selector: &str) -> Option<String> {
+    let selector = selector.trim();
+    let (name, labels) = match selector.find('{') {
+        Some(open) => (
+            selector[..open].trim(),
+            selector[open + 1..].trim_end().trim_end_matches('}'),
+        ),
+        None => (selector, ""),
+    };
+
+    if !name.is_empty() {
+        return None;
+    }
+
+    let matchers = split_label_matchers(labels);
+    if matchers.is_empty() {
+        return Some(format!("селектор '{}' не содержит ни имени метрики, ни условий на метки", selector));
+    }
+
+    if matchers.iter().all(|m| is_match_all(m)) {
+        return Some(format!(
+            "селектор '{}' без имени метрики, и все его условия совпадают с любым значением",
+            selector
+        ));
+    }
+
+    None
+}
+
+fn split_label_matchers(labels: &str) -> Vec<&str> {
+    let mut matchers = Vec::new();
+    let mut in_quotes = false;
+    let mut escaped = false;
+    let mut start = 0;
+
+    for (i, c) in labels.char_indices() {
+        match c {
+            '\\' if in_quotes => escaped = !escaped,
+            '"' if !escaped => in_quotes = !in_quotes,
+            ',' if !in_quotes => {
+                matchers.push(labels[start..i].trim());
+                start = i + 1;
+            }
+            _ => escaped = false,
+        }
+    }
+    matchers.push(labels[start..].trim());
+
+    matchers.into_iter().filter(|m| !m.is_empty()).collect()
+}
+
+fn is_match_all(matcher: &str) -> bool {
+    const MATCH_ALL_PATTERNS: [&str; 4] = [".*", ".+", ".*.*", "(.*)"];
+
+    let (op, value) = if let Some((_, value)) = matcher.split_once("=~") {
+        ("=~", value)
+    } else if let Some((_, value)) = matcher.split_once("!~") {
+        ("!~", value)
+    } else if let Some((_, value)) = matcher.split_once("!=") {
+        ("!=", value)
+    } else if let Some((_, value)) = matcher.split_once('=') {
+        ("=", value)
+    } else {
+        return false;
+    };
+
+    let value = value.trim().trim_matches(|c| c == '"' || c == '\'' || c == '`');
+    match op {
+        "=~" => MATCH_ALL_PATTERNS.contains(&value),
+        "!=" | "!~" => value.is_empty(),
+        _ => false,
+    }
+}
+
+const AGGREGATION_OPERATORS: [&str; 12] = [
+    "sum", "avg", "min", "max", "count", "topk", "bottomk", "quantile", "stddev", "stdvar", "group",
+    "count_values",
+];
+
+pub fn suggests_range_query(query: &str) -> bool {
+    has_range_selector(query) && !AGGREGATION_OPERATORS.iter().any(|op| contains_call(query, op))
+}
+
+fn has_range_selector(query: &str) -> bool {
+    let mut quote = None;
+    let mut escaped = false;
+
+    for (index, c) in query.char_indices() {
+        match quote {
+            Some(_) if escaped => escaped = false,
+            Some(_) if c == '\\' => escaped = true,
+            Some(open) if c == open => quote = None,
+            Some(_) => {}
+            None if matches!(c, '"' | '\'' | '`') => quote = Some(c),
+            None if c == '[' && query[index + 1..].trim_start().starts_with(|c: char| c.is_ascii_digit()) => {
+                return true;
+            }
+            None => {}
+        }
+    }
+    false
+}
+
+fn contains_call(query: &str, name: &str) -> bool {
+    let bytes = query.as_bytes();
+    let mut start = 0;
+
+    while let Some(pos) = query[start..].find(name) {
+        let begin = start + pos;
+        let end = begin + name.len();
+        let is_ident = |b: u8| b.is_ascii_alphanumeric() || b == b'_' || b == b':';
+
+        let bounded_left = begin == 0 || !is_ident(bytes[begin - 1]);
+        let bounded_right = end == bytes.len() || !is_ident(bytes[end]);
+
+        if bounded_left && bounded_right {
+            let tail = query[end..].trim_start();
+            if tail.starts_with('(') || tail.starts_with("by") || tail.starts_with("without") {
+                return true;
+            }
+        }
+
+        start = end;
+    }
+
+    false
+}
+
+const GROUPING_KEYWORDS: [&str; 6] = ["by", "without", "on", "ignoring", "group_left", "group_right"];
+
+const NON_SELECTOR_KEYWORDS: [&str; 8] = ["and", "or", "unless", "bool", "offset", "inf", "nan", "keep_metric_names"];
+
+pub fn extract_selectors(query: &str) -> Vec<String> {
+    let chars: Vec<char> = query.chars().collect();
+    let is_ident_start = |c: char| c.is_ascii_alphabetic() || c == '_' || c == ':';
+    let is_ident = |c: char| c.is_ascii_alphanumeric() || c == '_' || c == ':' || c == '.';
+
+    let mut selectors: Vec<String> = Vec::new();
+    let mut i = 0;
+
+    while i < chars.len() {
+        let c = chars[i];
+
+        if c == '"' || c == '\'' || c == '`' {
+            i = skip_quoted(&chars, i);
+        } else if c == '[' {
+            i = chars[i..].iter().position(|&c| c == ']').map_or(chars.len(), |p| i + p + 1);
+        } else if c == '{' {
+            let end = skip_braces(&chars, i);
+            selectors.push(chars[i..end].iter().collect());
+            i = end;
+        } else if c.is_ascii_digit() {
+            while i < chars.len() && (is_ident(chars[i]) || chars[i] == '.') {
+                i += 1;
+            }
+        } else if is_ident_start(c) {
+            let start = i;
+            while i < chars.len() && is_ident(chars[i]) {
+                i += 1;
+            }
+            let name: String = chars[start..i].iter().collect();
+
+            let mut next = i;
+            while next < chars.len() && chars[next].is_whitespace() {
+                next += 1;
+            }
+
+            let lower = name.to_lowercase();
+            if GROUPING_KEYWORDS.contains(&lower.as_str()) {
+                if chars.get(next) == Some(&'(') {
+                    i = chars[next..].iter().position(|&c| c == ')').map_or(chars.len(), |p| next + p + 1);
+                }
+            } else if lower == "offset" {
+                i = next;
+                while i < chars.len() && (is_ident(chars[i]) || chars[i] == '-') {
+                    i += 1;
+                }
+            } else if chars.get(next) == Some(&'(')
+                || NON_SELECTOR_KEYWORDS.contains(&lower.as_str())
+                || starts_with_word(&chars[next..], "by")
+                || starts_with_word(&chars[next..], "without")
+            {
+                continue;
+            } else if chars.get(next) == Some(&'{') {
+                let end = skip_braces(&chars, next);
+                selectors.push(format!("{}{}", name, chars[next..end].iter().collect::<String>()));
+                i = end;
+            } else {
+                selectors.push(name);
+            }
+        } else {
+            i += 1;
+        }
+    }
+
+    let mut seen = std::collections::HashSet::new();
+    selectors.retain(|s| seen.insert(s.clone()));
+    selectors
+}
+
+fn starts_with_word(chars: &[char], word: &str) -> bool {
+    let len = word.chars().count();
+    chars.len() >= len
+        && chars[..len].iter().copied().eq(word.chars())
+        && chars.get(len).is_none_or(|c| !(c.is_ascii_alphanumeric() || *c == '_'))
+}
+
+fn skip_quoted(chars: &[char], start: usize) -> usize {
+    let quote = chars[start];
+    let mut i = start + 1;
+    while i < chars.len() {
+        match chars[i] {
+            '\\' if quote != '`' => i += 2,
+            c if c == quote => return i + 1,
+            _ => i += 1,
+        }
+    }
+    chars.len()
+}
+
+fn skip_braces(chars: &[char], start: usize) -> usize {
+    let mut i = start + 1;
+    while i < chars.len() {
+        match chars[i] {
+            '"' | '\'' | '`' => i = skip_quoted(chars, i),
+            '}' => return i + 1,
+            _ => i += 1,
+        }
+    }
+    chars.len()
+}
+
+pub fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+
+    for (i, ca) in a.chars().enumerate() {
+        let mut current = vec![i + 1; b.len() + 1];
+        for (j, cb) in b.iter().enumerate() {
+            let cost = if ca == *cb { 0 } else { 1 };
+            current[j + 1] = (previous[j] + cost).min(previous[j + 1] + 1).min(current[j] + 1);
+        }
+        previous = current;
+    }
+
+    previous[b.len()]
+}
+
+pub fn suggest_names<'a>(name: &str, candidates: &'a [String], limit: usize) -> Vec<&'a str> {
+    let max_distance = (name.chars().count() / 3).max(2);
+
+    let mut ranked: Vec<(usize, &str)> = candidates
+        .iter()
+        .filter(|c| c.as_str() != name)
+        .map(|c| (edit_distance(name, c), c.as_str()))
+        .filter(|(distance, _)| *distance <= max_distance)
+        .collect();
+    ranked.sort();
+
+    ranked.into_iter().take(limit).map(|(_, c)| c).collect()
+}
+
+pub fn sha256_hex(content: &[u8]) -> String {
+    hex_digest(Sha256::digest(content).as_slice())
+}
+
+fn hex_digest(digest: &[u8]) -> String {
+    digest.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+pub fn checksum_path(file: &Path) -> PathBuf {
+    let mut path = file.as_os_str().to_owned();
+    path.push(".sha256");
+    PathBuf::from(path)
+}
+
+/// Пишет sidecar `<file>.sha256` в формате `sha256sum` с уже посчитанным хешем.
+pub fn write_checksum(file: &Path, hash: &str) -> std::io::Result<()> {
+    let name = file.file_name().map(|n| n.to_string_lossy()).unwrap_or_default();
+    std::fs::write(checksum_path(file), format!("{}  {}\n", hash, name))
+}
+
+/// Считает SHA-256 байт, проходящих через writer, по мере записи.
+pub struct HashingWriter<W> {
+    inner: W,
+    hasher: Sha256,
+}
+
+impl<W: std::io::Write> HashingWriter<W> {
+    pub fn new(inner: W) -> Self {
+        Self { inner, hasher: Sha256::new() }
+    }
+
+    pub fn finish(self) -> (W, String) {
+        (self.inner, hex_digest(self.hasher.finalize().as_slice()))
+    }
+}
+
+impl<W: std::io::Write> std::io::Write for HashingWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let written = self.inner.write(buf)?;
+        self.hasher.update(&buf[..written]);
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+pub fn read_checksum(file: &Path) -> std::io::Result<Option<String>> {
+    let content = std::fs::read_to_string(checksum_path(file))?;
+    Ok(content
+        .split_whitespace()
+        .next()
+        .filter(|hash| hash.len() == 64 && hash.chars().all(|c| c.is_ascii_hexdigit()))
+        .map(str::to_lowercase))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+    use crate::output::parse_timezone;
+    use crate::testing::assert_consistent_formats;
+
+    fn response() -> QueryResponse {
+        serde_json::from_str(
+            r#"{"status":"success","data":{"resultType":"vector","result":[
+                {"metric":{"__name__":"up","job":"node, \"edge\""},"value":[1700000000,"1"]},
+                {"metric":{"__name__":"up","instance":"a|b\tc"},"value":[1700000000,"0"]}
+            ]}}"#,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn query_renders_consistently_across_formats() {
+        let response = response();
+        assert_consistent_formats(&QueryTable::new(&response, LabelColumns::default()));
+        assert_consistent_formats(&QueryTable::new(&response, LabelColumns { show_name: false, hide_labels: true }));
+    }
+
+    #[test]
+    fn query_csv_and_tsv_have_label_columns_and_table_has_labels_column() {
+        let response = response();
+        let table = QueryTable::new(&response, LabelColumns::default());
+
+        let csv = table.render(&OutputFormat::Csv, true).unwrap();
+        assert!(csv.starts_with("timestamp,value,__name__,instance,job\n"), "{}", csv);
+        assert!(csv.contains(r#""node, ""edge""""#), "{}", csv);
+
+        let tsv = table.render(&OutputFormat::Tsv, true).unwrap();
+        assert!(tsv.contains("\ta|b c\t"), "{}", tsv);
+        assert!(tsv.contains("\tnode, \"edge\"\n"), "{}", tsv);
+
+        assert_eq!(table.headers(), ["timestamp", "value", "name", "labels"]);
+        let markdown = table.render(&OutputFormat::Markdown, true).unwrap();
+        assert!(markdown.contains("instance=a\\|b"), "{}", markdown);
+    }
+
+    #[test]
+    fn empty_query_table_reports_no_data() {
+        let response: QueryResponse =
+            serde_json::from_str(r#"{"status":"success","data":{"resultType":"vector","result":[]}}"#).unwrap();
+
+        let table = format_output(&response, &OutputFormat::Table, true, LabelColumns::default());
+        assert!(table.contains(crate::messages::t(crate::messages::Msg::NoData)));
+        assert_eq!(format_output(&response, &OutputFormat::Csv, true, LabelColumns::default()), "timestamp,value,__name__");
+        assert_eq!(format_output(&response, &OutputFormat::Tsv, true, LabelColumns::default()), "timestamp\tvalue\t__name__");
+        assert_eq!(format_output(&response, &OutputFormat::Markdown, true, LabelColumns::default()).lines().count(), 2);
+
+        for format in [OutputFormat::Json, OutputFormat::Yaml] {
+            let rendered = format_output(&response, &format, true, LabelColumns::default());
+            let value: serde_json::Value = serde_yaml::from_str(&rendered).unwrap();
+            assert_eq!(value["data"]["result"], serde_json::json!([]), "{}", rendered);
+        }
+    }
+
+    #[test]
+    fn tsv_has_same_fields_as_csv_with_tab_delimiter() {
+        let response: QueryResponse = serde_json::from_str(
+            r#"{"status":"success","data":{"resultType":"matrix","result":[
+                {"metric":{"__name__":"up","job":"node"},"values":[[1700000000,"1"],[1700000060,"0"]]},
+                {"metric":{"__name__":"up","instance":"db:9100"},"values":[[1700000000,"1"]]}
+            ]}}"#,
+        )
+        .unwrap();
+
+        let csv = format_output(&response, &OutputFormat::Csv, true, LabelColumns::default());
+        let tsv = format_output(&response, &OutputFormat::Tsv, true, LabelColumns::default());
+
+        assert_eq!(tsv, csv.replace(',', "\t"));
+        assert_eq!(tsv.lines().next(), Some("timestamp\tvalue\t__name__\tinstance\tjob"));
+        assert_eq!(tsv.lines().count(), 4);
+    }
+
+    #[test]
+    fn markdown_renders_header_separator_and_escaped_pipe() {
+        let response: QueryResponse = serde_json::from_str(
+            r#"{"status":"success","data":{"resultType":"vector","result":[
+                {"metric":{"__name__":"up","path":"a|b"},"value":[1700000000,"1"]}
+            ]}}"#,
+        )
+        .unwrap();
+
+        let markdown = format_output(&response, &OutputFormat::Markdown, true, LabelColumns::default());
+        let lines: Vec<&str> = markdown.lines().collect();
+
+        assert_eq!(lines.len(), 3, "{}", markdown);
+        assert!(lines[0].starts_with("| timestamp "), "{}", markdown);
+        assert!(lines[1].starts_with("|---"), "{}", markdown);
+        assert!(lines[2].contains(r"path=a\|b"), "{}", markdown);
+        assert_eq!(lines[2].matches(" | ").count(), 3, "{}", markdown);
+    }
+
+    #[test]
+    fn range_hint_detects_unaggregated_range_selectors() {
+        for query in ["rate(http_requests_total[5m])", "up[1h]", "avg_over_time(node_load1[10m])", "increase(x[1d:5m])"] {
+            assert!(suggests_range_query(query), "{}", query);
+        }
+        for query in [
+            "up",
+            "sum(rate(http_requests_total[5m]))",
+            "sum by (job) (rate(x[5m]))",
+            "topk(5, rate(x[5m]))",
+            "count_values(\"v\", x[5m])",
+            "x{path=\"[5m]\"}",
+            "x{path='\\'[5m]'}",
+        ] {
+            assert!(!suggests_range_query(query), "{}", query);
+        }
+    }
+
+    #[test]
+    fn range_hint_requires_aggregation_call_not_metric_prefix() {
+        assert!(suggests_range_query("rate(summary_requests[5m])"));
+        assert!(suggests_range_query("rate(max_connections[5m])"));
+        assert!(!suggests_range_query("max without (instance) (rate(x[5m]))"));
+    }
+
+    #[test]
+    fn auto_step_targets_about_a_thousand_points_with_minimum() {
+        let cases = [
+            (0, "15s"),
+            (300, "15s"),
+            (3600, "15s"),
+            (6 * 3600, "22s"),
+            (24 * 3600, "87s"),
+            (7 * 86400, "605s"),
+            (30 * 86400, "2592s"),
+        ];
+        for (range_seconds, expected) in cases {
+            assert_eq!(auto_step(range_seconds), expected, "range {}s", range_seconds);
+        }
+    }
+
+    fn counter(values: &[(f64, &str)]) -> Vec<(f64, String)> {
+        values.iter().map(|(ts, v)| (*ts, v.to_string())).collect()
+    }
+
+    #[test]
+    fn increase_and_rate_treat_counter_reset_as_restart_from_zero() {
+        let values = counter(&[(0.0, "10"), (10.0, "30"), (20.0, "5"), (30.0, "25")]);
+
+        assert_eq!(
+            derive_values(&values, DeriveMode::Increase),
+            counter(&[(10.0, "20"), (20.0, "5"), (30.0, "20")])
+        );
+        assert_eq!(
+            derive_values(&values, DeriveMode::Rate),
+            counter(&[(10.0, "2"), (20.0, "0.5"), (30.0, "2")])
+        );
+    }
+
+    #[test]
+    fn delta_keeps_negative_changes() {
+        let values = counter(&[(0.0, "10"), (10.0, "30"), (20.0, "5")]);
+
+        assert_eq!(derive_values(&values, DeriveMode::Delta), counter(&[(10.0, "20"), (20.0, "-25")]));
+    }
+
+    #[test]
+    fn derive_skips_unparsable_samples_and_non_increasing_timestamps() {
+        let values = counter(&[(0.0, "1"), (10.0, "NaN?"), (10.0, "3"), (10.0, "4"), (20.0, "8")]);
+
+        assert_eq!(derive_values(&values, DeriveMode::Rate), counter(&[(10.0, "0.2"), (20.0, "0.4")]));
+        assert!(derive_values(&counter(&[(0.0, "1")]), DeriveMode::Delta).is_empty());
+    }
+
+    #[test]
+    fn colorize_value_uses_strict_threshold_boundaries() {
+        let (warn, crit) = (Some(80.0), Some(90.0));
+
+        assert_eq!(colorize_value("80", warn, crit), "80".green().to_string());
+        assert_eq!(colorize_value("80.5", warn, crit), "80.5".yellow().to_string());
+        assert_eq!(colorize_value("90", warn, crit), "90".yellow().to_string());
+        assert_eq!(colorize_value("90.1", warn, crit), "90.1".red().to_string());
+        assert_eq!(colorize_value("95", None, crit), "95".red().to_string());
+        assert_eq!(colorize_value("85", None, crit), "85".green().to_string());
+        assert_eq!(colorize_value("85", warn, None), "85".yellow().to_string());
+    }
+
+    #[test]
+    fn colorize_value_leaves_plain_without_thresholds_or_number() {
+        assert_eq!(colorize_value("95", None, None), "95");
+        assert_eq!(colorize_value("NaN", Some(1.0), Some(2.0)), "NaN");
+        assert_eq!(colorize_value("+Inf", Some(1.0), Some(2.0)), "+Inf");
+        assert_eq!(colorize_value("n/a", Some(1.0), Some(2.0)), "n/a");
+    }
+
+    #[test]
+    fn retention_is_parsed_from_victoriametrics_units() {
+        const DAY: u64 = 86400;
+        let cases = [
+            ("1y", Some(365 * DAY)),
+            ("30d", Some(30 * DAY)),
+            ("2w", Some(14 * DAY)),
+            ("12h", Some(12 * 3600)),
+            ("1", Some(31 * DAY)),
+            ("3M", Some(93 * DAY)),
+            (" 1.5d ", Some(DAY + DAY / 2)),
+            ("10x", None),
+            ("d", None),
+        ];
+        for (value, expected) in cases {
+            assert_eq!(parse_retention(value).map(|d| d.as_secs()), expected, "{:?}", value);
+        }
+    }
+
+    #[test]
+    fn bytes_are_parsed_with_binary_units() {
+        let cases = [
+            ("512", Some(512)),
+            ("1KB", Some(1024)),
+            ("1.5 GB", Some(3 << 29)),
+            ("2TB", Some(2 << 40)),
+            ("10gib", Some(10 << 30)),
+            ("1 XB", None),
+            ("", None),
+        ];
+        for (value, expected) in cases {
+            assert_eq!(parse_bytes(value), expected, "{:?}", value);
+        }
+    }
+
+    #[test]
+    fn retention_and_usage_are_formatted_for_display() {
+        assert_eq!(format_retention(std::time::Duration::from_secs(365 * 86400)), "1y (365 дней)");
+        assert_eq!(format_retention(std::time::Duration::from_secs(30 * 86400 + 7200)), "30 дней 2h");
+        assert_eq!(format_retention(std::time::Duration::from_secs(5 * 3600)), "5h");
+        assert_eq!(format_bytes(3 << 29), "1.50 GB");
+        assert_eq!(format_percentage(1.0, 4.0), "25.00%");
+        assert_eq!(format_percentage(1.0, 0.0), "0.00%");
+
+        let bar = format_usage_bar(1.0, 4.0, 8);
+        assert!(bar.contains(&"██░░░░░░".green().to_string()), "{}", bar);
+        assert!(bar.ends_with("] 25.00%"), "{}", bar);
+    }
+
+    #[test]
+    fn timestamps_are_rendered_in_utc_and_named_zone() {
+        let utc = parse_timezone("UTC").unwrap();
+        let moscow = parse_timezone("Europe/Moscow").unwrap();
+        let new_york = parse_timezone("America/New_York").unwrap();
+
+        assert_eq!(render_timestamp_in(1700000000.0, utc), "2023-11-14 22:13:20 UTC");
+        assert_eq!(render_timestamp_in(1700000000.5, utc), "2023-11-14 22:13:20.500 UTC");
+        assert_eq!(render_timestamp_in(1700000000.0, moscow), "2023-11-15 01:13:20 MSK");
+        assert_eq!(render_timestamp_in(1700000000.0, new_york), "2023-11-14 17:13:20 EST");
+        assert!(parse_timezone("Mars/Olympus").is_err());
+    }
+
+    #[test]
+    fn broad_matchers_are_detected_and_narrow_ones_allowed() {
+        for selector in ["{}", "{__name__=~\".+\"}", "{job=~\".*\", instance!=\"\"}", " {__name__=~`.*`} "] {
+            assert!(broad_matcher_reason(selector).is_some(), "{}", selector);
+        }
+        for selector in ["up", "up{job=~\".*\"}", "{job=\"node\"}", "{__name__=~\".+\", job=\"a,b\"}"] {
+            assert_eq!(broad_matcher_reason(selector), None, "{}", selector);
+        }
+    }
+
+    #[test]
+    fn selectors_are_extracted_from_queries() {
+        let cases: [(&str, &[&str]); 6] = [
+            ("up", &["up"]),
+            ("rate(http_requests_total{job=\"api\"}[5m])", &["http_requests_total{job=\"api\"}"]),
+            (
+                "sum by (instance) (rate(node_cpu_seconds_total{mode!=\"idle\"}[1m])) / on(instance) group_left count(node_cpu_seconds_total)",
+                &["node_cpu_seconds_total{mode!=\"idle\"}", "node_cpu_seconds_total"],
+            ),
+            ("up offset 5m + up", &["up"]),
+            ("{__name__=~\"go_.*\", job=\"a}b\"} > 0.5", &["{__name__=~\"go_.*\", job=\"a}b\"}"]),
+            ("histogram_quantile(0.99, sum(rate(req_bucket[5m])) without (pod))", &["req_bucket"]),
+        ];
+        for (query, expected) in cases {
+            assert_eq!(extract_selectors(query), expected, "{}", query);
+        }
+    }
+
+    #[test]
+    fn non_finite_values_are_rendered_literally_in_every_format() {
+        assert_eq!(format_float(f64::NAN), "NaN");
+        assert_eq!(format_float(f64::INFINITY), "+Inf");
+        assert_eq!(format_float(f64::NEG_INFINITY), "-Inf");
+        for (raw, expected) in [("NaN", "NaN"), ("nan", "NaN"), ("+Inf", "+Inf"), ("inf", "+Inf"), ("-Inf", "-Inf"), ("1.5", "1.5")] {
+            assert_eq!(format_sample_value(raw), expected, "{}", raw);
+        }
+
+        let response: QueryResponse = serde_json::from_str(
+            r#"{"status":"success","data":{"resultType":"vector","result":[
+                {"metric":{"__name__":"a"},"value":[1700000000,"NaN"]},
+                {"metric":{"__name__":"b"},"value":[1700000000,"+Inf"]},
+                {"metric":{"__name__":"c"},"value":[1700000000,"-Inf"]}
+            ]}}"#,
+        )
+        .unwrap();
+        let columns = LabelColumns::default();
+
+        let csv = format_output(&response, &OutputFormat::Csv, true, columns);
+        assert_eq!(csv, "timestamp,value,__name__\n1700000000,NaN,a\n1700000000,+Inf,b\n1700000000,-Inf,c");
+        let tsv = format_output(&response, &OutputFormat::Tsv, true, columns);
+        assert_eq!(tsv, csv.replace(',', "\t"));
+        for format in [OutputFormat::Table, OutputFormat::Markdown, OutputFormat::Json, OutputFormat::Yaml] {
+            let rendered = format_output(&response, &format, true, columns);
+            for value in ["NaN", "+Inf", "-Inf"] {
+                assert!(rendered.contains(value), "{:?}: {}", format, rendered);
+            }
+        }
+    }
+
+    #[test]
+    fn suggestions_are_ranked_by_edit_distance() {
+        let candidates = [
+            "node_cpu_seconds_total",
+            "node_cpu_guest_seconds_total",
+            "node_memory_MemFree_bytes",
+            "process_cpu_seconds_total",
+            "up",
+        ]
+        .map(String::from);
+
+        assert_eq!(
+            suggest_names("node_cpu_second_total", &candidates, 3),
+            ["node_cpu_seconds_total", "process_cpu_seconds_total", "node_cpu_guest_seconds_total"]
+        );
+        assert_eq!(suggest_names("node_cpu_seconds_totl", &candidates, 1), ["node_cpu_seconds_total"]);
+        assert_eq!(suggest_names("upp", &candidates, 3), ["up"]);
+        assert!(suggest_names("http_requests_total", &candidates, 3).is_empty());
+        assert!(suggest_names("up", &candidates, 3).is_empty());
+    }
+
+    #[test]
+    fn edit_distance_counts_insertions_deletions_and_substitutions() {
+        assert_eq!(edit_distance("kitten", "sitting"), 3);
+        assert_eq!(edit_distance("", "up"), 2);
+        assert_eq!(edit_distance("up", "up"), 0);
+    }
+
+    #[test]
+    fn name_column_is_promoted_and_labels_can_be_hidden() {
+        let response = response();
+
+        let table = QueryTable::new(&response, LabelColumns::default());
+        assert_eq!(table.rows()[0][2], "up");
+        assert!(!table.rows()[0][3].contains("__name__"), "{:?}", table.rows());
+
+        let name_only = QueryTable::new(&response, LabelColumns { show_name: true, hide_labels: true });
+        assert_eq!(name_only.headers(), ["timestamp", "value", "name"]);
+        assert_eq!(name_only.render(&OutputFormat::Csv, true).unwrap(), "timestamp,value,__name__\n1700000000,1,up\n1700000000,0,up");
+
+        let bare = QueryTable::new(&response, LabelColumns { show_name: false, hide_labels: true });
+        assert_eq!(bare.headers(), ["timestamp", "value"]);
+        assert_eq!(bare.render(&OutputFormat::Csv, true).unwrap(), "timestamp,value\n1700000000,1\n1700000000,0");
+        assert!(!bare.render(&OutputFormat::Table, true).unwrap().contains("up"));
+    }
+
+    #[test]
+    fn validate_range_params_rejects_zero_or_invalid_step() {
+        for step in ["0", "0s", "-5", "abc", ""] {
+            assert!(validate_range_params("1700000000", "1700003600", step).is_err(), "{:?}", step);
+        }
+    }
+
+    #[test]
+    fn validate_range_params_rejects_start_after_end() {
+        assert!(validate_range_params("1700003600", "1700000000", "60s").is_err());
+        assert!(validate_range_params("2023-11-14T23:00:00Z", "2023-11-14T22:00:00Z", "1m").is_err());
+    }
+
+    #[test]
+    fn validate_range_params_accepts_valid_range() {
+        assert_eq!(validate_range_params("1700000000", "1700003600", "60s"), Ok(()));
+        assert_eq!(validate_range_params("1700000000", "1700000000", "15"), Ok(()));
+        assert_eq!(validate_range_params("2023-11-14T22:00:00Z", "2023-11-14T23:00:00Z", "1m"), Ok(()));
+        assert_eq!(validate_range_params("now-1h", "now", "5m"), Ok(()));
+    }
+
+    fn vars(pairs: &[(&str, &str)]) -> Vec<(String, String)> {
+        pairs.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect()
+    }
+
+    #[test]
+    fn substitute_vars_replaces_plain_and_braced_placeholders() {
+        let vars = vars(&[("region", "us-east"), ("job", "api"), ("job", "node")]);
+
+        assert_eq!(
+            substitute_vars("up{region=\"$region\",job=\"${job}\"}", &vars, false),
+            Ok("up{region=\"us-east\",job=\"node\"}".to_string())
+        );
+        assert_eq!(substitute_vars("rate(x[5m]) * $region_", &vars, true), Ok("rate(x[5m]) * $region_".to_string()));
+        assert_eq!(substitute_vars("up", &[], false), Ok("up".to_string()));
+    }
+
+    #[test]
+    fn substitute_vars_reports_missing_variables() {
+        let err = substitute_vars("up{env=\"$env\"}", &vars(&[("region", "x")]), false).unwrap_err();
+        assert!(err.contains("$env"), "{}", err);
+
+        assert_eq!(substitute_vars("up{env=\"${env}\"}", &[], true), Ok("up{env=\"${env}\"}".to_string()));
+        assert!(substitute_vars("up{env=\"${env\"}", &[], true).is_err());
+        assert!(substitute_vars("${1x}", &[], true).is_err());
+    }
+
+    #[test]
+    fn substitute_vars_keeps_escaped_and_bare_dollars() {
+        let vars = vars(&[("region", "eu")]);
+
+        assert_eq!(substitute_vars("label_replace(up, \"x\", \"$$1\", \"y\", \"(.*)\")", &vars, false), Ok("label_replace(up, \"x\", \"$1\", \"y\", \"(.*)\")".to_string()));
+        assert_eq!(substitute_vars("$$region", &vars, false), Ok("$region".to_string()));
+        assert_eq!(substitute_vars("cost > 5$", &vars, false), Ok("cost > 5$".to_string()));
+    }
+
+    #[test]
+    fn labels_render_in_the_same_sorted_order_every_time() {
+        let pairs = [("zone", "b"), ("__name__", "up"), ("job", "api"), ("instance", "h:9100"), ("a", "1")];
+
+        for round in 0..20 {
+            let mut metric = HashMap::new();
+            for index in 0..pairs.len() {
+                let (key, value) = pairs[(index + round) % pairs.len()];
+                metric.insert(key.to_string(), value.to_string());
+            }
+
+            assert_eq!(format_labels(&metric), "__name__=up, a=1, instance=h:9100, job=api, zone=b");
+            assert_eq!(format_series(&metric), "up{a=\"1\",instance=\"h:9100\",job=\"api\",zone=\"b\"}");
+        }
+    }
+
+    #[test]
+    fn round_sample_value_keeps_requested_decimal_places() {
+        assert_eq!(round_sample_value("3.14159", 2), "3.14");
+        assert_eq!(round_sample_value("2.675001", 2), "2.68");
+        assert_eq!(round_sample_value("1234.5", 0), "1235");
+        assert_eq!(round_sample_value("0.5", 3), "0.5");
+        assert_eq!(round_sample_value("-0.0004", 3), "0");
+        assert_eq!(round_sample_value("1e-7", 20), "0.0000001");
+    }
+
+    #[test]
+    fn round_sample_value_leaves_non_finite_values() {
+        for value in ["NaN", "+Inf", "-Inf", "n/a"] {
+            assert_eq!(round_sample_value(value, 2), value);
+        }
+    }
+
+    #[test]
+    fn range_points_counts_both_range_ends() {
+        assert_eq!(range_points("1700000000", "1700003600", "60s"), Some(61));
+        assert_eq!(range_points("1700000000", "1700003600", "7m"), Some(9));
+        assert_eq!(range_points("1700000000", "1700000000", "1s"), Some(1));
+        assert_eq!(range_points("2023-11-14T22:00:00Z", "2023-11-14T23:00:00Z", "1m"), Some(61));
+        assert_eq!(range_points("1700003600", "1700000000", "60s"), None);
+        assert_eq!(range_points("1700000000", "1700003600", "0"), None);
+    }
+
+    #[test]
+    fn cap_step_widens_step_to_stay_under_max_points() {
+        assert_eq!(cap_step("1700000000", "1700604800", "1s", 5000), Some("121s".to_string()));
+        assert!(range_points("1700000000", "1700604800", "121s").unwrap() <= 5000);
+        assert_eq!(cap_step("1700000000", "1700003600", "1s", 100), Some("37s".to_string()));
+        assert_eq!(cap_step("1700000000", "1700000010", "1s", 3), Some("5s".to_string()));
+    }
+
+    #[test]
+    fn cap_step_keeps_step_within_limit() {
+        assert_eq!(cap_step("1700000000", "1700003600", "60s", 61), None);
+        assert_eq!(cap_step("1700000000", "1700003600", "60s", 1000), None);
+        assert_eq!(cap_step("1700000000", "1700003600", "bad", 10), None);
+    }
+
+    const ABC_SHA256: &str = "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad";
+
+    #[test]
+    fn sha256_of_known_byte_stream() {
+        assert_eq!(sha256_hex(b"abc"), ABC_SHA256);
+        assert_eq!(sha256_hex(b""), "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855");
+    }
+
+    #[test]
+    fn checksum_sidecar_uses_sha256sum_format() {
+        let dir = tempfile::tempdir().unwrap();
+        let file = dir.path().join("export.txt");
+        write_checksum(&file, ABC_SHA256).unwrap();
+
+        let sidecar = dir.path().join("export.txt.sha256");
+        assert_eq!(checksum_path(&file), sidecar);
+        assert_eq!(std::fs::read_to_string(&sidecar).unwrap(), format!("{}  export.txt\n", ABC_SHA256));
+        assert_eq!(read_checksum(&file).unwrap(), Some(ABC_SHA256.to_string()));
+    }
+
+    #[test]
+    fn read_checksum_accepts_uppercase_and_rejects_garbage() {
+        let dir = tempfile::tempdir().unwrap();
+        let file = dir.path().join("data.txt");
+
+        std::fs::write(checksum_path(&file), ABC_SHA256.to_uppercase()).unwrap();
+        assert_eq!(read_checksum(&file).unwrap(), Some(ABC_SHA256.to_string()));
+
+        std::fs::write(checksum_path(&file), "not-a-hash  data.txt\n").unwrap();
+        assert_eq!(read_checksum(&file).unwrap(), None);
+    }
+
+    #[test]
+    fn hashing_writer_hashes_only_bytes_written_through_it() {
+        let mut writer = HashingWriter::new(b"prefix ".to_vec());
+        writer.write_all(b"ab").unwrap();
+        writer.write_all(b"c").unwrap();
+
+        let (written, hash) = writer.finish();
+        assert_eq!(written, b"prefix abc");
+        assert_eq!(hash, ABC_SHA256);
+    }
+}